@@ -1,5 +1,5 @@
 import * as path from 'path';
-import { loadTag, TagCarrier } from 'native-addon';
+import { loadTagAsync, TagCarrier } from 'native-addon';
 
 interface ISuppotedFile {
   name: string;
@@ -8,7 +8,7 @@ interface ISuppotedFile {
   tag: TagCarrier | null;
 }
 
-function getSupportedFileFomPath(filePath: string): ISuppotedFile {
+async function getSupportedFileFomPath(filePath: string): Promise<ISuppotedFile> {
   const supportedFile: ISuppotedFile = {
     name: path.basename(filePath, path.extname(filePath)),
     location: path.dirname(filePath),
@@ -18,8 +18,8 @@ function getSupportedFileFomPath(filePath: string): ISuppotedFile {
 
   // Metadata
   try {
-    const tag = loadTag(filePath);
-    supportedFile.tag = tag;
+    const { frames } = await loadTagAsync(filePath);
+    supportedFile.tag = frames;
   } catch (_) { /* */ }
 
   return supportedFile;
@@ -1,37 +1,64 @@
 import { ipcMain } from 'electron';
 import type { IpcMainEvent } from 'electron';
 
-import { loadTag, updateTag } from 'native-addon';
+import { loadTagAsync, TagError, updateTags } from 'native-addon';
 import type { TagCarrier } from 'native-addon';
 
 import IpcEvents from '../../common/IpcEvents';
 import type { ISuppotedFile } from '../../common/SupportedFile';
 
+/**
+ * Errors lose their custom properties when sent over IPC,
+ * so tag errors are flattened into plain objects
+ */
+function serializeTagError(error: unknown) {
+  if (error instanceof TagError) {
+    const { code, path, frameIndex, frameId } = error;
+    return {
+      name: error.name,
+      message: error.message,
+      code,
+      path,
+      frameIndex,
+      frameId,
+    };
+  }
+  if (error instanceof Error) {
+    return { name: error.name, message: error.message };
+  }
+
+  return { name: 'Error', message: String(error) };
+}
+
 function setupTagsProcess(loadedFiles: Map<string, ISuppotedFile>) {
   let currentFiles: string[] = [];
   let currentTag: TagCarrier = [];
 
   ipcMain.on(
     IpcEvents.renderer.wants.toSelectFile,
-    (event: IpcMainEvent, filePath: string) => {
+    async (event: IpcMainEvent, filePath: string) => {
       // Clear selection and select the file
       currentFiles = [];
       currentFiles.push(filePath);
 
-      // Load tags
+      // Request render update
+      event.sender.send(IpcEvents.main.has.updatedSelection, currentFiles);
+
+      // Load tags off the main thread
       currentTag = [];
 
       try {
-        currentTag = loadTag(filePath);
+        const { frames } = await loadTagAsync(filePath);
+
+        // Drop tags of a file that is no longer the selection
+        if (currentFiles.length !== 1 || currentFiles[0] !== filePath) return;
+        currentTag = frames;
 
         // Request tag section update
         event.sender.send(IpcEvents.main.wants.toRender.meta, currentTag);
       } catch (error) {
-        event.sender.send(IpcEvents.main.wants.toRender.error, error);
+        event.sender.send(IpcEvents.main.wants.toRender.error, serializeTagError(error));
       }
-
-      // Request render update
-      event.sender.send(IpcEvents.main.has.updatedSelection, currentFiles);
     },
   );
 
@@ -58,16 +85,21 @@ function setupTagsProcess(loadedFiles: Map<string, ISuppotedFile>) {
   );
 
   ipcMain.on(IpcEvents.renderer.wants.toWriteUpdate, (event: IpcMainEvent, mods: TagCarrier) => {
-    currentFiles.forEach((filePath) => {
-      const supportedFile = loadedFiles.get(filePath);
-      if (supportedFile) {
-        try {
-          currentTag = updateTag(supportedFile.path, mods);
+    const paths = currentFiles
+      .map((filePath) => loadedFiles.get(filePath)?.path)
+      .filter((filePath): filePath is string => filePath !== undefined);
+
+    updateTags(paths, mods).then((results) => {
+      results.forEach(({ error, tag }) => {
+        if (tag) {
+          currentTag = tag;
           event.sender.send(IpcEvents.main.wants.toRender.meta, currentTag);
-        } catch (error) {
-          event.sender.send(IpcEvents.main.wants.toRender.error, error);
+        } else if (error) {
+          event.sender.send(IpcEvents.main.wants.toRender.error, serializeTagError(error));
         }
-      }
+      });
+    }).catch((error) => {
+      event.sender.send(IpcEvents.main.wants.toRender.error, serializeTagError(error));
     });
   });
 }
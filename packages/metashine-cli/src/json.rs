@@ -0,0 +1,817 @@
+//! JSON forms of tags and frame carriers, matching what the addon hands to JavaScript: `read`
+//! prints tags as `loadTag` returns them and `write` reads updates like `updateTag`. Binary
+//! data, which JavaScript gets as ArrayBuffers, is written as base64 strings
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use metashine_core::{
+    carrier::{self, Carrier, FrameKind, PayloadHandle, FRAME_SCHEMA_VERSION},
+    error::{ErrorCode, TagError},
+    id3::{
+        self,
+        frame::{
+            Chapter, Comment, EncapsulatedObject, ExtendedLink, ExtendedText, InvolvedPeopleList,
+            InvolvedPeopleListItem, Lyrics, Private, SynchronisedLyrics, SynchronisedLyricsType,
+            TableOfContents, TimestampFormat, UniqueFileIdentifier,
+        },
+        Frame,
+    },
+    popularimeter,
+    tags::LoadedTag,
+};
+use serde_json::{json, Map, Value};
+
+/// Offsets of 0xFFFFFFFF mark chapters that don't use byte offsets
+const CHAPTER_OFFSET_UNUSED: u32 = u32::MAX;
+
+/// How frames are printed, like the `frameFormat` option of the addon
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum FrameFormat {
+    /// Legacy `[type, id, content, remove]` tuples
+    Tuple,
+    /// `{kind, id, value, op}` objects of the current frame schema version
+    Object,
+}
+
+fn bytes_to_json(bytes: &[u8]) -> Value {
+    Value::String(STANDARD.encode(bytes))
+}
+
+fn chapter_offset_to_json(offset: u32) -> Value {
+    match offset {
+        CHAPTER_OFFSET_UNUSED => Value::Null,
+        offset => json!(offset),
+    }
+}
+
+fn timestamp_format_to_str(format: TimestampFormat) -> &'static str {
+    match format {
+        TimestampFormat::Ms => "ms",
+        TimestampFormat::Mpeg => "mpeg",
+    }
+}
+
+fn str_to_timestamp_format(format: &str) -> Option<TimestampFormat> {
+    match format {
+        "ms" => Some(TimestampFormat::Ms),
+        "mpeg" => Some(TimestampFormat::Mpeg),
+        _ => None,
+    }
+}
+
+fn synchronised_lyrics_type_to_str(content_type: SynchronisedLyricsType) -> &'static str {
+    match content_type {
+        SynchronisedLyricsType::Other => "other",
+        SynchronisedLyricsType::Lyrics => "lyrics",
+        SynchronisedLyricsType::Transcription => "transcription",
+        SynchronisedLyricsType::PartName => "part name",
+        SynchronisedLyricsType::Event => "event",
+        SynchronisedLyricsType::Chord => "chord",
+        SynchronisedLyricsType::Trivia => "trivia",
+    }
+}
+
+fn str_to_synchronised_lyrics_type(content_type: &str) -> Option<SynchronisedLyricsType> {
+    match content_type {
+        "other" => Some(SynchronisedLyricsType::Other),
+        "lyrics" => Some(SynchronisedLyricsType::Lyrics),
+        "transcription" => Some(SynchronisedLyricsType::Transcription),
+        "part name" => Some(SynchronisedLyricsType::PartName),
+        "event" => Some(SynchronisedLyricsType::Event),
+        "chord" => Some(SynchronisedLyricsType::Chord),
+        "trivia" => Some(SynchronisedLyricsType::Trivia),
+        _ => None,
+    }
+}
+
+/// Sets the `data` of a picture or encapsulated object, or the `size` and `hash` of its
+/// handle when the data was left out
+fn set_payload(object: &mut Map<String, Value>, carrier: &Carrier, data: &[u8]) {
+    match &carrier.payload {
+        Some(payload) => {
+            object.insert("size".to_owned(), json!(payload.size));
+            object.insert("hash".to_owned(), json!(payload.hash));
+        }
+        None => {
+            object.insert("data".to_owned(), bytes_to_json(data));
+        }
+    }
+}
+
+fn frames_to_json<'f>(
+    frames: impl IntoIterator<Item = &'f Frame>,
+    format: FrameFormat,
+) -> Result<Value, TagError> {
+    carriers_to_json(&carrier::frames_to_carriers(frames)?, format)
+}
+
+fn content_to_json(carrier: &Carrier, format: FrameFormat) -> Result<Value, TagError> {
+    Ok(match &carrier.content {
+        id3::Content::Text(content) | id3::Content::Link(content) => json!(content),
+        id3::Content::ExtendedText(content) => json!({
+            "value": content.value,
+            "description": content.description,
+        }),
+        id3::Content::ExtendedLink(content) => json!({
+            "description": content.description,
+            "link": content.link,
+        }),
+        id3::Content::Comment(content) => json!({
+            "lang": content.lang,
+            "description": content.description,
+            "text": content.text,
+        }),
+        id3::Content::Popularimeter(content) => json!({
+            "user": content.user,
+            "rating": content.rating,
+            "counter": content.counter,
+        }),
+        id3::Content::Lyrics(content) => json!({
+            "lang": content.lang,
+            "description": content.description,
+            "text": content.text,
+        }),
+        id3::Content::SynchronisedLyrics(content) => json!({
+            "lang": content.lang,
+            "timestampFormat": timestamp_format_to_str(content.timestamp_format),
+            "contentType": synchronised_lyrics_type_to_str(content.content_type),
+            "description": content.description,
+            "content": content.content,
+        }),
+        id3::Content::Picture(content) => {
+            let mut picture = Map::new();
+            picture.insert("MIMEType".to_owned(), json!(content.mime_type));
+            picture.insert(
+                "pictureType".to_owned(),
+                json!(u8::from(content.picture_type)),
+            );
+            picture.insert("description".to_owned(), json!(content.description));
+            set_payload(&mut picture, carrier, &content.data);
+            Value::Object(picture)
+        }
+        id3::Content::EncapsulatedObject(content) => {
+            let mut object = Map::new();
+            object.insert("MIMEType".to_owned(), json!(content.mime_type));
+            object.insert("filename".to_owned(), json!(content.filename));
+            object.insert("description".to_owned(), json!(content.description));
+            set_payload(&mut object, carrier, &content.data);
+            Value::Object(object)
+        }
+        id3::Content::Private(content) => json!({
+            "owner": content.owner_identifier,
+            "data": bytes_to_json(&content.private_data),
+        }),
+        id3::Content::UniqueFileIdentifier(content) => json!({
+            "owner": content.owner_identifier,
+            "identifier": bytes_to_json(&content.identifier),
+        }),
+        id3::Content::Chapter(content) => json!({
+            "elementId": content.element_id,
+            "startTime": content.start_time,
+            "endTime": content.end_time,
+            "startOffset": chapter_offset_to_json(content.start_offset),
+            "endOffset": chapter_offset_to_json(content.end_offset),
+            "frames": frames_to_json(&content.frames, format)?,
+        }),
+        id3::Content::TableOfContents(content) => json!({
+            "elementId": content.element_id,
+            "topLevel": content.top_level,
+            "ordered": content.ordered,
+            "elements": content.elements,
+            "frames": frames_to_json(&content.frames, format)?,
+        }),
+        id3::Content::InvolvedPeopleList(content) => Value::Array(
+            content
+                .items
+                .iter()
+                .map(|item| {
+                    json!({
+                        "involvement": item.involvement,
+                        "involvee": item.involvee,
+                    })
+                })
+                .collect(),
+        ),
+        id3::Content::Unknown(content) if carrier.kind() == FrameKind::PlayCounter => {
+            json!(popularimeter::decode_play_counter(&content.data))
+        }
+        id3::Content::Unknown(content) => match carrier.data_type {
+            Some(data_type) => json!({ "data": bytes_to_json(&content.data), "type": data_type }),
+            None => json!({ "data": bytes_to_json(&content.data) }),
+        },
+        // Frames that are not implemented yet, carrier::frames_to_carriers rejects them
+        _ => {
+            return Err(TagError::new(
+                ErrorCode::UnsupportedFrame,
+                format!("Reading frame {} is not implemented yet", carrier.id),
+            ))
+        }
+    })
+}
+
+fn carrier_to_json(carrier: &Carrier, format: FrameFormat) -> Result<Value, TagError> {
+    let content = content_to_json(carrier, format)?;
+    let kind = carrier.kind().as_str();
+    Ok(match format {
+        FrameFormat::Tuple => json!([kind, carrier.id, content, false]),
+        FrameFormat::Object => json!({
+            "kind": kind,
+            "id": carrier.id,
+            "value": content,
+            "op": "set",
+        }),
+    })
+}
+
+pub fn carriers_to_json(carriers: &[Carrier], format: FrameFormat) -> Result<Value, TagError> {
+    carriers
+        .iter()
+        .map(|carrier| carrier_to_json(carrier, format))
+        .collect()
+}
+
+/// Converts a loaded tag to the object returned by `loadTag`
+pub fn loaded_tag_to_json(loaded: &LoadedTag, format: FrameFormat) -> Result<Value, TagError> {
+    let header = loaded.header.as_ref();
+    Ok(json!({
+        "format": loaded.format.as_str(),
+        "hadTag": loaded.had_tag,
+        "version": header.map(|header| header.version_name()),
+        "tagSize": header.map(|header| header.tag_size),
+        "paddingSize": header.map(|header| header.padding_size),
+        "flags": header.map(|header| json!({
+            "unsynchronisation": header.flags.unsynchronisation,
+            "extendedHeader": header.flags.extended_header,
+            "experimental": header.flags.experimental,
+            "footer": header.flags.footer,
+        })),
+        "frames": carriers_to_json(&loaded.carriers, format)?,
+    }))
+}
+
+/// Converts an error to the properties of the TagError objects the addon throws
+pub fn error_to_json(error: &TagError) -> Value {
+    json!({
+        "name": "TagError",
+        "code": error.code.as_str(),
+        "message": error.message,
+        "path": error.path,
+        "frameIndex": error.frame_index,
+        "frameId": error.frame_id,
+    })
+}
+
+/// Position of a frame carrier in an update, used to annotate carrier errors
+struct CarrierRef {
+    index: u32,
+    id: String,
+    /// Whether the carrier removes a frame, so missing fields are left empty
+    removal: bool,
+    /// The form of the carrier, which names its fields in errors like the addon does
+    format: FrameFormat,
+}
+
+impl CarrierRef {
+    fn error(&self, code: ErrorCode, message: impl Into<String>) -> TagError {
+        TagError::new(code, message).with_frame(self.index, &self.id)
+    }
+
+    /// Records the frame ID of the carrier, after the ID of the parent frame for sub-frames
+    fn push_id(&mut self, id: &str) {
+        self.id = match self.id.is_empty() {
+            true => id.to_string(),
+            false => format!("{}/{}", self.id, id),
+        };
+    }
+
+    /// Names a field of the content in errors: the content of tuples is field `2` and its
+    /// fields are named alone, while in objects they are `value.field`
+    fn field_name(&self, name: &str) -> String {
+        match self.format {
+            FrameFormat::Tuple => name.to_string(),
+            FrameFormat::Object if name.starts_with('[') => format!("value{}", name),
+            FrameFormat::Object => format!("value.{}", name),
+        }
+    }
+}
+
+/// Values frame carrier fields can have, with the empty value missing fields of removal
+/// carriers take
+trait CarrierField: Sized {
+    /// What the field must be, for error messages
+    const NAME: &'static str;
+
+    fn empty() -> Self;
+
+    fn from_json(value: &Value) -> Option<Self>;
+}
+
+impl CarrierField for String {
+    const NAME: &'static str = "a string";
+
+    fn empty() -> Self {
+        String::new()
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        value.as_str().map(str::to_owned)
+    }
+}
+
+impl CarrierField for f64 {
+    const NAME: &'static str = "a number";
+
+    fn empty() -> Self {
+        0.0
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        value.as_f64()
+    }
+}
+
+impl CarrierField for bool {
+    const NAME: &'static str = "a boolean";
+
+    fn empty() -> Self {
+        false
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        value.as_bool()
+    }
+}
+
+impl CarrierField for Map<String, Value> {
+    const NAME: &'static str = "an object";
+
+    fn empty() -> Self {
+        Map::new()
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        value.as_object().cloned()
+    }
+}
+
+impl CarrierField for Vec<Value> {
+    const NAME: &'static str = "an array";
+
+    fn empty() -> Self {
+        Vec::new()
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        value.as_array().cloned()
+    }
+}
+
+/// Binary data, given as a base64 string
+struct Bytes(Vec<u8>);
+
+impl CarrierField for Bytes {
+    const NAME: &'static str = "a base64 string";
+
+    fn empty() -> Self {
+        Bytes(Vec::new())
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        value
+            .as_str()
+            .and_then(|data| STANDARD.decode(data).ok())
+            .map(Bytes)
+    }
+}
+
+/// Describes a JSON value in carrier error messages
+fn json_type_name(value: Option<&Value>) -> &'static str {
+    match value {
+        None => "nothing",
+        Some(Value::Null) => "null",
+        Some(Value::Bool(_)) => "a boolean",
+        Some(Value::Number(_)) => "a number",
+        Some(Value::String(_)) => "a string",
+        Some(Value::Array(_)) => "an array",
+        Some(Value::Object(_)) => "an object",
+    }
+}
+
+/// Checks the type of a carrier field named `name` in errors, failing with `ERR_BAD_CARRIER`
+/// if it is missing or mistyped
+fn carrier_field<V: CarrierField>(
+    value: Option<&Value>,
+    name: &str,
+    at: &CarrierRef,
+) -> Result<V, TagError> {
+    if at.removal && matches!(value, None | Some(Value::Null)) {
+        return Ok(V::empty());
+    }
+    match value.and_then(V::from_json) {
+        Some(value) => Ok(value),
+        None => Err(at.error(
+            ErrorCode::BadCarrier,
+            format!(
+                "Field {} must be {}, got {}",
+                name,
+                V::NAME,
+                json_type_name(value)
+            ),
+        )),
+    }
+}
+
+/// Reads a field of the content of a frame carrier, failing with `ERR_BAD_CARRIER` if it is
+/// missing or mistyped
+fn carrier_get<V: CarrierField>(
+    object: &Map<String, Value>,
+    key: &str,
+    at: &CarrierRef,
+) -> Result<V, TagError> {
+    carrier_field(object.get(key), &at.field_name(key), at)
+}
+
+/// Reads a number field of the content of a frame carrier that must be an integer fitting in
+/// 32 bits, failing with `ERR_BAD_CARRIER` instead of saturating or truncating other numbers
+fn carrier_get_u32(
+    object: &Map<String, Value>,
+    key: &str,
+    at: &CarrierRef,
+) -> Result<u32, TagError> {
+    let number: f64 = carrier_get(object, key, at)?;
+    if number.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(&number) {
+        return Ok(number as u32);
+    }
+    Err(at.error(
+        ErrorCode::BadCarrier,
+        format!(
+            "Field {} must be an integer from 0 to {}, got {}",
+            at.field_name(key),
+            u32::MAX,
+            number
+        ),
+    ))
+}
+
+fn json_to_synchronised_lyrics(
+    content: &Map<String, Value>,
+    at: &CarrierRef,
+) -> Result<SynchronisedLyrics, TagError> {
+    let timestamp_format: String = carrier_get(content, "timestampFormat", at)?;
+    let timestamp_format = str_to_timestamp_format(&timestamp_format).ok_or_else(|| {
+        at.error(
+            ErrorCode::BadCarrier,
+            format!("Unknown timestamp format {}", timestamp_format),
+        )
+    })?;
+    let content_type: String = carrier_get(content, "contentType", at)?;
+    let content_type = str_to_synchronised_lyrics_type(&content_type).ok_or_else(|| {
+        at.error(
+            ErrorCode::BadCarrier,
+            format!("Unknown synchronised lyrics content type {}", content_type),
+        )
+    })?;
+
+    let pairs: Vec<Value> = carrier_get(content, "content", at)?;
+    let mut lines = Vec::new();
+    for (i, pair) in pairs.iter().enumerate() {
+        let pair = pair.as_array().ok_or_else(|| {
+            at.error(
+                ErrorCode::BadCarrier,
+                "Synchronised lyrics content must be [timestamp, text] pairs",
+            )
+        })?;
+        let timestamp: f64 = carrier_field(
+            pair.first(),
+            &at.field_name(&format!("content[{}][0]", i)),
+            at,
+        )?;
+        let text: String = carrier_field(
+            pair.get(1),
+            &at.field_name(&format!("content[{}][1]", i)),
+            at,
+        )?;
+        lines.push((timestamp.clamp(0.0, u32::MAX as f64) as u32, text));
+    }
+
+    Ok(SynchronisedLyrics {
+        lang: carrier_get(content, "lang", at)?,
+        timestamp_format,
+        content_type,
+        description: carrier_get(content, "description", at)?,
+        content: lines,
+    })
+}
+
+/// Reads an optional chapter byte offset, where null marks an unused offset
+fn json_chapter_offset(
+    chapter: &Map<String, Value>,
+    key: &str,
+    at: &CarrierRef,
+) -> Result<u32, TagError> {
+    match chapter.get(key) {
+        None | Some(Value::Null) => Ok(CHAPTER_OFFSET_UNUSED),
+        Some(_) => carrier_get_u32(chapter, key, at),
+    }
+}
+
+/// Builds the sub-frames of a chapter or table of contents, skipping carriers marked for removal
+fn json_frames_to_frames(frames: &[Value], at: &CarrierRef) -> Result<Vec<Frame>, TagError> {
+    let mut result = Vec::new();
+
+    for frame in frames {
+        let mut sub_at = CarrierRef {
+            index: at.index,
+            id: at.id.clone(),
+            removal: false,
+            format: FrameFormat::Tuple,
+        };
+        let carrier = json_to_carrier(frame, &mut sub_at)?;
+
+        if !carrier.remove {
+            let frame = carrier::carrier_to_frame(&carrier)
+                .map_err(|error| error.with_frame(sub_at.index, &sub_at.id))?;
+            result.push(frame);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Reads the content of a frame carrier
+fn json_to_content(
+    kind: FrameKind,
+    value: Option<&Value>,
+    value_key: &str,
+    at: &CarrierRef,
+) -> Result<id3::Content, TagError> {
+    if let FrameKind::Text | FrameKind::Link = kind {
+        let text: String = carrier_field(value, value_key, at)?;
+        return Ok(match kind {
+            FrameKind::Text => id3::Content::Text(text),
+            _ => id3::Content::Link(text),
+        });
+    }
+    if kind == FrameKind::PlayCounter {
+        let counter: f64 = carrier_field(value, value_key, at)?;
+        return Ok(id3::Content::Unknown(id3::frame::Unknown {
+            data: popularimeter::encode_play_counter(counter.max(0.0) as u64),
+            version: id3::Version::Id3v24,
+        }));
+    }
+    if kind == FrameKind::InvolvedPeople {
+        let people: Vec<Value> = carrier_field(value, value_key, at)?;
+        let mut items = Vec::new();
+        for (j, person) in people.iter().enumerate() {
+            let item = format!("[{}]", j);
+            let person: Map<String, Value> =
+                carrier_field(Some(person), &at.field_name(&item), at)?;
+            items.push(InvolvedPeopleListItem {
+                involvement: carrier_field(
+                    person.get("involvement"),
+                    &at.field_name(&format!("{}.involvement", item)),
+                    at,
+                )?,
+                involvee: carrier_field(
+                    person.get("involvee"),
+                    &at.field_name(&format!("{}.involvee", item)),
+                    at,
+                )?,
+            });
+        }
+        return Ok(id3::Content::InvolvedPeopleList(InvolvedPeopleList {
+            items,
+        }));
+    }
+
+    let content: Map<String, Value> = carrier_field(value, value_key, at)?;
+    let content = &content;
+    Ok(match kind {
+        FrameKind::ExtendedText => id3::Content::ExtendedText(ExtendedText {
+            description: carrier_get(content, "description", at)?,
+            value: carrier_get(content, "value", at)?,
+        }),
+        FrameKind::ExtendedLink => id3::Content::ExtendedLink(ExtendedLink {
+            description: carrier_get(content, "description", at)?,
+            link: carrier_get(content, "link", at)?,
+        }),
+        FrameKind::Lyrics => id3::Content::Lyrics(Lyrics {
+            lang: carrier_get(content, "lang", at)?,
+            description: carrier_get(content, "description", at)?,
+            text: carrier_get(content, "text", at)?,
+        }),
+        FrameKind::SynchronisedLyrics => {
+            id3::Content::SynchronisedLyrics(json_to_synchronised_lyrics(content, at)?)
+        }
+        FrameKind::Comment => id3::Content::Comment(Comment {
+            lang: carrier_get(content, "lang", at)?,
+            description: carrier_get(content, "description", at)?,
+            text: carrier_get(content, "text", at)?,
+        }),
+        FrameKind::Popularimeter => {
+            let rating: f64 = carrier_get(content, "rating", at)?;
+            let counter: f64 = carrier_get(content, "counter", at)?;
+            id3::Content::Popularimeter(id3::frame::Popularimeter {
+                user: carrier_get(content, "user", at)?,
+                rating: rating.clamp(0.0, 255.0) as u8,
+                counter: counter.max(0.0) as u64,
+            })
+        }
+        FrameKind::Chapter => {
+            let frames: Vec<Value> = carrier_get(content, "frames", at)?;
+            id3::Content::Chapter(Chapter {
+                element_id: carrier_get(content, "elementId", at)?,
+                start_time: carrier_get_u32(content, "startTime", at)?,
+                end_time: carrier_get_u32(content, "endTime", at)?,
+                start_offset: json_chapter_offset(content, "startOffset", at)?,
+                end_offset: json_chapter_offset(content, "endOffset", at)?,
+                frames: json_frames_to_frames(&frames, at)?,
+            })
+        }
+        FrameKind::TableOfContents => {
+            let elements: Vec<Value> = carrier_get(content, "elements", at)?;
+            let frames: Vec<Value> = carrier_get(content, "frames", at)?;
+            id3::Content::TableOfContents(TableOfContents {
+                element_id: carrier_get(content, "elementId", at)?,
+                top_level: carrier_get(content, "topLevel", at)?,
+                ordered: carrier_get(content, "ordered", at)?,
+                elements: elements
+                    .iter()
+                    .enumerate()
+                    .map(|(j, element)| {
+                        carrier_field(
+                            Some(element),
+                            &at.field_name(&format!("elements[{}]", j)),
+                            at,
+                        )
+                    })
+                    .collect::<Result<_, _>>()?,
+                frames: json_frames_to_frames(&frames, at)?,
+            })
+        }
+        FrameKind::Picture => {
+            let picture_type: f64 = carrier_get(content, "pictureType", at)?;
+            let Bytes(data) = carrier_get(content, "data", at)?;
+            id3::Content::Picture(id3::frame::Picture {
+                mime_type: carrier_get(content, "MIMEType", at)?,
+                picture_type: carrier::u8_to_picture_ype(picture_type as u8),
+                description: carrier_get(content, "description", at)?,
+                data,
+            })
+        }
+        FrameKind::EncapsulatedObject => {
+            let Bytes(data) = carrier_get(content, "data", at)?;
+            id3::Content::EncapsulatedObject(EncapsulatedObject {
+                mime_type: carrier_get(content, "MIMEType", at)?,
+                filename: carrier_get(content, "filename", at)?,
+                description: carrier_get(content, "description", at)?,
+                data,
+            })
+        }
+        FrameKind::Private => {
+            let Bytes(private_data) = carrier_get(content, "data", at)?;
+            id3::Content::Private(Private {
+                owner_identifier: carrier_get(content, "owner", at)?,
+                private_data,
+            })
+        }
+        FrameKind::UniqueFileIdentifier => {
+            let Bytes(identifier) = carrier_get(content, "identifier", at)?;
+            id3::Content::UniqueFileIdentifier(UniqueFileIdentifier {
+                owner_identifier: carrier_get(content, "owner", at)?,
+                identifier,
+            })
+        }
+        FrameKind::Unknown => {
+            let Bytes(data) = carrier_get(content, "data", at)?;
+            id3::Content::Unknown(id3::frame::Unknown {
+                data,
+                version: id3::Version::Id3v24,
+            })
+        }
+        FrameKind::Text | FrameKind::Link | FrameKind::PlayCounter | FrameKind::InvolvedPeople => {
+            unreachable!("contents that aren't objects are read above")
+        }
+    })
+}
+
+/// Reads a `{kind, id, value, op}` object or a legacy `[type, id, content, remove]` tuple
+/// frame carrier and records its frame ID in `at`
+fn json_to_carrier(value: &Value, at: &mut CarrierRef) -> Result<Carrier, TagError> {
+    let (frame_name, frame_type, remove, content, value_key) = match value {
+        Value::Array(tuple) => {
+            at.format = FrameFormat::Tuple;
+            let frame_name: String = carrier_field(tuple.get(1), "1", at)?;
+            at.push_id(&frame_name);
+            let frame_type: String = carrier_field(tuple.first(), "0", at)?;
+            let remove: bool = carrier_field(tuple.get(3), "3", at)?;
+            (frame_name, frame_type, remove, tuple.get(2), "2")
+        }
+        Value::Object(object) => {
+            at.format = FrameFormat::Object;
+            let frame_name: String = carrier_field(object.get("id"), "id", at)?;
+            at.push_id(&frame_name);
+
+            if let Some(schema) = object.get("schema") {
+                let schema: f64 = carrier_field(Some(schema), "schema", at)?;
+                if schema != FRAME_SCHEMA_VERSION as f64 {
+                    return Err(at.error(
+                        ErrorCode::BadCarrier,
+                        format!(
+                            "Frame schema version {} is not supported, expected {}",
+                            schema, FRAME_SCHEMA_VERSION
+                        ),
+                    ));
+                }
+            }
+            let frame_type: String = carrier_field(object.get("kind"), "kind", at)?;
+            let op: String = carrier_field(object.get("op"), "op", at)?;
+            let remove = match op.as_str() {
+                "set" => false,
+                "remove" => true,
+                op => {
+                    return Err(at.error(
+                        ErrorCode::BadCarrier,
+                        format!("Field op must be \"set\" or \"remove\", got {:?}", op),
+                    ))
+                }
+            };
+            (frame_name, frame_type, remove, object.get("value"), "value")
+        }
+        _ => {
+            return Err(at.error(
+                ErrorCode::BadCarrier,
+                "Frame carrier is not an object or an array",
+            ))
+        }
+    };
+
+    at.removal = remove;
+    let kind = FrameKind::from_name(&frame_type).ok_or_else(|| {
+        at.error(
+            ErrorCode::UnsupportedFrame,
+            format!("Saving frame of type {} is not implemented yet", frame_type),
+        )
+    })?;
+    // MP4 data atom type of unknown contents
+    let data_type = match (kind, content) {
+        (FrameKind::Unknown, Some(Value::Object(object))) => match object.get("type") {
+            None | Some(Value::Null) => None,
+            Some(_) => Some(carrier_get_u32(object, "type", at)?),
+        },
+        _ => None,
+    };
+    // Removals can pass a payload handle to only remove the frame holding its data
+    let payload = match (kind, content) {
+        (FrameKind::Picture | FrameKind::EncapsulatedObject, Some(Value::Object(object)))
+            if remove =>
+        {
+            match object.get("hash") {
+                None | Some(Value::Null) => None,
+                Some(_) => Some(PayloadHandle {
+                    size: carrier_get_u32(object, "size", at)? as usize,
+                    hash: carrier_get(object, "hash", at)?,
+                }),
+            }
+        }
+        _ => None,
+    };
+    let content = json_to_content(kind, content, value_key, at)?;
+
+    Ok(Carrier {
+        // Play counters are stored in unknown contents recognized by their ID
+        id: match kind {
+            FrameKind::PlayCounter => String::from("PCNT"),
+            _ => frame_name,
+        },
+        content,
+        remove,
+        payload,
+        data_type,
+    })
+}
+
+/// Reads the frame carriers of an update, an array of carriers like `updateTag` takes
+pub fn json_to_carriers(update: &Value) -> Result<Vec<Carrier>, TagError> {
+    let frames = update.as_array().ok_or_else(|| {
+        TagError::new(
+            ErrorCode::BadCarrier,
+            format!(
+                "An update must be an array of frame carriers, got {}",
+                json_type_name(Some(update))
+            ),
+        )
+    })?;
+
+    (0u32..)
+        .zip(frames)
+        .map(|(index, frame)| {
+            let mut at = CarrierRef {
+                index,
+                id: String::new(),
+                removal: false,
+                format: FrameFormat::Tuple,
+            };
+            json_to_carrier(frame, &mut at)
+        })
+        .collect()
+}
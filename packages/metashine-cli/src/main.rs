@@ -0,0 +1,433 @@
+//! The `metashine` command, reading and writing tags from the shell with the same core as the
+//! addon, for machines that can't run the app
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{Args, Parser, Subcommand};
+use metashine_core::{
+    atomic::SaveOptions,
+    document::{DocumentFormat, TagDocument},
+    error::TagError,
+    format::AUDIO_EXTENSIONS,
+    id3_version::WriteVersion,
+    tags,
+};
+use serde_json::{Map, Value};
+use walkdir::WalkDir;
+
+mod json;
+
+use json::FrameFormat;
+
+/// Exit code of runs where some files failed
+const EXIT_FAILURE: u8 = 1;
+/// Exit code of runs that couldn't start, like clap uses for bad arguments
+const EXIT_USAGE: u8 = 2;
+
+/// Appended to the name of a file, before the document format, to name the snapshot export
+/// writes for it
+const SNAPSHOT_SUFFIX: &str = ".metashine";
+
+/// Reads and writes the tags of audio files.
+///
+/// FILES can be files, directories, which are searched for audio files recursively, and glob
+/// patterns like 'music/**/*.flac'. Binary data such as pictures is written and read as
+/// base64 strings. The exit code is 1 when any file fails and 2 for bad arguments.
+#[derive(Parser)]
+#[command(name = "metashine", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the tags of files as JSON, like loadTag returns them. With one file the tag is
+    /// printed alone, otherwise as an object keyed by path
+    Read {
+        /// Leave picture and encapsulated object data out, giving its size and hash instead
+        #[arg(long)]
+        lazy_payloads: bool,
+        #[command(flatten)]
+        frames: FrameArgs,
+        #[arg(value_name = "FILES", required = true)]
+        paths: Vec<String>,
+    },
+    /// Apply a JSON array of frame carriers or frame objects to files, like updateTag
+    Write {
+        /// The JSON file holding the update, - for standard input
+        #[arg(short, long, value_name = "FILE")]
+        update: String,
+        #[command(flatten)]
+        write: WriteArgs,
+        #[arg(value_name = "FILES", required = true)]
+        paths: Vec<String>,
+    },
+    /// Remove the whole tag of files
+    Strip {
+        #[command(flatten)]
+        save: SaveArgs,
+        #[arg(value_name = "FILES", required = true)]
+        paths: Vec<String>,
+    },
+    /// Save the whole tag of each file to a snapshot like exportTag writes, named after the file
+    /// with .metashine.json or .metashine.yaml appended
+    Export {
+        /// Write the snapshots to this directory instead of next to the files
+        #[arg(long, value_name = "DIR")]
+        out_dir: Option<PathBuf>,
+        #[command(flatten)]
+        document: DocumentArgs,
+        #[arg(value_name = "FILES", required = true)]
+        paths: Vec<String>,
+    },
+    /// Replace the whole tag of each file with its snapshot written by export, in the ID3
+    /// version of the snapshot
+    Import {
+        /// Read the snapshots from this directory instead of next to the files
+        #[arg(long, value_name = "DIR")]
+        from_dir: Option<PathBuf>,
+        #[command(flatten)]
+        document: DocumentArgs,
+        #[command(flatten)]
+        save: SaveArgs,
+        #[arg(value_name = "FILES", required = true)]
+        paths: Vec<String>,
+    },
+}
+
+#[derive(Args)]
+struct FrameArgs {
+    /// Print frames as legacy tuples or as {kind, id, value, op} objects
+    #[arg(long, value_enum, default_value = "tuple")]
+    frame_format: FrameFormat,
+}
+
+#[derive(Args)]
+struct SaveArgs {
+    /// Keep the original file next to the updated one, with .bak appended to its name
+    #[arg(long)]
+    backup: bool,
+    /// Give the updated file the modification time of the original
+    #[arg(long)]
+    preserve_mtime: bool,
+}
+
+impl SaveArgs {
+    fn options(&self) -> SaveOptions {
+        SaveOptions {
+            backup: self.backup,
+            preserve_mtime: self.preserve_mtime,
+        }
+    }
+}
+
+#[derive(Args)]
+struct WriteArgs {
+    /// The ID3 version to write tags in: 2.2, 2.3, 2.4 or preserve
+    #[arg(long, value_name = "VERSION", value_parser = parse_write_version)]
+    id3_version: Option<WriteVersion>,
+    #[command(flatten)]
+    save: SaveArgs,
+}
+
+#[derive(Args)]
+struct DocumentArgs {
+    /// Write or read snapshots as json or yaml
+    #[arg(long, value_name = "FORMAT", default_value = "json", value_parser = parse_document_format)]
+    format: DocumentFormat,
+}
+
+fn parse_document_format(name: &str) -> Result<DocumentFormat, String> {
+    DocumentFormat::from_name(name).ok_or_else(|| format!("unknown document format {}", name))
+}
+
+fn parse_write_version(name: &str) -> Result<WriteVersion, String> {
+    WriteVersion::from_name(name).ok_or_else(|| format!("unknown ID3 version {}", name))
+}
+
+/// Tells whether an operand is a glob pattern rather than a path
+fn is_pattern(operand: &str) -> bool {
+    operand.contains(['*', '?', '['])
+}
+
+fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| AUDIO_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+/// Adds the audio files of a directory and its subdirectories, in name order
+fn push_directory(dir: &Path, paths: &mut Vec<String>, ok: &mut bool) {
+    for entry in WalkDir::new(dir).sort_by_file_name() {
+        match entry {
+            Ok(entry) if entry.file_type().is_file() && is_audio_file(entry.path()) => {
+                paths.push(entry.path().to_string_lossy().into_owned());
+            }
+            Ok(_) => {}
+            Err(error) => {
+                eprintln!("metashine: {}", error);
+                *ok = false;
+            }
+        }
+    }
+}
+
+/// Expands the file operands, reporting patterns matching nothing. Files named directly are
+/// kept even if they don't exist so reading them fails like with the addon
+fn collect_paths(operands: &[String], ok: &mut bool) -> Result<Vec<String>, String> {
+    let mut paths = Vec::new();
+
+    for operand in operands {
+        if is_pattern(operand) {
+            let matches = glob::glob(operand)
+                .map_err(|error| format!("bad pattern {}: {}", operand, error))?;
+            let count = paths.len();
+            for path in matches {
+                match path {
+                    Ok(path) if path.is_dir() => push_directory(&path, &mut paths, ok),
+                    Ok(path) => paths.push(path.to_string_lossy().into_owned()),
+                    Err(error) => {
+                        eprintln!("metashine: {}", error);
+                        *ok = false;
+                    }
+                }
+            }
+            if paths.len() == count {
+                eprintln!("metashine: no files match {}", operand);
+                *ok = false;
+            }
+        } else if Path::new(operand).is_dir() {
+            push_directory(Path::new(operand), &mut paths, ok);
+        } else {
+            paths.push(operand.clone());
+        }
+    }
+
+    let mut seen = HashSet::new();
+    paths.retain(|path| seen.insert(path.clone()));
+    Ok(paths)
+}
+
+fn report(error: &TagError) {
+    match &error.path {
+        Some(path) => eprintln!(
+            "metashine: {}: {} ({})",
+            path,
+            error.message,
+            error.code.as_str()
+        ),
+        None => eprintln!("metashine: {} ({})", error.message, error.code.as_str()),
+    }
+}
+
+/// Prints JSON to standard output, stopping quietly when the reader went away like with `head`
+fn print_json(value: &Value) {
+    let text = serde_json::to_string_pretty(value).expect("JSON values always serialize");
+    if let Err(error) = writeln!(io::stdout().lock(), "{}", text) {
+        if error.kind() != io::ErrorKind::BrokenPipe {
+            eprintln!("metashine: can't write to standard output: {}", error);
+        }
+    }
+}
+
+fn read_update(source: &str) -> Result<Value, String> {
+    let text = match source {
+        "-" => {
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|error| format!("can't read the update from standard input: {}", error))?;
+            text
+        }
+        path => fs::read_to_string(path)
+            .map_err(|error| format!("can't read the update from {}: {}", path, error))?,
+    };
+    serde_json::from_str(&text).map_err(|error| format!("the update is not valid JSON: {}", error))
+}
+
+/// Where the snapshot of a file is written and read
+fn snapshot_path(path: &str, dir: Option<&Path>, format: DocumentFormat) -> PathBuf {
+    let name = format!(
+        "{}{}.{}",
+        Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        SNAPSHOT_SUFFIX,
+        format.as_str()
+    );
+    match dir {
+        Some(dir) => dir.join(name),
+        None => Path::new(path).with_file_name(name),
+    }
+}
+
+/// Loads the tag of a file as the JSON `loadTag` returns
+fn load_json(path: &str, lazy_payloads: bool, format: FrameFormat) -> Result<Value, TagError> {
+    let loaded = tags::load(path)?;
+    let loaded = match lazy_payloads {
+        true => loaded.strip_payloads(),
+        false => loaded,
+    };
+    json::loaded_tag_to_json(&loaded, format).map_err(|error| error.with_path(path))
+}
+
+fn read(paths: &[String], lazy_payloads: bool, format: FrameFormat, single: bool) -> bool {
+    let mut ok = true;
+    let mut tags = Map::new();
+
+    for path in paths {
+        let value = match load_json(path, lazy_payloads, format) {
+            Ok(value) => value,
+            Err(error) => {
+                report(&error);
+                ok = false;
+                json::error_to_json(&error)
+            }
+        };
+        tags.insert(path.clone(), value);
+    }
+
+    match single {
+        // A file that failed to load prints nothing, its error was reported
+        true if ok => tags.values().for_each(print_json),
+        true => {}
+        false => print_json(&Value::Object(tags)),
+    }
+    ok
+}
+
+fn write(paths: &[String], update: &Value, write: &WriteArgs) -> Result<bool, String> {
+    let mods = json::json_to_carriers(update)
+        .map_err(|error| error.message.clone() + &at_frame(&error))?;
+    let version = write.id3_version.unwrap_or(WriteVersion::Preserve);
+
+    let mut ok = true;
+    for path in paths {
+        if let Err(error) = tags::update(path, &mods, version, write.save.options()) {
+            report(&error);
+            ok = false;
+        }
+    }
+    Ok(ok)
+}
+
+fn strip(paths: &[String], save: &SaveArgs) -> bool {
+    let mut ok = true;
+    for path in paths {
+        if let Err(error) = tags::strip(path, save.options()) {
+            report(&error);
+            ok = false;
+        }
+    }
+    ok
+}
+
+fn export(paths: &[String], out_dir: Option<&Path>, format: DocumentFormat) -> bool {
+    let mut ok = true;
+    for path in paths {
+        let export = || -> Result<(), TagError> {
+            let text = tags::export(path)?.to_text(format);
+            let snapshot_path = snapshot_path(path, out_dir, format);
+            fs::write(&snapshot_path, text)
+                .map_err(|error| TagError::from(error).with_path(&snapshot_path.to_string_lossy()))
+        };
+        if let Err(error) = export() {
+            report(&error);
+            ok = false;
+        }
+    }
+    ok
+}
+
+fn import(
+    paths: &[String],
+    from_dir: Option<&Path>,
+    format: DocumentFormat,
+    save: &SaveArgs,
+) -> bool {
+    let mut ok = true;
+    for path in paths {
+        let import = || -> Result<(), TagError> {
+            let snapshot_path = snapshot_path(path, from_dir, format);
+            let snapshot_name = snapshot_path.to_string_lossy().into_owned();
+            let document = fs::read_to_string(&snapshot_path)
+                .map_err(TagError::from)
+                .and_then(|text| TagDocument::parse(&text))
+                .map_err(|error| error.with_path(&snapshot_name))?;
+            tags::import(path, &document, save.options()).map(|_| ())
+        };
+        if let Err(error) = import() {
+            report(&error);
+            ok = false;
+        }
+    }
+    ok
+}
+
+/// Tells where in the update a carrier error is, for errors about the update itself
+fn at_frame(error: &TagError) -> String {
+    match (error.frame_index, &error.frame_id) {
+        (Some(index), Some(id)) if !id.is_empty() => format!(" (frame {}, {})", index, id),
+        (Some(index), _) => format!(" (frame {})", index),
+        _ => String::new(),
+    }
+}
+
+/// Runs a command, returning whether every file succeeded or why it couldn't start
+fn run(command: Command) -> Result<bool, String> {
+    let operands = match &command {
+        Command::Read { paths, .. }
+        | Command::Write { paths, .. }
+        | Command::Strip { paths, .. }
+        | Command::Export { paths, .. }
+        | Command::Import { paths, .. } => paths,
+    };
+    // A single file named directly is read alone, like loadTag
+    let single =
+        operands.len() == 1 && !is_pattern(&operands[0]) && !Path::new(&operands[0]).is_dir();
+    let mut ok = true;
+    let paths = collect_paths(operands, &mut ok)?;
+
+    let done = match &command {
+        Command::Read {
+            lazy_payloads,
+            frames,
+            ..
+        } => read(&paths, *lazy_payloads, frames.frame_format, single),
+        Command::Write { update, write, .. } => {
+            let update = read_update(update)?;
+            self::write(&paths, &update, write)?
+        }
+        Command::Strip { save, .. } => strip(&paths, save),
+        Command::Export {
+            out_dir, document, ..
+        } => export(&paths, out_dir.as_deref(), document.format),
+        Command::Import {
+            from_dir,
+            document,
+            save,
+            ..
+        } => import(&paths, from_dir.as_deref(), document.format, save),
+    };
+    Ok(ok && done)
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli.command) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(EXIT_FAILURE),
+        Err(message) => {
+            eprintln!("metashine: {}", message);
+            ExitCode::from(EXIT_USAGE)
+        }
+    }
+}
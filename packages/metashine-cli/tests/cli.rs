@@ -0,0 +1,193 @@
+use std::{
+    fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+use serde_json::{json, Value};
+
+/// Header of a 417 byte MPEG-1 layer III frame at 128 kbit/s and 44.1 kHz, joint stereo
+const MP3_FRAME_HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x44];
+
+/// A directory of its own for a test, holding an untagged MP3 file of silent frames
+fn mp3_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("metashine-cli-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut bytes = Vec::new();
+    for _ in 0..4 {
+        bytes.extend_from_slice(&MP3_FRAME_HEADER);
+        bytes.resize(bytes.len() + 413, 0);
+    }
+    fs::write(dir.join("a.mp3"), bytes).unwrap();
+    dir
+}
+
+fn metashine(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_metashine"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn read(path: &str) -> Value {
+    let output = metashine(&["read", "--frame-format", "object", path], "");
+    assert!(output.status.success());
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn write_then_read_prints_the_tag_like_load_tag() {
+    let dir = mp3_dir("write");
+    let path = dir.join("a.mp3").to_string_lossy().into_owned();
+    let update = json!([
+        ["text", "TIT2", "Title", false],
+        {
+            "kind": "picture",
+            "id": "APIC",
+            "value": {"MIMEType": "image/png", "pictureType": 3, "description": "", "data": "AAEC"},
+            "op": "set",
+        },
+    ]);
+
+    let output = metashine(&["write", "--update", "-", &path], &update.to_string());
+    assert!(output.status.success());
+
+    let tag = read(&path);
+    assert_eq!(tag["format"], "id3");
+    assert_eq!(tag["hadTag"], true);
+    assert_eq!(tag["version"], "2.4");
+    assert_eq!(
+        tag["frames"][0],
+        json!({"kind": "text", "id": "TIT2", "value": "Title", "op": "set"})
+    );
+    assert_eq!(tag["frames"][1]["value"]["data"], "AAEC");
+
+    // Directories give an object keyed by path
+    let output = metashine(&["read", &dir.to_string_lossy()], "");
+    let tags: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        tags[&path]["frames"][0],
+        json!(["text", "TIT2", "Title", false])
+    );
+}
+
+#[test]
+fn export_strip_and_import_restore_the_tag() {
+    let dir = mp3_dir("snapshot");
+    let path = dir.join("a.mp3").to_string_lossy().into_owned();
+    let update = json!([
+        ["text", "TIT2", "Title", false],
+        ["comment", "COMM", {"lang": "eng", "description": "", "text": "Note"}, false],
+    ]);
+    metashine(&["write", "-u", "-", &path], &update.to_string());
+    let original = fs::read(&path).unwrap();
+
+    assert!(metashine(&["export", &path], "").status.success());
+    let snapshot: Value =
+        serde_json::from_slice(&fs::read(dir.join("a.mp3.metashine.json")).unwrap()).unwrap();
+    assert_eq!(snapshot["format"], "id3");
+    assert_eq!(snapshot["frames"][1]["encoding"], "UTF-8");
+
+    assert!(metashine(&["export", "--format", "yaml", &path], "")
+        .status
+        .success());
+    assert!(metashine(&["strip", &path], "").status.success());
+    assert_eq!(read(&path)["hadTag"], false);
+
+    assert!(metashine(&["import", "--format", "yaml", &path], "")
+        .status
+        .success());
+    assert_eq!(fs::read(&path).unwrap(), original);
+}
+
+#[test]
+fn failures_set_the_exit_code() {
+    let dir = mp3_dir("failures");
+    let path = dir.join("a.mp3").to_string_lossy().into_owned();
+
+    let missing = dir.join("missing.mp3").to_string_lossy().into_owned();
+    let output = metashine(&["read", &path, &missing], "");
+    assert_eq!(output.status.code(), Some(1));
+    let tags: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(tags[&missing]["code"], "ERR_IO");
+    assert_eq!(tags[&path]["hadTag"], false);
+
+    let output = metashine(
+        &["write", "-u", "-", &path],
+        r#"[["text", "TIT2", 5, false]]"#,
+    );
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Field 2 must be a string"));
+}
+
+#[test]
+fn lazy_payload_handles_remove_their_frame() {
+    let dir = mp3_dir("lazy");
+    let path = dir.join("a.mp3").to_string_lossy().into_owned();
+    let object = |description: &str, data: &str| {
+        json!(["encapsulated object", "GEOB", {
+            "MIMEType": "application/octet-stream",
+            "filename": "a.bin",
+            "description": description,
+            "data": data,
+        }, false])
+    };
+    let update = json!([object("first", "AAEC"), object("second", "AwQF")]);
+    metashine(&["write", "-u", "-", &path], &update.to_string());
+
+    // Frames of a lazy read are sent back as removals without their data
+    let output = metashine(
+        &["read", "--lazy-payloads", "--frame-format", "object", &path],
+        "",
+    );
+    let tag: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let mut removal = tag["frames"][0].clone();
+    assert_eq!(removal["value"]["hash"].as_str().map(str::len), Some(64));
+    removal["op"] = json!("remove");
+    let output = metashine(&["write", "-u", "-", &path], &json!([removal]).to_string());
+    assert!(output.status.success());
+
+    let frames = &read(&path)["frames"];
+    assert_eq!(frames.as_array().unwrap().len(), 1);
+    assert_eq!(frames[0]["value"]["description"], "second");
+}
+
+#[test]
+fn chapter_times_must_be_32_bit_integers() {
+    let dir = mp3_dir("chapters");
+    let path = dir.join("a.mp3").to_string_lossy().into_owned();
+
+    for (start_time, message) in [
+        (
+            "1.5",
+            "Field value.startTime must be an integer from 0 to 4294967295, got 1.5",
+        ),
+        (
+            "-1",
+            "Field value.startTime must be an integer from 0 to 4294967295, got -1",
+        ),
+    ] {
+        let update = format!(
+            r#"[{{"kind": "chapter", "id": "CHAP", "op": "set", "value": {{
+                "elementId": "ch0", "startTime": {}, "endTime": 1000, "frames": []
+            }}}}]"#,
+            start_time
+        );
+        let output = metashine(&["write", "-u", "-", &path], &update);
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains(message));
+    }
+}
@@ -0,0 +1,129 @@
+//! Crash-safe file updates: changes are made to a temporary copy that replaces the original
+//! with a rename, so a file is never left half-written
+
+use std::{
+    fs::{self, File},
+    path::Path,
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::error::{ErrorCode, TagError};
+
+/// How updated files replace the originals
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SaveOptions {
+    /// Keep the original file next to the new one, with .bak appended to its name. For symbolic
+    /// links that is next to the file they point to
+    pub backup: bool,
+    /// Give the new file the modification time of the original
+    pub preserve_mtime: bool,
+}
+
+/// Tells apart the temporary files of updates running at the same time
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A hidden file next to path, so renaming it over path stays on the same file system
+fn temp_path(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp_name = format!(
+        ".{}.{}-{}.tmp",
+        name,
+        process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::SeqCst)
+    );
+    path.with_file_name(temp_name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(unix)]
+fn copy_owner(metadata: &fs::Metadata, temp: &str) {
+    use std::os::unix::fs::MetadataExt;
+
+    // Only the superuser can give files away, other users keep their own ownership
+    let _ = std::os::unix::fs::chown(temp, Some(metadata.uid()), Some(metadata.gid()));
+}
+
+#[cfg(not(unix))]
+fn copy_owner(_metadata: &fs::Metadata, _temp: &str) {}
+
+/// Flushes the directory entry of a renamed file to disk
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Updates the file at path through a temporary copy.
+///
+/// `edit` changes the copy in place and `verify` checks the result. The copy is then fsynced,
+/// given the permissions, owner and optionally the modification time of the original and
+/// renamed over it. If any step fails the copy is deleted and the original is left as it was.
+///
+/// Symbolic links are followed, so the file they point to is replaced and the links are kept.
+/// Read-only files are refused before anything is copied.
+pub fn update(
+    path: &str,
+    options: SaveOptions,
+    edit: impl FnOnce(&str) -> Result<(), TagError>,
+    verify: impl FnOnce(&str) -> Result<(), TagError>,
+) -> Result<(), TagError> {
+    let (target, metadata) = fs::canonicalize(path)
+        .and_then(|target| fs::metadata(&target).map(|metadata| (target, metadata)))
+        .map_err(|error| TagError::from(error).with_path(path))?;
+    if metadata.permissions().readonly() {
+        return Err(TagError::new(ErrorCode::Io, "The file is read-only").with_path(path));
+    }
+    let temp = temp_path(&target);
+
+    let prepare = || -> Result<(), TagError> {
+        fs::copy(&target, &temp)?;
+        edit(&temp)?;
+
+        let file = File::options().write(true).open(&temp)?;
+        if options.preserve_mtime {
+            file.set_modified(metadata.modified()?)?;
+        }
+        file.sync_all()?;
+        fs::set_permissions(&temp, metadata.permissions())?;
+        copy_owner(&metadata, &temp);
+
+        verify(&temp)
+    };
+
+    if let Err(error) = prepare() {
+        let _ = fs::remove_file(&temp);
+        return Err(error.with_path(path));
+    }
+
+    let replace = || -> std::io::Result<()> {
+        if options.backup {
+            let mut backup = target.clone().into_os_string();
+            backup.push(".bak");
+            let _ = fs::remove_file(&backup);
+            // The backup shares the original's data, copying it only where links don't work
+            if fs::hard_link(&target, &backup).is_err() {
+                fs::copy(&target, &backup)?;
+            }
+        }
+
+        fs::rename(&temp, &target)?;
+        sync_parent(&target)
+    };
+
+    replace().map_err(|error| {
+        let _ = fs::remove_file(&temp);
+        TagError::from(error).with_path(path)
+    })
+}
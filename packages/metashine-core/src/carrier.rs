@@ -0,0 +1,401 @@
+//! Frame carriers as plain Rust values, so tags can be read and written away from the
+//! JavaScript thread
+
+use id3::{frame::PictureType, Content, Frame};
+
+use crate::{
+    error::{ErrorCode, TagError},
+    hash,
+};
+
+/// Version of the `{kind, id, value, op}` frame objects, exported as `FRAME_SCHEMA_VERSION`
+pub const FRAME_SCHEMA_VERSION: u32 = 1;
+
+/// The kinds of frame a carrier can hold, named after the carrier types used by JavaScript
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameKind {
+    Text,
+    ExtendedText,
+    Link,
+    ExtendedLink,
+    Comment,
+    Popularimeter,
+    /// Stored in an unknown content with the PCNT ID
+    PlayCounter,
+    Lyrics,
+    SynchronisedLyrics,
+    Picture,
+    EncapsulatedObject,
+    Chapter,
+    TableOfContents,
+    InvolvedPeople,
+    Private,
+    UniqueFileIdentifier,
+    Unknown,
+}
+
+impl FrameKind {
+    pub const ALL: [FrameKind; 17] = [
+        FrameKind::Text,
+        FrameKind::ExtendedText,
+        FrameKind::Link,
+        FrameKind::ExtendedLink,
+        FrameKind::Comment,
+        FrameKind::Popularimeter,
+        FrameKind::PlayCounter,
+        FrameKind::Lyrics,
+        FrameKind::SynchronisedLyrics,
+        FrameKind::Picture,
+        FrameKind::EncapsulatedObject,
+        FrameKind::Chapter,
+        FrameKind::TableOfContents,
+        FrameKind::InvolvedPeople,
+        FrameKind::Private,
+        FrameKind::UniqueFileIdentifier,
+        FrameKind::Unknown,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FrameKind::Text => "text",
+            FrameKind::ExtendedText => "extended text",
+            FrameKind::Link => "link",
+            FrameKind::ExtendedLink => "extended link",
+            FrameKind::Comment => "comment",
+            FrameKind::Popularimeter => "popularimeter",
+            FrameKind::PlayCounter => "play counter",
+            FrameKind::Lyrics => "lyrics",
+            FrameKind::SynchronisedLyrics => "synchronised lyrics",
+            FrameKind::Picture => "picture",
+            FrameKind::EncapsulatedObject => "encapsulated object",
+            FrameKind::Chapter => "chapter",
+            FrameKind::TableOfContents => "table of contents",
+            FrameKind::InvolvedPeople => "involved people",
+            FrameKind::Private => "private",
+            FrameKind::UniqueFileIdentifier => "unique file identifier",
+            FrameKind::Unknown => "unknown",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        FrameKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.as_str() == name)
+    }
+
+    /// The ID the frame is written with whatever the carrier says, for kinds that only have one
+    pub fn fixed_id(&self) -> Option<&'static str> {
+        match self {
+            FrameKind::ExtendedText => Some("TXXX"),
+            FrameKind::ExtendedLink => Some("WXXX"),
+            FrameKind::Comment => Some("COMM"),
+            FrameKind::Popularimeter => Some("POPM"),
+            FrameKind::PlayCounter => Some("PCNT"),
+            FrameKind::Lyrics => Some("USLT"),
+            FrameKind::SynchronisedLyrics => Some("SYLT"),
+            FrameKind::Picture => Some("APIC"),
+            FrameKind::EncapsulatedObject => Some("GEOB"),
+            FrameKind::Private => Some("PRIV"),
+            FrameKind::UniqueFileIdentifier => Some("UFID"),
+            FrameKind::Chapter => Some("CHAP"),
+            FrameKind::TableOfContents => Some("CTOC"),
+            _ => None,
+        }
+    }
+}
+
+/// Stands for the binary data of a picture or encapsulated object left out of a carrier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadHandle {
+    pub size: usize,
+    /// SHA-256 digest of the data in hexadecimal
+    pub hash: String,
+}
+
+/// A frame carrier, the Rust side of a `[type, id, content, remove]` tuple.
+///
+/// Every tag format describes its frames with ID3 contents: Vorbis comments and MP4 items
+/// only use texts, extended texts, pictures and unknown data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Carrier {
+    pub id: String,
+    pub content: Content,
+    pub remove: bool,
+    /// Set when the data of a picture or encapsulated object content was left out
+    pub payload: Option<PayloadHandle>,
+    /// Type code of the MP4 data atom the value was read from, set when writing the value
+    /// back on its own would give it another type
+    pub data_type: Option<u32>,
+}
+
+impl Carrier {
+    pub fn new(id: impl Into<String>, content: Content) -> Self {
+        Carrier {
+            id: id.into(),
+            content,
+            remove: false,
+            payload: None,
+            data_type: None,
+        }
+    }
+
+    /// Leaves out the data of a picture or encapsulated object content, keeping a handle to it
+    pub fn strip_payload(&mut self) {
+        let data = match &mut self.content {
+            Content::Picture(picture) => &mut picture.data,
+            Content::EncapsulatedObject(object) => &mut object.data,
+            _ => return,
+        };
+        self.payload = Some(PayloadHandle {
+            size: data.len(),
+            hash: hash::sha256_hex(data),
+        });
+        *data = Vec::new();
+    }
+
+    /// The kind of frame the content stands for
+    pub fn kind(&self) -> FrameKind {
+        match &self.content {
+            Content::Text(_) => FrameKind::Text,
+            Content::ExtendedText(_) => FrameKind::ExtendedText,
+            Content::Link(_) => FrameKind::Link,
+            Content::ExtendedLink(_) => FrameKind::ExtendedLink,
+            Content::Comment(_) => FrameKind::Comment,
+            Content::Popularimeter(_) => FrameKind::Popularimeter,
+            Content::Lyrics(_) => FrameKind::Lyrics,
+            Content::SynchronisedLyrics(_) => FrameKind::SynchronisedLyrics,
+            Content::Picture(_) => FrameKind::Picture,
+            Content::EncapsulatedObject(_) => FrameKind::EncapsulatedObject,
+            Content::Chapter(_) => FrameKind::Chapter,
+            Content::TableOfContents(_) => FrameKind::TableOfContents,
+            Content::InvolvedPeopleList(_) => FrameKind::InvolvedPeople,
+            Content::Private(_) => FrameKind::Private,
+            Content::UniqueFileIdentifier(_) => FrameKind::UniqueFileIdentifier,
+            Content::Unknown(_) if self.id == "PCNT" => FrameKind::PlayCounter,
+            _ => FrameKind::Unknown,
+        }
+    }
+
+    /// Whether both carriers stand for the same frame of a tag, whatever their values
+    pub fn same_frame(&self, other: &Carrier) -> bool {
+        same_frame(&self.id, &self.content, &other.id, &other.content)
+    }
+
+    /// Whether a removal carrier removes an ID3 frame.
+    ///
+    /// Frames are matched on their identity like in `same_frame`, except that pictures must
+    /// also share their description and encapsulated objects their MIME type and filename.
+    /// Their data only matters for carriers with a payload handle, which only remove the frame
+    /// whose data has the digest of the handle.
+    pub fn removes(&self, frame: &Frame) -> bool {
+        match (&self.content, frame.content()) {
+            (Content::Picture(_), Content::Picture(b)) => {
+                self.removes_picture(b.picture_type, &b.description, &b.data)
+            }
+            (Content::EncapsulatedObject(a), Content::EncapsulatedObject(b)) => {
+                a.description == b.description
+                    && a.mime_type == b.mime_type
+                    && a.filename == b.filename
+                    && self.same_payload(&b.data)
+            }
+            (content, frame_content) => same_frame(&self.id, content, frame.id(), frame_content),
+        }
+    }
+
+    /// Whether a picture carrier removes or replaces a picture stored outside of ID3 frames,
+    /// matched on the same type, description and payload as ID3 pictures are in `removes`
+    pub fn removes_picture(
+        &self,
+        picture_type: PictureType,
+        description: &str,
+        data: &[u8],
+    ) -> bool {
+        match &self.content {
+            Content::Picture(picture) => {
+                picture.picture_type == picture_type
+                    && picture.description == description
+                    && self.same_payload(data)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether data has the digest of the payload handle, or the carrier has no handle
+    fn same_payload(&self, data: &[u8]) -> bool {
+        match &self.payload {
+            Some(payload) => hash::sha256_hex(data) == payload.hash,
+            None => true,
+        }
+    }
+}
+
+/// Whether two frames are the same frame of a tag, whatever their values: frames with the same
+/// ID and the same description, language, owner, picture type or element ID
+fn same_frame(id: &str, content: &Content, other_id: &str, other: &Content) -> bool {
+    if id != other_id {
+        return false;
+    }
+    match (content, other) {
+        (Content::ExtendedText(a), Content::ExtendedText(b)) => a.description == b.description,
+        (Content::ExtendedLink(a), Content::ExtendedLink(b)) => a.description == b.description,
+        (Content::Comment(a), Content::Comment(b)) => {
+            a.lang == b.lang && a.description == b.description
+        }
+        (Content::Lyrics(a), Content::Lyrics(b)) => {
+            a.lang == b.lang && a.description == b.description
+        }
+        (Content::SynchronisedLyrics(a), Content::SynchronisedLyrics(b)) => {
+            a.lang == b.lang && a.description == b.description
+        }
+        (Content::Popularimeter(a), Content::Popularimeter(b)) => a.user == b.user,
+        (Content::Picture(a), Content::Picture(b)) => a.picture_type == b.picture_type,
+        (Content::EncapsulatedObject(a), Content::EncapsulatedObject(b)) => {
+            a.description == b.description
+        }
+        (Content::Private(a), Content::Private(b)) => a.owner_identifier == b.owner_identifier,
+        (Content::UniqueFileIdentifier(a), Content::UniqueFileIdentifier(b)) => {
+            a.owner_identifier == b.owner_identifier
+        }
+        (Content::Chapter(a), Content::Chapter(b)) => a.element_id == b.element_id,
+        (Content::TableOfContents(a), Content::TableOfContents(b)) => a.element_id == b.element_id,
+        (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+    }
+}
+
+/// The frames a change to a tag adds, changes and removes
+#[derive(Debug, Clone, Default)]
+pub struct TagDiff {
+    pub added: Vec<Carrier>,
+    /// The old and new carriers of frames whose value changes
+    pub changed: Vec<(Carrier, Carrier)>,
+    pub removed: Vec<Carrier>,
+}
+
+/// Compares the carriers of a tag before and after a change.
+///
+/// Identical carriers are paired first, so repeated fields like Vorbis comments only show the
+/// values that differ, then the remaining ones are paired with `same_frame`.
+pub fn diff(old: &[Carrier], new: &[Carrier]) -> TagDiff {
+    let mut old: Vec<Option<&Carrier>> = old.iter().map(Some).collect();
+    let mut new: Vec<Option<&Carrier>> = new.iter().map(Some).collect();
+
+    for old_carrier in old.iter_mut() {
+        if let Some(i) = new
+            .iter()
+            .position(|new_carrier| *new_carrier == *old_carrier)
+        {
+            new[i] = None;
+            *old_carrier = None;
+        }
+    }
+
+    let mut diff = TagDiff::default();
+    for old_carrier in old.into_iter().flatten() {
+        let same = new.iter().position(|new_carrier| {
+            new_carrier.is_some_and(|new_carrier| new_carrier.same_frame(old_carrier))
+        });
+        match same.and_then(|i| new[i].take()) {
+            Some(new_carrier) => diff
+                .changed
+                .push((old_carrier.clone(), new_carrier.clone())),
+            None => diff.removed.push(old_carrier.clone()),
+        }
+    }
+    diff.added = new.into_iter().flatten().cloned().collect();
+
+    diff
+}
+
+pub fn u8_to_picture_ype(i: u8) -> PictureType {
+    match i {
+        0 => PictureType::Other,
+        1 => PictureType::Icon,
+        2 => PictureType::OtherIcon,
+        3 => PictureType::CoverFront,
+        4 => PictureType::CoverBack,
+        5 => PictureType::Leaflet,
+        6 => PictureType::Media,
+        7 => PictureType::LeadArtist,
+        8 => PictureType::Artist,
+        9 => PictureType::Conductor,
+        10 => PictureType::Band,
+        11 => PictureType::Composer,
+        12 => PictureType::Lyricist,
+        13 => PictureType::RecordingLocation,
+        14 => PictureType::DuringRecording,
+        15 => PictureType::DuringPerformance,
+        16 => PictureType::ScreenCapture,
+        17 => PictureType::BrightFish,
+        18 => PictureType::Illustration,
+        19 => PictureType::BandLogo,
+        20 => PictureType::PublisherLogo,
+        i => PictureType::Undefined(i),
+    }
+}
+
+/// Fails on the first frame, including chapter and table of contents sub-frames, whose
+/// content can't be converted to a carrier yet
+fn check_supported<'f>(frames: impl Iterator<Item = &'f Frame>) -> Result<(), TagError> {
+    for (i, frame) in (0u32..).zip(frames) {
+        match frame.content() {
+            Content::Chapter(chapter) => check_supported(chapter.frames.iter())?,
+            Content::TableOfContents(table) => check_supported(table.frames.iter())?,
+            Content::MpegLocationLookupTable(_) => {
+                return Err(TagError::new(
+                    ErrorCode::UnsupportedFrame,
+                    format!("Reading frame {} is not implemented yet", frame),
+                )
+                .with_frame(i, frame.id()))
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Converts ID3 frames to carriers, failing if one can't be read yet
+pub fn frames_to_carriers<'f>(
+    frames: impl IntoIterator<Item = &'f Frame>,
+) -> Result<Vec<Carrier>, TagError> {
+    let frames: Vec<&Frame> = frames.into_iter().collect();
+    check_supported(frames.iter().copied())?;
+    Ok(frames
+        .into_iter()
+        .map(|frame| Carrier::new(frame.id(), frame.content().clone()))
+        .collect())
+}
+
+/// Builds the ID3 frame a carrier sets, failing if its ID is malformed or its type can't be
+/// written yet
+pub fn carrier_to_frame(carrier: &Carrier) -> Result<Frame, TagError> {
+    // Frame IDs are four uppercase letters or digits, or three in ID3v2.2
+    let id = carrier.id.as_str();
+    let valid_id = (id.len() == 3 || id.len() == 4)
+        && id
+            .bytes()
+            .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit());
+    if !valid_id {
+        return Err(TagError::new(
+            ErrorCode::BadCarrier,
+            format!("{:?} is not a valid frame ID", id),
+        ));
+    }
+
+    // Most frame kinds have a single ID, whatever the carrier says
+    let id = match carrier.kind() {
+        FrameKind::Text | FrameKind::Link | FrameKind::InvolvedPeople => id,
+        FrameKind::Unknown => {
+            return Err(TagError::new(
+                ErrorCode::UnsupportedFrame,
+                format!(
+                    "Saving frame of type {} is not implemented yet",
+                    FrameKind::Unknown.as_str()
+                ),
+            ))
+        }
+        kind => kind.fixed_id().unwrap_or(id),
+    };
+
+    Ok(Frame::with_content(id, carrier.content.clone()))
+}
@@ -0,0 +1,108 @@
+//! Consistency checks for chapter (CHAP) and table of contents (CTOC) frames
+
+use std::collections::HashSet;
+
+use id3::{
+    frame::{Chapter, TableOfContents},
+    Content, Frame,
+};
+
+/// A problem found in the chapters of a tag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChapterIssue {
+    /// Element ID of the chapter or table of contents with the problem
+    pub element_id: String,
+    pub message: String,
+}
+
+impl ChapterIssue {
+    fn new(element_id: &str, message: String) -> Self {
+        ChapterIssue {
+            element_id: element_id.to_string(),
+            message,
+        }
+    }
+}
+
+/// Checks that element IDs are unique, chapters have valid time ranges and don't overlap,
+/// there is at most one top-level table of contents and every CTOC child exists
+pub fn validate<'f>(frames: impl IntoIterator<Item = &'f Frame>) -> Vec<ChapterIssue> {
+    let mut chapters: Vec<&Chapter> = Vec::new();
+    let mut tables: Vec<&TableOfContents> = Vec::new();
+
+    for frame in frames {
+        match frame.content() {
+            Content::Chapter(chapter) => chapters.push(chapter),
+            Content::TableOfContents(table) => tables.push(table),
+            _ => {}
+        }
+    }
+
+    let mut issues = Vec::new();
+
+    // Element IDs
+    let mut element_ids = HashSet::new();
+    let ids = chapters
+        .iter()
+        .map(|chapter| &chapter.element_id)
+        .chain(tables.iter().map(|table| &table.element_id));
+    for id in ids {
+        if !element_ids.insert(id.as_str()) {
+            issues.push(ChapterIssue::new(
+                id,
+                format!("Element ID {} is used more than once", id),
+            ));
+        }
+    }
+
+    // Time ranges
+    for chapter in &chapters {
+        if chapter.end_time < chapter.start_time {
+            issues.push(ChapterIssue::new(
+                &chapter.element_id,
+                format!(
+                    "Chapter ends at {} ms before it starts at {} ms",
+                    chapter.end_time, chapter.start_time
+                ),
+            ));
+        }
+    }
+
+    let mut sorted = chapters.clone();
+    sorted.sort_by_key(|chapter| (chapter.start_time, chapter.end_time));
+    for pair in sorted.windows(2) {
+        if pair[1].start_time < pair[0].end_time {
+            issues.push(ChapterIssue::new(
+                &pair[1].element_id,
+                format!(
+                    "Chapter starts at {} ms before chapter {} ends at {} ms",
+                    pair[1].start_time, pair[0].element_id, pair[0].end_time
+                ),
+            ));
+        }
+    }
+
+    // Tables of contents
+    let top_level = tables.iter().filter(|table| table.top_level).count();
+    if top_level > 1 {
+        for table in tables.iter().filter(|table| table.top_level).skip(1) {
+            issues.push(ChapterIssue::new(
+                &table.element_id,
+                format!("There are {} top-level tables of contents", top_level),
+            ));
+        }
+    }
+
+    for table in &tables {
+        for child in &table.elements {
+            if !element_ids.contains(child.as_str()) {
+                issues.push(ChapterIssue::new(
+                    &table.element_id,
+                    format!("Child element {} does not exist", child),
+                ));
+            }
+        }
+    }
+
+    issues
+}
@@ -0,0 +1,135 @@
+//! Decoding of MP3, FLAC, Ogg Vorbis and WAV audio to samples with symphonia
+
+use std::{fs::File, io, path::Path};
+
+use symphonia::core::{
+    audio::{Channels, SampleBuffer, SignalSpec},
+    codecs::{DecoderOptions, CODEC_TYPE_NULL, CODEC_TYPE_OPUS},
+    errors::Error,
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+};
+
+use crate::error::{ErrorCode, TagError};
+
+impl From<Error> for TagError {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::IoError(error) => TagError::from(error),
+            error => TagError::new(ErrorCode::Decode, error.to_string()),
+        }
+    }
+}
+
+/// The format of the decoded samples
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamSpec {
+    pub sample_rate: u32,
+    /// The channels in the order their samples are interleaved
+    pub channels: Vec<Channels>,
+}
+
+/// Decodes the first audio track of the file at path. Opus and other codecs symphonia has no
+/// decoder for fail with `ErrorCode::UnsupportedCodec`.
+///
+/// `start` receives the format of the stream before its first samples and returns the state
+/// `add` is then given every decoded packet with, as samples interleaved and scaled to
+/// -1.0..1.0. Encoder delay and padding announced by the file are left out and damaged packets
+/// are skipped.
+pub fn decode<S>(
+    path: &str,
+    start: impl FnOnce(&StreamSpec) -> S,
+    mut add: impl FnMut(&mut S, &[f32]),
+) -> Result<S, TagError> {
+    let decode = || -> Result<S, TagError> {
+        let file = File::open(path)?;
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            hint.with_extension(extension);
+        }
+
+        let format_options = FormatOptions {
+            enable_gapless: true,
+            ..Default::default()
+        };
+        let mut format = symphonia::default::get_probe()
+            .format(&hint, stream, &format_options, &MetadataOptions::default())?
+            .format;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or_else(|| TagError::new(ErrorCode::Decode, "The file has no audio track"))?;
+        let track_id = track.id;
+        let codecs = symphonia::default::get_codecs();
+        if codecs.get_codec(track.codec_params.codec).is_none() {
+            let message = match track.codec_params.codec {
+                CODEC_TYPE_OPUS => "Opus audio cannot be decoded",
+                _ => "The audio codec of the file cannot be decoded",
+            };
+            return Err(TagError::new(ErrorCode::UnsupportedCodec, message));
+        }
+        let mut decoder = codecs.make(&track.codec_params, &DecoderOptions::default())?;
+
+        let mut start = Some(start);
+        let mut stream: Option<(SignalSpec, S)> = None;
+        let mut samples: Option<SampleBuffer<f32>> = None;
+        loop {
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                Err(Error::IoError(error)) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(error) => return Err(error.into()),
+            };
+            if packet.track_id() != track_id {
+                continue;
+            }
+
+            let decoded = match decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(Error::DecodeError(_)) => continue,
+                Err(error) => return Err(error.into()),
+            };
+
+            let spec = *decoded.spec();
+            if let (None, Some(start)) = (&stream, start.take()) {
+                let stream_spec = StreamSpec {
+                    sample_rate: spec.rate,
+                    channels: spec.channels.iter().collect(),
+                };
+                stream = Some((spec, start(&stream_spec)));
+            }
+            let state = match &mut stream {
+                Some((stream_spec, _)) if *stream_spec != spec => {
+                    return Err(TagError::new(
+                        ErrorCode::Decode,
+                        "The sample rate or channels change within the stream",
+                    ))
+                }
+                Some((_, state)) => state,
+                None => continue,
+            };
+
+            let frames = decoded.capacity();
+            if samples
+                .as_ref()
+                .is_none_or(|buffer| buffer.capacity() < frames * spec.channels.count())
+            {
+                samples = Some(SampleBuffer::new(frames as u64, spec));
+            }
+            if let Some(buffer) = &mut samples {
+                buffer.copy_interleaved_ref(decoded);
+                add(state, buffer.samples());
+            }
+        }
+
+        stream
+            .map(|(_, state)| state)
+            .ok_or_else(|| TagError::new(ErrorCode::Decode, "The file has no audio"))
+    };
+
+    decode().map_err(|error| error.with_path(path))
+}
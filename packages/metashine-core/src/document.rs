@@ -0,0 +1,832 @@
+//! Whole tags as JSON or YAML documents, exported for review and imported back as they were.
+//!
+//! A document holds the schema version, the tag format and the tag in the layout of that
+//! format. Binary data is written as base64 strings.
+//!
+//! ```yaml
+//! schema: 1
+//! format: id3
+//! version: '2.4'
+//! frames:
+//! - id: TIT2
+//!   encoding: UTF-8
+//!   kind: text
+//!   text: Title
+//! - id: APIC
+//!   encoding: ISO-8859-1
+//!   fileAlterPreservation: true
+//!   kind: picture
+//!   MIMEType: image/png
+//!   pictureType: 3
+//!   description: ''
+//!   data: iVBORw0KGgo=
+//! ```
+//!
+//! ID3 frames are listed in the order they are stored, with their text encoding and status
+//! flags. `kind` names their content like the kinds of frame objects and the content fields
+//! are those of frame object values. Frames the id3 crate can't tell apart from their ID are
+//! `unknown` frames holding the raw content. Frames with `tagAlterPreservation` are dropped when
+//! a tag is written, as ID3 asks of frames that must not outlive a change to their tag.
+//!
+//! Vorbis comment documents hold the `vendor` string, the `fields` as `{name, value}` objects
+//! and the `pictures` in FLAC picture layout. MP4 documents hold the `items`, each with its
+//! `ident` like `©nam` or `----:com.apple.iTunes:name` and its `data` atoms, as `text` for
+//! UTF-8 atoms and as base64 `data` for others.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use id3::{
+    frame::{
+        Chapter, Comment, EncapsulatedObject, ExtendedLink, ExtendedText, InvolvedPeopleList,
+        InvolvedPeopleListItem, Lyrics, Picture, Popularimeter, Private, SynchronisedLyrics,
+        SynchronisedLyricsType, TableOfContents, TimestampFormat, UniqueFileIdentifier, Unknown,
+    },
+    Content, Encoding, Frame, Tag, Version,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    carrier,
+    error::{ErrorCode, TagError},
+    flac::VorbisTag,
+    format::TagFormat,
+    mp4::{DataAtom, Ident, Item, Mp4Tag},
+    vorbis::{FlacPicture, VorbisComment},
+};
+
+/// Version of the document layout, written to and checked in the `schema` field
+pub const DOCUMENT_SCHEMA_VERSION: u32 = 1;
+
+/// Type code of MP4 data atoms holding UTF-8 text
+const MP4_UTF8: u32 = 1;
+
+/// Chapter offsets of 0xFFFFFFFF mark chapters that don't use byte offsets, written as null
+const CHAPTER_OFFSET_UNUSED: u32 = u32::MAX;
+
+/// The text layouts documents are written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentFormat {
+    Json,
+    Yaml,
+}
+
+impl DocumentFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(DocumentFormat::Json),
+            "yaml" => Some(DocumentFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentFormat::Json => "json",
+            DocumentFormat::Yaml => "yaml",
+        }
+    }
+}
+
+mod base64_data {
+    use super::{Engine, BASE64};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&BASE64.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        BASE64
+            .decode(text)
+            .map_err(|error| D::Error::custom(format!("invalid base64 data: {}", error)))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Version")]
+enum VersionDef {
+    #[serde(rename = "2.2")]
+    Id3v22,
+    #[serde(rename = "2.3")]
+    Id3v23,
+    #[serde(rename = "2.4")]
+    Id3v24,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "TimestampFormat", rename_all = "lowercase")]
+enum TimestampFormatDef {
+    Mpeg,
+    Ms,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "SynchronisedLyricsType", rename_all = "lowercase")]
+enum SynchronisedLyricsTypeDef {
+    Other,
+    Lyrics,
+    Transcription,
+    #[serde(rename = "part name")]
+    PartName,
+    Event,
+    Chord,
+    Trivia,
+}
+
+/// The text encoding of an ID3 frame, named as in the ID3 standard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextEncoding {
+    #[serde(rename = "ISO-8859-1")]
+    Latin1,
+    #[serde(rename = "UTF-16")]
+    Utf16,
+    #[serde(rename = "UTF-16BE")]
+    Utf16Be,
+    #[serde(rename = "UTF-8")]
+    Utf8,
+}
+
+impl From<Encoding> for TextEncoding {
+    fn from(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::Latin1 => TextEncoding::Latin1,
+            Encoding::UTF16 => TextEncoding::Utf16,
+            Encoding::UTF16BE => TextEncoding::Utf16Be,
+            Encoding::UTF8 => TextEncoding::Utf8,
+        }
+    }
+}
+
+impl From<TextEncoding> for Encoding {
+    fn from(encoding: TextEncoding) -> Self {
+        match encoding {
+            TextEncoding::Latin1 => Encoding::Latin1,
+            TextEncoding::Utf16 => Encoding::UTF16,
+            TextEncoding::Utf16Be => Encoding::UTF16BE,
+            TextEncoding::Utf8 => Encoding::UTF8,
+        }
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InvolvedPerson {
+    pub involvement: String,
+    pub involvee: String,
+}
+
+/// The content of an ID3 frame, tagged with the kind of frame objects holding it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all_fields = "camelCase")]
+pub enum DocumentContent {
+    #[serde(rename = "text")]
+    Text { text: String },
+    #[serde(rename = "extended text")]
+    ExtendedText { description: String, value: String },
+    #[serde(rename = "link")]
+    Link { link: String },
+    #[serde(rename = "extended link")]
+    ExtendedLink { description: String, link: String },
+    #[serde(rename = "comment")]
+    Comment {
+        lang: String,
+        description: String,
+        text: String,
+    },
+    #[serde(rename = "popularimeter")]
+    Popularimeter {
+        user: String,
+        rating: u8,
+        counter: u64,
+    },
+    #[serde(rename = "lyrics")]
+    Lyrics {
+        lang: String,
+        description: String,
+        text: String,
+    },
+    #[serde(rename = "synchronised lyrics")]
+    SynchronisedLyrics {
+        lang: String,
+        #[serde(with = "TimestampFormatDef")]
+        timestamp_format: TimestampFormat,
+        #[serde(with = "SynchronisedLyricsTypeDef")]
+        content_type: SynchronisedLyricsType,
+        description: String,
+        content: Vec<(u32, String)>,
+    },
+    #[serde(rename = "picture")]
+    Picture {
+        #[serde(rename = "MIMEType")]
+        mime_type: String,
+        picture_type: u8,
+        description: String,
+        #[serde(with = "base64_data")]
+        data: Vec<u8>,
+    },
+    #[serde(rename = "encapsulated object")]
+    EncapsulatedObject {
+        #[serde(rename = "MIMEType")]
+        mime_type: String,
+        filename: String,
+        description: String,
+        #[serde(with = "base64_data")]
+        data: Vec<u8>,
+    },
+    #[serde(rename = "chapter")]
+    Chapter {
+        element_id: String,
+        start_time: u32,
+        end_time: u32,
+        start_offset: Option<u32>,
+        end_offset: Option<u32>,
+        frames: Vec<DocumentFrame>,
+    },
+    #[serde(rename = "table of contents")]
+    TableOfContents {
+        element_id: String,
+        top_level: bool,
+        ordered: bool,
+        elements: Vec<String>,
+        frames: Vec<DocumentFrame>,
+    },
+    #[serde(rename = "involved people")]
+    InvolvedPeople { items: Vec<InvolvedPerson> },
+    #[serde(rename = "private")]
+    Private {
+        owner: String,
+        #[serde(with = "base64_data")]
+        data: Vec<u8>,
+    },
+    #[serde(rename = "unique file identifier")]
+    UniqueFileIdentifier {
+        owner: String,
+        #[serde(with = "base64_data")]
+        identifier: Vec<u8>,
+    },
+    #[serde(rename = "unknown")]
+    Unknown {
+        #[serde(with = "base64_data")]
+        data: Vec<u8>,
+    },
+}
+
+/// An ID3 frame as it is stored in a tag
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentFrame {
+    pub id: String,
+    /// Left out for frames without text, new frames get the default of the tag version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<TextEncoding>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub tag_alter_preservation: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub file_alter_preservation: bool,
+    #[serde(flatten)]
+    pub content: DocumentContent,
+}
+
+fn offset_to_document(offset: u32) -> Option<u32> {
+    Some(offset).filter(|offset| *offset != CHAPTER_OFFSET_UNUSED)
+}
+
+fn bad_document(message: impl Into<String>) -> TagError {
+    TagError::new(ErrorCode::BadDocument, message)
+}
+
+impl DocumentFrame {
+    pub fn from_frame(frame: &Frame) -> Result<Self, TagError> {
+        let content = match frame.content() {
+            Content::Text(text) => DocumentContent::Text { text: text.clone() },
+            Content::Link(link) => DocumentContent::Link { link: link.clone() },
+            Content::ExtendedText(content) => DocumentContent::ExtendedText {
+                description: content.description.clone(),
+                value: content.value.clone(),
+            },
+            Content::ExtendedLink(content) => DocumentContent::ExtendedLink {
+                description: content.description.clone(),
+                link: content.link.clone(),
+            },
+            Content::Comment(content) => DocumentContent::Comment {
+                lang: content.lang.clone(),
+                description: content.description.clone(),
+                text: content.text.clone(),
+            },
+            Content::Popularimeter(content) => DocumentContent::Popularimeter {
+                user: content.user.clone(),
+                rating: content.rating,
+                counter: content.counter,
+            },
+            Content::Lyrics(content) => DocumentContent::Lyrics {
+                lang: content.lang.clone(),
+                description: content.description.clone(),
+                text: content.text.clone(),
+            },
+            Content::SynchronisedLyrics(content) => DocumentContent::SynchronisedLyrics {
+                lang: content.lang.clone(),
+                timestamp_format: content.timestamp_format,
+                content_type: content.content_type,
+                description: content.description.clone(),
+                content: content.content.clone(),
+            },
+            Content::Picture(content) => DocumentContent::Picture {
+                mime_type: content.mime_type.clone(),
+                picture_type: content.picture_type.into(),
+                description: content.description.clone(),
+                data: content.data.clone(),
+            },
+            Content::EncapsulatedObject(content) => DocumentContent::EncapsulatedObject {
+                mime_type: content.mime_type.clone(),
+                filename: content.filename.clone(),
+                description: content.description.clone(),
+                data: content.data.clone(),
+            },
+            Content::Chapter(content) => DocumentContent::Chapter {
+                element_id: content.element_id.clone(),
+                start_time: content.start_time,
+                end_time: content.end_time,
+                start_offset: offset_to_document(content.start_offset),
+                end_offset: offset_to_document(content.end_offset),
+                frames: frames_to_document(&content.frames)?,
+            },
+            Content::TableOfContents(content) => DocumentContent::TableOfContents {
+                element_id: content.element_id.clone(),
+                top_level: content.top_level,
+                ordered: content.ordered,
+                elements: content.elements.clone(),
+                frames: frames_to_document(&content.frames)?,
+            },
+            Content::InvolvedPeopleList(content) => DocumentContent::InvolvedPeople {
+                items: content
+                    .items
+                    .iter()
+                    .map(|item| InvolvedPerson {
+                        involvement: item.involvement.clone(),
+                        involvee: item.involvee.clone(),
+                    })
+                    .collect(),
+            },
+            Content::Private(content) => DocumentContent::Private {
+                owner: content.owner_identifier.clone(),
+                data: content.private_data.clone(),
+            },
+            Content::UniqueFileIdentifier(content) => DocumentContent::UniqueFileIdentifier {
+                owner: content.owner_identifier.clone(),
+                identifier: content.identifier.clone(),
+            },
+            // MPEG location lookup tables and contents added to the id3 crate later
+            content => DocumentContent::Unknown {
+                data: content.to_unknown()?.data.clone(),
+            },
+        };
+
+        Ok(DocumentFrame {
+            id: frame.id().to_owned(),
+            encoding: frame.encoding().map(TextEncoding::from),
+            tag_alter_preservation: frame.tag_alter_preservation(),
+            file_alter_preservation: frame.file_alter_preservation(),
+            content,
+        })
+    }
+
+    pub fn to_frame(&self) -> Result<Frame, TagError> {
+        // Frame::with_content panics on IDs of other lengths
+        if !matches!(self.id.len(), 3 | 4)
+            || !self
+                .id
+                .bytes()
+                .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit())
+        {
+            return Err(bad_document(format!(
+                "Frame ID {:?} must be 4 uppercase letters or digits",
+                self.id
+            )));
+        }
+
+        let content = match &self.content {
+            DocumentContent::Text { text } => Content::Text(text.clone()),
+            DocumentContent::Link { link } => Content::Link(link.clone()),
+            DocumentContent::ExtendedText { description, value } => {
+                Content::ExtendedText(ExtendedText {
+                    description: description.clone(),
+                    value: value.clone(),
+                })
+            }
+            DocumentContent::ExtendedLink { description, link } => {
+                Content::ExtendedLink(ExtendedLink {
+                    description: description.clone(),
+                    link: link.clone(),
+                })
+            }
+            DocumentContent::Comment {
+                lang,
+                description,
+                text,
+            } => Content::Comment(Comment {
+                lang: lang.clone(),
+                description: description.clone(),
+                text: text.clone(),
+            }),
+            DocumentContent::Popularimeter {
+                user,
+                rating,
+                counter,
+            } => Content::Popularimeter(Popularimeter {
+                user: user.clone(),
+                rating: *rating,
+                counter: *counter,
+            }),
+            DocumentContent::Lyrics {
+                lang,
+                description,
+                text,
+            } => Content::Lyrics(Lyrics {
+                lang: lang.clone(),
+                description: description.clone(),
+                text: text.clone(),
+            }),
+            DocumentContent::SynchronisedLyrics {
+                lang,
+                timestamp_format,
+                content_type,
+                description,
+                content,
+            } => Content::SynchronisedLyrics(SynchronisedLyrics {
+                lang: lang.clone(),
+                timestamp_format: *timestamp_format,
+                content_type: *content_type,
+                description: description.clone(),
+                content: content.clone(),
+            }),
+            DocumentContent::Picture {
+                mime_type,
+                picture_type,
+                description,
+                data,
+            } => Content::Picture(Picture {
+                mime_type: mime_type.clone(),
+                picture_type: carrier::u8_to_picture_ype(*picture_type),
+                description: description.clone(),
+                data: data.clone(),
+            }),
+            DocumentContent::EncapsulatedObject {
+                mime_type,
+                filename,
+                description,
+                data,
+            } => Content::EncapsulatedObject(EncapsulatedObject {
+                mime_type: mime_type.clone(),
+                filename: filename.clone(),
+                description: description.clone(),
+                data: data.clone(),
+            }),
+            DocumentContent::Chapter {
+                element_id,
+                start_time,
+                end_time,
+                start_offset,
+                end_offset,
+                frames,
+            } => Content::Chapter(Chapter {
+                element_id: element_id.clone(),
+                start_time: *start_time,
+                end_time: *end_time,
+                start_offset: start_offset.unwrap_or(CHAPTER_OFFSET_UNUSED),
+                end_offset: end_offset.unwrap_or(CHAPTER_OFFSET_UNUSED),
+                frames: frames_from_document(frames)?,
+            }),
+            DocumentContent::TableOfContents {
+                element_id,
+                top_level,
+                ordered,
+                elements,
+                frames,
+            } => Content::TableOfContents(TableOfContents {
+                element_id: element_id.clone(),
+                top_level: *top_level,
+                ordered: *ordered,
+                elements: elements.clone(),
+                frames: frames_from_document(frames)?,
+            }),
+            DocumentContent::InvolvedPeople { items } => {
+                Content::InvolvedPeopleList(InvolvedPeopleList {
+                    items: items
+                        .iter()
+                        .map(|item| InvolvedPeopleListItem {
+                            involvement: item.involvement.clone(),
+                            involvee: item.involvee.clone(),
+                        })
+                        .collect(),
+                })
+            }
+            DocumentContent::Private { owner, data } => Content::Private(Private {
+                owner_identifier: owner.clone(),
+                private_data: data.clone(),
+            }),
+            DocumentContent::UniqueFileIdentifier { owner, identifier } => {
+                Content::UniqueFileIdentifier(UniqueFileIdentifier {
+                    owner_identifier: owner.clone(),
+                    identifier: identifier.clone(),
+                })
+            }
+            DocumentContent::Unknown { data } => Content::Unknown(Unknown {
+                data: data.clone(),
+                version: Version::Id3v24,
+            }),
+        };
+
+        let mut frame =
+            Frame::with_content(&self.id, content).set_encoding(self.encoding.map(Encoding::from));
+        frame.set_tag_alter_preservation(self.tag_alter_preservation);
+        frame.set_file_alter_preservation(self.file_alter_preservation);
+        Ok(frame)
+    }
+}
+
+fn frames_to_document(frames: &[Frame]) -> Result<Vec<DocumentFrame>, TagError> {
+    frames.iter().map(DocumentFrame::from_frame).collect()
+}
+
+fn frames_from_document(frames: &[DocumentFrame]) -> Result<Vec<Frame>, TagError> {
+    frames.iter().map(DocumentFrame::to_frame).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Id3Document {
+    /// Frames are written in this version as they are, without translating them
+    #[serde(with = "VersionDef")]
+    pub version: Version,
+    pub frames: Vec<DocumentFrame>,
+}
+
+impl Id3Document {
+    /// The tag holding the frames of the document, repeated ones included
+    pub fn to_tag(&self) -> Result<Tag, TagError> {
+        (0u32..)
+            .zip(&self.frames)
+            .map(|(i, frame)| {
+                frame
+                    .to_frame()
+                    .map_err(|error| error.with_frame(i, &frame.id))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VorbisField {
+    pub name: String,
+    pub value: String,
+}
+
+/// A picture in the FLAC PICTURE block layout
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentPicture {
+    pub picture_type: u32,
+    #[serde(rename = "MIMEType")]
+    pub mime_type: String,
+    pub description: String,
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub colors: u32,
+    #[serde(with = "base64_data")]
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VorbisDocument {
+    pub vendor: String,
+    pub fields: Vec<VorbisField>,
+    /// Fields without `=`, left out when there are none
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unnamed: Vec<String>,
+    pub pictures: Vec<DocumentPicture>,
+}
+
+impl From<&VorbisTag> for VorbisDocument {
+    fn from(tag: &VorbisTag) -> Self {
+        VorbisDocument {
+            vendor: tag.comment.vendor.clone(),
+            fields: tag
+                .comment
+                .fields
+                .iter()
+                .map(|(name, value)| VorbisField {
+                    name: name.clone(),
+                    value: value.clone(),
+                })
+                .collect(),
+            unnamed: tag.comment.unnamed.clone(),
+            pictures: tag
+                .pictures
+                .iter()
+                .map(|picture| DocumentPicture {
+                    picture_type: picture.picture_type,
+                    mime_type: picture.mime_type.clone(),
+                    description: picture.description.clone(),
+                    width: picture.width,
+                    height: picture.height,
+                    depth: picture.depth,
+                    colors: picture.colors,
+                    data: picture.data.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl VorbisDocument {
+    pub fn to_tag(&self) -> VorbisTag {
+        VorbisTag {
+            comment: VorbisComment {
+                vendor: self.vendor.clone(),
+                fields: self
+                    .fields
+                    .iter()
+                    .map(|field| (field.name.clone(), field.value.clone()))
+                    .collect(),
+                unnamed: self.unnamed.clone(),
+            },
+            pictures: self
+                .pictures
+                .iter()
+                .map(|picture| FlacPicture {
+                    picture_type: picture.picture_type,
+                    mime_type: picture.mime_type.clone(),
+                    description: picture.description.clone(),
+                    width: picture.width,
+                    height: picture.height,
+                    depth: picture.depth,
+                    colors: picture.colors,
+                    data: picture.data.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The value of an MP4 data atom, readable text for UTF-8 atoms
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DocumentValue {
+    Text {
+        text: String,
+    },
+    Data {
+        #[serde(with = "base64_data")]
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentData {
+    #[serde(rename = "type")]
+    pub type_code: u32,
+    pub locale: u32,
+    #[serde(flatten)]
+    pub value: DocumentValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentItem {
+    pub ident: String,
+    pub data: Vec<DocumentData>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mp4Document {
+    pub items: Vec<DocumentItem>,
+}
+
+impl From<&Mp4Tag> for Mp4Document {
+    fn from(tag: &Mp4Tag) -> Self {
+        let data_to_document = |data: &DataAtom| DocumentData {
+            type_code: data.type_code,
+            locale: data.locale,
+            value: match (data.type_code, std::str::from_utf8(&data.value)) {
+                (MP4_UTF8, Ok(text)) => DocumentValue::Text {
+                    text: text.to_owned(),
+                },
+                _ => DocumentValue::Data {
+                    data: data.value.clone(),
+                },
+            },
+        };
+
+        Mp4Document {
+            items: tag
+                .items
+                .iter()
+                .map(|item| DocumentItem {
+                    ident: item.ident.to_string(),
+                    data: item.data.iter().map(data_to_document).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Mp4Document {
+    pub fn to_tag(&self) -> Result<Mp4Tag, TagError> {
+        let items = self
+            .items
+            .iter()
+            .map(|item| {
+                let ident = Ident::parse(&item.ident).ok_or_else(|| {
+                    bad_document(format!(
+                        "Item {:?} is neither an atom type nor ----:mean:name",
+                        item.ident
+                    ))
+                })?;
+                let data = item
+                    .data
+                    .iter()
+                    .map(|data| DataAtom {
+                        type_code: data.type_code,
+                        locale: data.locale,
+                        value: match &data.value {
+                            DocumentValue::Text { text } => text.as_bytes().to_vec(),
+                            DocumentValue::Data { data } => data.clone(),
+                        },
+                    })
+                    .collect();
+                Ok(Item { ident, data })
+            })
+            .collect::<Result<_, TagError>>()?;
+        Ok(Mp4Tag { items })
+    }
+}
+
+/// A tag in the layout of its format
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "format", rename_all = "lowercase")]
+pub enum DocumentTag {
+    Id3(Id3Document),
+    Vorbis(VorbisDocument),
+    Mp4(Mp4Document),
+}
+
+/// A whole tag as exported by `exportTag`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TagDocument {
+    pub schema: u32,
+    #[serde(flatten)]
+    pub tag: DocumentTag,
+}
+
+impl TagDocument {
+    pub fn new(tag: DocumentTag) -> Self {
+        TagDocument {
+            schema: DOCUMENT_SCHEMA_VERSION,
+            tag,
+        }
+    }
+
+    pub fn from_id3(version: Version, frames: &[Frame]) -> Result<Self, TagError> {
+        Ok(TagDocument::new(DocumentTag::Id3(Id3Document {
+            version,
+            frames: frames_to_document(frames)?,
+        })))
+    }
+
+    pub fn format(&self) -> TagFormat {
+        match self.tag {
+            DocumentTag::Id3(_) => TagFormat::Id3,
+            DocumentTag::Vorbis(_) => TagFormat::Vorbis,
+            DocumentTag::Mp4(_) => TagFormat::Mp4,
+        }
+    }
+
+    pub fn to_text(&self, format: DocumentFormat) -> String {
+        // Documents only hold strings, numbers, booleans and lists, which always serialize
+        match format {
+            DocumentFormat::Json => {
+                serde_json::to_string_pretty(self).expect("tag documents serialize to JSON") + "\n"
+            }
+            DocumentFormat::Yaml => {
+                serde_yaml::to_string(self).expect("tag documents serialize to YAML")
+            }
+        }
+    }
+
+    /// Parses a document, as JSON when it starts with `{` and as YAML otherwise
+    pub fn parse(text: &str) -> Result<Self, TagError> {
+        let document: TagDocument = match text.trim_start().starts_with('{') {
+            true => serde_json::from_str(text).map_err(|error| bad_document(error.to_string()))?,
+            false => serde_yaml::from_str(text).map_err(|error| bad_document(error.to_string()))?,
+        };
+        if document.schema != DOCUMENT_SCHEMA_VERSION {
+            return Err(bad_document(format!(
+                "Tag document schema {} is not supported, expected {}",
+                document.schema, DOCUMENT_SCHEMA_VERSION
+            )));
+        }
+        Ok(document)
+    }
+}
@@ -0,0 +1,127 @@
+use std::fmt;
+
+/// Stable error codes, exposed to JavaScript as `error.code` and by `as_str`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The file could not be read or written
+    Io,
+    /// The tag in the file is malformed
+    Parse,
+    /// The frame type cannot be read or written yet
+    UnsupportedFrame,
+    /// A frame carrier received from JavaScript has the wrong shape
+    BadCarrier,
+    /// The batch operation was cancelled before the file was started
+    Cancelled,
+    /// The written file did not read back as expected, so the original was kept
+    Verification,
+    /// The tag has no frame of the kind asked for at the given index
+    FrameNotFound,
+    /// The audio of the file could not be decoded
+    Decode,
+    /// The audio of the file is in a codec there is no decoder for, such as Opus
+    UnsupportedCodec,
+    /// A tag document to import is malformed or holds a tag of another format
+    BadDocument,
+    /// Working on the file panicked, a bug in metashine rather than in the file
+    Internal,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 11] = [
+        ErrorCode::Io,
+        ErrorCode::Parse,
+        ErrorCode::UnsupportedFrame,
+        ErrorCode::BadCarrier,
+        ErrorCode::Cancelled,
+        ErrorCode::Verification,
+        ErrorCode::FrameNotFound,
+        ErrorCode::Decode,
+        ErrorCode::UnsupportedCodec,
+        ErrorCode::BadDocument,
+        ErrorCode::Internal,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::Io => "ERR_IO",
+            ErrorCode::Parse => "ERR_PARSE",
+            ErrorCode::UnsupportedFrame => "ERR_UNSUPPORTED_FRAME",
+            ErrorCode::BadCarrier => "ERR_BAD_CARRIER",
+            ErrorCode::Cancelled => "ERR_CANCELLED",
+            ErrorCode::Verification => "ERR_VERIFY",
+            ErrorCode::FrameNotFound => "ERR_FRAME_NOT_FOUND",
+            ErrorCode::Decode => "ERR_DECODE",
+            ErrorCode::UnsupportedCodec => "ERR_UNSUPPORTED_CODEC",
+            ErrorCode::BadDocument => "ERR_BAD_DOCUMENT",
+            ErrorCode::Internal => "ERR_INTERNAL",
+        }
+    }
+}
+
+/// An error raised while loading or updating a tag, with the file and frame it concerns
+#[derive(Debug, Clone)]
+pub struct TagError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub path: Option<String>,
+    pub frame_index: Option<u32>,
+    pub frame_id: Option<String>,
+}
+
+impl TagError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        TagError {
+            code,
+            message: message.into(),
+            path: None,
+            frame_index: None,
+            frame_id: None,
+        }
+    }
+
+    pub fn with_path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn with_frame(mut self, index: u32, id: &str) -> Self {
+        self.frame_index = Some(index);
+        self.frame_id = Some(id.to_string());
+        self
+    }
+}
+
+impl fmt::Display for TagError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}: ", path)?;
+        }
+        match (self.frame_index, &self.frame_id) {
+            (Some(index), Some(id)) => write!(f, "frame {} ({}): ", index, id)?,
+            (Some(index), None) => write!(f, "frame {}: ", index)?,
+            (None, Some(id)) => write!(f, "frame {}: ", id)?,
+            (None, None) => {}
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for TagError {}
+
+impl From<id3::Error> for TagError {
+    fn from(error: id3::Error) -> Self {
+        let code = match error.kind {
+            id3::ErrorKind::Io(_) => ErrorCode::Io,
+            id3::ErrorKind::UnsupportedFeature => ErrorCode::UnsupportedFrame,
+            _ => ErrorCode::Parse,
+        };
+        TagError::new(code, error.to_string())
+    }
+}
+
+impl From<std::io::Error> for TagError {
+    fn from(error: std::io::Error) -> Self {
+        TagError::new(ErrorCode::Io, error.to_string())
+    }
+}
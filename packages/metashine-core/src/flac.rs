@@ -0,0 +1,246 @@
+//! FLAC backend reading and writing VORBIS_COMMENT and PICTURE metadata blocks
+
+use std::fs;
+
+use crate::{
+    error::{ErrorCode, TagError},
+    format::Layout,
+    hash,
+    vorbis::{FlacPicture, VorbisComment},
+};
+
+const MAGIC: &[u8; 4] = b"fLaC";
+
+const BLOCK_STREAMINFO: u8 = 0;
+const BLOCK_PADDING: u8 = 1;
+const BLOCK_VORBIS_COMMENT: u8 = 4;
+const BLOCK_PICTURE: u8 = 6;
+
+/// Largest metadata block body, its length is stored in 24 bits
+const MAX_BLOCK_LEN: usize = (1 << 24) - 1;
+
+/// The metadata of a FLAC or Ogg file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VorbisTag {
+    pub comment: VorbisComment,
+    pub pictures: Vec<FlacPicture>,
+}
+
+struct MetadataBlock<'b> {
+    block_type: u8,
+    data: &'b [u8],
+}
+
+/// A FLAC stream split into the bytes before it, its metadata blocks and its audio frames
+struct FlacFile<'b> {
+    /// An ID3v2 tag some software puts before the stream marker, kept as is
+    prefix: &'b [u8],
+    blocks: Vec<MetadataBlock<'b>>,
+    audio: &'b [u8],
+}
+
+/// Length of an ID3v2 tag starting with the 10 byte header in bytes, zero if there is none
+pub fn id3v2_len(bytes: &[u8]) -> usize {
+    if bytes.len() < 10 || &bytes[..3] != b"ID3" {
+        return 0;
+    }
+    let size = bytes[6..10]
+        .iter()
+        .fold(0usize, |size, byte| (size << 7) | (*byte & 0x7f) as usize);
+    let footer = if bytes[5] & 0x10 != 0 { 10 } else { 0 };
+    10 + size + footer
+}
+
+/// Checks whether bytes start a FLAC stream, optionally after an ID3v2 tag
+pub fn is_flac(bytes: &[u8]) -> bool {
+    let start = id3v2_len(bytes);
+    bytes.len() >= start + 4 && &bytes[start..start + 4] == MAGIC
+}
+
+/// Checks whether bytes start with the FLAC stream marker
+pub fn is_flac_stream(head: &[u8]) -> bool {
+    head.starts_with(MAGIC)
+}
+
+fn parse(bytes: &[u8]) -> Result<FlacFile<'_>, TagError> {
+    let start = id3v2_len(bytes);
+    if !is_flac(bytes) {
+        return Err(TagError::new(ErrorCode::Parse, "Not a FLAC stream"));
+    }
+
+    let mut position = start + MAGIC.len();
+    let mut blocks = Vec::new();
+    loop {
+        if position + 4 > bytes.len() {
+            return Err(TagError::new(
+                ErrorCode::Parse,
+                "Unexpected end of FLAC metadata",
+            ));
+        }
+        let header = &bytes[position..position + 4];
+        let last = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7f;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        position += 4;
+
+        if position + len > bytes.len() {
+            return Err(TagError::new(
+                ErrorCode::Parse,
+                format!("FLAC metadata block of type {} is truncated", block_type),
+            ));
+        }
+        blocks.push(MetadataBlock {
+            block_type,
+            data: &bytes[position..position + len],
+        });
+        position += len;
+
+        if last {
+            break;
+        }
+    }
+
+    match blocks.first() {
+        Some(block) if block.block_type == BLOCK_STREAMINFO => {}
+        _ => {
+            return Err(TagError::new(
+                ErrorCode::Parse,
+                "FLAC stream does not start with STREAMINFO",
+            ))
+        }
+    }
+
+    Ok(FlacFile {
+        prefix: &bytes[..start],
+        blocks,
+        audio: &bytes[position..],
+    })
+}
+
+pub fn read_from_bytes(bytes: &[u8]) -> Result<VorbisTag, TagError> {
+    let file = parse(bytes)?;
+    let mut tag = VorbisTag::default();
+
+    for block in &file.blocks {
+        match block.block_type {
+            BLOCK_VORBIS_COMMENT => tag.comment = VorbisComment::parse(block.data)?,
+            BLOCK_PICTURE => tag.pictures.push(FlacPicture::parse(block.data)?),
+            _ => {}
+        }
+    }
+
+    Ok(tag)
+}
+
+/// Replaces the comment and picture blocks of a FLAC stream.
+///
+/// Every other block, including STREAMINFO and SEEKTABLE, is copied untouched and in its
+/// original order. Padding is reused on purpose: the first PADDING block grows or shrinks by the
+/// change in size of the tag when it can, so the audio frames keep their offset and the file
+/// isn't rewritten past the metadata. Padding too small to absorb the change is kept as it is
+/// and the audio moves. The new blocks take the place of the old comment block, or follow the
+/// last non-padding block if the stream had none.
+pub fn write_to_bytes(bytes: &[u8], tag: &VorbisTag) -> Result<Vec<u8>, TagError> {
+    let file = parse(bytes)?;
+
+    let comment = tag.comment.to_bytes();
+    let pictures: Vec<Vec<u8>> = tag.pictures.iter().map(FlacPicture::to_bytes).collect();
+    let mut new_blocks: Vec<(u8, &[u8])> = vec![(BLOCK_VORBIS_COMMENT, &comment)];
+    new_blocks.extend(pictures.iter().map(|data| (BLOCK_PICTURE, data.as_slice())));
+
+    let insert_at = file
+        .blocks
+        .iter()
+        .position(|block| block.block_type == BLOCK_VORBIS_COMMENT)
+        .unwrap_or_else(|| {
+            file.blocks
+                .iter()
+                .rposition(|block| block.block_type != BLOCK_PADDING)
+                .map_or(file.blocks.len(), |i| i + 1)
+        });
+
+    let padding: Vec<u8>;
+    let mut blocks: Vec<(u8, &[u8])> = Vec::new();
+    for (i, block) in file.blocks.iter().enumerate() {
+        if i == insert_at {
+            blocks.append(&mut new_blocks);
+        }
+        if block.block_type != BLOCK_VORBIS_COMMENT && block.block_type != BLOCK_PICTURE {
+            blocks.push((block.block_type, block.data));
+        }
+    }
+    blocks.append(&mut new_blocks);
+
+    // The first padding block grows or shrinks by the change in size when it can, so the
+    // audio frames stay where they were
+    let old_len: usize = file.blocks.iter().map(|block| 4 + block.data.len()).sum();
+    let new_len: usize = blocks.iter().map(|(_, data)| 4 + data.len()).sum();
+    if let Some(block) = blocks
+        .iter_mut()
+        .find(|(block_type, _)| *block_type == BLOCK_PADDING)
+    {
+        let resized = (block.1.len() + old_len).checked_sub(new_len);
+        if let Some(resized) = resized.filter(|len| *len <= MAX_BLOCK_LEN) {
+            padding = vec![0; resized];
+            block.1 = &padding;
+        }
+    }
+
+    let mut output = Vec::with_capacity(bytes.len() + comment.len());
+    output.extend_from_slice(file.prefix);
+    output.extend_from_slice(MAGIC);
+    for (i, (block_type, data)) in blocks.iter().enumerate() {
+        if data.len() > MAX_BLOCK_LEN {
+            return Err(TagError::new(
+                ErrorCode::UnsupportedFrame,
+                format!("FLAC metadata block of {} bytes is too large", data.len()),
+            ));
+        }
+        let last = if i == blocks.len() - 1 { 0x80 } else { 0 };
+        let len = (data.len() as u32).to_be_bytes();
+        output.extend_from_slice(&[last | block_type, len[1], len[2], len[3]]);
+        output.extend_from_slice(data);
+    }
+    output.extend_from_slice(file.audio);
+
+    Ok(output)
+}
+
+/// Works out the size of the comment and picture blocks `write_to_bytes` writes and how far
+/// the audio frames move
+pub fn layout(bytes: &[u8], tag: &VorbisTag) -> Result<Layout, TagError> {
+    let old_audio_start = bytes.len() - parse(bytes)?.audio.len();
+    let output = write_to_bytes(bytes, tag)?;
+    let file = parse(&output)?;
+
+    let tag_size = file
+        .blocks
+        .iter()
+        .filter(|block| {
+            block.block_type == BLOCK_VORBIS_COMMENT || block.block_type == BLOCK_PICTURE
+        })
+        .map(|block| 4 + block.data.len())
+        .sum();
+    let audio_start = output.len() - file.audio.len();
+
+    Ok(Layout {
+        tag_size,
+        audio_shift: audio_start as i64 - old_audio_start as i64,
+    })
+}
+
+/// SHA-256 digest of the audio frames, which writing a tag must leave untouched
+pub fn audio_hash(bytes: &[u8]) -> Result<[u8; 32], TagError> {
+    Ok(hash::sha256(parse(bytes)?.audio))
+}
+
+pub fn read_from_path(path: &str) -> Result<VorbisTag, TagError> {
+    let bytes = fs::read(path).map_err(|error| TagError::from(error).with_path(path))?;
+    read_from_bytes(&bytes).map_err(|error| error.with_path(path))
+}
+
+pub fn write_to_path(path: &str, tag: &VorbisTag) -> Result<(), TagError> {
+    let bytes = fs::read(path).map_err(|error| TagError::from(error).with_path(path))?;
+    let output = write_to_bytes(&bytes, tag).map_err(|error| error.with_path(path))?;
+    fs::write(path, output).map_err(|error| TagError::from(error).with_path(path))
+}
@@ -0,0 +1,115 @@
+//! Detection of the tag format a file uses
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+};
+
+use crate::{error::TagError, flac, mp4, ogg};
+
+/// Tag formats `loadTag` and `updateTag` can work with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagFormat {
+    /// ID3v2 tags of MP3, WAV and AIFF files
+    Id3,
+    /// Vorbis comments of FLAC, Ogg Vorbis and Opus streams
+    Vorbis,
+    /// iTunes metadata atoms of MP4, M4A and M4B files
+    Mp4,
+}
+
+impl TagFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TagFormat::Id3 => "id3",
+            TagFormat::Vorbis => "vorbis",
+            TagFormat::Mp4 => "mp4",
+        }
+    }
+}
+
+/// Extensions of the audio files whose tags can be read, in lower case
+pub const AUDIO_EXTENSIONS: &[&str] = &[
+    "mp3", "wav", "aif", "aiff", "flac", "ogg", "oga", "opus", "m4a", "m4b", "mp4",
+];
+
+/// How writing a tag changes the file it is written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    /// Bytes taken by the written tag, including its headers and any padding inside it
+    pub tag_size: usize,
+    /// Bytes the audio moves by, negative when it moves towards the start of the file
+    pub audio_shift: i64,
+}
+
+/// Containers the tag is read from and written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    /// Files tagged by the id3 crate
+    Id3,
+    Flac,
+    Ogg,
+    Mp4,
+}
+
+impl Container {
+    pub fn tag_format(&self) -> TagFormat {
+        match self {
+            Container::Id3 => TagFormat::Id3,
+            Container::Flac | Container::Ogg => TagFormat::Vorbis,
+            Container::Mp4 => TagFormat::Mp4,
+        }
+    }
+}
+
+/// Bytes of the stream start needed to recognize a container
+const HEAD_LEN: usize = 12;
+
+/// Detects the container from the first bytes of a stream, after any ID3v2 tag
+fn detect_stream(head: &[u8]) -> Container {
+    if flac::is_flac_stream(head) {
+        Container::Flac
+    } else if ogg::is_ogg_stream(head) {
+        Container::Ogg
+    } else if mp4::is_mp4_stream(head) {
+        Container::Mp4
+    } else {
+        Container::Id3
+    }
+}
+
+/// Reads into buf until it is full or the file ends, returning the number of bytes read
+fn read_head(file: &mut File, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match file.read(&mut buf[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+/// Detects the container of a file, falling back to ID3
+pub fn detect(path: &str) -> Result<Container, TagError> {
+    let detect = || -> std::io::Result<Container> {
+        let mut file = File::open(path)?;
+
+        let mut header = [0u8; 10];
+        let read = read_head(&mut file, &mut header)?;
+        let start = flac::id3v2_len(&header[..read]);
+        file.seek(SeekFrom::Start(start as u64))?;
+
+        let mut head = [0u8; HEAD_LEN];
+        let read = read_head(&mut file, &mut head)?;
+        Ok(detect_stream(&head[..read]))
+    };
+
+    detect().map_err(|error| TagError::from(error).with_path(path))
+}
+
+/// Detects the container of a file held in memory, falling back to ID3
+pub fn detect_bytes(bytes: &[u8]) -> Container {
+    let start = flac::id3v2_len(bytes).min(bytes.len());
+    detect_stream(&bytes[start..])
+}
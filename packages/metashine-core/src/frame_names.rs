@@ -0,0 +1,116 @@
+//! The Vorbis comment fields and MP4 atoms standing for ID3 frames, so carriers keyed by ID3
+//! frame IDs are written to and read from every tag format alike
+
+/// ID3 frame IDs and the Vorbis comment fields holding the same values, following the names
+/// MusicBrainz Picard writes
+const VORBIS_FIELDS: [(&str, &str); 33] = [
+    ("TIT1", "GROUPING"),
+    ("TIT2", "TITLE"),
+    ("TIT3", "SUBTITLE"),
+    ("TPE1", "ARTIST"),
+    ("TPE2", "ALBUMARTIST"),
+    ("TPE3", "CONDUCTOR"),
+    ("TPE4", "REMIXER"),
+    ("TALB", "ALBUM"),
+    ("TRCK", "TRACKNUMBER"),
+    ("TPOS", "DISCNUMBER"),
+    ("TSST", "DISCSUBTITLE"),
+    ("TCON", "GENRE"),
+    ("TDRC", "DATE"),
+    ("TDOR", "ORIGINALDATE"),
+    ("TCOM", "COMPOSER"),
+    ("TEXT", "LYRICIST"),
+    ("TPUB", "LABEL"),
+    ("TCOP", "COPYRIGHT"),
+    ("TSRC", "ISRC"),
+    ("TBPM", "BPM"),
+    ("TKEY", "KEY"),
+    ("TMOO", "MOOD"),
+    ("TLAN", "LANGUAGE"),
+    ("TMED", "MEDIA"),
+    ("TENC", "ENCODEDBY"),
+    ("TSSE", "ENCODER"),
+    ("TCMP", "COMPILATION"),
+    ("TSOT", "TITLESORT"),
+    ("TSOP", "ARTISTSORT"),
+    ("TSOA", "ALBUMSORT"),
+    ("TSO2", "ALBUMARTISTSORT"),
+    ("TSOC", "COMPOSERSORT"),
+    ("APIC", "METADATA_BLOCK_PICTURE"),
+];
+
+/// ID3 frame IDs and the MP4 atoms holding the same values. User defined texts are iTunes
+/// freeform atoms
+const MP4_ATOMS: [(&str, &str); 21] = [
+    ("TIT1", "©grp"),
+    ("TIT2", "©nam"),
+    ("TPE1", "©ART"),
+    ("TPE2", "aART"),
+    ("TALB", "©alb"),
+    ("TRCK", "trkn"),
+    ("TPOS", "disk"),
+    ("TCON", "©gen"),
+    ("TDRC", "©day"),
+    ("TCOM", "©wrt"),
+    ("TCOP", "cprt"),
+    ("TBPM", "tmpo"),
+    ("TSSE", "©too"),
+    ("TCMP", "cpil"),
+    ("TSOT", "sonm"),
+    ("TSOP", "soar"),
+    ("TSOA", "soal"),
+    ("TSO2", "soaa"),
+    ("TSOC", "soco"),
+    ("TXXX", "----"),
+    ("APIC", "covr"),
+];
+
+/// The frame IDs ID3v2.3 and ID3v2.4 define
+const ID3_FRAME_IDS: [&str; 103] = [
+    "AENC", "APIC", "ASPI", "COMM", "COMR", "ENCR", "EQU2", "EQUA", "ETCO", "GEOB", "GRID", "GRP1",
+    "IPLS", "LINK", "MCDI", "MLLT", "MVIN", "MVNM", "OWNE", "PCNT", "PCST", "POPM", "POSS", "PRIV",
+    "RBUF", "RVA2", "RVAD", "RVRB", "SEEK", "SIGN", "SYLT", "SYTC", "TALB", "TBPM", "TCAT", "TCMP",
+    "TCOM", "TCON", "TCOP", "TDAT", "TDEN", "TDES", "TDLY", "TDOR", "TDRC", "TDRL", "TDTG", "TENC",
+    "TEXT", "TFLT", "TGID", "TIME", "TIPL", "TIT1", "TIT2", "TIT3", "TKEY", "TKWD", "TLAN", "TLEN",
+    "TMCL", "TMED", "TMOO", "TOAL", "TOFN", "TOLY", "TOPE", "TORY", "TOWN", "TPE1", "TPE2", "TPE3",
+    "TPE4", "TPOS", "TPRO", "TPUB", "TRCK", "TRDA", "TRSN", "TRSO", "TSIZ", "TSO2", "TSOA", "TSOC",
+    "TSOP", "TSOT", "TSRC", "TSSE", "TSST", "TXXX", "TYER", "UFID", "USER", "USLT", "WCOM", "WCOP",
+    "WOAF", "WOAR", "WOAS", "WORS", "WPAY", "WPUB", "WXXX",
+];
+
+/// Whether id is an ID3v2.3 or ID3v2.4 frame ID, so it can't stand for a field or atom itself
+pub fn is_id3_frame_id(id: &str) -> bool {
+    ID3_FRAME_IDS.contains(&id)
+}
+
+/// The Vorbis comment field of an ID3 frame
+pub fn id3_to_vorbis(id: &str) -> Option<&'static str> {
+    VORBIS_FIELDS
+        .iter()
+        .find(|(frame_id, _)| *frame_id == id)
+        .map(|(_, field)| *field)
+}
+
+/// The ID3 frame of a Vorbis comment field, whose names are case-insensitive
+pub fn vorbis_to_id3(field: &str) -> Option<&'static str> {
+    VORBIS_FIELDS
+        .iter()
+        .find(|(_, name)| name.eq_ignore_ascii_case(field))
+        .map(|(frame_id, _)| *frame_id)
+}
+
+/// The MP4 atom of an ID3 frame
+pub fn id3_to_mp4(id: &str) -> Option<&'static str> {
+    MP4_ATOMS
+        .iter()
+        .find(|(frame_id, _)| *frame_id == id)
+        .map(|(_, atom)| *atom)
+}
+
+/// The ID3 frame of an MP4 atom
+pub fn mp4_to_id3(atom: &str) -> Option<&'static str> {
+    MP4_ATOMS
+        .iter()
+        .find(|(_, name)| *name == atom)
+        .map(|(frame_id, _)| *frame_id)
+}
@@ -0,0 +1,234 @@
+//! Reading the frames of an ID3v2 tag one at a time, keeping what the id3 crate drops when it
+//! reads a whole tag: repeated frames and the order they are stored in, frame status flags and
+//! text encodings
+
+use std::io::Cursor;
+
+use id3::{
+    frame::{Chapter, TableOfContents},
+    Content, Encoding, Frame, Tag, Version,
+};
+
+use crate::{
+    error::{ErrorCode, TagError},
+    id3_header,
+};
+
+/// Bytes some frame flags add after the frame header of ID3v2.3: the decompressed size of
+/// compressed frames, the encryption method and the group identifier
+const V3_HEADER_EXTENSIONS: [(u8, usize); 3] = [(0x80, 4), (0x40, 1), (0x20, 1)];
+/// The same for ID3v2.4: the group identifier, the encryption method and the data length
+const V4_HEADER_EXTENSIONS: [(u8, usize); 3] = [(0x40, 1), (0x04, 1), (0x01, 4)];
+
+const V3_COMPRESSION_OR_ENCRYPTION: u8 = 0xc0;
+const V4_COMPRESSION_OR_ENCRYPTION: u8 = 0x0c;
+const V4_UNSYNCHRONISATION: u8 = 0x02;
+
+/// What the id3 crate drops from a stored frame
+#[derive(Debug, Default)]
+struct StoredInfo {
+    tag_alter_preservation: bool,
+    file_alter_preservation: bool,
+    /// Left out for frames without a text encoding and compressed or encrypted frames
+    encoding: Option<Encoding>,
+    /// The frames embedded in a chapter or table of contents
+    embedded: Vec<StoredInfo>,
+}
+
+/// Whether content starts with a text encoding byte for frames with this ID, following the
+/// content types the id3 crate decodes
+fn has_encoding(id: &[u8]) -> bool {
+    id.starts_with(b"T")
+        || matches!(
+            id,
+            b"WXXX"
+                | b"WXX"
+                | b"COMM"
+                | b"COM"
+                | b"USLT"
+                | b"ULT"
+                | b"SYLT"
+                | b"SLT"
+                | b"APIC"
+                | b"PIC"
+                | b"GEOB"
+                | b"GEO"
+                | b"IPLS"
+                | b"IPL"
+                | b"GRP1"
+        )
+}
+
+fn encoding_from_byte(byte: u8) -> Option<Encoding> {
+    match byte {
+        0 => Some(Encoding::Latin1),
+        1 => Some(Encoding::UTF16),
+        2 => Some(Encoding::UTF16BE),
+        3 => Some(Encoding::UTF8),
+        _ => None,
+    }
+}
+
+/// Where the frames embedded in a chapter or table of contents start in its content
+fn embedded_frames_start(id: &[u8], content: &[u8]) -> Option<usize> {
+    let after_element_id = content.iter().position(|byte| *byte == 0)? + 1;
+    match id {
+        // Start and end times and offsets
+        b"CHAP" => Some(after_element_id + 16),
+        // Flags, then a count of child element IDs
+        b"CTOC" => {
+            let count = *content.get(after_element_id + 1)?;
+            let mut position = after_element_id + 2;
+            for _ in 0..count {
+                position += content
+                    .get(position..)?
+                    .iter()
+                    .position(|byte| *byte == 0)?
+                    + 1;
+            }
+            Some(position)
+        }
+        _ => None,
+    }
+}
+
+/// Reads the status flags and text encoding of a stored frame, header included
+fn stored_info(major: u8, frame: &[u8]) -> StoredInfo {
+    if major == 2 {
+        // ID3v2.2 frames have no flags
+        let encoding = match has_encoding(&frame[..3]) {
+            true => frame.get(6).copied().and_then(encoding_from_byte),
+            false => None,
+        };
+        return StoredInfo {
+            encoding,
+            ..StoredInfo::default()
+        };
+    }
+
+    let id = &frame[..4];
+    let (status, format) = (frame[8], frame[9]);
+    let (tag_alter, file_alter, hidden, extensions) = match major {
+        3 => (
+            0x80,
+            0x40,
+            V3_COMPRESSION_OR_ENCRYPTION,
+            V3_HEADER_EXTENSIONS,
+        ),
+        _ => (
+            0x40,
+            0x20,
+            V4_COMPRESSION_OR_ENCRYPTION,
+            V4_HEADER_EXTENSIONS,
+        ),
+    };
+    let mut info = StoredInfo {
+        tag_alter_preservation: status & tag_alter != 0,
+        file_alter_preservation: status & file_alter != 0,
+        ..StoredInfo::default()
+    };
+    if format & hidden != 0 {
+        return info;
+    }
+
+    let start: usize = 10
+        + extensions
+            .iter()
+            .filter(|(flag, _)| format & flag != 0)
+            .map(|(_, len)| len)
+            .sum::<usize>();
+    let content = frame.get(start..).unwrap_or_default();
+    let content = match major == 4 && format & V4_UNSYNCHRONISATION != 0 {
+        true => id3_header::decode_unsynchronisation(content),
+        false => content.to_vec(),
+    };
+
+    if has_encoding(id) {
+        info.encoding = content.first().copied().and_then(encoding_from_byte);
+    }
+    if let Some(embedded_start) = embedded_frames_start(id, &content) {
+        let embedded = content.get(embedded_start..).unwrap_or_default();
+        info.embedded = id3_header::split_frames(major, embedded)
+            .into_iter()
+            .map(|frame| stored_info(major, frame))
+            .collect();
+    }
+    info
+}
+
+/// Gives a frame decoded by the id3 crate back what it dropped. Embedded frames are only
+/// matched up when the id3 crate decoded all of them
+fn restore(frame: &Frame, info: &StoredInfo) -> Frame {
+    let restore_all = |frames: &[Frame]| -> Vec<Frame> {
+        frames
+            .iter()
+            .zip(&info.embedded)
+            .map(|(frame, info)| restore(frame, info))
+            .collect()
+    };
+    let content = match frame.content() {
+        Content::Chapter(chapter) if chapter.frames.len() == info.embedded.len() => {
+            Content::Chapter(Chapter {
+                frames: restore_all(&chapter.frames),
+                ..chapter.clone()
+            })
+        }
+        Content::TableOfContents(toc) if toc.frames.len() == info.embedded.len() => {
+            Content::TableOfContents(TableOfContents {
+                frames: restore_all(&toc.frames),
+                ..toc.clone()
+            })
+        }
+        content => content.clone(),
+    };
+
+    let mut restored =
+        Frame::with_content(frame.id(), content).set_encoding(info.encoding.or(frame.encoding()));
+    restored.set_tag_alter_preservation(info.tag_alter_preservation);
+    restored.set_file_alter_preservation(info.file_alter_preservation);
+    restored
+}
+
+/// Decodes a stored frame with the id3 crate, as the only frame of a tag of its version
+fn decode(major: u8, frame: &[u8]) -> Result<Option<Frame>, TagError> {
+    let size = frame.len() as u32;
+    let mut tag = b"ID3".to_vec();
+    tag.extend_from_slice(&[major, 0, 0]);
+    tag.extend((0..4).rev().map(|i| ((size >> (7 * i)) & 0x7f) as u8));
+    tag.extend_from_slice(frame);
+
+    Ok(Tag::read_from2(Cursor::new(tag))?.frames().next().cloned())
+}
+
+/// Reads the frames of a whole tag, header included, in the order they are stored. Frames
+/// the id3 crate would replace with a later one of the same ID and description are kept
+pub fn read(bytes: &[u8]) -> Result<(Version, Vec<Frame>), TagError> {
+    let (major, data) = id3_header::frame_data(bytes)
+        .ok_or_else(|| TagError::new(ErrorCode::Parse, "The ID3v2 tag header is malformed"))?;
+    let version = match major {
+        2 => Version::Id3v22,
+        3 => Version::Id3v23,
+        _ => Version::Id3v24,
+    };
+
+    let id_len = if major == 2 { 3 } else { 4 };
+    let mut frames = Vec::new();
+    for (i, stored) in (0u32..).zip(id3_header::split_frames(major, &data)) {
+        let frame = decode(major, stored)
+            .map_err(|error| error.with_frame(i, &String::from_utf8_lossy(&stored[..id_len])))?;
+        if let Some(frame) = frame {
+            frames.push(restore(&frame, &stored_info(major, stored)));
+        }
+    }
+
+    Ok((version, frames))
+}
+
+/// Reads the frames of the ID3v2 tag of the file at path, see `read`. Files without a tag
+/// have no frames and are written as ID3v2.4
+pub fn read_from_path(path: &str) -> Result<(Version, Vec<Frame>), TagError> {
+    match id3_header::read_tag_from_path(path)? {
+        Some(bytes) => read(&bytes).map_err(|error| error.with_path(path)),
+        None => Ok((Version::Id3v24, Vec::new())),
+    }
+}
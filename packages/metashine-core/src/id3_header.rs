@@ -0,0 +1,254 @@
+//! Reading the ID3v2 tag header the id3 crate does not expose: version, flags, size and padding,
+//! and splitting the tag into its stored frames
+
+use std::{
+    fs::File,
+    io::{self, Cursor, Read, Seek, SeekFrom},
+};
+
+use crate::{error::TagError, flac, hash};
+
+const FLAG_UNSYNCHRONISATION: u8 = 0x80;
+const FLAG_EXTENDED_HEADER: u8 = 0x40;
+const FLAG_EXPERIMENTAL: u8 = 0x20;
+const FLAG_FOOTER: u8 = 0x10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeaderFlags {
+    pub unsynchronisation: bool,
+    /// In ID3v2.2 this bit marks a compressed tag instead
+    pub extended_header: bool,
+    pub experimental: bool,
+    pub footer: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Id3Header {
+    /// Major version: 2, 3 or 4
+    pub major: u8,
+    pub revision: u8,
+    pub flags: HeaderFlags,
+    /// Size of the whole tag including its header and footer
+    pub tag_size: usize,
+    /// Zero bytes between the last frame and the end of the tag
+    pub padding_size: usize,
+}
+
+impl Id3Header {
+    pub fn version_name(&self) -> String {
+        format!("2.{}", self.major)
+    }
+}
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0usize, |size, byte| (size << 7) | (*byte & 0x7f) as usize)
+}
+
+fn big_endian(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0usize, |size, byte| (size << 8) | *byte as usize)
+}
+
+/// Reverses unsynchronisation, dropping the zero byte inserted after every 0xFF
+pub fn decode_unsynchronisation(bytes: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut previous = 0u8;
+    for byte in bytes {
+        if !(previous == 0xff && *byte == 0) {
+            decoded.push(*byte);
+        }
+        previous = *byte;
+    }
+    decoded
+}
+
+/// Splits the frames at the start of frame data into the bytes of each frame, headers
+/// included, stopping at padding or at a frame running past the end of the data
+pub fn split_frames(major: u8, data: &[u8]) -> Vec<&[u8]> {
+    let (header_len, id_len) = if major == 2 { (6, 3) } else { (10, 4) };
+    let mut frames = Vec::new();
+    let mut position = 0;
+
+    while position + header_len <= data.len() && data[position] != 0 {
+        // Sizes are as wide as IDs
+        let size_bytes = &data[position + id_len..position + 2 * id_len];
+        let size = match major {
+            4 => syncsafe(size_bytes),
+            _ => big_endian(size_bytes),
+        };
+
+        let end = position + header_len + size;
+        if end > data.len() {
+            break;
+        }
+        frames.push(&data[position..end]);
+        position = end;
+    }
+
+    frames
+}
+
+/// Returns the major version and frame data of a tag starting at the beginning of bytes,
+/// which must hold the whole tag. Frame data is what follows the header and extended
+/// header, padding included, with the unsynchronisation of ID3v2.2 and ID3v2.3 tags reversed
+pub fn frame_data(bytes: &[u8]) -> Option<(u8, Vec<u8>)> {
+    if bytes.len() < 10 || &bytes[..3] != b"ID3" || !(2..=4).contains(&bytes[3]) {
+        return None;
+    }
+
+    let major = bytes[3];
+    let flags = bytes[5];
+    let size = syncsafe(&bytes[6..10]);
+
+    let body = &bytes[10..bytes.len().min(10 + size)];
+    let body = if flags & FLAG_UNSYNCHRONISATION != 0 && major < 4 {
+        decode_unsynchronisation(body)
+    } else {
+        body.to_vec()
+    };
+
+    // The extended header is not part of the frames
+    let extended_len = match (major, flags & FLAG_EXTENDED_HEADER != 0) {
+        (3, true) if body.len() >= 4 => big_endian(&body[..4]) + 4,
+        (4, true) if body.len() >= 4 => syncsafe(&body[..4]),
+        _ => 0,
+    }
+    .min(body.len());
+
+    Some((major, body[extended_len..].to_vec()))
+}
+
+/// Parses the header of a tag starting at the beginning of bytes, which must hold the whole tag
+pub fn parse(bytes: &[u8]) -> Option<Id3Header> {
+    let (major, frames) = frame_data(bytes)?;
+    let flags = bytes[5];
+    let size = syncsafe(&bytes[6..10]);
+    let flags = HeaderFlags {
+        unsynchronisation: flags & FLAG_UNSYNCHRONISATION != 0,
+        extended_header: flags & FLAG_EXTENDED_HEADER != 0,
+        experimental: flags & FLAG_EXPERIMENTAL != 0,
+        footer: major == 4 && flags & FLAG_FOOTER != 0,
+    };
+    let frames_len: usize = split_frames(major, &frames)
+        .iter()
+        .map(|frame| frame.len())
+        .sum();
+
+    Some(Id3Header {
+        major,
+        revision: bytes[4],
+        flags,
+        tag_size: 10 + size + if flags.footer { 10 } else { 0 },
+        padding_size: frames.len() - frames_len,
+    })
+}
+
+/// Finds the offset of an ID3 chunk in a RIFF (WAV) or FORM (AIFF) stream
+fn find_chunk(reader: &mut (impl Read + Seek), little_endian: bool) -> io::Result<Option<u64>> {
+    let stream_len = reader.seek(SeekFrom::End(0))?;
+    let mut position = 12u64;
+
+    while position + 8 <= stream_len {
+        reader.seek(SeekFrom::Start(position))?;
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
+
+        let size_bytes = [header[4], header[5], header[6], header[7]];
+        let size = if little_endian {
+            u32::from_le_bytes(size_bytes)
+        } else {
+            u32::from_be_bytes(size_bytes)
+        } as u64;
+
+        if header[..4].eq_ignore_ascii_case(b"id3 ") {
+            return Ok(Some(position + 8));
+        }
+        position += 8 + size + size % 2;
+    }
+
+    Ok(None)
+}
+
+/// The chunks of a RIFF (WAV) or FORM (AIFF) stream but its ID3 chunks, headers included
+fn chunks_without_id3(bytes: &[u8], little_endian: bool) -> Vec<u8> {
+    let mut chunks = bytes[8..12].to_vec();
+    let mut position = 12;
+
+    while position + 8 <= bytes.len() {
+        let size_bytes = [
+            bytes[position + 4],
+            bytes[position + 5],
+            bytes[position + 6],
+            bytes[position + 7],
+        ];
+        let size = if little_endian {
+            u32::from_le_bytes(size_bytes)
+        } else {
+            u32::from_be_bytes(size_bytes)
+        } as usize;
+        let end = (position + 8 + size + size % 2).min(bytes.len());
+
+        if !bytes[position..position + 4].eq_ignore_ascii_case(b"id3 ") {
+            chunks.extend_from_slice(&bytes[position..end]);
+        }
+        position = end;
+    }
+
+    chunks
+}
+
+/// SHA-256 digest of what the id3 crate must leave untouched when writing a tag: the stream
+/// after the ID3v2 tag, or the chunks of a WAV or AIFF stream but its ID3 chunk
+pub fn audio_hash(bytes: &[u8]) -> [u8; 32] {
+    match bytes.get(..4) {
+        Some(b"RIFF") if bytes.len() >= 12 => hash::sha256(&chunks_without_id3(bytes, true)),
+        Some(b"FORM") if bytes.len() >= 12 => hash::sha256(&chunks_without_id3(bytes, false)),
+        _ => hash::sha256(&bytes[flac::id3v2_len(bytes).min(bytes.len())..]),
+    }
+}
+
+/// Reads the ID3v2 tag of a stream, at its start or in a WAV or AIFF chunk, header included
+fn read_tag(mut reader: impl Read + Seek) -> io::Result<Option<Vec<u8>>> {
+    let mut head = [0u8; 12];
+    let read = reader.read(&mut head)?;
+    let start = match &head[..read.min(4)] {
+        b"RIFF" => find_chunk(&mut reader, true)?,
+        b"FORM" => find_chunk(&mut reader, false)?,
+        _ => Some(0),
+    };
+    let start = match start {
+        Some(start) => start,
+        None => return Ok(None),
+    };
+
+    reader.seek(SeekFrom::Start(start))?;
+    let mut header = [0u8; 10];
+    if reader.read_exact(&mut header).is_err() || &header[..3] != b"ID3" {
+        return Ok(None);
+    }
+
+    let size = syncsafe(&header[6..10]);
+    let mut bytes = header.to_vec();
+    reader.take(size as u64).read_to_end(&mut bytes)?;
+    Ok(Some(bytes))
+}
+
+/// Reads the whole ID3v2 tag of the file at path, header included
+pub fn read_tag_from_path(path: &str) -> Result<Option<Vec<u8>>, TagError> {
+    File::open(path)
+        .and_then(read_tag)
+        .map_err(|error| TagError::from(error).with_path(path))
+}
+
+/// Reads the header of the ID3v2 tag of the file at path
+pub fn read_from_path(path: &str) -> Result<Option<Id3Header>, TagError> {
+    Ok(read_tag_from_path(path)?.and_then(|bytes| parse(&bytes)))
+}
+
+/// Reads the header of the ID3v2 tag of a file held in memory
+pub fn read_from_bytes(bytes: &[u8]) -> Result<Option<Id3Header>, TagError> {
+    Ok(read_tag(Cursor::new(bytes))?.and_then(|bytes| parse(&bytes)))
+}
@@ -0,0 +1,169 @@
+//! Choosing the ID3v2 version a tag is written in and translating frames between versions
+
+use id3::{
+    frame::{InvolvedPeopleList, InvolvedPeopleListItem},
+    Content, Frame, Tag, TagLike, Timestamp, Version,
+};
+
+use crate::error::{ErrorCode, TagError};
+
+/// The version `updateTag` writes ID3 tags in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteVersion {
+    /// Keep the version the file was read with, ID3v2.4 for new tags
+    Preserve,
+    Version(Version),
+}
+
+impl WriteVersion {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "preserve" => Some(WriteVersion::Preserve),
+            "2.2" => Some(WriteVersion::Version(Version::Id3v22)),
+            "2.3" => Some(WriteVersion::Version(Version::Id3v23)),
+            "2.4" => Some(WriteVersion::Version(Version::Id3v24)),
+            _ => None,
+        }
+    }
+
+    pub fn resolve(&self, tag: &Tag) -> Version {
+        match self {
+            WriteVersion::Preserve => tag.version(),
+            WriteVersion::Version(version) => *version,
+        }
+    }
+}
+
+fn text<'t>(tag: &'t Tag, id: &str) -> Option<&'t str> {
+    tag.get(id).and_then(|frame| frame.content().text())
+}
+
+/// Reads the `DDMM` or `HHMM` value of a TDAT or TIME frame
+fn two_pairs(value: &str) -> Option<(u8, u8)> {
+    let value = value.trim();
+    if value.len() != 4 || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    Some((value[..2].parse().ok()?, value[2..].parse().ok()?))
+}
+
+fn involved_people(tag: &Tag, ids: &[&str]) -> Vec<InvolvedPeopleListItem> {
+    tag.frames()
+        .filter(|frame| ids.contains(&frame.id()))
+        .filter_map(|frame| match frame.content() {
+            Content::InvolvedPeopleList(list) => Some(list.items.clone()),
+            _ => None,
+        })
+        .flatten()
+        .collect()
+}
+
+fn set_involved_people(tag: &mut Tag, id: &str, items: Vec<InvolvedPeopleListItem>) {
+    tag.add_frame(Frame::with_content(
+        id,
+        Content::InvolvedPeopleList(InvolvedPeopleList { items }),
+    ));
+}
+
+/// Replaces ID3v2.4 frames with their ID3v2.2 and ID3v2.3 counterparts: TDRC with
+/// TYER/TDAT/TIME, TDOR with TORY and TIPL/TMCL with IPLS
+fn downgrade(tag: &mut Tag) {
+    if let Some(recorded) = text(tag, "TDRC").and_then(|value| value.parse::<Timestamp>().ok()) {
+        tag.remove("TDRC");
+        tag.remove("TDAT");
+        tag.remove("TIME");
+        tag.set_text("TYER", format!("{:04}", recorded.year));
+        if let (Some(month), Some(day)) = (recorded.month, recorded.day) {
+            tag.set_text("TDAT", format!("{:02}{:02}", day, month));
+        }
+        if let (Some(hour), Some(minute)) = (recorded.hour, recorded.minute) {
+            tag.set_text("TIME", format!("{:02}{:02}", hour, minute));
+        }
+    }
+
+    if let Some(original) = text(tag, "TDOR").and_then(|value| value.parse::<Timestamp>().ok()) {
+        tag.remove("TDOR");
+        tag.set_text("TORY", format!("{:04}", original.year));
+    }
+
+    let people = involved_people(tag, &["TIPL", "TMCL"]);
+    if !people.is_empty() {
+        let mut items = involved_people(tag, &["IPLS"]);
+        items.extend(people);
+        tag.remove("IPLS");
+        tag.remove("TIPL");
+        tag.remove("TMCL");
+        set_involved_people(tag, "IPLS", items);
+    }
+}
+
+/// Replaces ID3v2.3 frames with their ID3v2.4 counterparts: TYER/TDAT/TIME with TDRC,
+/// TORY with TDOR and IPLS with TIPL
+fn upgrade(tag: &mut Tag) {
+    if let Some(year) = text(tag, "TYER").and_then(|value| value.trim().parse::<i32>().ok()) {
+        let mut recorded = Timestamp {
+            year,
+            month: None,
+            day: None,
+            hour: None,
+            minute: None,
+            second: None,
+        };
+        if let Some((day, month)) = text(tag, "TDAT").and_then(two_pairs) {
+            recorded.month = Some(month);
+            recorded.day = Some(day);
+            if let Some((hour, minute)) = text(tag, "TIME").and_then(two_pairs) {
+                recorded.hour = Some(hour);
+                recorded.minute = Some(minute);
+            }
+        }
+
+        tag.remove("TYER");
+        tag.remove("TDAT");
+        tag.remove("TIME");
+        if tag.get("TDRC").is_none() {
+            tag.set_text("TDRC", recorded.to_string());
+        }
+    }
+
+    if let Some(year) = text(tag, "TORY").and_then(|value| value.trim().parse::<i32>().ok()) {
+        tag.remove("TORY");
+        if tag.get("TDOR").is_none() {
+            tag.set_text("TDOR", format!("{:04}", year));
+        }
+    }
+
+    let people = involved_people(tag, &["IPLS"]);
+    if !people.is_empty() {
+        let mut items = involved_people(tag, &["TIPL"]);
+        items.extend(people);
+        tag.remove("IPLS");
+        tag.remove("TIPL");
+        set_involved_people(tag, "TIPL", items);
+    }
+}
+
+/// Translates the frames of a tag to the version it is about to be written in.
+///
+/// Fails if a frame has no ID3v2.2 counterpart when writing ID3v2.2.
+pub fn translate(tag: &mut Tag, version: Version) -> Result<(), TagError> {
+    match version {
+        Version::Id3v24 => upgrade(tag),
+        Version::Id3v22 | Version::Id3v23 => downgrade(tag),
+    }
+
+    if version == Version::Id3v22 {
+        if let Some((i, frame)) = (0u32..)
+            .zip(tag.frames())
+            .find(|(_, frame)| frame.id_for_version(Version::Id3v22).is_none())
+        {
+            return Err(TagError::new(
+                ErrorCode::UnsupportedFrame,
+                format!("Frame {} can't be stored in ID3v2.2", frame.id()),
+            )
+            .with_frame(i, frame.id()));
+        }
+    }
+
+    Ok(())
+}
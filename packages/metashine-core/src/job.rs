@@ -0,0 +1,98 @@
+//! Progress and cancellation of batch operations running on the worker pool
+
+use std::{
+    fs,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+};
+
+use crate::{
+    error::{ErrorCode, TagError},
+    pool,
+};
+
+/// Progress of a job after one of its files is finished
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub files_done: usize,
+    pub files_total: usize,
+    pub bytes_processed: u64,
+}
+
+/// Called with the path of every file a job finishes and the progress after it
+pub type ProgressCallback = Box<dyn Fn(&str, &Progress) + Send + Sync>;
+
+/// State shared by the files of a batch operation and its JavaScript handle.
+///
+/// Cancellation is only checked before a file is started, so a file being written is always
+/// finished.
+pub struct Job {
+    cancelled: AtomicBool,
+    files_total: usize,
+    files_done: AtomicUsize,
+    bytes_processed: AtomicU64,
+    on_progress: Option<ProgressCallback>,
+}
+
+impl Job {
+    pub fn new(files_total: usize) -> Self {
+        Job {
+            cancelled: AtomicBool::new(false),
+            files_total,
+            files_done: AtomicUsize::new(0),
+            bytes_processed: AtomicU64::new(0),
+            on_progress: None,
+        }
+    }
+
+    pub fn with_progress(mut self, on_progress: ProgressCallback) -> Self {
+        self.on_progress = Some(on_progress);
+        self
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Fails with `ERR_CANCELLED` if the job was cancelled before the file at path started
+    pub fn check(&self, path: &str) -> Result<(), TagError> {
+        match self.cancelled.load(Ordering::SeqCst) {
+            true => {
+                Err(TagError::new(ErrorCode::Cancelled, "The job was cancelled").with_path(path))
+            }
+            false => Ok(()),
+        }
+    }
+
+    /// Records that the file at path is finished, counting its size as processed
+    pub fn finish(&self, path: &str) -> Progress {
+        let size = fs::metadata(path)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+
+        Progress {
+            files_done: self.files_done.fetch_add(1, Ordering::SeqCst) + 1,
+            files_total: self.files_total,
+            bytes_processed: self.bytes_processed.fetch_add(size, Ordering::SeqCst) + size,
+        }
+    }
+
+    /// Works on the file at path unless the job was cancelled, then reports the progress.
+    /// Panics become `ERR_INTERNAL` errors of the file
+    pub fn run<T>(
+        &self,
+        path: &str,
+        f: impl FnOnce() -> Result<T, TagError>,
+    ) -> Result<T, TagError> {
+        self.check(path)?;
+        let result = pool::catch(f).map_err(|error| match error.path {
+            Some(_) => error,
+            None => error.with_path(path),
+        });
+        let progress = self.finish(path);
+
+        if let Some(on_progress) = &self.on_progress {
+            on_progress(path, &progress);
+        }
+        result
+    }
+}
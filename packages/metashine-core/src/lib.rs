@@ -0,0 +1,34 @@
+//! Reading and writing the tags and audio properties of MP3, FLAC, Ogg, MP4 and WAV files.
+//!
+//! Frames of every tag format are exchanged as [`Carrier`]s holding ID3 contents, see the
+//! [`carrier`] module. [`tags`] loads, previews and updates whole tags, from paths or from
+//! files held in memory, and everything reports failures as a [`TagError`].
+
+pub mod atomic;
+pub mod carrier;
+pub mod chapters;
+pub mod decode;
+pub mod document;
+pub mod error;
+pub mod flac;
+pub mod format;
+pub mod frame_names;
+pub mod hash;
+pub mod id3_frames;
+pub mod id3_header;
+pub mod id3_version;
+pub mod job;
+pub mod loudness;
+pub mod lrc;
+pub mod mp4;
+pub mod mpeg;
+pub mod ogg;
+pub mod pool;
+pub mod popularimeter;
+pub mod tags;
+pub mod vorbis;
+
+pub use id3;
+
+pub use carrier::{Carrier, FrameKind};
+pub use error::{ErrorCode, TagError};
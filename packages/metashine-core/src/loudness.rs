@@ -0,0 +1,345 @@
+//! Loudness measurement per ITU-R BS.1770 and EBU R128, and the ReplayGain 2.0 gains written
+//! from it
+
+use std::f64::consts::PI;
+
+use id3::{frame::ExtendedText, Content};
+use symphonia::core::audio::Channels;
+
+use crate::{
+    atomic::SaveOptions,
+    carrier::Carrier,
+    decode,
+    error::TagError,
+    format::{self, TagFormat},
+    id3_version::WriteVersion,
+    tags::{self, UpdatedTag},
+};
+
+/// The level ReplayGain 2.0 gains bring tracks to, in LUFS
+const REPLAYGAIN_REFERENCE: f64 = -18.0;
+
+/// Gating blocks are 400 ms long and start every 100 ms
+const SUB_BLOCKS_PER_BLOCK: usize = 4;
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+/// Loudness of a mean square, in LUFS
+fn to_lufs(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+/// A second order IIR filter in transposed direct form II
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+}
+
+impl Biquad {
+    fn process(&self, state: &mut [f64; 2], x: f64) -> f64 {
+        let y = self.b[0] * x + state[0];
+        state[0] = self.b[1] * x - self.a[1] * y + state[1];
+        state[1] = self.b[2] * x - self.a[2] * y;
+        y
+    }
+}
+
+/// The two stages of the K-weighting filter, a high shelf modelling the head followed by a
+/// high pass, derived from their analog prototypes for any sample rate
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = sample_rate as f64;
+
+    let f0 = 1681.974450955533;
+    let gain = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+
+    [shelf, high_pass]
+}
+
+/// Interpolates a channel to find peaks between samples, with a windowed sinc polyphase filter
+struct Oversampler {
+    /// For every output phase, the taps as (delay, coefficient)
+    phases: Vec<Vec<(usize, f64)>>,
+    history: Vec<f64>,
+    position: usize,
+}
+
+impl Oversampler {
+    const TAPS: usize = 49;
+
+    fn new(factor: usize) -> Self {
+        let mut phases = vec![Vec::new(); factor];
+        for j in 0..Self::TAPS {
+            let m = j as f64 - (Self::TAPS - 1) as f64 / 2.0;
+            let sinc = match m == 0.0 {
+                true => 1.0,
+                false => (m * PI / factor as f64).sin() / (m * PI / factor as f64),
+            };
+            let window = 0.5 * (1.0 - (2.0 * PI * j as f64 / (Self::TAPS - 1) as f64).cos());
+            let coefficient = sinc * window;
+            if coefficient.abs() > 1e-9 {
+                phases[j % factor].push((j / factor, coefficient));
+            }
+        }
+
+        Oversampler {
+            phases,
+            history: vec![0.0; Self::TAPS.div_ceil(factor)],
+            position: 0,
+        }
+    }
+
+    /// Adds a sample, returning the largest absolute value of the interpolated ones it yields
+    fn peak(&mut self, x: f64) -> f64 {
+        let len = self.history.len();
+        self.history[self.position] = x;
+
+        let mut peak = 0f64;
+        for taps in &self.phases {
+            let y: f64 = taps
+                .iter()
+                .map(|(delay, coefficient)| {
+                    coefficient * self.history[(self.position + len - delay) % len]
+                })
+                .sum();
+            peak = peak.max(y.abs());
+        }
+
+        self.position = (self.position + 1) % len;
+        peak
+    }
+}
+
+/// Weight of a channel in the sum of channel energies, surround channels count 1.5 dB more and
+/// the LFE channel is left out
+fn channel_weight(channel: Channels) -> f64 {
+    if channel == Channels::LFE1 || channel == Channels::LFE2 {
+        0.0
+    } else if channel == Channels::SIDE_LEFT
+        || channel == Channels::SIDE_RIGHT
+        || channel == Channels::REAR_LEFT
+        || channel == Channels::REAR_RIGHT
+    {
+        1.41
+    } else {
+        1.0
+    }
+}
+
+/// Measures the loudness of interleaved samples as they are decoded
+struct Meter {
+    weights: Vec<f64>,
+    filters: [Biquad; 2],
+    /// The state of both filter stages for every channel
+    filter_states: Vec<[[f64; 2]; 2]>,
+    /// True peaks are found at 4 times the sample rate below 96 kHz and at twice the sample
+    /// rate below 192 kHz
+    oversamplers: Vec<Oversampler>,
+    sub_block_len: usize,
+    sub_block_frames: usize,
+    sub_block_energy: f64,
+    /// Weighted sums of squares of the finished 100 ms sub-blocks
+    sub_blocks: Vec<f64>,
+    sample_peak: f64,
+    true_peak: f64,
+}
+
+impl Meter {
+    fn new(spec: &decode::StreamSpec) -> Self {
+        let channels = spec.channels.len();
+        let factor = match spec.sample_rate {
+            rate if rate < 96000 => 4,
+            rate if rate < 192000 => 2,
+            _ => 1,
+        };
+
+        Meter {
+            weights: spec.channels.iter().copied().map(channel_weight).collect(),
+            filters: k_weighting(spec.sample_rate),
+            filter_states: vec![[[0.0; 2]; 2]; channels],
+            oversamplers: (0..channels).map(|_| Oversampler::new(factor)).collect(),
+            sub_block_len: ((spec.sample_rate as f64 / 10.0).round() as usize).max(1),
+            sub_block_frames: 0,
+            sub_block_energy: 0.0,
+            sub_blocks: Vec::new(),
+            sample_peak: 0.0,
+            true_peak: 0.0,
+        }
+    }
+
+    fn add(&mut self, samples: &[f32]) {
+        let channels = self.weights.len();
+        for frame in samples.chunks_exact(channels) {
+            for (channel, sample) in frame.iter().enumerate() {
+                let x = *sample as f64;
+                self.sample_peak = self.sample_peak.max(x.abs());
+                self.true_peak = self.true_peak.max(self.oversamplers[channel].peak(x));
+
+                let state = &mut self.filter_states[channel];
+                let y = self.filters[0].process(&mut state[0], x);
+                let y = self.filters[1].process(&mut state[1], y);
+                self.sub_block_energy += self.weights[channel] * y * y;
+            }
+
+            self.sub_block_frames += 1;
+            if self.sub_block_frames == self.sub_block_len {
+                self.sub_blocks.push(self.sub_block_energy);
+                self.sub_block_frames = 0;
+                self.sub_block_energy = 0.0;
+            }
+        }
+    }
+
+    fn finish(self) -> Loudness {
+        let block_len = (SUB_BLOCKS_PER_BLOCK * self.sub_block_len) as f64;
+        let blocks = self
+            .sub_blocks
+            .windows(SUB_BLOCKS_PER_BLOCK)
+            .map(|sub_blocks| sub_blocks.iter().sum::<f64>() / block_len)
+            .collect();
+
+        Loudness {
+            blocks,
+            sample_peak: self.sample_peak,
+            true_peak: self.true_peak.max(self.sample_peak),
+        }
+    }
+}
+
+/// The measurements of a track or album
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Loudness {
+    /// Mean square of the K-weighted channels of each gating block
+    blocks: Vec<f64>,
+    /// Largest absolute sample value, 1.0 being full scale
+    pub sample_peak: f64,
+    /// Largest absolute value of the signal between samples, 1.0 being full scale
+    pub true_peak: f64,
+}
+
+impl Loudness {
+    /// Measures an album as one track made of all of its tracks
+    pub fn album<'l>(tracks: impl IntoIterator<Item = &'l Loudness>) -> Loudness {
+        tracks
+            .into_iter()
+            .fold(Loudness::default(), |mut album, track| {
+                album.blocks.extend_from_slice(&track.blocks);
+                album.sample_peak = album.sample_peak.max(track.sample_peak);
+                album.true_peak = album.true_peak.max(track.true_peak);
+                album
+            })
+    }
+
+    /// Integrated loudness in LUFS, gated as EBU R128 specifies. None for silence and for
+    /// tracks shorter than one gating block
+    pub fn integrated(&self) -> Option<f64> {
+        let mean = |blocks: &[f64]| match blocks.len() {
+            0 => None,
+            len => Some(blocks.iter().sum::<f64>() / len as f64),
+        };
+
+        let audible: Vec<f64> = self
+            .blocks
+            .iter()
+            .copied()
+            .filter(|energy| to_lufs(*energy) > ABSOLUTE_GATE)
+            .collect();
+        let threshold = to_lufs(mean(&audible)?) + RELATIVE_GATE;
+        let gated: Vec<f64> = audible
+            .into_iter()
+            .filter(|energy| to_lufs(*energy) > threshold)
+            .collect();
+
+        mean(&gated).map(to_lufs)
+    }
+
+    /// The ReplayGain 2.0 gain in dB
+    pub fn replaygain(&self) -> Option<f64> {
+        self.integrated()
+            .map(|loudness| REPLAYGAIN_REFERENCE - loudness)
+    }
+}
+
+/// Decodes the file at path and measures its loudness
+pub fn analyze(path: &str) -> Result<Loudness, TagError> {
+    decode::decode(path, Meter::new, Meter::add).map(Meter::finish)
+}
+
+/// Builds the carriers setting the gain and peak fields of a tag format.
+///
+/// ID3 tags get REPLAYGAIN_* user defined text frames, MP4 files REPLAYGAIN_* freeform atoms
+/// and Vorbis comments REPLAYGAIN_* fields. RVA2 frames are out of scope: players read the
+/// TXXX frames instead. So are the R128_* fields of Opus, whose audio can't be decoded.
+fn gain_carriers(format: TagFormat, track: &Loudness, album: Option<&Loudness>) -> Vec<Carrier> {
+    let mut fields = Vec::new();
+    let mut add = |name: &str, loudness: &Loudness| {
+        if let Some(gain) = loudness.replaygain() {
+            fields.push((
+                format!("REPLAYGAIN_{}_GAIN", name),
+                format!("{:.2} dB", gain),
+            ));
+            fields.push((
+                format!("REPLAYGAIN_{}_PEAK", name),
+                format!("{:.6}", loudness.true_peak),
+            ));
+        }
+    };
+    add("TRACK", track);
+    if let Some(album) = album {
+        add("ALBUM", album);
+    }
+
+    fields
+        .into_iter()
+        .map(|(name, value)| match format {
+            TagFormat::Vorbis => Carrier::new(name, Content::Text(value)),
+            TagFormat::Id3 | TagFormat::Mp4 => Carrier::new(
+                "TXXX",
+                Content::ExtendedText(ExtendedText {
+                    description: name,
+                    value,
+                }),
+            ),
+        })
+        .collect()
+}
+
+/// Writes the track and optionally album gains to the tag of the file at path. Nothing is
+/// written for silent tracks, returning None
+pub fn write_gains(
+    path: &str,
+    track: &Loudness,
+    album: Option<&Loudness>,
+    write_version: WriteVersion,
+    save: SaveOptions,
+) -> Result<Option<UpdatedTag>, TagError> {
+    if track.integrated().is_none() {
+        return Ok(None);
+    }
+
+    let format = format::detect(path)?.tag_format();
+    let mods = gain_carriers(format, track, album);
+    tags::update(path, &mods, write_version, save).map(Some)
+}
@@ -0,0 +1,106 @@
+//! Conversion between synchronised lyrics (SYLT) and LRC text
+
+/// Parses LRC text into `(milliseconds, text)` pairs sorted by time.
+///
+/// Lines with several timestamps (`[00:12.00][00:45.10]Chorus`) produce one pair per timestamp
+/// and an `[offset:±ms]` tag is applied to every timestamp. Other ID tags are ignored.
+pub fn parse(lrc: &str) -> Vec<(u32, String)> {
+    let mut offset: i64 = 0;
+    let mut lines: Vec<(i64, String)> = Vec::new();
+
+    for line in lrc.lines() {
+        let mut rest = line.trim();
+        let mut timestamps = Vec::new();
+
+        while let Some(tag) = rest.strip_prefix('[') {
+            let end = match tag.find(']') {
+                Some(end) => end,
+                None => break,
+            };
+            let (tag, remainder) = (&tag[..end], &tag[end + 1..]);
+
+            if let Some(time) = parse_timestamp(tag) {
+                timestamps.push(time);
+            } else if let Some(value) = tag.strip_prefix("offset:") {
+                offset = value.trim().parse().unwrap_or(0);
+            }
+
+            rest = remainder;
+        }
+
+        for time in timestamps {
+            lines.push((time, rest.trim().to_string()));
+        }
+    }
+
+    // A positive offset makes lyrics appear sooner
+    let mut lines: Vec<(u32, String)> = lines
+        .into_iter()
+        .map(|(time, text)| ((time - offset).clamp(0, u32::MAX as i64) as u32, text))
+        .collect();
+    lines.sort_by_key(|(time, _)| *time);
+    lines
+}
+
+/// Parses `mm:ss`, `mm:ss.xx`, `mm:ss.xxx` and `mm:ss:xx` into milliseconds
+fn parse_timestamp(tag: &str) -> Option<i64> {
+    let (minutes, seconds) = tag.split_once(':')?;
+    let minutes: i64 = minutes.trim().parse().ok()?;
+
+    let (seconds, fraction) = match seconds.find(['.', ':']) {
+        Some(i) => (&seconds[..i], &seconds[i + 1..]),
+        None => (seconds, ""),
+    };
+    let seconds: i64 = seconds.trim().parse().ok()?;
+    if !fraction.chars().all(|c| c.is_ascii_digit()) || fraction.len() > 3 {
+        return None;
+    }
+    let fraction: i64 = match fraction.len() {
+        0 => 0,
+        1 => fraction.parse::<i64>().ok()? * 100,
+        2 => fraction.parse::<i64>().ok()? * 10,
+        _ => fraction.parse().ok()?,
+    };
+
+    Some((minutes * 60 + seconds) * 1000 + fraction)
+}
+
+fn format_timestamp(ms: u32) -> String {
+    let centiseconds = (ms + 5) / 10;
+    format!(
+        "[{:02}:{:02}.{:02}]",
+        centiseconds / 6000,
+        centiseconds / 100 % 60,
+        centiseconds % 100
+    )
+}
+
+/// Formats `(milliseconds, text)` pairs as LRC text.
+///
+/// A non-zero offset is written as an `[offset:]` tag and subtracted from every timestamp so the
+/// lyrics play back at the same times. With `compact` lines with identical text are merged into
+/// a single line with several timestamps.
+pub fn format(content: &[(u32, String)], offset: i32, compact: bool) -> String {
+    let mut lines: Vec<(Vec<u32>, &str)> = Vec::new();
+
+    for (time, text) in content {
+        let time = (*time as i64 + offset as i64).clamp(0, u32::MAX as i64) as u32;
+        match lines.iter_mut().find(|(_, t)| compact && *t == text) {
+            Some((times, _)) => times.push(time),
+            None => lines.push((vec![time], text)),
+        }
+    }
+
+    let mut lrc = String::new();
+    if offset != 0 {
+        lrc.push_str(&format!("[offset:{:+}]\n", offset));
+    }
+    for (times, text) in lines {
+        for time in times {
+            lrc.push_str(&format_timestamp(time));
+        }
+        lrc.push_str(text);
+        lrc.push('\n');
+    }
+    lrc
+}
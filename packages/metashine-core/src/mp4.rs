@@ -0,0 +1,756 @@
+//! MP4 backend reading and writing the iTunes metadata atoms in `moov/udta/meta/ilst`
+
+use std::{convert::TryInto, fs};
+
+use crate::{
+    error::{ErrorCode, TagError},
+    format::Layout,
+    hash,
+};
+
+/// Data atom type codes, as defined by the QuickTime well-known types
+pub const TYPE_IMPLICIT: u32 = 0;
+pub const TYPE_UTF8: u32 = 1;
+pub const TYPE_UTF16: u32 = 2;
+pub const TYPE_JPEG: u32 = 13;
+pub const TYPE_PNG: u32 = 14;
+pub const TYPE_SIGNED_INT: u32 = 21;
+pub const TYPE_BMP: u32 = 27;
+
+/// Mean of the freeform atoms written by iTunes
+pub const ITUNES_MEAN: &str = "com.apple.iTunes";
+
+/// Atoms whose children lead to the chunk offset tables or the item list
+const CONTAINERS: [&[u8; 4]; 7] = [
+    b"moov", b"trak", b"mdia", b"minf", b"stbl", b"udta", b"meta",
+];
+
+/// Integer atoms and the width of their big-endian value in bytes
+const INTEGER_ATOMS: [(&[u8; 4], usize); 14] = [
+    (b"tmpo", 2),
+    (b"cpil", 1),
+    (b"pgap", 1),
+    (b"pcst", 1),
+    (b"hdvd", 1),
+    (b"shwm", 1),
+    (b"stik", 1),
+    (b"rtng", 1),
+    (b"tves", 4),
+    (b"tvsn", 4),
+    (b"sfID", 4),
+    (b"atID", 4),
+    (b"cnID", 4),
+    (b"plID", 8),
+];
+
+/// Identifies an item of the list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ident {
+    /// An item named by its atom type, like `©nam` or `trkn`
+    Fourcc([u8; 4]),
+    /// A `----` item named by its `mean` and `name` children
+    Freeform { mean: String, name: String },
+}
+
+/// A `data` atom holding one value of an item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataAtom {
+    pub type_code: u32,
+    pub locale: u32,
+    pub value: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    pub ident: Ident,
+    pub data: Vec<DataAtom>,
+}
+
+/// The item list of an MP4 file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mp4Tag {
+    pub items: Vec<Item>,
+}
+
+/// Converts an atom type to a string, reading bytes as Latin-1 so `©` survives
+pub fn fourcc_to_string(fourcc: &[u8; 4]) -> String {
+    fourcc.iter().map(|byte| *byte as char).collect()
+}
+
+pub fn string_to_fourcc(name: &str) -> Option<[u8; 4]> {
+    let bytes: Vec<u8> = name
+        .chars()
+        .map(|c| {
+            if (c as u32) < 0x100 {
+                Some(c as u8)
+            } else {
+                None
+            }
+        })
+        .collect::<Option<_>>()?;
+    bytes.try_into().ok()
+}
+
+impl Ident {
+    /// Parses an item name, either an atom type or `----:mean:name`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.strip_prefix("----:") {
+            Some(freeform) => {
+                let (mean, name) = freeform.split_once(':')?;
+                Some(Ident::Freeform {
+                    mean: mean.to_string(),
+                    name: name.to_string(),
+                })
+            }
+            None => string_to_fourcc(name).map(Ident::Fourcc),
+        }
+    }
+
+    pub fn freeform(name: &str) -> Self {
+        Ident::Freeform {
+            mean: ITUNES_MEAN.to_string(),
+            name: name.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Ident {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ident::Fourcc(fourcc) => write!(f, "{}", fourcc_to_string(fourcc)),
+            Ident::Freeform { mean, name } => write!(f, "----:{}:{}", mean, name),
+        }
+    }
+}
+
+fn integer_width(fourcc: &[u8; 4]) -> Option<usize> {
+    INTEGER_ATOMS
+        .iter()
+        .find(|(atom, _)| *atom == fourcc)
+        .map(|(_, width)| *width)
+}
+
+fn is_pair(fourcc: &[u8; 4]) -> bool {
+    fourcc == b"trkn" || fourcc == b"disk"
+}
+
+/// Encodes a signed big-endian integer in width bytes, or the fewest of 1, 2, 4 and 8 bytes
+/// holding it when the width of the atom is unknown
+fn integer_atom(text: &str, width: Option<usize>) -> Result<DataAtom, String> {
+    let number = text
+        .trim()
+        .parse::<i64>()
+        .map_err(|_| format!("{} is not a number", text))?;
+    let bytes = number.to_be_bytes();
+    let sign = if number < 0 { 0xff } else { 0 };
+    let fits = |width: usize| {
+        bytes[..8 - width].iter().all(|byte| *byte == sign)
+            && (bytes[8 - width] & 0x80 == sign & 0x80)
+    };
+
+    let width = match width {
+        Some(width) if fits(width) => width,
+        Some(width) => return Err(format!("{} does not fit in {} bytes", number, width)),
+        None => [1, 2, 4, 8]
+            .iter()
+            .copied()
+            .find(|width| fits(*width))
+            .unwrap_or(8),
+    };
+    Ok(DataAtom::new(TYPE_SIGNED_INT, bytes[8 - width..].to_vec()))
+}
+
+impl DataAtom {
+    pub fn new(type_code: u32, value: Vec<u8>) -> Self {
+        DataAtom {
+            type_code,
+            locale: 0,
+            value,
+        }
+    }
+
+    /// Reads the value as text: strings, integers, and `number/total` for `trkn` and `disk`
+    pub fn to_text(&self, ident: &Ident) -> Option<String> {
+        match self.type_code {
+            TYPE_UTF8 => String::from_utf8(self.value.clone()).ok(),
+            TYPE_UTF16 => {
+                let units: Vec<u16> = self
+                    .value
+                    .chunks_exact(2)
+                    .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                    .collect();
+                String::from_utf16(&units).ok()
+            }
+            TYPE_SIGNED_INT if matches!(self.value.len(), 1 | 2 | 4 | 8) => {
+                let sign = if self.value[0] & 0x80 != 0 { 0xff } else { 0 };
+                let mut bytes = [sign; 8];
+                bytes[8 - self.value.len()..].copy_from_slice(&self.value);
+                Some(i64::from_be_bytes(bytes).to_string())
+            }
+            TYPE_IMPLICIT => match ident {
+                Ident::Fourcc(fourcc) if is_pair(fourcc) && self.value.len() >= 6 => {
+                    let number = u16::from_be_bytes([self.value[2], self.value[3]]);
+                    let total = u16::from_be_bytes([self.value[4], self.value[5]]);
+                    Some(match total {
+                        0 => number.to_string(),
+                        total => format!("{}/{}", number, total),
+                    })
+                }
+                Ident::Fourcc(fourcc) if fourcc == b"gnre" && self.value.len() == 2 => {
+                    Some(u16::from_be_bytes([self.value[0], self.value[1]]).to_string())
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Encodes text the way iTunes stores the item, or as the given type code when it is one
+    /// `to_text` reads
+    pub fn from_text(ident: &Ident, text: &str, type_code: Option<u32>) -> Result<Self, String> {
+        let fourcc = match ident {
+            Ident::Fourcc(fourcc) => Some(fourcc),
+            Ident::Freeform { .. } => None,
+        };
+
+        match type_code {
+            Some(TYPE_UTF8) => return Ok(DataAtom::new(TYPE_UTF8, text.into())),
+            Some(TYPE_UTF16) => {
+                let value = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+                return Ok(DataAtom::new(TYPE_UTF16, value));
+            }
+            Some(TYPE_SIGNED_INT) => {
+                return integer_atom(text, fourcc.and_then(integer_width));
+            }
+            _ => {}
+        }
+
+        let fourcc = match fourcc {
+            Some(fourcc) => fourcc,
+            None => return Ok(DataAtom::new(TYPE_UTF8, text.into())),
+        };
+
+        if is_pair(fourcc) {
+            let (number, total) = text.split_once('/').unwrap_or((text, "0"));
+            let parse = |part: &str| {
+                part.trim()
+                    .parse::<u16>()
+                    .map_err(|_| format!("{} is not a number/total pair", text))
+            };
+            let (number, total) = (parse(number)?, parse(total)?);
+
+            let mut value = vec![0, 0];
+            value.extend_from_slice(&number.to_be_bytes());
+            value.extend_from_slice(&total.to_be_bytes());
+            if fourcc == b"trkn" {
+                value.extend_from_slice(&[0, 0]);
+            }
+            Ok(DataAtom::new(TYPE_IMPLICIT, value))
+        } else if fourcc == b"gnre" {
+            let genre = text
+                .trim()
+                .parse::<u16>()
+                .map_err(|_| format!("{} is not a genre number", text))?;
+            Ok(DataAtom::new(TYPE_IMPLICIT, genre.to_be_bytes().to_vec()))
+        } else if let Some(width) = integer_width(fourcc) {
+            integer_atom(text, Some(width))
+        } else {
+            Ok(DataAtom::new(TYPE_UTF8, text.into()))
+        }
+    }
+
+    pub fn picture_mime_type(&self) -> Option<&'static str> {
+        match self.type_code {
+            TYPE_JPEG => Some("image/jpeg"),
+            TYPE_PNG => Some("image/png"),
+            TYPE_BMP => Some("image/bmp"),
+            _ => None,
+        }
+    }
+
+    pub fn picture(mime_type: &str, data: Vec<u8>) -> Option<Self> {
+        let type_code = match mime_type {
+            "image/jpeg" | "image/jpg" => TYPE_JPEG,
+            "image/png" => TYPE_PNG,
+            "image/bmp" => TYPE_BMP,
+            _ => return None,
+        };
+        Some(DataAtom::new(type_code, data))
+    }
+}
+
+impl Mp4Tag {
+    /// Replaces the values of an item, adding it at the end if it does not exist
+    pub fn set(&mut self, ident: Ident, data: Vec<DataAtom>) {
+        match self.items.iter_mut().find(|item| item.ident == ident) {
+            Some(item) => item.data = data,
+            None => self.items.push(Item { ident, data }),
+        }
+    }
+
+    /// Adds a value to an item, adding it at the end if it does not exist
+    pub fn push(&mut self, ident: Ident, data: DataAtom) {
+        match self.items.iter_mut().find(|item| item.ident == ident) {
+            Some(item) => item.data.push(data),
+            None => self.items.push(Item {
+                ident,
+                data: vec![data],
+            }),
+        }
+    }
+
+    pub fn remove(&mut self, ident: &Ident) {
+        self.items.retain(|item| item.ident != *ident);
+    }
+}
+
+/// Location of an atom in a byte slice
+struct AtomHeader {
+    kind: [u8; 4],
+    start: usize,
+    body_start: usize,
+    end: usize,
+}
+
+/// Reads the header of the atom at position, which must end before end
+fn read_header(bytes: &[u8], position: usize, end: usize) -> Result<AtomHeader, TagError> {
+    let truncated = || TagError::new(ErrorCode::Parse, "MP4 atom is truncated");
+
+    if position + 8 > end {
+        return Err(truncated());
+    }
+    let size = u32::from_be_bytes([
+        bytes[position],
+        bytes[position + 1],
+        bytes[position + 2],
+        bytes[position + 3],
+    ]) as usize;
+    let mut kind = [0u8; 4];
+    kind.copy_from_slice(&bytes[position + 4..position + 8]);
+
+    let (body_start, size) = match size {
+        // The atom extends to the end of its parent
+        0 => (position + 8, end - position),
+        // A 64-bit size follows the type
+        1 => {
+            if position + 16 > end {
+                return Err(truncated());
+            }
+            let mut large = [0u8; 8];
+            large.copy_from_slice(&bytes[position + 8..position + 16]);
+            (position + 16, u64::from_be_bytes(large) as usize)
+        }
+        size => (position + 8, size),
+    };
+
+    let atom_end = position.checked_add(size).ok_or_else(truncated)?;
+    if atom_end > end || atom_end < body_start {
+        return Err(truncated());
+    }
+
+    Ok(AtomHeader {
+        kind,
+        start: position,
+        body_start,
+        end: atom_end,
+    })
+}
+
+enum Body {
+    Leaf(Vec<u8>),
+    /// Children of a container, after the version and flags of full atoms like `meta`
+    Container {
+        prefix: Vec<u8>,
+        children: Vec<Atom>,
+        /// Bytes after the last child that don't form an atom, like the zero terminator
+        /// QuickTime may end `udta` with
+        trailer: Vec<u8>,
+    },
+}
+
+/// An atom of the `moov` tree
+struct Atom {
+    kind: [u8; 4],
+    body: Body,
+}
+
+impl Atom {
+    fn parse(bytes: &[u8], header: &AtomHeader) -> Result<Self, TagError> {
+        let body = &bytes[header.body_start..header.end];
+
+        if !CONTAINERS.contains(&&header.kind) {
+            return Ok(Atom {
+                kind: header.kind,
+                body: Body::Leaf(body.to_vec()),
+            });
+        }
+
+        // QuickTime files omit the version and flags of `meta`
+        let prefix_len = match &header.kind {
+            b"meta" if body.get(4..8) != Some(b"hdlr") => 4,
+            _ => 0,
+        };
+        if body.len() < prefix_len {
+            return Err(TagError::new(ErrorCode::Parse, "MP4 atom is truncated"));
+        }
+
+        let mut children = Vec::new();
+        let mut position = header.body_start + prefix_len;
+        while position < header.end {
+            let child = match read_header(bytes, position, header.end) {
+                Ok(child) => child,
+                Err(_) if &header.kind == b"udta" => break,
+                Err(error) => return Err(error),
+            };
+            children.push(Atom::parse(bytes, &child)?);
+            position = child.end;
+        }
+
+        Ok(Atom {
+            kind: header.kind,
+            body: Body::Container {
+                prefix: body[..prefix_len].to_vec(),
+                children,
+                trailer: bytes[position.min(header.end)..header.end].to_vec(),
+            },
+        })
+    }
+
+    fn leaf(kind: &[u8; 4], data: Vec<u8>) -> Self {
+        Atom {
+            kind: *kind,
+            body: Body::Leaf(data),
+        }
+    }
+
+    fn container(kind: &[u8; 4], prefix: Vec<u8>, children: Vec<Atom>) -> Self {
+        Atom {
+            kind: *kind,
+            body: Body::Container {
+                prefix,
+                children,
+                trailer: Vec::new(),
+            },
+        }
+    }
+
+    fn child(&self, kind: &[u8; 4]) -> Option<&Atom> {
+        match &self.body {
+            Body::Container { children, .. } => children.iter().find(|child| child.kind == *kind),
+            Body::Leaf(_) => None,
+        }
+    }
+
+    /// Returns the child of a container, appending the one made by create if it is missing
+    fn child_or_insert(&mut self, kind: &[u8; 4], create: impl FnOnce() -> Atom) -> &mut Atom {
+        let children = match &mut self.body {
+            Body::Container { children, .. } => children,
+            Body::Leaf(_) => unreachable!("Only containers have children"),
+        };
+        let position = match children.iter().position(|child| child.kind == *kind) {
+            Some(position) => position,
+            None => {
+                children.push(create());
+                children.len() - 1
+            }
+        };
+        &mut children[position]
+    }
+
+    fn write(&self, output: &mut Vec<u8>) {
+        let start = output.len();
+        output.extend_from_slice(&[0; 4]);
+        output.extend_from_slice(&self.kind);
+
+        match &self.body {
+            Body::Leaf(data) => output.extend_from_slice(data),
+            Body::Container {
+                prefix,
+                children,
+                trailer,
+            } => {
+                output.extend_from_slice(prefix);
+                for child in children {
+                    child.write(output);
+                }
+                output.extend_from_slice(trailer);
+            }
+        }
+
+        write_size(output, start);
+    }
+
+    /// Adds delta to every chunk offset of `stco` and `co64` atoms pointing at or after from
+    fn shift_chunk_offsets(&mut self, from: u64, delta: i64) -> Result<(), TagError> {
+        match &mut self.body {
+            Body::Container { children, .. } => {
+                for child in children {
+                    child.shift_chunk_offsets(from, delta)?;
+                }
+            }
+            Body::Leaf(data) if &self.kind == b"stco" || &self.kind == b"co64" => {
+                let width = if &self.kind == b"stco" { 4 } else { 8 };
+                if data.len() < 8 {
+                    return Err(TagError::new(
+                        ErrorCode::Parse,
+                        "MP4 chunk offset table is truncated",
+                    ));
+                }
+                let count = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+                if data.len() < 8 + count * width {
+                    return Err(TagError::new(
+                        ErrorCode::Parse,
+                        "MP4 chunk offset table is truncated",
+                    ));
+                }
+
+                for entry in data[8..8 + count * width].chunks_exact_mut(width) {
+                    let mut bytes = [0u8; 8];
+                    bytes[8 - width..].copy_from_slice(entry);
+                    let offset = u64::from_be_bytes(bytes);
+                    if offset < from {
+                        continue;
+                    }
+
+                    let shifted = (offset as i64 + delta) as u64;
+                    if width == 4 && shifted > u32::MAX as u64 {
+                        return Err(TagError::new(
+                            ErrorCode::UnsupportedFrame,
+                            "MP4 chunk offsets would not fit in 32 bits after resizing the tag",
+                        ));
+                    }
+                    entry.copy_from_slice(&shifted.to_be_bytes()[8 - width..]);
+                }
+            }
+            Body::Leaf(_) => {}
+        }
+        Ok(())
+    }
+}
+
+/// Writes the size of the atom starting at start, which ends at the end of output
+fn write_size(output: &mut [u8], start: usize) {
+    let size = (output.len() - start) as u32;
+    output[start..start + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+/// Checks whether bytes start with an `ftyp` atom
+pub fn is_mp4_stream(head: &[u8]) -> bool {
+    head.len() >= 8 && &head[4..8] == b"ftyp"
+}
+
+/// Returns the top-level atoms of the file
+fn top_level_atoms(bytes: &[u8]) -> Result<Vec<AtomHeader>, TagError> {
+    let mut atoms = Vec::new();
+    let mut position = 0;
+    while position < bytes.len() {
+        let atom = read_header(bytes, position, bytes.len())?;
+        position = atom.end;
+        atoms.push(atom);
+    }
+    Ok(atoms)
+}
+
+fn find_moov(atoms: &[AtomHeader]) -> Result<&AtomHeader, TagError> {
+    atoms
+        .iter()
+        .find(|atom| &atom.kind == b"moov")
+        .ok_or_else(|| TagError::new(ErrorCode::Parse, "MP4 file has no moov atom"))
+}
+
+fn parse_string_atom(bytes: &[u8]) -> String {
+    // Skip the version and flags
+    String::from_utf8_lossy(bytes.get(4..).unwrap_or_default()).into_owned()
+}
+
+fn parse_ilst(bytes: &[u8]) -> Result<Mp4Tag, TagError> {
+    let mut tag = Mp4Tag::default();
+
+    let mut position = 0;
+    while position < bytes.len() {
+        let item = read_header(bytes, position, bytes.len())?;
+        position = item.end;
+
+        let mut mean = None;
+        let mut name = None;
+        let mut data = Vec::new();
+
+        let mut child_position = item.body_start;
+        while child_position < item.end {
+            let child = read_header(bytes, child_position, item.end)?;
+            child_position = child.end;
+            let body = &bytes[child.body_start..child.end];
+
+            match &child.kind {
+                b"mean" => mean = Some(parse_string_atom(body)),
+                b"name" => name = Some(parse_string_atom(body)),
+                b"data" if body.len() >= 8 => data.push(DataAtom {
+                    type_code: u32::from_be_bytes([0, body[1], body[2], body[3]]),
+                    locale: u32::from_be_bytes([body[4], body[5], body[6], body[7]]),
+                    value: body[8..].to_vec(),
+                }),
+                _ => {}
+            }
+        }
+
+        let ident = match (&item.kind, mean, name) {
+            (b"----", Some(mean), Some(name)) => Ident::Freeform { mean, name },
+            (b"----", _, _) => {
+                return Err(TagError::new(
+                    ErrorCode::Parse,
+                    "MP4 freeform atom is missing its mean or name",
+                ))
+            }
+            (kind, _, _) => Ident::Fourcc(*kind),
+        };
+        tag.items.push(Item { ident, data });
+    }
+
+    Ok(tag)
+}
+
+fn ilst_to_bytes(tag: &Mp4Tag) -> Vec<u8> {
+    let mut output = Vec::new();
+
+    for item in &tag.items {
+        let start = output.len();
+        output.extend_from_slice(&[0; 4]);
+
+        match &item.ident {
+            Ident::Fourcc(fourcc) => output.extend_from_slice(fourcc),
+            Ident::Freeform { mean, name } => {
+                output.extend_from_slice(b"----");
+                for (kind, value) in [(b"mean", mean), (b"name", name)] {
+                    let child = output.len();
+                    output.extend_from_slice(&[0; 4]);
+                    output.extend_from_slice(kind);
+                    output.extend_from_slice(&[0; 4]);
+                    output.extend_from_slice(value.as_bytes());
+                    write_size(&mut output, child);
+                }
+            }
+        }
+
+        for data in &item.data {
+            let child = output.len();
+            output.extend_from_slice(&[0; 4]);
+            output.extend_from_slice(b"data");
+            output.extend_from_slice(&(data.type_code & 0x00ff_ffff).to_be_bytes());
+            output.extend_from_slice(&data.locale.to_be_bytes());
+            output.extend_from_slice(&data.value);
+            write_size(&mut output, child);
+        }
+
+        write_size(&mut output, start);
+    }
+
+    output
+}
+
+/// The handler iTunes puts in `meta` before the item list
+fn metadata_handler() -> Atom {
+    let mut data = vec![0; 8];
+    data.extend_from_slice(b"mdir");
+    data.extend_from_slice(b"appl");
+    data.extend_from_slice(&[0; 8]);
+    data.push(0);
+    Atom::leaf(b"hdlr", data)
+}
+
+pub fn read_from_bytes(bytes: &[u8]) -> Result<Mp4Tag, TagError> {
+    let atoms = top_level_atoms(bytes)?;
+    let moov = Atom::parse(bytes, find_moov(&atoms)?)?;
+
+    let ilst = moov
+        .child(b"udta")
+        .and_then(|udta| udta.child(b"meta"))
+        .and_then(|meta| meta.child(b"ilst"));
+
+    match ilst {
+        Some(Atom {
+            body: Body::Leaf(data),
+            ..
+        }) => parse_ilst(data),
+        _ => Ok(Mp4Tag::default()),
+    }
+}
+
+/// Replaces the item list of an MP4 file.
+///
+/// The `moov` atom is rebuilt with the new list, creating `udta`, `meta` and `ilst` as needed.
+/// When `moov` changes size and comes before the media data, `mdat` moves with it and every
+/// `stco`/`co64` chunk offset pointing after `moov` is shifted by the same amount.
+pub fn write_to_bytes(bytes: &[u8], tag: &Mp4Tag) -> Result<Vec<u8>, TagError> {
+    let atoms = top_level_atoms(bytes)?;
+    let moov_header = find_moov(&atoms)?;
+    let mut moov = Atom::parse(bytes, moov_header)?;
+
+    let udta = moov.child_or_insert(b"udta", || Atom::container(b"udta", Vec::new(), Vec::new()));
+    let meta = udta.child_or_insert(b"meta", || {
+        Atom::container(b"meta", vec![0; 4], vec![metadata_handler()])
+    });
+    let ilst = meta.child_or_insert(b"ilst", || Atom::leaf(b"ilst", Vec::new()));
+    ilst.body = Body::Leaf(ilst_to_bytes(tag));
+
+    let mut new_moov = Vec::new();
+    moov.write(&mut new_moov);
+
+    let old_len = moov_header.end - moov_header.start;
+    let delta = new_moov.len() as i64 - old_len as i64;
+    if delta != 0 {
+        if atoms.iter().any(|atom| &atom.kind == b"moof") {
+            return Err(TagError::new(
+                ErrorCode::UnsupportedFrame,
+                "Fragmented MP4 files can't be retagged",
+            ));
+        }
+        moov.shift_chunk_offsets(moov_header.end as u64, delta)?;
+        new_moov.clear();
+        moov.write(&mut new_moov);
+    }
+
+    let mut output = Vec::with_capacity(bytes.len() + new_moov.len());
+    output.extend_from_slice(&bytes[..moov_header.start]);
+    output.extend_from_slice(&new_moov);
+    output.extend_from_slice(&bytes[moov_header.end..]);
+
+    Ok(output)
+}
+
+/// Works out the size of the item list `write_to_bytes` writes and how far the media data moves
+pub fn layout(bytes: &[u8], tag: &Mp4Tag) -> Result<Layout, TagError> {
+    let media_start = |bytes: &[u8]| -> Result<usize, TagError> {
+        Ok(top_level_atoms(bytes)?
+            .iter()
+            .find(|atom| &atom.kind == b"mdat")
+            .map_or(bytes.len(), |atom| atom.start))
+    };
+    let output = write_to_bytes(bytes, tag)?;
+
+    Ok(Layout {
+        tag_size: 8 + ilst_to_bytes(tag).len(),
+        audio_shift: media_start(&output)? as i64 - media_start(bytes)? as i64,
+    })
+}
+
+/// SHA-256 digest of the atoms around `moov`, holding the media data writing a tag must leave
+/// untouched
+pub fn audio_hash(bytes: &[u8]) -> Result<[u8; 32], TagError> {
+    let atoms = top_level_atoms(bytes)?;
+    let moov = find_moov(&atoms)?;
+    let mut media = bytes[..moov.start].to_vec();
+    media.extend_from_slice(&bytes[moov.end..]);
+
+    Ok(hash::sha256(&media))
+}
+
+pub fn read_from_path(path: &str) -> Result<Mp4Tag, TagError> {
+    let bytes = fs::read(path).map_err(|error| TagError::from(error).with_path(path))?;
+    read_from_bytes(&bytes).map_err(|error| error.with_path(path))
+}
+
+pub fn write_to_path(path: &str, tag: &Mp4Tag) -> Result<(), TagError> {
+    let bytes = fs::read(path).map_err(|error| TagError::from(error).with_path(path))?;
+    let output = write_to_bytes(&bytes, tag).map_err(|error| error.with_path(path))?;
+    fs::write(path, output).map_err(|error| TagError::from(error).with_path(path))
+}
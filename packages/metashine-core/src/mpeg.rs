@@ -0,0 +1,676 @@
+//! MPEG audio stream properties read from frame headers and Xing, Info and VBRI headers
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+};
+
+use id3::Tag;
+
+use crate::{
+    error::{ErrorCode, TagError},
+    flac,
+    format::{self, Container},
+};
+
+/// Bytes searched for the first frame after the ID3v2 tag
+const SYNC_WINDOW: usize = 64 * 1024;
+
+const BITRATES_V1: [[u32; 15]; 3] = [
+    [
+        0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+    ],
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+    ],
+    [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ],
+];
+
+const BITRATES_V2: [[u32; 15]; 3] = [
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+    ],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MpegVersion {
+    V1,
+    V2,
+    V2_5,
+}
+
+impl MpegVersion {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MpegVersion::V1 => "1",
+            MpegVersion::V2 => "2",
+            MpegVersion::V2_5 => "2.5",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelMode {
+    Stereo,
+    JointStereo,
+    DualChannel,
+    Mono,
+}
+
+impl ChannelMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChannelMode::Stereo => "stereo",
+            ChannelMode::JointStereo => "joint stereo",
+            ChannelMode::DualChannel => "dual channel",
+            ChannelMode::Mono => "mono",
+        }
+    }
+
+    pub fn channels(&self) -> u8 {
+        match self {
+            ChannelMode::Mono => 1,
+            _ => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitrateMode {
+    /// Constant bitrate
+    Cbr,
+    /// Variable bitrate
+    Vbr,
+    /// Average bitrate, a variable bitrate aiming at a target
+    Abr,
+}
+
+impl BitrateMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BitrateMode::Cbr => "CBR",
+            BitrateMode::Vbr => "VBR",
+            BitrateMode::Abr => "ABR",
+        }
+    }
+}
+
+/// The header starting every MPEG audio frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub version: MpegVersion,
+    /// 1, 2 or 3
+    pub layer: u8,
+    /// In kbit/s
+    pub bitrate: u32,
+    pub sample_rate: u32,
+    pub channel_mode: ChannelMode,
+    /// Length of the whole frame including this header in bytes
+    pub frame_len: usize,
+}
+
+impl FrameHeader {
+    /// Parses a frame header, rejecting reserved values and free format frames
+    pub fn parse(bytes: &[u8]) -> Option<FrameHeader> {
+        if bytes.len() < 4 || bytes[0] != 0xff || bytes[1] & 0xe0 != 0xe0 {
+            return None;
+        }
+
+        let version = match (bytes[1] >> 3) & 0x03 {
+            0 => MpegVersion::V2_5,
+            2 => MpegVersion::V2,
+            3 => MpegVersion::V1,
+            _ => return None,
+        };
+        let layer = match (bytes[1] >> 1) & 0x03 {
+            1 => 3,
+            2 => 2,
+            3 => 1,
+            _ => return None,
+        };
+
+        let bitrate_index = (bytes[2] >> 4) as usize;
+        if bitrate_index == 0 || bitrate_index == 15 {
+            return None;
+        }
+        let bitrate = match version {
+            MpegVersion::V1 => BITRATES_V1[layer as usize - 1][bitrate_index],
+            _ => BITRATES_V2[layer as usize - 1][bitrate_index],
+        };
+
+        let sample_rate = match ((bytes[2] >> 2) & 0x03, version) {
+            (3, _) => return None,
+            (index, MpegVersion::V1) => [44100, 48000, 32000][index as usize],
+            (index, MpegVersion::V2) => [22050, 24000, 16000][index as usize],
+            (index, MpegVersion::V2_5) => [11025, 12000, 8000][index as usize],
+        };
+        let padding = ((bytes[2] >> 1) & 0x01) as usize;
+
+        let channel_mode = match bytes[3] >> 6 {
+            0 => ChannelMode::Stereo,
+            1 => ChannelMode::JointStereo,
+            2 => ChannelMode::DualChannel,
+            _ => ChannelMode::Mono,
+        };
+
+        let frame_len = match (layer, version) {
+            (1, _) => (12 * bitrate as usize * 1000 / sample_rate as usize + padding) * 4,
+            (3, MpegVersion::V2 | MpegVersion::V2_5) => {
+                72 * bitrate as usize * 1000 / sample_rate as usize + padding
+            }
+            _ => 144 * bitrate as usize * 1000 / sample_rate as usize + padding,
+        };
+
+        Some(FrameHeader {
+            version,
+            layer,
+            bitrate,
+            sample_rate,
+            channel_mode,
+            frame_len,
+        })
+    }
+
+    pub fn samples_per_frame(&self) -> u32 {
+        match (self.layer, self.version) {
+            (1, _) => 384,
+            (3, MpegVersion::V2 | MpegVersion::V2_5) => 576,
+            _ => 1152,
+        }
+    }
+
+    /// Length of the Layer III side information following the header
+    fn side_info_len(&self) -> usize {
+        match (self.version, self.channel_mode) {
+            (MpegVersion::V1, ChannelMode::Mono) => 17,
+            (MpegVersion::V1, _) => 32,
+            (_, ChannelMode::Mono) => 9,
+            (_, _) => 17,
+        }
+    }
+
+    /// Whether another frame belongs to the same stream
+    fn matches(&self, other: &FrameHeader) -> bool {
+        self.version == other.version
+            && self.layer == other.layer
+            && self.sample_rate == other.sample_rate
+    }
+}
+
+/// Which header in the first frame describes the whole stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VbrHeaderKind {
+    /// Written by LAME and others for variable bitrate streams
+    Xing,
+    /// The Xing header LAME writes for constant bitrate streams
+    Info,
+    /// Written by Fraunhofer encoders
+    Vbri,
+}
+
+impl VbrHeaderKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VbrHeaderKind::Xing => "Xing",
+            VbrHeaderKind::Info => "Info",
+            VbrHeaderKind::Vbri => "VBRI",
+        }
+    }
+}
+
+/// Who set a ReplayGain value of the LAME extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GainOriginator {
+    Unset,
+    Artist,
+    User,
+    /// Worked out by the encoder
+    Automatic,
+    /// Worked out from the RMS average
+    Rms,
+    Other(u8),
+}
+
+impl GainOriginator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GainOriginator::Unset => "unset",
+            GainOriginator::Artist => "artist",
+            GainOriginator::User => "user",
+            GainOriginator::Automatic => "automatic",
+            GainOriginator::Rms => "rms",
+            GainOriginator::Other(_) => "other",
+        }
+    }
+}
+
+/// A radio (track) or audiophile (album) ReplayGain adjustment
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayGain {
+    /// In dB
+    pub gain: f64,
+    pub originator: GainOriginator,
+}
+
+impl ReplayGain {
+    /// Decodes the 3 bit name, 3 bit originator, sign bit and 9 bit gain in tenths of dB,
+    /// returning None if the name is not set
+    fn parse(bytes: &[u8]) -> Option<ReplayGain> {
+        let field = u16::from_be_bytes([bytes[0], bytes[1]]);
+        if field >> 13 == 0 {
+            return None;
+        }
+        let originator = match (field >> 10) & 0x07 {
+            0 => GainOriginator::Unset,
+            1 => GainOriginator::Artist,
+            2 => GainOriginator::User,
+            3 => GainOriginator::Automatic,
+            4 => GainOriginator::Rms,
+            other => GainOriginator::Other(other as u8),
+        };
+        let gain = (field & 0x01ff) as f64 / 10.0;
+
+        Some(ReplayGain {
+            gain: if field & 0x0200 != 0 { -gain } else { gain },
+            originator,
+        })
+    }
+}
+
+/// Length of the LAME extension, up to and including its CRC
+const LAME_HEADER_LEN: usize = 36;
+
+/// The extension LAME and encoders based on it add after a Xing or Info header
+#[derive(Debug, Clone, PartialEq)]
+pub struct LameHeader {
+    /// Such as LAME3.100 or Lavc58.54
+    pub encoder: String,
+    /// Revision of the extension format
+    pub revision: u8,
+    /// 1 and 8 are CBR, 2 and 9 are ABR, 3 to 6 are VBR
+    pub vbr_method: u8,
+    /// Lowpass filter frequency in Hz
+    pub lowpass: Option<u32>,
+    /// Peak amplitude, 1.0 being full scale
+    pub peak: Option<f64>,
+    pub radio_gain: Option<ReplayGain>,
+    pub audiophile_gain: Option<ReplayGain>,
+    /// Target bitrate of ABR streams, minimum bitrate of VBR streams in kbit/s, 255 or more
+    /// is stored as 255
+    pub bitrate: u8,
+    /// Samples the encoder added before the audio
+    pub encoder_delay: u16,
+    /// Samples the encoder added after the audio to fill the last frame
+    pub encoder_padding: u16,
+    /// Bytes from the start of the frame holding the header to the end of the audio
+    pub music_length: u32,
+    /// CRC-16 of the audio frames after the frame holding the header
+    pub music_crc: u16,
+    /// CRC-16 of the frame holding the header up to this field
+    pub crc: u16,
+    /// Whether crc matches the frame
+    pub crc_valid: bool,
+}
+
+impl LameHeader {
+    /// Parses the extension starting at offset `at` of the first frame
+    fn parse(frame: &[u8], at: usize) -> Option<LameHeader> {
+        let bytes = frame.get(at..at + LAME_HEADER_LEN)?;
+        let encoder = &bytes[..9];
+        if !encoder[..4].iter().all(u8::is_ascii_alphanumeric) {
+            return None;
+        }
+        let encoder = encoder
+            .iter()
+            .take_while(|byte| byte.is_ascii_graphic())
+            .map(|byte| *byte as char)
+            .collect();
+
+        let peak = u32::from_be_bytes([bytes[11], bytes[12], bytes[13], bytes[14]]);
+        let crc = u16::from_be_bytes([bytes[34], bytes[35]]);
+
+        Some(LameHeader {
+            encoder,
+            revision: bytes[9] >> 4,
+            vbr_method: bytes[9] & 0x0f,
+            lowpass: Some(bytes[10] as u32 * 100).filter(|lowpass| *lowpass != 0),
+            // A fixed point number with 23 fractional bits
+            peak: Some(peak as f64 / (1 << 23) as f64).filter(|_| peak != 0),
+            radio_gain: ReplayGain::parse(&bytes[15..17]),
+            audiophile_gain: ReplayGain::parse(&bytes[17..19]),
+            bitrate: bytes[20],
+            encoder_delay: ((bytes[21] as u16) << 4) | (bytes[22] >> 4) as u16,
+            encoder_padding: (((bytes[22] & 0x0f) as u16) << 8) | bytes[23] as u16,
+            music_length: u32::from_be_bytes([bytes[28], bytes[29], bytes[30], bytes[31]]),
+            music_crc: u16::from_be_bytes([bytes[32], bytes[33]]),
+            crc,
+            crc_valid: crc16(&frame[..at + 34]) == crc,
+        })
+    }
+}
+
+/// The CRC-16 LAME uses, with the reversed polynomial 0xA001 and a zero initial value
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u16, |crc, _| match crc & 1 {
+            1 => (crc >> 1) ^ 0xa001,
+            _ => crc >> 1,
+        })
+    })
+}
+
+/// Encoder delay and padding iTunes stores in an iTunSMPB comment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ITunesGapless {
+    pub encoder_delay: u32,
+    pub encoder_padding: u32,
+    /// Samples of the audio without the delay and padding
+    pub samples: u64,
+}
+
+impl ITunesGapless {
+    /// Parses the hexadecimal fields of an iTunSMPB value, of which the second, third and
+    /// fourth are the delay, padding and sample count
+    pub fn parse(value: &str) -> Option<ITunesGapless> {
+        let fields: Vec<&str> = value.split_whitespace().collect();
+        if fields.len() < 4 {
+            return None;
+        }
+        Some(ITunesGapless {
+            encoder_delay: u32::from_str_radix(fields[1], 16).ok()?,
+            encoder_padding: u32::from_str_radix(fields[2], 16).ok()?,
+            samples: u64::from_str_radix(fields[3], 16).ok()?,
+        })
+    }
+
+    /// Finds an iTunSMPB comment or user defined text frame in an ID3 tag
+    fn from_tag(tag: &Tag) -> Option<ITunesGapless> {
+        let comments = tag
+            .comments()
+            .filter(|comment| comment.description == "iTunSMPB")
+            .map(|comment| comment.text.as_str());
+        let texts = tag
+            .extended_texts()
+            .filter(|text| text.description == "iTunSMPB")
+            .map(|text| text.value.as_str());
+        comments.chain(texts).find_map(ITunesGapless::parse)
+    }
+}
+
+/// The header in the first frame of a stream giving the frame count, length and encoder
+#[derive(Debug, Clone, PartialEq)]
+pub struct VbrHeader {
+    pub kind: VbrHeaderKind,
+    /// Audio frames in the stream, not counting the frame holding this header
+    pub frames: Option<u32>,
+    /// Length of the stream in bytes, including the frame holding this header
+    pub bytes: Option<u32>,
+    pub lame: Option<LameHeader>,
+}
+
+impl VbrHeader {
+    /// Finds a Xing, Info or VBRI header in the first frame of a stream
+    fn parse(header: &FrameHeader, frame: &[u8]) -> Option<VbrHeader> {
+        let be_u32 = |bytes: &[u8]| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+        let xing_at = 4 + header.side_info_len();
+        let xing = frame.get(xing_at..).unwrap_or_default();
+        if xing.len() >= 8 && (&xing[..4] == b"Xing" || &xing[..4] == b"Info") {
+            let kind = match &xing[..4] {
+                b"Xing" => VbrHeaderKind::Xing,
+                _ => VbrHeaderKind::Info,
+            };
+            let flags = be_u32(&xing[4..8]);
+
+            // The fields the flags announce follow each other, the LAME extension comes last
+            let mut position = 8;
+            let mut field = |flag: u32, len: usize| -> Option<&[u8]> {
+                if flags & flag == 0 {
+                    return None;
+                }
+                let bytes = xing.get(position..position + len);
+                position += len;
+                bytes
+            };
+            let frames = field(0x01, 4).map(be_u32);
+            let bytes = field(0x02, 4).map(be_u32);
+            field(0x04, 100);
+            field(0x08, 4);
+
+            return Some(VbrHeader {
+                kind,
+                frames,
+                bytes,
+                lame: LameHeader::parse(frame, xing_at + position),
+            });
+        }
+
+        let vbri = frame.get(4 + 32..).unwrap_or_default();
+        if vbri.len() >= 18 && &vbri[..4] == b"VBRI" {
+            return Some(VbrHeader {
+                kind: VbrHeaderKind::Vbri,
+                frames: Some(be_u32(&vbri[14..18])),
+                bytes: Some(be_u32(&vbri[10..14])),
+                lame: None,
+            });
+        }
+
+        None
+    }
+}
+
+/// What `probeAudio` reports about an MPEG audio stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioProperties {
+    pub version: MpegVersion,
+    pub layer: u8,
+    pub sample_rate: u32,
+    pub channel_mode: ChannelMode,
+    /// Duration in seconds
+    pub duration: f64,
+    /// Average bitrate in kbit/s
+    pub bitrate: f64,
+    /// Bitrate the stream was encoded with in kbit/s, None for VBR streams
+    pub nominal_bitrate: Option<u32>,
+    pub bitrate_mode: BitrateMode,
+    pub frames: u64,
+    pub vbr_header: Option<VbrHeader>,
+    /// The iTunSMPB comment of the ID3v2 tag
+    pub itunes_gapless: Option<ITunesGapless>,
+    /// Offset of the first frame in the file
+    pub audio_start: u64,
+    /// Length of the audio frames in bytes, not counting tags at the end of the file
+    pub audio_len: u64,
+}
+
+impl AudioProperties {
+    pub fn lame(&self) -> Option<&LameHeader> {
+        self.vbr_header
+            .as_ref()
+            .and_then(|header| header.lame.as_ref())
+    }
+
+    pub fn encoder(&self) -> Option<&str> {
+        self.lame()
+            .map(|lame| lame.encoder.as_str())
+            .filter(|encoder| !encoder.is_empty())
+    }
+}
+
+/// Offset of the end of the audio frames, before any ID3v1 tag and APEv2 tag at the end
+fn audio_end(reader: &mut (impl Read + Seek), stream_len: u64) -> io::Result<u64> {
+    let mut end = stream_len;
+
+    if end >= 128 {
+        let mut id3v1 = [0u8; 3];
+        reader.seek(SeekFrom::Start(end - 128))?;
+        reader.read_exact(&mut id3v1)?;
+        if &id3v1 == b"TAG" {
+            end -= 128;
+        }
+    }
+
+    if end >= 32 {
+        let mut footer = [0u8; 32];
+        reader.seek(SeekFrom::Start(end - 32))?;
+        reader.read_exact(&mut footer)?;
+        if &footer[..8] == b"APETAGEX" {
+            // The size covers the items and the footer, the header is announced by a flag
+            let size = u32::from_le_bytes([footer[12], footer[13], footer[14], footer[15]]) as u64;
+            let header = if footer[23] & 0x80 != 0 { 32 } else { 0 };
+            end = end.saturating_sub(size + header);
+        }
+    }
+
+    Ok(end)
+}
+
+/// Finds the first frame in bytes that is followed by another frame of the same stream
+fn find_first_frame(bytes: &[u8]) -> Option<(usize, FrameHeader)> {
+    (0..bytes.len()).find_map(|position| {
+        let header = FrameHeader::parse(&bytes[position..])?;
+        let next = position + header.frame_len;
+        match bytes.get(next..) {
+            Some(rest) if rest.len() >= 4 => match FrameHeader::parse(rest) {
+                Some(next) if header.matches(&next) => Some((position, header)),
+                _ => None,
+            },
+            // A stream of a single frame
+            _ => Some((position, header)),
+        }
+    })
+}
+
+/// Counts the frames from start to end, returning the count and whether all of them had the
+/// same bitrate
+fn scan_frames(
+    reader: &mut BufReader<impl Read + Seek>,
+    first: &FrameHeader,
+    start: u64,
+    end: u64,
+) -> io::Result<(u64, bool)> {
+    let mut frames = 0u64;
+    let mut constant = true;
+    let mut position = start;
+    reader.seek(SeekFrom::Start(start))?;
+
+    while position + 4 <= end {
+        let mut bytes = [0u8; 4];
+        reader.read_exact(&mut bytes)?;
+        let header = match FrameHeader::parse(&bytes) {
+            Some(header) if header.matches(first) => header,
+            _ => break,
+        };
+
+        frames += 1;
+        constant &= header.bitrate == first.bitrate;
+        position += header.frame_len as u64;
+        reader.seek_relative(header.frame_len as i64 - 4)?;
+    }
+
+    Ok((frames, constant))
+}
+
+fn probe(reader: impl Read + Seek) -> Result<AudioProperties, TagError> {
+    let mut reader = BufReader::new(reader);
+    let stream_len = reader.seek(SeekFrom::End(0))?;
+    let end = audio_end(&mut reader, stream_len)?;
+
+    // Skip the ID3v2 tags at the start, some files have more than one
+    let mut start = 0u64;
+    loop {
+        let mut header = [0u8; 10];
+        reader.seek(SeekFrom::Start(start))?;
+        if reader.read_exact(&mut header).is_err() {
+            break;
+        }
+        match flac::id3v2_len(&header) {
+            0 => break,
+            len => start += len as u64,
+        }
+    }
+
+    let mut window = Vec::new();
+    reader.seek(SeekFrom::Start(start))?;
+    (&mut reader)
+        .take((SYNC_WINDOW as u64).min(end.saturating_sub(start)))
+        .read_to_end(&mut window)?;
+    if window.starts_with(b"RIFF")
+        || window.starts_with(b"FORM")
+        || format::detect_bytes(&window) != Container::Id3
+    {
+        return Err(TagError::new(ErrorCode::Parse, "Not an MPEG audio stream"));
+    }
+    let (offset, first) = find_first_frame(&window)
+        .ok_or_else(|| TagError::new(ErrorCode::Parse, "No MPEG audio frames found"))?;
+    let audio_start = start + offset as u64;
+    let audio_len = end - audio_start;
+
+    let frame = &window[offset..window.len().min(offset + first.frame_len)];
+    let vbr_header = match first.layer {
+        3 => VbrHeader::parse(&first, frame),
+        _ => None,
+    };
+
+    let vbr_method = vbr_header
+        .as_ref()
+        .and_then(|header| header.lame.as_ref())
+        .map(|lame| (lame.vbr_method, lame.bitrate));
+    let (frames, constant) = match vbr_header.as_ref().and_then(|header| header.frames) {
+        Some(frames) => {
+            let info = vbr_header.as_ref().map(|header| header.kind) == Some(VbrHeaderKind::Info);
+            let cbr_method = matches!(vbr_method, Some((1 | 8, _)));
+            (frames as u64, info || cbr_method)
+        }
+        None => scan_frames(&mut reader, &first, audio_start, end)?,
+    };
+
+    let duration = frames as f64 * first.samples_per_frame() as f64 / first.sample_rate as f64;
+    let stream_bytes = vbr_header
+        .as_ref()
+        .and_then(|header| header.bytes)
+        .map_or(audio_len, |bytes| bytes as u64);
+    let bitrate = match duration > 0.0 {
+        true => stream_bytes as f64 * 8.0 / duration / 1000.0,
+        false => first.bitrate as f64,
+    };
+
+    let (bitrate_mode, nominal_bitrate) = match vbr_method {
+        _ if constant => (BitrateMode::Cbr, Some(first.bitrate)),
+        Some((2 | 9, target)) => (BitrateMode::Abr, Some(target as u32)),
+        _ => (BitrateMode::Vbr, None),
+    };
+
+    reader.seek(SeekFrom::Start(0))?;
+    let itunes_gapless = Tag::read_from2(&mut reader)
+        .ok()
+        .and_then(|tag| ITunesGapless::from_tag(&tag));
+
+    Ok(AudioProperties {
+        version: first.version,
+        layer: first.layer,
+        sample_rate: first.sample_rate,
+        channel_mode: first.channel_mode,
+        duration,
+        bitrate,
+        nominal_bitrate,
+        bitrate_mode,
+        frames,
+        vbr_header,
+        itunes_gapless,
+        audio_start,
+        audio_len,
+    })
+}
+
+/// Reads the audio properties of the MPEG audio file at path
+pub fn probe_path(path: &str) -> Result<AudioProperties, TagError> {
+    File::open(path)
+        .map_err(TagError::from)
+        .and_then(probe)
+        .map_err(|error| error.with_path(path))
+}
@@ -0,0 +1,455 @@
+//! Ogg backend reading and writing the comment header of Vorbis and Opus streams
+
+use std::fs;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+use crate::{
+    error::{ErrorCode, TagError},
+    flac::VorbisTag,
+    format::Layout,
+    hash,
+    vorbis::{FlacPicture, VorbisComment},
+};
+
+const CAPTURE_PATTERN: &[u8; 4] = b"OggS";
+
+const HEADER_CONTINUED: u8 = 0x01;
+const HEADER_FIRST: u8 = 0x02;
+
+/// Largest number of lacing values in a page
+const MAX_SEGMENTS: usize = 255;
+
+/// Field holding base64 encoded FLAC picture blocks
+const PICTURE_FIELD: &str = "METADATA_BLOCK_PICTURE";
+
+/// CRC-32 with polynomial 0x04c11db7, no reflection and no final xor, as used by Ogg pages
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |crc, byte| {
+        (crc << 8) ^ CRC_TABLE[(((crc >> 24) as u8) ^ byte) as usize]
+    })
+}
+
+/// Codecs whose comment header can be edited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Codec {
+    Vorbis,
+    Opus,
+}
+
+impl Codec {
+    /// Number of header packets before the first audio packet
+    fn header_packets(&self) -> usize {
+        match self {
+            Codec::Vorbis => 3,
+            Codec::Opus => 2,
+        }
+    }
+
+    /// Bytes preceding the comment in the comment header packet
+    fn comment_magic(&self) -> &'static [u8] {
+        match self {
+            Codec::Vorbis => b"\x03vorbis",
+            Codec::Opus => b"OpusTags",
+        }
+    }
+}
+
+struct Page<'b> {
+    header_type: u8,
+    granule: u64,
+    serial: u32,
+    sequence: u32,
+    lacing: &'b [u8],
+    body: &'b [u8],
+    /// The whole page as stored in the file
+    raw: &'b [u8],
+}
+
+/// Checks whether bytes start with an Ogg page
+pub fn is_ogg_stream(head: &[u8]) -> bool {
+    head.starts_with(CAPTURE_PATTERN)
+}
+
+fn parse_pages(bytes: &[u8]) -> Result<(Vec<Page<'_>>, &[u8]), TagError> {
+    let mut pages = Vec::new();
+    let mut position = 0;
+
+    while position + 27 <= bytes.len() && &bytes[position..position + 4] == CAPTURE_PATTERN {
+        let header = &bytes[position..position + 27];
+        let segment_count = header[26] as usize;
+        let lacing_end = position + 27 + segment_count;
+        if lacing_end > bytes.len() {
+            return Err(TagError::new(ErrorCode::Parse, "Ogg page is truncated"));
+        }
+        let lacing = &bytes[position + 27..lacing_end];
+        let body_len: usize = lacing.iter().map(|len| *len as usize).sum();
+        let end = lacing_end + body_len;
+        if end > bytes.len() {
+            return Err(TagError::new(ErrorCode::Parse, "Ogg page is truncated"));
+        }
+
+        let le_u32 = |at: usize| {
+            u32::from_le_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]])
+        };
+        let mut granule = [0u8; 8];
+        granule.copy_from_slice(&header[6..14]);
+
+        pages.push(Page {
+            header_type: header[5],
+            granule: u64::from_le_bytes(granule),
+            serial: le_u32(14),
+            sequence: le_u32(18),
+            lacing,
+            body: &bytes[lacing_end..end],
+            raw: &bytes[position..end],
+        });
+        position = end;
+    }
+
+    if pages.is_empty() {
+        return Err(TagError::new(ErrorCode::Parse, "Not an Ogg stream"));
+    }
+
+    Ok((pages, &bytes[position..]))
+}
+
+/// The header packets of the first logical stream and the pages holding them
+struct Headers {
+    codec: Codec,
+    serial: u32,
+    packets: Vec<Vec<u8>>,
+    /// Indices of the pages of the stream that hold header packets
+    pages: Vec<usize>,
+}
+
+fn read_headers(pages: &[Page]) -> Result<Headers, TagError> {
+    let first = &pages[0];
+    if first.header_type & HEADER_FIRST == 0 {
+        return Err(TagError::new(
+            ErrorCode::Parse,
+            "Ogg stream does not begin with a first page",
+        ));
+    }
+
+    let codec = if first.body.starts_with(b"\x01vorbis") {
+        Codec::Vorbis
+    } else if first.body.starts_with(b"OpusHead") {
+        Codec::Opus
+    } else {
+        return Err(TagError::new(
+            ErrorCode::UnsupportedFrame,
+            "Only Vorbis and Opus comments can be edited in Ogg files",
+        ));
+    };
+
+    let serial = first.serial;
+    let mut packets = Vec::new();
+    let mut header_pages = Vec::new();
+    let mut packet = Vec::new();
+
+    for (i, page) in pages.iter().enumerate() {
+        if page.serial != serial {
+            continue;
+        }
+        header_pages.push(i);
+
+        let mut offset = 0;
+        for (segment, len) in page.lacing.iter().enumerate() {
+            let len = *len as usize;
+            packet.extend_from_slice(&page.body[offset..offset + len]);
+            offset += len;
+
+            if len < 255 {
+                packets.push(std::mem::take(&mut packet));
+
+                if packets.len() == codec.header_packets() {
+                    if segment != page.lacing.len() - 1 {
+                        return Err(TagError::new(
+                            ErrorCode::Parse,
+                            "Ogg header packets don't end on a page boundary",
+                        ));
+                    }
+                    return Ok(Headers {
+                        codec,
+                        serial,
+                        packets,
+                        pages: header_pages,
+                    });
+                }
+            }
+        }
+    }
+
+    Err(TagError::new(
+        ErrorCode::Parse,
+        "Ogg stream ends before its header packets",
+    ))
+}
+
+pub fn read_from_bytes(bytes: &[u8]) -> Result<VorbisTag, TagError> {
+    let (pages, _) = parse_pages(bytes)?;
+    let headers = read_headers(&pages)?;
+
+    let packet = &headers.packets[1];
+    let magic = headers.codec.comment_magic();
+    if !packet.starts_with(magic) {
+        return Err(TagError::new(
+            ErrorCode::Parse,
+            "Ogg comment header is missing",
+        ));
+    }
+    let mut comment = VorbisComment::parse(&packet[magic.len()..])?;
+
+    let mut tag = VorbisTag::default();
+    for (name, value) in comment
+        .fields
+        .iter()
+        .filter(|(name, _)| name == PICTURE_FIELD)
+    {
+        let data = BASE64
+            .decode(value.trim())
+            .map_err(|error| TagError::new(ErrorCode::Parse, format!("Bad {}: {}", name, error)))?;
+        tag.pictures.push(FlacPicture::parse(&data)?);
+    }
+    comment.remove(PICTURE_FIELD);
+    tag.comment = comment;
+
+    Ok(tag)
+}
+
+fn comment_packet(codec: Codec, old_packet: &[u8], tag: &VorbisTag) -> Result<Vec<u8>, TagError> {
+    let magic = codec.comment_magic();
+    if !old_packet.starts_with(magic) {
+        return Err(TagError::new(
+            ErrorCode::Parse,
+            "Ogg comment header is missing",
+        ));
+    }
+    let (_, old_len) = VorbisComment::parse_prefix(&old_packet[magic.len()..])?;
+    let trailer = &old_packet[magic.len() + old_len..];
+
+    let mut comment = tag.comment.clone();
+    comment.remove(PICTURE_FIELD);
+    for picture in &tag.pictures {
+        comment.push(PICTURE_FIELD, &BASE64.encode(picture.to_bytes()));
+    }
+
+    let mut packet = magic.to_vec();
+    packet.extend_from_slice(&comment.to_bytes());
+    match codec {
+        // The framing bit
+        Codec::Vorbis => packet.push(1),
+        // Opus keeps binary data after the comments when its first bit is set
+        Codec::Opus => packet.extend_from_slice(trailer),
+    }
+    Ok(packet)
+}
+
+fn write_page(
+    output: &mut Vec<u8>,
+    header_type: u8,
+    granule: u64,
+    serial: u32,
+    sequence: u32,
+    lacing: &[u8],
+    body: &[u8],
+) {
+    let start = output.len();
+    output.extend_from_slice(CAPTURE_PATTERN);
+    output.push(0);
+    output.push(header_type);
+    output.extend_from_slice(&granule.to_le_bytes());
+    output.extend_from_slice(&serial.to_le_bytes());
+    output.extend_from_slice(&sequence.to_le_bytes());
+    output.extend_from_slice(&[0; 4]);
+    output.push(lacing.len() as u8);
+    output.extend_from_slice(lacing);
+    output.extend_from_slice(body);
+
+    let crc = crc32(&output[start..]);
+    output[start + 22..start + 26].copy_from_slice(&crc.to_le_bytes());
+}
+
+/// Splits packets into pages of at most 255 segments, ending the last page with the last packet.
+///
+/// Returns `(continued, ends_packet, lacing, body)` for every page.
+fn paginate(packets: &[Vec<u8>]) -> Vec<(bool, bool, Vec<u8>, Vec<u8>)> {
+    let mut pages = Vec::new();
+    let mut lacing = Vec::new();
+    let mut body = Vec::new();
+    let mut continued = false;
+    let mut ends_packet = false;
+
+    for packet in packets {
+        let mut remaining: &[u8] = packet;
+        loop {
+            if lacing.len() == MAX_SEGMENTS {
+                let next_continued = !ends_packet_at_end(&lacing);
+                pages.push((
+                    continued,
+                    ends_packet,
+                    std::mem::take(&mut lacing),
+                    std::mem::take(&mut body),
+                ));
+                continued = next_continued;
+                ends_packet = false;
+            }
+
+            let len = remaining.len().min(255);
+            lacing.push(len as u8);
+            body.extend_from_slice(&remaining[..len]);
+            remaining = &remaining[len..];
+
+            if len < 255 {
+                ends_packet = true;
+                break;
+            }
+        }
+    }
+
+    if !lacing.is_empty() {
+        pages.push((continued, ends_packet, lacing, body));
+    }
+    pages
+}
+
+fn ends_packet_at_end(lacing: &[u8]) -> bool {
+    lacing.last().is_none_or(|len| *len < 255)
+}
+
+/// Replaces the comment header of the first logical stream.
+///
+/// The identification header page is copied untouched, the comment header (and the Vorbis
+/// setup header sharing its pages) is re-paginated, and the following pages of the stream are
+/// renumbered with recomputed CRCs when the number of header pages changes. Pages of other
+/// multiplexed streams are copied untouched.
+pub fn write_to_bytes(bytes: &[u8], tag: &VorbisTag) -> Result<Vec<u8>, TagError> {
+    let (pages, trailer) = parse_pages(bytes)?;
+    let headers = read_headers(&pages)?;
+
+    // The identification header must be the only packet on the first page
+    let first_page_packets = pages[0].lacing.iter().filter(|len| **len < 255).count();
+    if first_page_packets != 1 || pages[0].lacing.last() == Some(&255) {
+        return Err(TagError::new(
+            ErrorCode::Parse,
+            "Ogg identification header does not fill the first page",
+        ));
+    }
+    let mut packets = headers.packets[1..].to_vec();
+    packets[0] = comment_packet(headers.codec, &packets[0], tag)?;
+
+    let new_pages = paginate(&packets);
+    let old_count = headers.pages.len() - 1;
+    let shift = new_pages.len() as i64 - old_count as i64;
+    let first_sequence = pages[headers.pages[1]].sequence;
+    let last_header_page = *headers.pages.last().unwrap_or(&0);
+
+    let mut output = Vec::with_capacity(bytes.len() + 1024);
+    for (i, page) in pages.iter().enumerate() {
+        let is_stream = page.serial == headers.serial;
+
+        if is_stream && i == headers.pages[1] {
+            for (j, (continued, ends_packet, lacing, body)) in new_pages.iter().enumerate() {
+                let header_type = if *continued { HEADER_CONTINUED } else { 0 };
+                let granule = if *ends_packet { 0 } else { u64::MAX };
+                write_page(
+                    &mut output,
+                    header_type,
+                    granule,
+                    headers.serial,
+                    first_sequence + j as u32,
+                    lacing,
+                    body,
+                );
+            }
+        } else if is_stream && i > 0 && i <= last_header_page {
+            // Replaced by the new header pages
+        } else if is_stream && i > last_header_page && shift != 0 {
+            write_page(
+                &mut output,
+                page.header_type,
+                page.granule,
+                page.serial,
+                (page.sequence as i64 + shift) as u32,
+                page.lacing,
+                page.body,
+            );
+        } else {
+            output.extend_from_slice(page.raw);
+        }
+    }
+    output.extend_from_slice(trailer);
+
+    Ok(output)
+}
+
+/// Works out the size of the comment header `write_to_bytes` writes and how far the audio
+/// pages move
+pub fn layout(bytes: &[u8], tag: &VorbisTag) -> Result<Layout, TagError> {
+    let output = write_to_bytes(bytes, tag)?;
+    let (pages, _) = parse_pages(&output)?;
+    let headers = read_headers(&pages)?;
+
+    Ok(Layout {
+        tag_size: headers.packets[1].len(),
+        audio_shift: output.len() as i64 - bytes.len() as i64,
+    })
+}
+
+/// SHA-256 digest of the audio, which writing a tag must leave untouched.
+///
+/// Covers the pages of the stream after its header pages without their sequence numbers and
+/// CRCs, which change when the number of header pages does, and the pages of other streams as
+/// stored.
+pub fn audio_hash(bytes: &[u8]) -> Result<[u8; 32], TagError> {
+    let (pages, trailer) = parse_pages(bytes)?;
+    let headers = read_headers(&pages)?;
+    let last_header_page = *headers.pages.last().unwrap_or(&0);
+
+    let mut audio = Vec::with_capacity(bytes.len());
+    for (i, page) in pages.iter().enumerate() {
+        if page.serial != headers.serial {
+            audio.extend_from_slice(page.raw);
+        } else if i > last_header_page {
+            audio.push(page.header_type);
+            audio.extend_from_slice(&page.granule.to_le_bytes());
+            audio.extend_from_slice(page.lacing);
+            audio.extend_from_slice(page.body);
+        }
+    }
+    audio.extend_from_slice(trailer);
+
+    Ok(hash::sha256(&audio))
+}
+
+pub fn read_from_path(path: &str) -> Result<VorbisTag, TagError> {
+    let bytes = fs::read(path).map_err(|error| TagError::from(error).with_path(path))?;
+    read_from_bytes(&bytes).map_err(|error| error.with_path(path))
+}
+
+pub fn write_to_path(path: &str, tag: &VorbisTag) -> Result<(), TagError> {
+    let bytes = fs::read(path).map_err(|error| TagError::from(error).with_path(path))?;
+    let output = write_to_bytes(&bytes, tag).map_err(|error| error.with_path(path))?;
+    fs::write(path, output).map_err(|error| TagError::from(error).with_path(path))
+}
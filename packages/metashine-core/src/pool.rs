@@ -0,0 +1,162 @@
+//! The worker threads asynchronous functions do their file I/O and parsing on
+
+use std::{
+    any::Any,
+    collections::VecDeque,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use crate::error::{ErrorCode, TagError};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct State {
+    queue: VecDeque<Job>,
+    /// Worker threads currently draining the queue
+    workers: usize,
+    /// Largest number of jobs running at once
+    limit: usize,
+}
+
+/// Runs jobs on at most `limit` threads at once.
+///
+/// Workers are started as jobs are queued and exit once the queue is empty, so an idle pool
+/// holds no threads and the limit can change at any time.
+pub struct Pool {
+    state: Arc<Mutex<State>>,
+}
+
+impl Pool {
+    fn new(limit: usize) -> Self {
+        Pool {
+            state: Arc::new(Mutex::new(State {
+                queue: VecDeque::new(),
+                workers: 0,
+                limit: limit.max(1),
+            })),
+        }
+    }
+
+    /// Sets the concurrency limit, extra workers finish their current job and exit
+    pub fn set_limit(&self, limit: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.limit = limit.max(1);
+        self.start_workers(&mut state);
+    }
+
+    pub fn spawn(&self, job: impl FnOnce() + Send + 'static) {
+        let mut state = self.state.lock().unwrap();
+        state.queue.push_back(Box::new(job));
+        self.start_workers(&mut state);
+    }
+
+    fn start_workers(&self, state: &mut State) {
+        while state.workers < state.limit && state.workers < state.queue.len() {
+            state.workers += 1;
+            let shared = Arc::clone(&self.state);
+            thread::spawn(move || work(shared));
+        }
+    }
+}
+
+fn work(state: Arc<Mutex<State>>) {
+    loop {
+        let job = {
+            let mut state = state.lock().unwrap();
+            if state.workers > state.limit {
+                state.workers -= 1;
+                return;
+            }
+            match state.queue.pop_front() {
+                Some(job) => job,
+                None => {
+                    state.workers -= 1;
+                    return;
+                }
+            }
+        };
+        // A panicking job must not take its worker down, the worker count would never drop
+        // and queued jobs could wait forever
+        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+    }
+}
+
+fn panic_error(payload: Box<dyn Any + Send>) -> TagError {
+    let message = match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => String::from("unknown panic"),
+    };
+    TagError::new(ErrorCode::Internal, format!("Worker panicked: {}", message))
+}
+
+/// Runs f, turning a panic into an `ERR_INTERNAL` error so the promise waiting for the result
+/// still settles
+pub fn catch<R>(f: impl FnOnce() -> Result<R, TagError>) -> Result<R, TagError> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| Err(panic_error(payload)))
+}
+
+/// The pool shared by all asynchronous functions, sized to the available parallelism
+pub fn global() -> &'static Pool {
+    static POOL: std::sync::OnceLock<Pool> = std::sync::OnceLock::new();
+    POOL.get_or_init(|| {
+        Pool::new(
+            thread::available_parallelism()
+                .map(|parallelism| parallelism.get())
+                .unwrap_or(4),
+        )
+    })
+}
+
+/// Runs f on every item in the pool and passes the results, in the order of the items, to
+/// done once all of them are finished. Items whose f panicked get an `ERR_INTERNAL` error
+pub fn map<T, R, F, D>(items: Vec<T>, f: F, done: D)
+where
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(T) -> R + Send + Sync + 'static,
+    D: FnOnce(Vec<Result<R, TagError>>) + Send + 'static,
+{
+    if items.is_empty() {
+        done(Vec::new());
+        return;
+    }
+
+    struct Batch<R, D> {
+        results: Vec<Option<Result<R, TagError>>>,
+        remaining: usize,
+        done: Option<D>,
+    }
+
+    let batch = Arc::new(Mutex::new(Batch {
+        results: items.iter().map(|_| None).collect(),
+        remaining: items.len(),
+        done: Some(done),
+    }));
+    let f = Arc::new(f);
+
+    for (i, item) in items.into_iter().enumerate() {
+        let batch = Arc::clone(&batch);
+        let f = Arc::clone(&f);
+
+        global().spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| f(item))).map_err(panic_error);
+
+            let mut batch = batch.lock().unwrap();
+            batch.results[i] = Some(result);
+            batch.remaining -= 1;
+            if batch.remaining == 0 {
+                let results = batch.results.drain(..).flatten().collect();
+                if let Some(done) = batch.done.take() {
+                    drop(batch);
+                    done(results);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,103 @@
+//! Helpers for popularimeter (POPM) ratings and play counter (PCNT) frames
+
+/// The way a player maps its star ratings onto the 0–255 POPM rating byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RatingConvention {
+    /// Windows Media Player, also used by Windows Explorer
+    WindowsMediaPlayer,
+    /// foobar2000 with foo_playcount/foo_quicktag
+    Foobar2000,
+    /// MusicBee, which supports half stars
+    MusicBee,
+}
+
+impl RatingConvention {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wmp" => Some(RatingConvention::WindowsMediaPlayer),
+            "foobar2000" => Some(RatingConvention::Foobar2000),
+            "musicbee" => Some(RatingConvention::MusicBee),
+            _ => None,
+        }
+    }
+
+    /// Raw ratings written for each amount of stars
+    fn table(&self) -> &'static [(f64, u8)] {
+        match self {
+            RatingConvention::WindowsMediaPlayer | RatingConvention::Foobar2000 => &[
+                (0.0, 0),
+                (1.0, 1),
+                (2.0, 64),
+                (3.0, 128),
+                (4.0, 196),
+                (5.0, 255),
+            ],
+            RatingConvention::MusicBee => &[
+                (0.0, 0),
+                (0.5, 13),
+                (1.0, 1),
+                (1.5, 54),
+                (2.0, 64),
+                (2.5, 118),
+                (3.0, 128),
+                (3.5, 186),
+                (4.0, 196),
+                (4.5, 242),
+                (5.0, 255),
+            ],
+        }
+    }
+}
+
+/// Converts a raw POPM rating to stars (0–5).
+///
+/// Values written by the player itself map exactly, anything else falls into the ranges
+/// Windows uses to display ratings written by other software.
+pub fn rating_to_stars(rating: u8, convention: RatingConvention) -> f64 {
+    if let Some((stars, _)) = convention.table().iter().find(|(_, raw)| *raw == rating) {
+        return *stars;
+    }
+
+    match rating {
+        0 => 0.0,
+        1..=31 => 1.0,
+        32..=95 => 2.0,
+        96..=159 => 3.0,
+        160..=223 => 4.0,
+        _ => 5.0,
+    }
+}
+
+/// Converts stars (0–5) to the raw POPM rating the player would write.
+///
+/// Stars are rounded to the nearest step the player supports.
+pub fn stars_to_rating(stars: f64, convention: RatingConvention) -> u8 {
+    convention
+        .table()
+        .iter()
+        .min_by(|(a, _), (b, _)| {
+            (a - stars)
+                .abs()
+                .partial_cmp(&(b - stars).abs())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .map(|(_, raw)| *raw)
+        .unwrap_or(0)
+}
+
+/// Decodes the big-endian counter of a PCNT frame
+pub fn decode_play_counter(data: &[u8]) -> u64 {
+    data.iter()
+        .fold(0u64, |counter, byte| (counter << 8) | *byte as u64)
+}
+
+/// Encodes a PCNT counter using at least four bytes, as required by the specification
+pub fn encode_play_counter(counter: u64) -> Vec<u8> {
+    let bytes = counter.to_be_bytes();
+    let first = bytes
+        .iter()
+        .position(|byte| *byte != 0)
+        .unwrap_or(bytes.len())
+        .min(4);
+    bytes[first..].to_vec()
+}
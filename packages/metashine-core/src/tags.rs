@@ -0,0 +1,990 @@
+//! Loading and updating the tag of a file in any supported format, without touching
+//! JavaScript so it can run on worker threads
+
+use std::{
+    fs,
+    io::Cursor,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use id3::{
+    frame::{ExtendedText, Picture, Unknown},
+    Content, Encoder, Tag, TagLike, Version,
+};
+
+use crate::{
+    atomic::{self, SaveOptions},
+    carrier::{self, Carrier, TagDiff},
+    document::{DocumentTag, Mp4Document, TagDocument, VorbisDocument},
+    error::{ErrorCode, TagError},
+    flac::{self, VorbisTag},
+    format::{self, Container, Layout, TagFormat},
+    frame_names, id3_frames,
+    id3_header::{self, Id3Header},
+    id3_version::{self, WriteVersion},
+    job::Job,
+    mp4::{self, DataAtom, Ident, Mp4Tag},
+    ogg, pool,
+    vorbis::FlacPicture,
+};
+
+/// The tag of a file as returned by `loadTag`
+#[derive(Debug, Clone)]
+pub struct LoadedTag {
+    pub format: TagFormat,
+    /// Whether the file had a tag, an empty one is returned otherwise
+    pub had_tag: bool,
+    /// The ID3v2 header, for ID3 files that have a tag
+    pub header: Option<Id3Header>,
+    pub carriers: Vec<Carrier>,
+}
+
+/// The tag written by `updateTag`
+#[derive(Debug, Clone)]
+pub struct UpdatedTag {
+    pub format: TagFormat,
+    pub carriers: Vec<Carrier>,
+}
+
+/// Where a tag is read from: a file or a whole file held in memory
+#[derive(Debug, Clone, Copy)]
+enum Source<'s> {
+    Path(&'s str),
+    Bytes(&'s [u8]),
+}
+
+impl Source<'_> {
+    fn container(self) -> Result<Container, TagError> {
+        match self {
+            Source::Path(path) => format::detect(path),
+            Source::Bytes(bytes) => Ok(format::detect_bytes(bytes)),
+        }
+    }
+
+    fn id3_header(self) -> Result<Option<Id3Header>, TagError> {
+        match self {
+            Source::Path(path) => id3_header::read_from_path(path),
+            Source::Bytes(bytes) => id3_header::read_from_bytes(bytes),
+        }
+    }
+}
+
+/// Reads the ID3 tag of a source or creates a new one if it has none
+fn read_tag(source: Source) -> Result<Tag, TagError> {
+    let read = match source {
+        Source::Path(path) => Tag::read_from_path(path),
+        Source::Bytes(bytes) => Tag::read_from2(Cursor::new(bytes)),
+    };
+    match read {
+        Ok(tag) => Ok(tag),
+        Err(error) => match error.kind {
+            id3::ErrorKind::NoTag => Ok(Tag::new()),
+            _ => Err(TagError::from(error)),
+        },
+    }
+}
+
+fn read_vorbis_tag(container: Container, source: Source) -> Result<VorbisTag, TagError> {
+    match (container, source) {
+        (Container::Ogg, Source::Path(path)) => ogg::read_from_path(path),
+        (Container::Ogg, Source::Bytes(bytes)) => ogg::read_from_bytes(bytes),
+        (_, Source::Path(path)) => flac::read_from_path(path),
+        (_, Source::Bytes(bytes)) => flac::read_from_bytes(bytes),
+    }
+}
+
+fn write_vorbis_tag(container: Container, path: &str, tag: &VorbisTag) -> Result<(), TagError> {
+    match container {
+        Container::Ogg => ogg::write_to_path(path, tag),
+        _ => flac::write_to_path(path, tag),
+    }
+}
+
+fn read_mp4_tag(source: Source) -> Result<Mp4Tag, TagError> {
+    match source {
+        Source::Path(path) => mp4::read_from_path(path),
+        Source::Bytes(bytes) => mp4::read_from_bytes(bytes),
+    }
+}
+
+/// Adds path to errors that don't name their file yet
+fn at_path(path: &str) -> impl Fn(TagError) -> TagError + '_ {
+    move |error| match error.path {
+        Some(_) => error,
+        None => error.with_path(path),
+    }
+}
+
+fn load_from(source: Source) -> Result<LoadedTag, TagError> {
+    let container = source.container()?;
+    let mut header = None;
+    let (carriers, had_tag) = match container {
+        Container::Id3 => {
+            // Read tag or create a new one
+            let tag = read_tag(source)?;
+            header = source.id3_header()?;
+            (carrier::frames_to_carriers(tag.frames())?, header.is_some())
+        }
+        Container::Flac | Container::Ogg => {
+            let tag = read_vorbis_tag(container, source)?;
+            // A vendor string alone is left by stripping, it doesn't make a tag
+            let had_tag = !tag.comment.fields.is_empty()
+                || !tag.comment.unnamed.is_empty()
+                || !tag.pictures.is_empty();
+            (vorbis_tag_to_carriers(&tag), had_tag)
+        }
+        Container::Mp4 => {
+            let tag = read_mp4_tag(source)?;
+            (mp4_tag_to_carriers(&tag), !tag.items.is_empty())
+        }
+    };
+
+    Ok(LoadedTag {
+        format: container.tag_format(),
+        had_tag,
+        header,
+        carriers,
+    })
+}
+
+pub fn load(path: &str) -> Result<LoadedTag, TagError> {
+    load_from(Source::Path(path)).map_err(at_path(path))
+}
+
+impl LoadedTag {
+    /// Leaves the data of pictures and encapsulated objects out of the carriers, keeping
+    /// handles to be passed to `load_payload`
+    pub fn strip_payloads(mut self) -> Self {
+        self.carriers.iter_mut().for_each(Carrier::strip_payload);
+        self
+    }
+}
+
+/// Reads the data of the picture or encapsulated object at index in the carriers of a file
+pub fn load_payload(path: &str, index: usize) -> Result<Vec<u8>, TagError> {
+    let mut carriers = load(path)?.carriers;
+    let not_found = || {
+        TagError::new(
+            ErrorCode::FrameNotFound,
+            format!("Frame {} is not a picture or encapsulated object", index),
+        )
+        .with_path(path)
+    };
+
+    if index >= carriers.len() {
+        return Err(not_found());
+    }
+    match carriers.swap_remove(index).content {
+        Content::Picture(picture) => Ok(picture.data),
+        Content::EncapsulatedObject(object) => Ok(object.data),
+        _ => Err(not_found()),
+    }
+}
+
+/// Loads the tag of a whole file held in memory
+pub fn load_from_bytes(bytes: &[u8]) -> Result<LoadedTag, TagError> {
+    load_from(Source::Bytes(bytes))
+}
+
+/// Fails unless the file written to temp reads back with the carriers that were written and
+/// the audio of the original, whose digest is audio
+fn verify_written(
+    temp: &str,
+    container: Container,
+    written: &[Carrier],
+    audio: &[u8; 32],
+) -> Result<(), TagError> {
+    let verification_error = |message: String| TagError::new(ErrorCode::Verification, message);
+
+    let read_back = load_from(Source::Path(temp))?.carriers;
+    if let Some((i, (written, read_back))) = written
+        .iter()
+        .zip(&read_back)
+        .enumerate()
+        .find(|(_, (written, read_back))| !reads_back_as(written, read_back))
+    {
+        let message = match written.id == read_back.id {
+            true => format!(
+                "The written {} frame reads back with another value",
+                written.id
+            ),
+            false => format!(
+                "The written {} frame reads back as {}",
+                written.id, read_back.id
+            ),
+        };
+        return Err(verification_error(message).with_frame(i as u32, &written.id));
+    }
+    if written.len() != read_back.len() {
+        return Err(verification_error(format!(
+            "The written tag reads back with {} frames instead of {}",
+            read_back.len(),
+            written.len()
+        )));
+    }
+
+    verify_audio(temp, container, audio)
+}
+
+/// Fails unless the audio of the file written to temp has the digest audio of the original
+fn verify_audio(temp: &str, container: Container, audio: &[u8; 32]) -> Result<(), TagError> {
+    match audio_hash(container, &fs::read(temp)?)? == *audio {
+        true => Ok(()),
+        false => Err(TagError::new(
+            ErrorCode::Verification,
+            "The audio changed while writing the tag",
+        )),
+    }
+}
+
+/// Whether a carrier read back from a written tag is the carrier that was written. Unknown
+/// frames remember the ID3 version they were read from, which translating a tag changes
+fn reads_back_as(written: &Carrier, read_back: &Carrier) -> bool {
+    match (&written.content, &read_back.content) {
+        (Content::Unknown(a), Content::Unknown(b)) if a.version != b.version => {
+            let mut read_back = read_back.clone();
+            read_back.content = Content::Unknown(Unknown {
+                version: a.version,
+                data: b.data.clone(),
+            });
+            *written == read_back
+        }
+        _ => written == read_back,
+    }
+}
+
+/// SHA-256 digest of the audio of a file, which writing a tag must leave untouched
+fn audio_hash(container: Container, bytes: &[u8]) -> Result<[u8; 32], TagError> {
+    match container {
+        Container::Id3 => Ok(id3_header::audio_hash(bytes)),
+        Container::Flac => flac::audio_hash(bytes),
+        Container::Ogg => ogg::audio_hash(bytes),
+        Container::Mp4 => mp4::audio_hash(bytes),
+    }
+}
+
+/// A tag with carrier modifications applied, not written yet
+enum Modified {
+    Id3(Tag, Version),
+    Vorbis(VorbisTag),
+    Mp4(Mp4Tag),
+}
+
+/// Reads the tag of a source and applies carrier modifications to it, translating ID3 tags to
+/// the version they are written in
+fn modify(
+    source: Source,
+    container: Container,
+    mods: &[Carrier],
+    write_version: WriteVersion,
+) -> Result<Modified, TagError> {
+    Ok(match container {
+        Container::Id3 => {
+            let mut tag = read_tag(source)?;
+            apply_id3_mods(&mut tag, mods)?;
+
+            let version = write_version.resolve(&tag);
+            id3_version::translate(&mut tag, version)?;
+            Modified::Id3(tag, version)
+        }
+        Container::Flac | Container::Ogg => {
+            let mut tag = read_vorbis_tag(container, source)?;
+            apply_vorbis_mods(&mut tag, mods)?;
+            Modified::Vorbis(tag)
+        }
+        Container::Mp4 => {
+            let mut tag = read_mp4_tag(source)?;
+            apply_mp4_mods(&mut tag, mods)?;
+            Modified::Mp4(tag)
+        }
+    })
+}
+
+/// Sizes an ID3 tag written over the tag of a source, returning its layout and the padding it
+/// is written with. A tag that fits in the space of the old one is padded to its size so the
+/// audio stays in place, a larger one moves the audio.
+fn id3_layout(source: Source, tag: &Tag, version: Version) -> Result<(Layout, usize), TagError> {
+    let mut encoded = Vec::new();
+    tag.write_to(&mut encoded, version)?;
+    let available = source.id3_header()?.map_or(0, |header| header.tag_size);
+
+    Ok(match available.checked_sub(encoded.len()) {
+        Some(padding) => (
+            Layout {
+                tag_size: available,
+                audio_shift: 0,
+            },
+            padding,
+        ),
+        None => (
+            Layout {
+                tag_size: encoded.len(),
+                audio_shift: (encoded.len() - available) as i64,
+            },
+            0,
+        ),
+    })
+}
+
+/// Applies carrier modifications to the tag of a file and replaces the file with a verified
+/// copy holding the new tag
+pub fn update(
+    path: &str,
+    mods: &[Carrier],
+    write_version: WriteVersion,
+    save: SaveOptions,
+) -> Result<UpdatedTag, TagError> {
+    let update = || -> Result<UpdatedTag, TagError> {
+        let container = format::detect(path)?;
+        let audio = audio_hash(container, &fs::read(path)?)?;
+        let verify =
+            |temp: &str, carriers: &[Carrier]| verify_written(temp, container, carriers, &audio);
+        let carriers = match modify(Source::Path(path), container, mods, write_version)? {
+            Modified::Id3(tag, version) => {
+                let carriers = carrier::frames_to_carriers(tag.frames())?;
+                let (_, padding) = id3_layout(Source::Path(path), &tag, version)?;
+                let encoder = Encoder::new().version(version).padding(padding);
+                atomic::update(
+                    path,
+                    save,
+                    |temp| Ok(encoder.write_to_path(&tag, temp)?),
+                    |temp| verify(temp, &carriers),
+                )?;
+                carriers
+            }
+            Modified::Vorbis(tag) => {
+                let carriers = vorbis_tag_to_carriers(&tag);
+                atomic::update(
+                    path,
+                    save,
+                    |temp| write_vorbis_tag(container, temp, &tag),
+                    |temp| verify(temp, &carriers),
+                )?;
+                carriers
+            }
+            Modified::Mp4(tag) => {
+                let carriers = mp4_tag_to_carriers(&tag);
+                atomic::update(
+                    path,
+                    save,
+                    |temp| mp4::write_to_path(temp, &tag),
+                    |temp| verify(temp, &carriers),
+                )?;
+                carriers
+            }
+        };
+
+        Ok(UpdatedTag {
+            format: container.tag_format(),
+            carriers,
+        })
+    };
+
+    update().map_err(at_path(path))
+}
+
+/// Applies the same carrier modifications to many files on the pool, calling done with a
+/// result for every path in order.
+///
+/// With stop_on_error, files not started yet when one fails are skipped and get `None`.
+/// Cancelled files fail with `ERR_CANCELLED`.
+pub fn update_many(
+    paths: Vec<String>,
+    mods: Vec<Carrier>,
+    write_version: WriteVersion,
+    save: SaveOptions,
+    stop_on_error: bool,
+    job: Arc<Job>,
+    done: impl FnOnce(Vec<Option<Result<UpdatedTag, TagError>>>) + Send + 'static,
+) {
+    let stopped = AtomicBool::new(false);
+    pool::map(
+        paths,
+        move |path| {
+            if stopped.load(Ordering::SeqCst) {
+                return None;
+            }
+            let result = job.run(&path, || update(&path, &mods, write_version, save));
+            if result.is_err() && stop_on_error {
+                stopped.store(true, Ordering::SeqCst);
+            }
+            Some(result)
+        },
+        |results| {
+            done(
+                results
+                    .into_iter()
+                    .map(|result| result.unwrap_or_else(|error| Some(Err(error))))
+                    .collect(),
+            )
+        },
+    );
+}
+
+/// Applies carrier modifications to the tag of a whole file held in memory, returning the file
+/// with the new tag and the same audio
+pub fn update_bytes(
+    bytes: &[u8],
+    mods: &[Carrier],
+    write_version: WriteVersion,
+) -> Result<(Vec<u8>, UpdatedTag), TagError> {
+    let source = Source::Bytes(bytes);
+    let container = source.container()?;
+    let (output, carriers) = match modify(source, container, mods, write_version)? {
+        Modified::Id3(tag, version) => {
+            let (_, padding) = id3_layout(source, &tag, version)?;
+            let mut output = Cursor::new(bytes.to_vec());
+            Encoder::new()
+                .version(version)
+                .padding(padding)
+                .write_to_file(&tag, &mut output)?;
+            (
+                output.into_inner(),
+                carrier::frames_to_carriers(tag.frames())?,
+            )
+        }
+        Modified::Vorbis(tag) => {
+            let output = match container {
+                Container::Ogg => ogg::write_to_bytes(bytes, &tag)?,
+                _ => flac::write_to_bytes(bytes, &tag)?,
+            };
+            (output, vorbis_tag_to_carriers(&tag))
+        }
+        Modified::Mp4(tag) => (mp4::write_to_bytes(bytes, &tag)?, mp4_tag_to_carriers(&tag)),
+    };
+
+    Ok((
+        output,
+        UpdatedTag {
+            format: container.tag_format(),
+            carriers,
+        },
+    ))
+}
+
+/// Removes the whole tag of a file through a verified copy, keeping the vendor string of
+/// Vorbis comments. Returns whether the file had a tag, files without one are left untouched
+pub fn strip(path: &str, save: SaveOptions) -> Result<bool, TagError> {
+    let strip = || -> Result<bool, TagError> {
+        let loaded = load(path)?;
+        if !loaded.had_tag {
+            return Ok(false);
+        }
+
+        let container = format::detect(path)?;
+        let audio = audio_hash(container, &fs::read(path)?)?;
+        let verify = |temp: &str| verify_written(temp, container, &[], &audio);
+        match container {
+            Container::Id3 => atomic::update(
+                path,
+                save,
+                |temp| {
+                    Tag::remove_from_path(temp)?;
+                    Ok(())
+                },
+                verify,
+            )?,
+            Container::Flac | Container::Ogg => {
+                let mut tag = read_vorbis_tag(container, Source::Path(path))?;
+                tag.comment.fields.clear();
+                tag.comment.unnamed.clear();
+                tag.pictures.clear();
+                atomic::update(
+                    path,
+                    save,
+                    |temp| write_vorbis_tag(container, temp, &tag),
+                    verify,
+                )?
+            }
+            Container::Mp4 => atomic::update(
+                path,
+                save,
+                |temp| mp4::write_to_path(temp, &Mp4Tag::default()),
+                verify,
+            )?,
+        }
+        Ok(true)
+    };
+
+    strip().map_err(at_path(path))
+}
+
+/// What `update` would do to a file, returned by `previewUpdate`
+#[derive(Debug, Clone)]
+pub struct Preview {
+    pub format: TagFormat,
+    pub diff: TagDiff,
+    pub layout: Layout,
+}
+
+/// Works out the changes `update` would make to the tag of a file without writing it
+pub fn preview(
+    path: &str,
+    mods: &[Carrier],
+    write_version: WriteVersion,
+) -> Result<Preview, TagError> {
+    let preview = || -> Result<Preview, TagError> {
+        let old = load(path)?.carriers;
+        let container = format::detect(path)?;
+        let (carriers, layout) = match modify(Source::Path(path), container, mods, write_version)? {
+            Modified::Id3(tag, version) => {
+                let (layout, _) = id3_layout(Source::Path(path), &tag, version)?;
+                (carrier::frames_to_carriers(tag.frames())?, layout)
+            }
+            Modified::Vorbis(tag) => {
+                let bytes = fs::read(path)?;
+                let layout = match container {
+                    Container::Ogg => ogg::layout(&bytes, &tag)?,
+                    _ => flac::layout(&bytes, &tag)?,
+                };
+                (vorbis_tag_to_carriers(&tag), layout)
+            }
+            Modified::Mp4(tag) => {
+                let layout = mp4::layout(&fs::read(path)?, &tag)?;
+                (mp4_tag_to_carriers(&tag), layout)
+            }
+        };
+
+        Ok(Preview {
+            format: container.tag_format(),
+            diff: carrier::diff(&old, &carriers),
+            layout,
+        })
+    };
+
+    preview().map_err(at_path(path))
+}
+
+/// Reads the whole tag of a file into a document, ID3 frames as they are stored
+pub fn export(path: &str) -> Result<TagDocument, TagError> {
+    let export = || -> Result<TagDocument, TagError> {
+        let container = format::detect(path)?;
+        Ok(match container {
+            Container::Id3 => {
+                let (version, frames) = id3_frames::read_from_path(path)?;
+                TagDocument::from_id3(version, &frames)?
+            }
+            Container::Flac | Container::Ogg => {
+                let tag = read_vorbis_tag(container, Source::Path(path))?;
+                TagDocument::new(DocumentTag::Vorbis(VorbisDocument::from(&tag)))
+            }
+            Container::Mp4 => {
+                let tag = read_mp4_tag(Source::Path(path))?;
+                TagDocument::new(DocumentTag::Mp4(Mp4Document::from(&tag)))
+            }
+        })
+    };
+
+    export().map_err(at_path(path))
+}
+
+/// Fails unless the tag read back from the written file is the one that was written
+fn verify_identical(identical: bool) -> Result<(), TagError> {
+    match identical {
+        true => Ok(()),
+        false => Err(TagError::new(
+            ErrorCode::Verification,
+            "The written tag reads back differently from the document",
+        )),
+    }
+}
+
+/// Replaces the whole tag of a file with the tag of a document through a verified copy. ID3
+/// frames are written in the stored order and version of the document, except for frames with
+/// tag alter preservation set, which ID3 asks to drop when their tag changes. The tag read back
+/// from the copy must match what was written byte for byte.
+pub fn import(
+    path: &str,
+    document: &TagDocument,
+    save: SaveOptions,
+) -> Result<UpdatedTag, TagError> {
+    let import = || -> Result<UpdatedTag, TagError> {
+        let container = format::detect(path)?;
+        if document.format() != container.tag_format() {
+            return Err(TagError::new(
+                ErrorCode::BadDocument,
+                format!(
+                    "The document holds a tag of format {} but the file takes {}",
+                    document.format().as_str(),
+                    container.tag_format().as_str()
+                ),
+            ));
+        }
+
+        let audio = audio_hash(container, &fs::read(path)?)?;
+        let carriers = match &document.tag {
+            DocumentTag::Id3(document) => {
+                let tag: Tag = document
+                    .to_tag()?
+                    .frames()
+                    .filter(|frame| !frame.tag_alter_preservation())
+                    .cloned()
+                    .collect();
+                let (_, padding) = id3_layout(Source::Path(path), &tag, document.version)?;
+                let encoder = Encoder::new().version(document.version).padding(padding);
+                let mut encoded = Vec::new();
+                encoder.encode(&tag, &mut encoded)?;
+                atomic::update(
+                    path,
+                    save,
+                    |temp| Ok(encoder.write_to_path(&tag, temp)?),
+                    |temp| {
+                        let read_back = id3_header::read_tag_from_path(temp)?;
+                        verify_identical(read_back.as_ref() == Some(&encoded))?;
+                        verify_audio(temp, container, &audio)
+                    },
+                )?;
+                carrier::frames_to_carriers(tag.frames())?
+            }
+            DocumentTag::Vorbis(document) => {
+                let tag = document.to_tag();
+                atomic::update(
+                    path,
+                    save,
+                    |temp| write_vorbis_tag(container, temp, &tag),
+                    |temp| {
+                        let read_back = read_vorbis_tag(container, Source::Path(temp))?;
+                        verify_identical(read_back == tag)?;
+                        verify_audio(temp, container, &audio)
+                    },
+                )?;
+                vorbis_tag_to_carriers(&tag)
+            }
+            DocumentTag::Mp4(document) => {
+                let tag = document.to_tag()?;
+                atomic::update(
+                    path,
+                    save,
+                    |temp| mp4::write_to_path(temp, &tag),
+                    |temp| {
+                        verify_identical(mp4::read_from_path(temp)? == tag)?;
+                        verify_audio(temp, container, &audio)
+                    },
+                )?;
+                mp4_tag_to_carriers(&tag)
+            }
+        };
+
+        Ok(UpdatedTag {
+            format: container.tag_format(),
+            carriers,
+        })
+    };
+
+    import().map_err(at_path(path))
+}
+
+/// Removes or adds the frames of the carriers
+fn apply_id3_mods(tag: &mut Tag, mods: &[Carrier]) -> Result<(), TagError> {
+    for (i, carrier) in (0u32..).zip(mods) {
+        // Remove or set this frame
+        if carrier.remove {
+            // Keep every frame the carrier doesn't stand for, the frames of a read tag are
+            // unique so adding them back in order changes nothing else
+            let mut kept = Tag::with_version(tag.version());
+            for frame in tag.frames().filter(|frame| !carrier.removes(frame)) {
+                kept.add_frame(frame.clone());
+            }
+            *tag = kept;
+        } else {
+            let frame = carrier::carrier_to_frame(carrier)
+                .map_err(|error| error.with_frame(i, &carrier.id))?;
+            tag.add_frame(frame);
+        }
+    }
+
+    Ok(())
+}
+
+fn unsupported_type(i: u32, carrier: &Carrier, target: &str) -> TagError {
+    TagError::new(
+        ErrorCode::UnsupportedFrame,
+        format!(
+            "Frames of type {} can't be stored in {}",
+            carrier.kind().as_str(),
+            target
+        ),
+    )
+    .with_frame(i, &carrier.id)
+}
+
+/// A carrier with an ID3 frame ID that has no counterpart in another tag format, which would
+/// otherwise be written as a field or atom of that name
+fn unmapped_frame(i: u32, carrier: &Carrier, target: &str) -> TagError {
+    TagError::new(
+        ErrorCode::BadCarrier,
+        format!(
+            "The ID3 frame {} has no counterpart in {}",
+            carrier.id, target
+        ),
+    )
+    .with_frame(i, &carrier.id)
+}
+
+/// The Vorbis comment field a text carrier is written to: the field of its ID3 frame, or its
+/// ID taken as a field name
+fn vorbis_field(i: u32, carrier: &Carrier) -> Result<String, TagError> {
+    match frame_names::id3_to_vorbis(&carrier.id) {
+        Some(field) => Ok(field.to_owned()),
+        None if frame_names::is_id3_frame_id(&carrier.id) => {
+            Err(unmapped_frame(i, carrier, "Vorbis comments"))
+        }
+        None => Ok(carrier.id.to_uppercase()),
+    }
+}
+
+/// Converts Vorbis comments to text carriers keyed by the ID3 frame of their field, or by field
+/// name for fields without one, and pictures to APIC picture carriers
+fn vorbis_tag_to_carriers(tag: &VorbisTag) -> Vec<Carrier> {
+    let texts = tag.comment.fields.iter().map(|(name, value)| {
+        let id = frame_names::vorbis_to_id3(name).unwrap_or(name);
+        Carrier::new(id, Content::Text(value.clone()))
+    });
+
+    let pictures = tag.pictures.iter().map(|picture| {
+        Carrier::new(
+            "APIC",
+            Content::Picture(Picture {
+                mime_type: picture.mime_type.clone(),
+                picture_type: carrier::u8_to_picture_ype(picture.picture_type as u8),
+                description: picture.description.clone(),
+                data: picture.data.clone(),
+            }),
+        )
+    });
+
+    texts.chain(pictures).collect()
+}
+
+/// Applies text and picture carriers to Vorbis comments.
+///
+/// Text carriers keyed by an ID3 frame ID are written to the field of that frame and others to
+/// the field they name. The first carrier setting a field replaces all of its values and later
+/// ones in the same update add values, so multi-valued fields like ARTIST can be written.
+/// Picture carriers replace or remove the pictures with their type and description, like ID3
+/// pictures, and only the one with their payload when they hold a payload handle.
+fn apply_vorbis_mods(tag: &mut VorbisTag, mods: &[Carrier]) -> Result<(), TagError> {
+    let mut replaced_fields: Vec<String> = Vec::new();
+
+    for (i, carrier) in (0u32..).zip(mods) {
+        match &carrier.content {
+            Content::Text(value) => {
+                let name = vorbis_field(i, carrier)?;
+                if carrier.remove {
+                    tag.comment.remove(&name);
+                } else {
+                    if !replaced_fields.contains(&name) {
+                        tag.comment.remove(&name);
+                        replaced_fields.push(name.clone());
+                    }
+                    tag.comment.push(&name, value);
+                }
+            }
+            Content::Picture(picture) => {
+                let picture_type = u8::from(picture.picture_type) as u32;
+
+                tag.pictures.retain(|picture| {
+                    !carrier.removes_picture(
+                        carrier::u8_to_picture_ype(picture.picture_type as u8),
+                        &picture.description,
+                        &picture.data,
+                    )
+                });
+
+                if !carrier.remove {
+                    tag.pictures.push(FlacPicture {
+                        picture_type,
+                        mime_type: picture.mime_type.clone(),
+                        description: picture.description.clone(),
+                        data: picture.data.clone(),
+                        ..Default::default()
+                    });
+                }
+            }
+            _ => return Err(unsupported_type(i, carrier, "Vorbis comments")),
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts MP4 items to carriers keyed by the ID3 frame of their atom, or named after the atom
+/// for atoms without one.
+///
+/// Text and integer values become text carriers, `trkn` and `disk` as `number/total`,
+/// iTunes freeform atoms become extended text carriers with the TXXX ID, cover art becomes
+/// picture carriers with the APIC ID and anything else is returned as unknown. Carriers keep
+/// the type code of their data atom when it isn't the one the value would be written with.
+fn mp4_tag_to_carriers(tag: &Mp4Tag) -> Vec<Carrier> {
+    let mut carriers = Vec::new();
+
+    for item in &tag.items {
+        let name = item.ident.to_string();
+        let id = frame_names::mp4_to_id3(&name).unwrap_or(&name);
+
+        for data in &item.data {
+            let text = data.to_text(&item.ident);
+            let default_type = match &text {
+                Some(text) => DataAtom::from_text(&item.ident, text, None)
+                    .ok()
+                    .map(|data| data.type_code),
+                None => Some(mp4::TYPE_IMPLICIT),
+            };
+
+            let mut carrier = match (&item.ident, text) {
+                (
+                    Ident::Freeform {
+                        mean,
+                        name: description,
+                    },
+                    Some(value),
+                ) if mean == mp4::ITUNES_MEAN => Carrier::new(
+                    "TXXX",
+                    Content::ExtendedText(ExtendedText {
+                        description: description.clone(),
+                        value,
+                    }),
+                ),
+                (_, Some(value)) => Carrier::new(id, Content::Text(value)),
+                (Ident::Fourcc(fourcc), None)
+                    if fourcc == b"covr" && data.picture_mime_type().is_some() =>
+                {
+                    Carrier::new(
+                        "APIC",
+                        Content::Picture(Picture {
+                            mime_type: data.picture_mime_type().unwrap_or_default().to_string(),
+                            picture_type: id3::frame::PictureType::CoverFront,
+                            description: String::new(),
+                            data: data.value.clone(),
+                        }),
+                    )
+                }
+                (_, None) => Carrier::new(
+                    id,
+                    Content::Unknown(Unknown {
+                        data: data.value.clone(),
+                        version: Version::Id3v24,
+                    }),
+                ),
+            };
+
+            if !matches!(carrier.content, Content::Picture(_))
+                && default_type != Some(data.type_code)
+            {
+                carrier.data_type = Some(data.type_code);
+            }
+            carriers.push(carrier);
+        }
+    }
+
+    carriers
+}
+
+/// Applies carriers to MP4 items.
+///
+/// Text and unknown carriers keyed by an ID3 frame ID are written to the atom of that frame and
+/// others to the atom they name. As with Vorbis comments, the first carrier setting an item replaces its values and later
+/// ones in the same update add values. All cover art lives in the single covr item.
+fn apply_mp4_mods(tag: &mut Mp4Tag, mods: &[Carrier]) -> Result<(), TagError> {
+    let original = tag.clone();
+    let mut replaced_items: Vec<Ident> = Vec::new();
+
+    for (i, carrier) in (0u32..).zip(mods) {
+        let error = |code, message: String| TagError::new(code, message).with_frame(i, &carrier.id);
+
+        let ident = match &carrier.content {
+            Content::ExtendedText(extended_text) => {
+                if extended_text.description.is_empty() {
+                    return Err(error(
+                        ErrorCode::BadCarrier,
+                        String::from("Field description is missing or has the wrong type"),
+                    ));
+                }
+                Ident::freeform(&extended_text.description)
+            }
+            Content::Picture(_) => Ident::Fourcc(*b"covr"),
+            Content::Text(_) | Content::Unknown(_) => {
+                let atom = match frame_names::id3_to_mp4(&carrier.id) {
+                    Some(atom) => atom,
+                    None if frame_names::is_id3_frame_id(&carrier.id) => {
+                        return Err(unmapped_frame(i, carrier, "MP4 files"))
+                    }
+                    None => carrier.id.as_str(),
+                };
+                match Ident::parse(atom) {
+                    Some(ident) => ident,
+                    None => {
+                        return Err(error(
+                            ErrorCode::BadCarrier,
+                            format!("{} is not an MP4 atom name", carrier.id),
+                        ))
+                    }
+                }
+            }
+            _ => return Err(unsupported_type(i, carrier, "MP4 files")),
+        };
+
+        if carrier.remove {
+            tag.remove(&ident);
+            continue;
+        }
+
+        // Text read from the file is written back with its original data atom, and new text
+        // takes the type of the values the item held unless the carrier names one
+        let original_data = original
+            .items
+            .iter()
+            .filter(|item| item.ident == ident)
+            .flat_map(|item| &item.data);
+        let type_code = carrier
+            .data_type
+            .or_else(|| original_data.clone().next().map(|data| data.type_code));
+        let from_text = |value: &str| {
+            let existing = original_data.clone().find(|data| {
+                data.to_text(&ident).as_deref() == Some(value)
+                    && type_code.is_none_or(|code| code == data.type_code)
+            });
+            match existing {
+                Some(data) => Ok(data.clone()),
+                None => DataAtom::from_text(&ident, value, type_code)
+                    .map_err(|message| error(ErrorCode::BadCarrier, message)),
+            }
+        };
+
+        let data = match &carrier.content {
+            Content::Text(value) => from_text(value)?,
+            Content::ExtendedText(extended_text) => from_text(&extended_text.value)?,
+            Content::Picture(picture) => {
+                match DataAtom::picture(&picture.mime_type, picture.data.clone()) {
+                    Some(data) => data,
+                    None => {
+                        return Err(error(
+                            ErrorCode::UnsupportedFrame,
+                            format!(
+                                "Cover art of type {} can't be stored in MP4 files",
+                                picture.mime_type
+                            ),
+                        ))
+                    }
+                }
+            }
+            Content::Unknown(unknown) => DataAtom::new(
+                carrier.data_type.unwrap_or(mp4::TYPE_IMPLICIT),
+                unknown.data.clone(),
+            ),
+            _ => unreachable!("unsupported carriers are rejected above"),
+        };
+
+        if replaced_items.contains(&ident) {
+            tag.push(ident, data);
+        } else {
+            replaced_items.push(ident.clone());
+            tag.set(ident, vec![data]);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,175 @@
+//! Vorbis comments and FLAC picture structures, shared by the FLAC and Ogg backends
+
+use crate::error::{ErrorCode, TagError};
+
+/// A Vorbis comment header: a vendor string and a list of `NAME=value` fields
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VorbisComment {
+    pub vendor: String,
+    pub fields: Vec<(String, String)>,
+    /// Fields without `=` written by other taggers, kept as they are so rewriting the comment
+    /// doesn't lose them
+    pub unnamed: Vec<String>,
+}
+
+/// A picture in the FLAC PICTURE block layout, also used by METADATA_BLOCK_PICTURE fields
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlacPicture {
+    pub picture_type: u32,
+    pub mime_type: String,
+    pub description: String,
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub colors: u32,
+    pub data: Vec<u8>,
+}
+
+/// Reads big- or little-endian integers and length-prefixed fields from a byte slice
+struct Reader<'b> {
+    bytes: &'b [u8],
+    position: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn new(bytes: &'b [u8]) -> Self {
+        Reader { bytes, position: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'b [u8], TagError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| TagError::new(ErrorCode::Parse, "Unexpected end of metadata block"))?;
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn u32_le(&mut self) -> Result<u32, TagError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u32_be(&mut self) -> Result<u32, TagError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self, len: usize) -> Result<String, TagError> {
+        let bytes = self.take(len)?;
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+impl VorbisComment {
+    /// Parses a comment header without the packet type and framing bit used by Ogg streams
+    pub fn parse(bytes: &[u8]) -> Result<Self, TagError> {
+        Self::parse_prefix(bytes).map(|(comment, _)| comment)
+    }
+
+    /// Parses a comment header at the start of bytes, returning it and its length
+    pub fn parse_prefix(bytes: &[u8]) -> Result<(Self, usize), TagError> {
+        let mut reader = Reader::new(bytes);
+
+        let vendor_len = reader.u32_le()? as usize;
+        let vendor = reader.string(vendor_len)?;
+
+        let count = reader.u32_le()?;
+        let mut fields = Vec::new();
+        let mut unnamed = Vec::new();
+        for _ in 0..count {
+            let len = reader.u32_le()? as usize;
+            let field = reader.string(len)?;
+            match field.split_once('=') {
+                Some((name, value)) => fields.push((name.to_uppercase(), value.to_string())),
+                None => unnamed.push(field),
+            }
+        }
+
+        let comment = VorbisComment {
+            vendor,
+            fields,
+            unnamed,
+        };
+        Ok((comment, reader.position))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(&(self.vendor.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(self.vendor.as_bytes());
+
+        let count = self.fields.len() + self.unnamed.len();
+        bytes.extend_from_slice(&(count as u32).to_le_bytes());
+        let fields = self
+            .fields
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value));
+        for field in fields.chain(self.unnamed.iter().cloned()) {
+            bytes.extend_from_slice(&(field.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(field.as_bytes());
+        }
+
+        bytes
+    }
+
+    /// Removes every value of a field, field names are case-insensitive
+    pub fn remove(&mut self, name: &str) {
+        self.fields
+            .retain(|(field, _)| !field.eq_ignore_ascii_case(name));
+    }
+
+    pub fn push(&mut self, name: &str, value: &str) {
+        self.fields.push((name.to_uppercase(), value.to_string()));
+    }
+}
+
+impl FlacPicture {
+    pub fn parse(bytes: &[u8]) -> Result<Self, TagError> {
+        let mut reader = Reader::new(bytes);
+
+        let picture_type = reader.u32_be()?;
+        let mime_len = reader.u32_be()? as usize;
+        let mime_type = reader.string(mime_len)?;
+        let description_len = reader.u32_be()? as usize;
+        let description = reader.string(description_len)?;
+        let width = reader.u32_be()?;
+        let height = reader.u32_be()?;
+        let depth = reader.u32_be()?;
+        let colors = reader.u32_be()?;
+        let data_len = reader.u32_be()? as usize;
+        let data = reader.take(data_len)?.to_vec();
+
+        Ok(FlacPicture {
+            picture_type,
+            mime_type,
+            description,
+            width,
+            height,
+            depth,
+            colors,
+            data,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(32 + self.data.len());
+
+        bytes.extend_from_slice(&self.picture_type.to_be_bytes());
+        bytes.extend_from_slice(&(self.mime_type.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(self.mime_type.as_bytes());
+        bytes.extend_from_slice(&(self.description.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(self.description.as_bytes());
+        bytes.extend_from_slice(&self.width.to_be_bytes());
+        bytes.extend_from_slice(&self.height.to_be_bytes());
+        bytes.extend_from_slice(&self.depth.to_be_bytes());
+        bytes.extend_from_slice(&self.colors.to_be_bytes());
+        bytes.extend_from_slice(&(self.data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&self.data);
+
+        bytes
+    }
+}
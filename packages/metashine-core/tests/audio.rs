@@ -0,0 +1,446 @@
+mod common;
+
+use std::{fs, io::Cursor};
+
+use metashine_core::{
+    atomic::SaveOptions,
+    flac, hash,
+    id3::{frame::Comment, Content, Encoder, Frame, Tag, TagLike, Version},
+    id3_version::WriteVersion,
+    loudness::{self, Loudness},
+    lrc,
+    mpeg::{
+        self, BitrateMode, ChannelMode, FrameHeader, GainOriginator, ITunesGapless, MpegVersion,
+        ReplayGain, VbrHeaderKind,
+    },
+    popularimeter::{self, RatingConvention},
+    tags, ErrorCode,
+};
+
+/// Where a Xing or Info header starts in a MPEG-1 joint stereo frame, after its side info
+const XING_AT: usize = 4 + 32;
+
+/// A first frame holding a Xing or Info header announcing the frame count and stream length,
+/// followed by `extension`
+fn xing_frame(kind: &[u8; 4], frames: u32, bytes: u32, extension: &[u8]) -> Vec<u8> {
+    let mut frame = common::MP3_FRAME_HEADER.to_vec();
+    frame.resize(XING_AT, 0);
+    frame.extend_from_slice(kind);
+    frame.extend_from_slice(&3u32.to_be_bytes());
+    frame.extend_from_slice(&frames.to_be_bytes());
+    frame.extend_from_slice(&bytes.to_be_bytes());
+    frame.extend_from_slice(extension);
+    frame.resize(common::MP3_FRAME_LEN, 0);
+    frame
+}
+
+/// A stream of `frames` audio frames after a first frame holding a Xing or Info header
+fn xing_mp3(kind: &[u8; 4], frames: usize, extension: &[u8]) -> Vec<u8> {
+    let len = (frames + 1) * common::MP3_FRAME_LEN;
+    let mut bytes = xing_frame(kind, frames as u32, len as u32, extension);
+    bytes.extend_from_slice(&common::mp3(frames));
+    bytes
+}
+
+#[test]
+fn mpeg_frame_headers_are_parsed() {
+    let header = FrameHeader::parse(&common::MP3_FRAME_HEADER).unwrap();
+
+    assert_eq!(header.version, MpegVersion::V1);
+    assert_eq!(header.layer, 3);
+    assert_eq!(header.bitrate, 128);
+    assert_eq!(header.sample_rate, 44100);
+    assert_eq!(header.channel_mode, ChannelMode::JointStereo);
+    assert_eq!(header.frame_len, common::MP3_FRAME_LEN);
+    assert_eq!(header.samples_per_frame(), 1152);
+
+    assert!(FrameHeader::parse(&[0xFF, 0xFB, 0xF0, 0x44]).is_none());
+}
+
+#[test]
+fn cbr_streams_without_vbr_header_are_scanned() {
+    let path = common::temp_file("cbr.mp3", &common::mp3(100));
+    let properties = mpeg::probe_path(&path).unwrap();
+
+    assert_eq!(properties.frames, 100);
+    assert_eq!(properties.bitrate_mode, BitrateMode::Cbr);
+    assert_eq!(properties.nominal_bitrate, Some(128));
+    assert_eq!(properties.audio_start, 0);
+    assert!((properties.duration - 100.0 * 1152.0 / 44100.0).abs() < 1e-9);
+    assert!(properties.vbr_header.is_none());
+}
+
+/// Where the CRC of the LAME extension starts in a frame made by `xing_frame`
+const LAME_CRC_AT: usize = XING_AT + 16 + 34;
+
+/// The CRC-16 LAME computes over the frame holding its extension
+fn lame_crc(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u16, |crc, _| match crc & 1 {
+            1 => (crc >> 1) ^ 0xa001,
+            _ => crc >> 1,
+        })
+    })
+}
+
+/// A LAME 3.100 extension with the given VBR method and bitrate, a 19.5 kHz lowpass, a full
+/// scale peak, a radio gain of -5.2 dB, 576 samples of delay and 1152 of padding
+fn lame_extension(vbr_method: u8, bitrate: u8) -> Vec<u8> {
+    let mut extension = b"LAME3.100".to_vec();
+    extension.extend_from_slice(&[vbr_method, 195]);
+    extension.extend_from_slice(&0x0080_0000u32.to_be_bytes());
+    extension.extend_from_slice(&[0x2E, 0x34, 0, 0, 0, bitrate]);
+    extension.extend_from_slice(&[0x24, 0x04, 0x80, 0, 0, 0, 0]);
+    extension.extend_from_slice(&1234u32.to_be_bytes());
+    extension.extend_from_slice(&[0xAB, 0xCD, 0, 0]);
+    extension
+}
+
+/// A stream whose first frame holds a Xing header with a LAME extension and its CRC
+fn lame_mp3(vbr_method: u8, bitrate: u8) -> Vec<u8> {
+    let mut bytes = xing_mp3(b"Xing", 50, &lame_extension(vbr_method, bitrate));
+    let crc = lame_crc(&bytes[..LAME_CRC_AT]);
+    bytes[LAME_CRC_AT..LAME_CRC_AT + 2].copy_from_slice(&crc.to_be_bytes());
+    bytes
+}
+
+#[test]
+fn vbr_streams_are_measured_from_their_xing_header() {
+    let bytes = xing_mp3(b"Xing", 50, &[]);
+    let path = common::temp_file("vbr.mp3", &bytes);
+    let properties = mpeg::probe_path(&path).unwrap();
+
+    let duration = 50.0 * 1152.0 / 44100.0;
+    assert_eq!(properties.frames, 50);
+    assert_eq!(properties.bitrate_mode, BitrateMode::Vbr);
+    assert_eq!(properties.nominal_bitrate, None);
+    assert!((properties.duration - duration).abs() < 1e-9);
+    let bitrate = bytes.len() as f64 * 8.0 / duration / 1000.0;
+    assert!((properties.bitrate - bitrate).abs() < 1e-9);
+    assert_eq!(properties.version, MpegVersion::V1);
+    assert_eq!(properties.layer, 3);
+    assert_eq!(properties.sample_rate, 44100);
+    assert_eq!(properties.channel_mode, ChannelMode::JointStereo);
+
+    let header = properties.vbr_header.as_ref().unwrap();
+    assert_eq!(header.kind, VbrHeaderKind::Xing);
+    assert_eq!(header.frames, Some(50));
+    assert_eq!(header.bytes, Some(bytes.len() as u32));
+    assert!(header.lame.is_none());
+    assert_eq!(properties.encoder(), None);
+}
+
+#[test]
+fn info_headers_mark_constant_bitrate_streams() {
+    let path = common::temp_file("info.mp3", &xing_mp3(b"Info", 20, &[]));
+    let properties = mpeg::probe_path(&path).unwrap();
+
+    assert_eq!(properties.frames, 20);
+    assert_eq!(properties.bitrate_mode, BitrateMode::Cbr);
+    assert_eq!(properties.nominal_bitrate, Some(128));
+    assert_eq!(
+        properties.vbr_header.map(|header| header.kind),
+        Some(VbrHeaderKind::Info)
+    );
+}
+
+#[test]
+fn audio_lies_between_the_id3v2_and_id3v1_tags() {
+    let mut tag = Tag::new();
+    tag.add_frame(Frame::text("TIT2", "Song"));
+    let mut file = Cursor::new(common::mp3(10));
+    Encoder::new()
+        .version(Version::Id3v24)
+        .padding(100)
+        .write_to_file(&tag, &mut file)
+        .unwrap();
+    let mut bytes = file.into_inner();
+    let tag_len = bytes.len() - 10 * common::MP3_FRAME_LEN;
+    let mut id3v1 = b"TAG".to_vec();
+    id3v1.resize(128, 0);
+    bytes.extend_from_slice(&id3v1);
+
+    let path = common::temp_file("tagged.mp3", &bytes);
+    let properties = mpeg::probe_path(&path).unwrap();
+
+    assert_eq!(properties.audio_start, tag_len as u64);
+    assert_eq!(properties.audio_len, 10 * common::MP3_FRAME_LEN as u64);
+    assert_eq!(properties.frames, 10);
+    assert_eq!(properties.bitrate_mode, BitrateMode::Cbr);
+}
+
+#[test]
+fn lame_extensions_are_decoded() {
+    let path = common::temp_file("lame.mp3", &lame_mp3(0x04, 32));
+    let properties = mpeg::probe_path(&path).unwrap();
+
+    assert_eq!(properties.encoder(), Some("LAME3.100"));
+    assert_eq!(properties.bitrate_mode, BitrateMode::Vbr);
+    let lame = properties.lame().unwrap();
+    assert_eq!(lame.revision, 0);
+    assert_eq!(lame.vbr_method, 4);
+    assert_eq!(lame.lowpass, Some(19500));
+    assert_eq!(lame.peak, Some(1.0));
+    assert_eq!(
+        lame.radio_gain,
+        Some(ReplayGain {
+            gain: -5.2,
+            originator: GainOriginator::Automatic,
+        })
+    );
+    assert_eq!(lame.audiophile_gain, None);
+    assert_eq!(lame.bitrate, 32);
+    assert_eq!(lame.encoder_delay, 576);
+    assert_eq!(lame.encoder_padding, 1152);
+    assert_eq!(lame.music_length, 1234);
+    assert_eq!(lame.music_crc, 0xABCD);
+    assert!(lame.crc_valid);
+}
+
+#[test]
+fn lame_methods_tell_the_bitrate_mode() {
+    for (vbr_method, mode, nominal) in [
+        (0x01, BitrateMode::Cbr, Some(128)),
+        (0x02, BitrateMode::Abr, Some(160)),
+        (0x09, BitrateMode::Abr, Some(160)),
+        (0x05, BitrateMode::Vbr, None),
+    ] {
+        let path = common::temp_file("lame.mp3", &lame_mp3(vbr_method, 160));
+        let properties = mpeg::probe_path(&path).unwrap();
+
+        assert_eq!(properties.bitrate_mode, mode, "method {}", vbr_method);
+        assert_eq!(properties.nominal_bitrate, nominal, "method {}", vbr_method);
+    }
+}
+
+#[test]
+fn lame_crcs_catch_changed_headers() {
+    let mut bytes = lame_mp3(0x04, 32);
+    // Lower the lowpass after the CRC was computed
+    bytes[XING_AT + 16 + 10] = 180;
+    let path = common::temp_file("lame.mp3", &bytes);
+    let lame = mpeg::probe_path(&path).unwrap().lame().cloned().unwrap();
+
+    assert_eq!(lame.lowpass, Some(18000));
+    assert!(!lame.crc_valid);
+}
+
+#[test]
+fn itunes_gapless_comments_are_parsed() {
+    let value = " 00000000 00000210 000003C0 0000000000AC4400 00000000 00000000";
+    let gapless = ITunesGapless {
+        encoder_delay: 0x210,
+        encoder_padding: 0x3C0,
+        samples: 0xAC4400,
+    };
+    assert_eq!(ITunesGapless::parse(value), Some(gapless));
+    assert_eq!(ITunesGapless::parse("00000000 00000210"), None);
+    assert_eq!(ITunesGapless::parse("00000000 0000021G 000003C0 0"), None);
+
+    let mut tag = Tag::new();
+    tag.add_frame(Frame::with_content(
+        "COMM",
+        Content::Comment(Comment {
+            lang: String::from("eng"),
+            description: String::from("iTunSMPB"),
+            text: String::from(value),
+        }),
+    ));
+    let mut file = Cursor::new(common::mp3(10));
+    Encoder::new()
+        .version(Version::Id3v23)
+        .write_to_file(&tag, &mut file)
+        .unwrap();
+    let path = common::temp_file("gapless.mp3", &file.into_inner());
+
+    assert_eq!(
+        mpeg::probe_path(&path).unwrap().itunes_gapless,
+        Some(gapless)
+    );
+}
+
+#[test]
+fn other_formats_are_not_mpeg_streams() {
+    let path = common::temp_file("sine.wav", &common::sine_wav(44100, 1, 0.1, 0.5));
+    let error = mpeg::probe_path(&path).unwrap_err();
+
+    assert_eq!(error.code, ErrorCode::Parse);
+    assert_eq!(error.path.as_deref(), Some(path.as_str()));
+}
+
+#[test]
+fn sine_loudness_matches_bs1770() {
+    // A 997 Hz sine peaking at -20 dBFS measures -20 LUFS in stereo and -23 LUFS in mono
+    for (channels, expected) in [(2, -20.0), (1, -23.01)] {
+        let wav = common::sine_wav(48000, channels, 5.0, 0.1);
+        let path = common::temp_file("sine.wav", &wav);
+        let measured = loudness::analyze(&path).unwrap();
+
+        let integrated = measured.integrated().unwrap();
+        assert!((integrated - expected).abs() < 0.05, "{}", integrated);
+        assert!((measured.replaygain().unwrap() - (-18.0 - integrated)).abs() < 1e-9);
+        assert!((measured.sample_peak - 0.1).abs() < 0.001);
+        assert!(measured.true_peak >= measured.sample_peak);
+    }
+}
+
+#[test]
+fn silence_has_no_loudness() {
+    let path = common::temp_file("silence.wav", &common::sine_wav(44100, 2, 1.0, 0.0));
+
+    assert_eq!(loudness::analyze(&path).unwrap().integrated(), None);
+}
+
+#[test]
+fn albums_are_measured_as_one_track() {
+    let loud = common::temp_file("loud.wav", &common::sine_wav(48000, 2, 5.0, 0.1));
+    let quiet = common::temp_file("quiet.wav", &common::sine_wav(48000, 2, 5.0, 0.05));
+    let loud = loudness::analyze(&loud).unwrap();
+    let quiet = loudness::analyze(&quiet).unwrap();
+    let album = Loudness::album([&loud, &quiet]);
+
+    let integrated = album.integrated().unwrap();
+    assert!(integrated < loud.integrated().unwrap());
+    assert!(integrated > quiet.integrated().unwrap());
+    assert_eq!(album.sample_peak, loud.sample_peak);
+    assert_eq!(album.true_peak, loud.true_peak);
+}
+
+#[test]
+fn gains_are_written_as_replaygain_texts() {
+    let path = common::temp_file("sine.wav", &common::sine_wav(48000, 2, 5.0, 0.1));
+    let track = loudness::analyze(&path).unwrap();
+    let album = Loudness::album([&track]);
+    loudness::write_gains(
+        &path,
+        &track,
+        Some(&album),
+        WriteVersion::Preserve,
+        SaveOptions::default(),
+    )
+    .unwrap()
+    .unwrap();
+
+    let texts: Vec<(String, String)> = tags::load(&path)
+        .unwrap()
+        .carriers
+        .into_iter()
+        .filter_map(|carrier| match carrier.content {
+            Content::ExtendedText(text) => Some((text.description, text.value)),
+            _ => None,
+        })
+        .collect();
+    let gain = format!("{:.2} dB", track.replaygain().unwrap());
+    let peak = format!("{:.6}", track.true_peak);
+    for name in ["TRACK", "ALBUM"] {
+        assert!(texts.contains(&(format!("REPLAYGAIN_{}_GAIN", name), gain.clone())));
+        assert!(texts.contains(&(format!("REPLAYGAIN_{}_PEAK", name), peak.clone())));
+    }
+}
+
+#[test]
+fn vorbis_comments_get_only_replaygain_fields() {
+    let wav = common::temp_file("sine.wav", &common::sine_wav(48000, 2, 5.0, 0.1));
+    let track = loudness::analyze(&wav).unwrap();
+    let album = Loudness::album([&track]);
+    let path = common::temp_file("song.flac", &common::flac());
+    loudness::write_gains(
+        &path,
+        &track,
+        Some(&album),
+        WriteVersion::Preserve,
+        SaveOptions::default(),
+    )
+    .unwrap()
+    .unwrap();
+
+    let tag = flac::read_from_bytes(&fs::read(&path).unwrap()).unwrap();
+    let names: Vec<&str> = tag
+        .comment
+        .fields
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "REPLAYGAIN_TRACK_GAIN",
+            "REPLAYGAIN_TRACK_PEAK",
+            "REPLAYGAIN_ALBUM_GAIN",
+            "REPLAYGAIN_ALBUM_PEAK"
+        ]
+    );
+}
+
+#[test]
+fn silent_tracks_get_no_gains() {
+    let wav = common::sine_wav(44100, 2, 1.0, 0.0);
+    let path = common::temp_file("silence.wav", &wav);
+    let track = loudness::analyze(&path).unwrap();
+    let written = loudness::write_gains(
+        &path,
+        &track,
+        None,
+        WriteVersion::Preserve,
+        SaveOptions::default(),
+    )
+    .unwrap();
+
+    assert!(written.is_none());
+    assert_eq!(std::fs::read(&path).unwrap(), wav);
+}
+
+#[test]
+fn opus_loudness_is_unsupported() {
+    let path = common::temp_file("song.opus", &common::opus(2));
+    let error = loudness::analyze(&path).unwrap_err();
+
+    assert_eq!(error.code, ErrorCode::UnsupportedCodec);
+    assert_eq!(error.code.as_str(), "ERR_UNSUPPORTED_CODEC");
+    assert_eq!(error.path.as_deref(), Some(path.as_str()));
+}
+
+#[test]
+fn sha256_matches_known_digests() {
+    assert_eq!(
+        hash::sha256_hex(b""),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        hash::sha256_hex(&[b'a'; 1000]),
+        "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+    );
+}
+
+#[test]
+fn lrc_round_trips() {
+    let lines = lrc::parse("[ti:Song]\n[00:01.50]One\n[00:03.00][00:07.25]Two\n");
+
+    assert_eq!(
+        lines,
+        [
+            (1500, String::from("One")),
+            (3000, String::from("Two")),
+            (7250, String::from("Two"))
+        ]
+    );
+    assert_eq!(lrc::parse(&lrc::format(&lines, 0, false)), lines);
+}
+
+#[test]
+fn ratings_map_to_stars() {
+    for convention in [
+        RatingConvention::WindowsMediaPlayer,
+        RatingConvention::Foobar2000,
+        RatingConvention::MusicBee,
+    ] {
+        for stars in [0.0, 1.0, 2.0, 3.0, 4.0, 5.0] {
+            let rating = popularimeter::stars_to_rating(stars, convention);
+            assert_eq!(popularimeter::rating_to_stars(rating, convention), stars);
+        }
+    }
+
+    let counter = popularimeter::encode_play_counter(0x1_0000_0000);
+    assert_eq!(counter.len(), 5);
+    assert_eq!(popularimeter::decode_play_counter(&counter), 0x1_0000_0000);
+}
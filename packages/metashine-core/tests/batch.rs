@@ -0,0 +1,201 @@
+mod common;
+
+use std::{
+    fs,
+    path::Path,
+    sync::{mpsc, Arc, Mutex, OnceLock, Weak},
+    time::Duration,
+};
+
+use metashine_core::{
+    atomic::SaveOptions,
+    id3::Content,
+    id3_version::WriteVersion,
+    job::Job,
+    pool,
+    tags::{self, UpdatedTag},
+    Carrier, ErrorCode, TagError,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+type Results = Vec<Option<Result<UpdatedTag, TagError>>>;
+
+fn title(value: &str) -> Vec<Carrier> {
+    vec![Carrier::new("TIT2", Content::Text(value.to_string()))]
+}
+
+fn update_many(paths: &[String], stop_on_error: bool, job: Job) -> Results {
+    let (sender, receiver) = mpsc::channel();
+    tags::update_many(
+        paths.to_vec(),
+        title("Batch"),
+        WriteVersion::Preserve,
+        SaveOptions::default(),
+        stop_on_error,
+        Arc::new(job),
+        move |results| sender.send(results).unwrap(),
+    );
+    receiver.recv_timeout(TIMEOUT).unwrap()
+}
+
+fn mp3_files(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|_| common::temp_file("batch.mp3", &common::mp3(4)))
+        .collect()
+}
+
+/// The path of a file that doesn't exist, so updating it fails
+fn missing_file() -> String {
+    let path = common::temp_file("missing.mp3", &[]);
+    fs::remove_file(&path).unwrap();
+    path
+}
+
+fn loaded_title(path: &str) -> Option<Content> {
+    tags::load(path)
+        .unwrap()
+        .carriers
+        .into_iter()
+        .find(|carrier| carrier.id == "TIT2")
+        .map(|carrier| carrier.content)
+}
+
+/// Whether the directory of path holds nothing but the file itself, so no temporary copy was
+/// left behind
+fn alone_in_dir(path: &str) -> bool {
+    let dir = Path::new(path).parent().unwrap();
+    fs::read_dir(dir).unwrap().count() == 1
+}
+
+#[test]
+fn best_effort_updates_report_a_result_for_every_file() {
+    let mut paths = mp3_files(4);
+    paths.insert(2, missing_file());
+
+    let results = update_many(&paths, false, Job::new(paths.len()));
+
+    assert_eq!(results.len(), paths.len());
+    for (path, result) in paths.iter().zip(&results) {
+        let result = result
+            .as_ref()
+            .expect("no file is skipped without stopOnError");
+        if path == &paths[2] {
+            let error = result.as_ref().unwrap_err();
+            assert_eq!(error.code, ErrorCode::Io);
+            assert_eq!(error.path.as_deref(), Some(path.as_str()));
+        } else {
+            assert!(result.is_ok());
+            assert_eq!(
+                loaded_title(path),
+                Some(Content::Text(String::from("Batch")))
+            );
+            assert!(alone_in_dir(path));
+        }
+    }
+}
+
+#[test]
+fn stop_on_error_skips_files_not_started() {
+    // With a single worker the files are updated in order, so none is started after the failure
+    pool::global().set_limit(1);
+    let mut paths = mp3_files(3);
+    paths.insert(1, missing_file());
+
+    let results = update_many(&paths, true, Job::new(paths.len()));
+
+    assert!(matches!(results[0], Some(Ok(_))));
+    assert!(matches!(results[1], Some(Err(_))));
+    assert!(results[2].is_none());
+    assert!(results[3].is_none());
+    assert_eq!(
+        loaded_title(&paths[0]),
+        Some(Content::Text(String::from("Batch")))
+    );
+    for path in &paths[2..] {
+        assert_eq!(fs::read(path).unwrap(), common::mp3(4));
+    }
+}
+
+#[test]
+fn updates_report_progress_for_every_file() {
+    let paths = mp3_files(3);
+    let (sender, receiver) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    let job = Job::new(paths.len()).with_progress(Box::new(move |path, progress| {
+        sender
+            .lock()
+            .unwrap()
+            .send((path.to_string(), *progress))
+            .unwrap();
+    }));
+
+    update_many(&paths, false, job);
+
+    // Files may finish on several workers at once, so reports can arrive out of order
+    let mut reports: Vec<_> = receiver.try_iter().collect();
+    reports.sort_by_key(|(_, progress)| progress.files_done);
+    assert_eq!(reports.len(), paths.len());
+    let file_size = fs::metadata(&paths[0]).unwrap().len();
+    for (i, (path, progress)) in (1..).zip(&reports) {
+        assert!(paths.contains(path));
+        assert_eq!(progress.files_done, i);
+        assert_eq!(progress.files_total, paths.len());
+        assert_eq!(progress.bytes_processed, i as u64 * file_size);
+    }
+}
+
+#[test]
+fn cancelled_batches_leave_remaining_files_untouched() {
+    // With a single worker, cancelling once the second file is finished cancels all the others
+    pool::global().set_limit(1);
+    let paths = mp3_files(5);
+    // The callback cancels the job it belongs to, which only exists once the callback does
+    let this_job: Arc<OnceLock<Weak<Job>>> = Arc::new(OnceLock::new());
+    let cancelled = Arc::clone(&this_job);
+    let job = Arc::new(
+        Job::new(paths.len()).with_progress(Box::new(move |_, progress| {
+            if progress.files_done == 2 {
+                cancelled.get().and_then(Weak::upgrade).unwrap().cancel();
+            }
+        })),
+    );
+    this_job.set(Arc::downgrade(&job)).unwrap();
+    let (sender, receiver) = mpsc::channel();
+    tags::update_many(
+        paths.clone(),
+        title("Batch"),
+        WriteVersion::Preserve,
+        SaveOptions::default(),
+        false,
+        job,
+        move |results| sender.send(results).unwrap(),
+    );
+    let results = receiver.recv_timeout(TIMEOUT).unwrap();
+
+    assert_eq!(
+        results
+            .iter()
+            .filter(|result| matches!(result, Some(Ok(_))))
+            .count(),
+        2
+    );
+    for (i, (path, result)) in paths.iter().zip(results).enumerate() {
+        match result.unwrap() {
+            Ok(_) => {
+                assert!(i < 2);
+                assert_eq!(
+                    loaded_title(path),
+                    Some(Content::Text(String::from("Batch")))
+                );
+            }
+            Err(error) => {
+                assert!(i >= 2);
+                assert_eq!(error.code, ErrorCode::Cancelled);
+                assert_eq!(error.path.as_deref(), Some(path.as_str()));
+                assert_eq!(fs::read(path).unwrap(), common::mp3(4));
+            }
+        }
+        assert!(alone_in_dir(path));
+    }
+}
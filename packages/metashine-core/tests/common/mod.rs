@@ -0,0 +1,332 @@
+//! Synthetic audio files for the tests
+
+#![allow(dead_code)]
+
+use std::{
+    f64::consts::PI,
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Header of a 417 byte MPEG-1 layer III frame at 128 kbit/s and 44.1 kHz, joint stereo
+pub const MP3_FRAME_HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x44];
+pub const MP3_FRAME_LEN: usize = 417;
+
+/// An MP3 stream of silent frames without any tag
+pub fn mp3(frames: usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(frames * MP3_FRAME_LEN);
+    for _ in 0..frames {
+        bytes.extend_from_slice(&MP3_FRAME_HEADER);
+        bytes.resize(bytes.len() + MP3_FRAME_LEN - 4, 0);
+    }
+    bytes
+}
+
+/// A FLAC stream with only a STREAMINFO block, followed by bytes standing for audio frames
+pub fn flac() -> Vec<u8> {
+    let mut bytes = b"fLaC".to_vec();
+    bytes.extend_from_slice(&[0x80, 0, 0, 34]);
+    // 4096 sample blocks, unknown frame sizes, 44.1 kHz stereo 16 bit, 44100 samples
+    bytes.extend_from_slice(&[0x10, 0x00, 0x10, 0x00, 0, 0, 0, 0, 0, 0]);
+    bytes.extend_from_slice(&[0x0A, 0xC4, 0x42, 0xF0, 0x00, 0x00, 0xAC, 0x44]);
+    bytes.extend_from_slice(&[0; 16]);
+    bytes.extend_from_slice(&[0xFF, 0xF8, 0x69, 0x08, 0x00, 0x00, 0x00, 0x00]);
+    bytes
+}
+
+/// A FLAC stream like `flac` with the given metadata blocks after its STREAMINFO block
+pub fn flac_with_blocks(blocks: &[(u8, Vec<u8>)]) -> Vec<u8> {
+    let plain = flac();
+    let mut bytes = plain[..42].to_vec();
+    bytes[4] = 0;
+    for (i, (block_type, data)) in blocks.iter().enumerate() {
+        let last = if i == blocks.len() - 1 { 0x80 } else { 0 };
+        let len = (data.len() as u32).to_be_bytes();
+        bytes.extend_from_slice(&[last | block_type, len[1], len[2], len[3]]);
+        bytes.extend_from_slice(data);
+    }
+    bytes.extend_from_slice(&plain[42..]);
+    bytes
+}
+
+/// The body of a Vorbis comment holding the fields as they are given
+pub fn vorbis_comment(vendor: &str, fields: &[&str]) -> Vec<u8> {
+    let mut bytes = (vendor.len() as u32).to_le_bytes().to_vec();
+    bytes.extend_from_slice(vendor.as_bytes());
+    bytes.extend_from_slice(&(fields.len() as u32).to_le_bytes());
+    for field in fields {
+        bytes.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(field.as_bytes());
+    }
+    bytes
+}
+
+/// CRC-32 of an Ogg page with its checksum field taken as zero, computed bit by bit
+pub fn ogg_crc(page: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for (i, byte) in page.iter().enumerate() {
+        let byte = if (22..26).contains(&i) { 0 } else { *byte };
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Serial number of the logical stream in the Ogg files built here
+pub const OGG_SERIAL: u32 = 0x1234_5678;
+
+/// An Ogg page holding whole packets
+pub fn ogg_page(header_type: u8, granule: u64, sequence: u32, packets: &[&[u8]]) -> Vec<u8> {
+    let mut lacing = Vec::new();
+    let mut body = Vec::new();
+    for packet in packets {
+        lacing.resize(lacing.len() + packet.len() / 255, 255);
+        lacing.push((packet.len() % 255) as u8);
+        body.extend_from_slice(packet);
+    }
+
+    let mut page = b"OggS\0".to_vec();
+    page.push(header_type);
+    page.extend_from_slice(&granule.to_le_bytes());
+    page.extend_from_slice(&OGG_SERIAL.to_le_bytes());
+    page.extend_from_slice(&sequence.to_le_bytes());
+    page.extend_from_slice(&[0; 4]);
+    page.push(lacing.len() as u8);
+    page.extend_from_slice(&lacing);
+    page.extend_from_slice(&body);
+    let crc = ogg_crc(&page);
+    page[22..26].copy_from_slice(&crc.to_le_bytes());
+    page
+}
+
+/// The fields of an Ogg page header tests look at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OggPage {
+    pub header_type: u8,
+    pub granule: u64,
+    pub sequence: u32,
+    pub lacing: Vec<u8>,
+    pub body: Vec<u8>,
+    pub crc_matches: bool,
+}
+
+pub fn ogg_pages(bytes: &[u8]) -> Vec<OggPage> {
+    let mut pages = Vec::new();
+    let mut position = 0;
+    while position < bytes.len() {
+        let header = &bytes[position..position + 27];
+        assert_eq!(&header[..4], b"OggS");
+        let lacing = bytes[position + 27..position + 27 + header[26] as usize].to_vec();
+        let body_start = position + 27 + lacing.len();
+        let end = body_start + lacing.iter().map(|len| *len as usize).sum::<usize>();
+        let crc = u32::from_le_bytes([header[22], header[23], header[24], header[25]]);
+
+        let mut granule = [0; 8];
+        granule.copy_from_slice(&header[6..14]);
+        pages.push(OggPage {
+            header_type: header[5],
+            granule: u64::from_le_bytes(granule),
+            sequence: u32::from_le_bytes([header[18], header[19], header[20], header[21]]),
+            body: bytes[body_start..end].to_vec(),
+            lacing,
+            crc_matches: ogg_crc(&bytes[position..end]) == crc,
+        });
+        position = end;
+    }
+    pages
+}
+
+/// Joins the segments of Ogg pages back into packets
+pub fn ogg_packets(pages: &[OggPage]) -> Vec<Vec<u8>> {
+    let mut packets = Vec::new();
+    let mut packet = Vec::new();
+    for page in pages {
+        let mut offset = 0;
+        for len in &page.lacing {
+            packet.extend_from_slice(&page.body[offset..offset + *len as usize]);
+            offset += *len as usize;
+            if *len < 255 {
+                packets.push(std::mem::take(&mut packet));
+            }
+        }
+    }
+    packets
+}
+
+/// An Opus stream with an empty comment header and audio pages of one 100 byte packet each
+pub fn opus(audio_pages: u32) -> Vec<u8> {
+    let mut head = b"OpusHead".to_vec();
+    // Version 1, stereo, 312 samples of pre-skip, 48 kHz, no gain, channel mapping family 0
+    head.extend_from_slice(&[1, 2, 0x38, 0x01, 0x80, 0xBB, 0, 0, 0, 0, 0]);
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend_from_slice(&vorbis_comment("test", &[]));
+
+    let mut bytes = ogg_page(0x02, 0, 0, &[&head]);
+    bytes.extend_from_slice(&ogg_page(0, 0, 1, &[&tags]));
+    for i in 0..audio_pages {
+        let header_type = if i == audio_pages - 1 { 0x04 } else { 0 };
+        let packet = [i as u8; 100];
+        let granule = 960 * (i as u64 + 1);
+        bytes.extend_from_slice(&ogg_page(header_type, granule, i + 2, &[&packet]));
+    }
+    bytes
+}
+
+/// An Ogg Vorbis stream whose comment and setup headers share a page, followed by one audio page
+pub fn ogg_vorbis() -> Vec<u8> {
+    let mut identification = b"\x01vorbis".to_vec();
+    // Version 0, stereo, 44.1 kHz, no bitrates, 256 and 2048 sample blocks, framing bit
+    identification.extend_from_slice(&[0, 0, 0, 0, 2, 0x44, 0xAC, 0, 0]);
+    identification.extend_from_slice(&[0; 12]);
+    identification.extend_from_slice(&[0xB8, 1]);
+    let mut comment = b"\x03vorbis".to_vec();
+    comment.extend_from_slice(&vorbis_comment("test", &["TITLE=Title"]));
+    comment.push(1);
+    let mut setup = b"\x05vorbis".to_vec();
+    setup.extend_from_slice(&[0x5A; 300]);
+
+    let mut bytes = ogg_page(0x02, 0, 0, &[&identification]);
+    bytes.extend_from_slice(&ogg_page(0, 0, 1, &[&comment, &setup]));
+    bytes.extend_from_slice(&ogg_page(0x04, 1024, 2, &[&[7; 50]]));
+    bytes
+}
+
+/// An MP4 atom of a type with the given body
+pub fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut bytes = ((8 + body.len()) as u32).to_be_bytes().to_vec();
+    bytes.extend_from_slice(kind);
+    bytes.extend_from_slice(body);
+    bytes
+}
+
+/// An `ilst` item holding one data atom
+pub fn mp4_item(kind: &[u8; 4], type_code: u32, value: &[u8]) -> Vec<u8> {
+    let mut data = type_code.to_be_bytes().to_vec();
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(value);
+    atom(kind, &atom(b"data", &data))
+}
+
+/// The `udta` atom iTunes writes around an item list
+pub fn mp4_udta(ilst: &[u8]) -> Vec<u8> {
+    let mut handler = vec![0; 8];
+    handler.extend_from_slice(b"mdirappl");
+    handler.extend_from_slice(&[0; 9]);
+    let mut meta = vec![0; 4];
+    meta.extend_from_slice(&atom(b"hdlr", &handler));
+    meta.extend_from_slice(&atom(b"ilst", ilst));
+    atom(b"udta", &atom(b"meta", &meta))
+}
+
+/// Chunks of the media data in the MP4 files built here, found through their offsets
+pub const MP4_CHUNKS: [&[u8; 4]; 2] = [b"CHK0", b"CHK1"];
+
+/// An MP4 file whose `moov` comes before `mdat`, with a `stco` or `co64` table pointing at
+/// the chunks of `MP4_CHUNKS` and the given `udta` atom
+pub fn mp4(offsets: &[u8; 4], udta: Option<&[u8]>) -> Vec<u8> {
+    let mut ftyp = b"M4A ".to_vec();
+    ftyp.extend_from_slice(&[0; 4]);
+    ftyp.extend_from_slice(b"M4A mp42isom");
+    let ftyp = atom(b"ftyp", &ftyp);
+
+    let mut mdat = MP4_CHUNKS[0].to_vec();
+    mdat.extend_from_slice(&[0; 60]);
+    mdat.extend_from_slice(MP4_CHUNKS[1]);
+    mdat.extend_from_slice(&[0; 60]);
+    let chunk_offsets = [0, 64];
+
+    let moov = |mdat_start: u64| {
+        let mut table = vec![0; 4];
+        table.extend_from_slice(&(chunk_offsets.len() as u32).to_be_bytes());
+        for offset in chunk_offsets {
+            let offset = mdat_start + 8 + offset;
+            match offsets {
+                b"stco" => table.extend_from_slice(&(offset as u32).to_be_bytes()),
+                _ => table.extend_from_slice(&offset.to_be_bytes()),
+            }
+        }
+        let stbl = atom(b"stbl", &atom(offsets, &table));
+        let trak = atom(b"trak", &atom(b"mdia", &atom(b"minf", &stbl)));
+
+        let mut body = atom(b"mvhd", &[0; 100]);
+        body.extend_from_slice(&trak);
+        body.extend_from_slice(udta.unwrap_or_default());
+        atom(b"moov", &body)
+    };
+    let mdat_start = (ftyp.len() + moov(0).len()) as u64;
+
+    let mut bytes = ftyp;
+    bytes.extend_from_slice(&moov(mdat_start));
+    bytes.extend_from_slice(&atom(b"mdat", &mdat));
+    bytes
+}
+
+/// Reads the entries of the first `stco` or `co64` table of an MP4 file
+pub fn mp4_chunk_offsets(bytes: &[u8]) -> Vec<u64> {
+    let find = |kind: &[u8]| bytes.windows(4).position(|window| window == kind);
+    let (position, width) = match (find(b"stco"), find(b"co64")) {
+        (Some(position), _) => (position, 4),
+        (_, Some(position)) => (position, 8),
+        _ => panic!("the file has no chunk offset table"),
+    };
+    let table = &bytes[position + 8..];
+    let count = u32::from_be_bytes([table[0], table[1], table[2], table[3]]) as usize;
+    table[4..4 + count * width]
+        .chunks_exact(width)
+        .map(|entry| {
+            entry
+                .iter()
+                .fold(0u64, |offset, byte| (offset << 8) | *byte as u64)
+        })
+        .collect()
+}
+
+/// A 16 bit PCM WAV file holding a 997 Hz sine on every channel with the given peak
+pub fn sine_wav(sample_rate: u32, channels: u16, seconds: f64, peak: f64) -> Vec<u8> {
+    let frames = (sample_rate as f64 * seconds) as u32;
+    let data_len = frames * channels as u32 * 2;
+
+    let mut bytes = b"RIFF".to_vec();
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&channels.to_le_bytes());
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(sample_rate * channels as u32 * 2).to_le_bytes());
+    bytes.extend_from_slice(&(channels * 2).to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+
+    for i in 0..frames {
+        let value = peak * (2.0 * PI * 997.0 * i as f64 / sample_rate as f64).sin();
+        let sample = (value * i16::MAX as f64).round() as i16;
+        for _ in 0..channels {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+/// Writes bytes to a new file in a directory of the system temporary directory
+pub fn temp_file(name: &str, bytes: &[u8]) -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let dir: PathBuf = std::env::temp_dir().join(format!(
+        "metashine-core-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, bytes).unwrap();
+    path.to_string_lossy().into_owned()
+}
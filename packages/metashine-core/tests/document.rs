@@ -0,0 +1,178 @@
+mod common;
+
+use std::{fs, io::Cursor};
+
+use metashine_core::{
+    atomic::SaveOptions,
+    carrier::Carrier,
+    document::{DocumentFormat, DocumentTag, TagDocument, TextEncoding},
+    id3::{
+        frame::{
+            Chapter, Comment, EncapsulatedObject, ExtendedText, Picture, PictureType, Private,
+            Unknown,
+        },
+        Content, Encoder, Encoding, Frame, Tag, Version,
+    },
+    id3_version::WriteVersion,
+    tags, ErrorCode,
+};
+
+fn frame(id: &str, content: Content, encoding: Encoding) -> Frame {
+    Frame::with_content(id, content).set_encoding(Some(encoding))
+}
+
+fn comment(text: &str) -> Content {
+    Content::Comment(Comment {
+        lang: String::from("eng"),
+        description: String::new(),
+        text: text.to_string(),
+    })
+}
+
+/// An MP3 file whose tag holds what reading a whole tag with the id3 crate loses
+fn mp3_with_stored_details() -> Vec<u8> {
+    let mut picture = frame(
+        "APIC",
+        Content::Picture(Picture {
+            mime_type: String::from("image/png"),
+            picture_type: PictureType::CoverFront,
+            description: String::from("front"),
+            data: vec![0, 1, 2, 0xff],
+        }),
+        Encoding::Latin1,
+    );
+    picture.set_file_alter_preservation(true);
+
+    let tag: Tag = vec![
+        frame(
+            "TIT2",
+            Content::Text(String::from("Title")),
+            Encoding::Latin1,
+        ),
+        frame(
+            "TXXX",
+            Content::ExtendedText(ExtendedText {
+                description: String::from("MOOD"),
+                value: String::from("calm"),
+            }),
+            Encoding::UTF16,
+        ),
+        // The id3 crate keeps only the last of two comments with the same description
+        frame("COMM", comment("First"), Encoding::UTF8),
+        frame("COMM", comment("Second"), Encoding::UTF16BE),
+        picture,
+        frame(
+            "GEOB",
+            Content::EncapsulatedObject(EncapsulatedObject {
+                mime_type: String::from("application/octet-stream"),
+                filename: String::from("a.bin"),
+                description: String::new(),
+                data: vec![9, 8, 7],
+            }),
+            Encoding::UTF8,
+        ),
+        Frame::with_content(
+            "PRIV",
+            Content::Private(Private {
+                owner_identifier: String::from("metashine"),
+                private_data: vec![1, 2, 3],
+            }),
+        ),
+        Frame::with_content(
+            "XABC",
+            Content::Unknown(Unknown {
+                data: vec![0xde, 0xad],
+                version: Version::Id3v24,
+            }),
+        ),
+        Frame::with_content(
+            "CHAP",
+            Content::Chapter(Chapter {
+                element_id: String::from("ch0"),
+                start_time: 0,
+                end_time: 1000,
+                start_offset: u32::MAX,
+                end_offset: u32::MAX,
+                frames: vec![frame(
+                    "TIT2",
+                    Content::Text(String::from("Intro")),
+                    Encoding::Latin1,
+                )],
+            }),
+        ),
+    ]
+    .into_iter()
+    .collect();
+
+    let mut file = Cursor::new(common::mp3(2));
+    Encoder::new()
+        .version(Version::Id3v24)
+        .padding(0)
+        .write_to_file(&tag, &mut file)
+        .unwrap();
+    file.into_inner()
+}
+
+#[test]
+fn id3_tags_are_restored_byte_for_byte() {
+    let original = mp3_with_stored_details();
+    let path = common::temp_file("song.mp3", &original);
+
+    let document = tags::export(&path).unwrap();
+    let frames = match &document.tag {
+        DocumentTag::Id3(id3) => &id3.frames,
+        _ => panic!("MP3 files export ID3 documents"),
+    };
+    assert_eq!(frames.len(), 9);
+    assert_eq!(frames[3].encoding, Some(TextEncoding::Utf16Be));
+    assert!(frames[4].file_alter_preservation);
+
+    for format in [DocumentFormat::Json, DocumentFormat::Yaml].iter() {
+        let text = document.to_text(*format);
+        assert_eq!(TagDocument::parse(&text).unwrap(), document);
+    }
+
+    assert!(tags::strip(&path, SaveOptions::default()).unwrap());
+    let updated = tags::import(&path, &document, SaveOptions::default()).unwrap();
+    assert_eq!(updated.carriers.len(), 9);
+    assert_eq!(fs::read(&path).unwrap(), original);
+}
+
+#[test]
+fn vorbis_comments_round_trip_through_yaml() {
+    let (bytes, _) = tags::update_bytes(
+        &common::flac(),
+        &[
+            Carrier::new("TITLE", Content::Text(String::from("Title"))),
+            Carrier::new("ARTIST", Content::Text(String::from("One\0Two"))),
+        ],
+        WriteVersion::Preserve,
+    )
+    .unwrap();
+    let path = common::temp_file("song.flac", &bytes);
+
+    let text = tags::export(&path).unwrap().to_text(DocumentFormat::Yaml);
+    let document = TagDocument::parse(&text).unwrap();
+    tags::strip(&path, SaveOptions::default()).unwrap();
+    tags::import(&path, &document, SaveOptions::default()).unwrap();
+    assert_eq!(tags::export(&path).unwrap(), document);
+}
+
+#[test]
+fn mismatched_documents_are_rejected() {
+    let mp3 = common::temp_file("song.mp3", &mp3_with_stored_details());
+    let flac = common::temp_file("song.flac", &common::flac());
+    let document = tags::export(&mp3).unwrap();
+
+    let error = tags::import(&flac, &document, SaveOptions::default()).unwrap_err();
+    assert_eq!(error.code, ErrorCode::BadDocument);
+    assert_eq!(fs::read(&flac).unwrap(), common::flac());
+
+    let text = document
+        .to_text(DocumentFormat::Json)
+        .replacen("\"schema\": 1", "\"schema\": 2", 1);
+    assert_eq!(
+        TagDocument::parse(&text).unwrap_err().code,
+        ErrorCode::BadDocument
+    );
+}
@@ -0,0 +1,56 @@
+use std::{sync::mpsc, time::Duration};
+
+use metashine_core::{pool, ErrorCode, TagError};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[test]
+fn panicking_items_still_settle_their_batch() {
+    let (sender, receiver) = mpsc::channel();
+    pool::map(
+        (0..8).collect(),
+        |i: u32| {
+            if i == 3 {
+                panic!("item {} is broken", i);
+            }
+            i * 2
+        },
+        move |results| sender.send(results).unwrap(),
+    );
+
+    let results = receiver.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(results.len(), 8);
+    for (i, result) in (0..).zip(&results) {
+        match result {
+            Ok(double) => assert_eq!(*double, i * 2),
+            Err(error) => {
+                assert_eq!(i, 3);
+                assert_eq!(error.code, ErrorCode::Internal);
+                assert_eq!(error.message, "Worker panicked: item 3 is broken");
+            }
+        }
+    }
+    assert!(results[3].is_err());
+}
+
+#[test]
+fn workers_outlive_panicking_jobs() {
+    // With a single worker, a job queued after a panicking one only runs if the worker survived
+    pool::global().set_limit(1);
+    let (sender, receiver) = mpsc::channel();
+    pool::global().spawn(|| panic!("broken job"));
+    pool::global().spawn(move || sender.send("done").unwrap());
+
+    assert_eq!(receiver.recv_timeout(TIMEOUT), Ok("done"));
+    pool::global().set_limit(4);
+}
+
+#[test]
+fn panics_become_internal_errors() {
+    let error = pool::catch(|| -> Result<(), TagError> { panic!("{}", String::from("owned")) })
+        .unwrap_err();
+    assert_eq!(error.code, ErrorCode::Internal);
+    assert_eq!(error.message, "Worker panicked: owned");
+
+    assert_eq!(pool::catch(|| Ok::<_, TagError>(1)).unwrap(), 1);
+}
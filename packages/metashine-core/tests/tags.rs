@@ -0,0 +1,1244 @@
+mod common;
+
+use std::fs;
+
+use metashine_core::{
+    atomic::SaveOptions,
+    carrier::{self, Carrier},
+    chapters, flac,
+    format::{Layout, TagFormat},
+    id3::{
+        frame::{
+            Chapter, Comment, EncapsulatedObject, ExtendedText, InvolvedPeopleList,
+            InvolvedPeopleListItem, Picture, PictureType, Popularimeter, Private,
+            SynchronisedLyrics, SynchronisedLyricsType, TableOfContents, TimestampFormat,
+            UniqueFileIdentifier, Unknown,
+        },
+        Content, Frame, Version,
+    },
+    id3_header::{self, HeaderFlags, Id3Header},
+    id3_version::WriteVersion,
+    lrc, mp4, ogg, popularimeter, tags, ErrorCode, FrameKind,
+};
+
+fn text(id: &str, value: &str) -> Carrier {
+    Carrier::new(id, Content::Text(value.to_string()))
+}
+
+fn removal(mut carrier: Carrier) -> Carrier {
+    carrier.remove = true;
+    carrier
+}
+
+fn picture(data: &[u8]) -> Content {
+    Content::Picture(Picture {
+        mime_type: String::from("image/png"),
+        picture_type: PictureType::CoverFront,
+        description: String::from("front"),
+        data: data.to_vec(),
+    })
+}
+
+#[test]
+fn untagged_mp3_loads_an_empty_id3_tag() {
+    let tag = tags::load_from_bytes(&common::mp3(4)).unwrap();
+
+    assert_eq!(tag.format, TagFormat::Id3);
+    assert!(!tag.had_tag);
+    assert!(tag.header.is_none());
+    assert!(tag.carriers.is_empty());
+}
+
+#[test]
+fn id3_frames_round_trip_in_memory() {
+    let audio = common::mp3(4);
+    let mods = vec![
+        text("TIT2", "Title"),
+        Carrier::new(
+            "TXXX",
+            Content::ExtendedText(ExtendedText {
+                description: String::from("MOOD"),
+                value: String::from("calm"),
+            }),
+        ),
+        Carrier::new(
+            "COMM",
+            Content::Comment(Comment {
+                lang: String::from("eng"),
+                description: String::new(),
+                text: String::from("A comment"),
+            }),
+        ),
+        Carrier::new("APIC", picture(&[1, 2, 3])),
+    ];
+
+    let (bytes, updated) = tags::update_bytes(&audio, &mods, WriteVersion::Preserve).unwrap();
+    assert_eq!(updated.carriers, mods);
+    assert!(bytes.ends_with(&audio), "the audio is kept after the tag");
+
+    let loaded = tags::load_from_bytes(&bytes).unwrap();
+    assert!(loaded.had_tag);
+    assert_eq!(loaded.header.unwrap().version_name(), "2.4");
+    assert_eq!(loaded.carriers, mods);
+    let kinds: Vec<FrameKind> = loaded.carriers.iter().map(Carrier::kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            FrameKind::Text,
+            FrameKind::ExtendedText,
+            FrameKind::Comment,
+            FrameKind::Picture
+        ]
+    );
+}
+
+#[test]
+fn in_memory_updates_match_file_updates() {
+    let files = [
+        ("song.mp3", common::mp3(2), text("TIT2", "Title")),
+        (
+            "song.wav",
+            common::sine_wav(8000, 1, 0.01, 0.5),
+            text("TIT2", "Title"),
+        ),
+        ("song.flac", common::flac(), text("TIT2", "Title")),
+        ("song.opus", common::opus(2), text("TIT2", "Title")),
+        (
+            "song.m4a",
+            common::mp4(b"stco", None),
+            text("TIT2", "Title"),
+        ),
+    ];
+
+    for (name, bytes, title) in files {
+        let mods = [title];
+        let (output, updated) = tags::update_bytes(&bytes, &mods, WriteVersion::Preserve).unwrap();
+        assert_eq!(updated.carriers, mods, "{}", name);
+        assert_eq!(
+            tags::load_from_bytes(&output).unwrap().carriers,
+            mods,
+            "{}",
+            name
+        );
+
+        let path = common::temp_file(name, &bytes);
+        tags::update(&path, &mods, WriteVersion::Preserve, SaveOptions::default()).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), output, "{}", name);
+    }
+}
+
+#[test]
+fn removal_carriers_only_remove_their_frame() {
+    let (bytes, _) = tags::update_bytes(
+        &common::mp3(2),
+        &[text("TIT2", "Title"), text("TPE1", "Artist")],
+        WriteVersion::Preserve,
+    )
+    .unwrap();
+
+    let (bytes, updated) =
+        tags::update_bytes(&bytes, &[removal(text("TIT2", ""))], WriteVersion::Preserve).unwrap();
+    assert_eq!(updated.carriers, [text("TPE1", "Artist")]);
+    assert_eq!(
+        tags::load_from_bytes(&bytes).unwrap().carriers,
+        [text("TPE1", "Artist")]
+    );
+}
+
+#[test]
+fn removals_match_frames_on_their_identity() {
+    let comment = |lang: &str, description: &str| {
+        Carrier::new(
+            "COMM",
+            Content::Comment(Comment {
+                lang: lang.to_string(),
+                description: description.to_string(),
+                text: String::from("text"),
+            }),
+        )
+    };
+    let extended_text = |description: &str| {
+        Carrier::new(
+            "TXXX",
+            Content::ExtendedText(ExtendedText {
+                description: description.to_string(),
+                value: String::from("value"),
+            }),
+        )
+    };
+    let picture = |picture_type: PictureType, description: &str| {
+        Carrier::new(
+            "APIC",
+            Content::Picture(Picture {
+                mime_type: String::from("image/png"),
+                picture_type,
+                description: description.to_string(),
+                data: vec![1, 2, 3],
+            }),
+        )
+    };
+    let private = |owner: &str| {
+        Carrier::new(
+            "PRIV",
+            Content::Private(Private {
+                owner_identifier: owner.to_string(),
+                private_data: vec![1],
+            }),
+        )
+    };
+    let unique_file_identifier = |owner: &str| {
+        Carrier::new(
+            "UFID",
+            Content::UniqueFileIdentifier(UniqueFileIdentifier {
+                owner_identifier: owner.to_string(),
+                identifier: vec![2],
+            }),
+        )
+    };
+    let frames = [
+        comment("eng", ""),
+        comment("eng", "other"),
+        comment("deu", ""),
+        extended_text("A"),
+        extended_text("B"),
+        picture(PictureType::CoverFront, "front"),
+        picture(PictureType::CoverBack, "back"),
+        private("a"),
+        private("b"),
+        unique_file_identifier("a"),
+        unique_file_identifier("b"),
+    ];
+    let (bytes, _) = tags::update_bytes(&common::mp3(2), &frames, WriteVersion::Preserve).unwrap();
+
+    // Values don't matter, and a picture with another description is another frame
+    let removals = [
+        comment("eng", ""),
+        extended_text("A"),
+        picture(PictureType::CoverFront, "front"),
+        picture(PictureType::CoverBack, "other"),
+        private("b"),
+        unique_file_identifier("a"),
+    ]
+    .map(|mut carrier| {
+        carrier.remove = true;
+        if let Content::Comment(comment) = &mut carrier.content {
+            comment.text.clear();
+        }
+        carrier
+    });
+    let (_, updated) = tags::update_bytes(&bytes, &removals, WriteVersion::Preserve).unwrap();
+    assert_eq!(
+        updated.carriers,
+        [
+            comment("eng", "other"),
+            comment("deu", ""),
+            extended_text("B"),
+            picture(PictureType::CoverBack, "back"),
+            private("a"),
+            unique_file_identifier("b"),
+        ]
+    );
+}
+
+#[test]
+fn id3_version_can_be_converted() {
+    let (bytes, _) = tags::update_bytes(
+        &common::mp3(2),
+        &[text("TIT2", "Title"), text("TDRC", "2021")],
+        WriteVersion::Version(Version::Id3v23),
+    )
+    .unwrap();
+
+    let loaded = tags::load_from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.header.unwrap().version_name(), "2.3");
+    assert_eq!(
+        loaded.carriers,
+        [text("TIT2", "Title"), text("TYER", "2021")]
+    );
+}
+
+fn involved_people(id: &str, items: &[(&str, &str)]) -> Carrier {
+    Carrier::new(
+        id,
+        Content::InvolvedPeopleList(InvolvedPeopleList {
+            items: items
+                .iter()
+                .map(|(involvement, involvee)| InvolvedPeopleListItem {
+                    involvement: involvement.to_string(),
+                    involvee: involvee.to_string(),
+                })
+                .collect(),
+        }),
+    )
+}
+
+/// An ID3v2.3 tag holding a TIT2 frame after the given extended header, then padding
+fn id3v23_tag(flags: u8, extended_header: &[u8], padding: usize) -> Vec<u8> {
+    let mut body = extended_header.to_vec();
+    body.extend_from_slice(b"TIT2\0\0\0\x06\0\0\0Title");
+    body.resize(body.len() + padding, 0);
+
+    let size = body.len() as u32;
+    let mut tag = vec![b'I', b'D', b'3', 3, 0, flags];
+    tag.extend((0..4).rev().map(|i| ((size >> (7 * i)) & 0x7f) as u8));
+    tag.extend_from_slice(&body);
+    tag
+}
+
+#[test]
+fn id3_headers_report_flags_size_and_padding() {
+    // Unsynchronisation and experimental flags, no byte to unsynchronise
+    let mut mp3 = id3v23_tag(0xA0, &[], 20);
+    mp3.extend_from_slice(&common::mp3(2));
+    let loaded = tags::load_from_bytes(&mp3).unwrap();
+    assert!(loaded.had_tag);
+    assert_eq!(loaded.carriers, [text("TIT2", "Title")]);
+    assert_eq!(
+        loaded.header,
+        Some(Id3Header {
+            major: 3,
+            revision: 0,
+            flags: HeaderFlags {
+                unsynchronisation: true,
+                extended_header: false,
+                experimental: true,
+                footer: false,
+            },
+            tag_size: 10 + 16 + 20,
+            padding_size: 20,
+        })
+    );
+
+    // A 10 byte extended header is neither frames nor padding
+    let extended_header = [0, 0, 0, 6, 0, 0, 0, 0, 0, 10];
+    let mut mp3 = id3v23_tag(0x40, &extended_header, 10);
+    mp3.extend_from_slice(&common::mp3(2));
+    let header = id3_header::read_from_bytes(&mp3).unwrap().unwrap();
+    assert!(header.flags.extended_header);
+    assert_eq!(header.tag_size, 10 + 10 + 16 + 10);
+    assert_eq!(header.padding_size, 10);
+}
+
+#[test]
+fn id3_chunks_of_wav_files_are_found() {
+    let mut wav = common::sine_wav(8000, 1, 0.01, 0.5);
+    let tag = id3v23_tag(0, &[], 4);
+    wav.extend_from_slice(b"id3 ");
+    wav.extend_from_slice(&(tag.len() as u32).to_le_bytes());
+    wav.extend_from_slice(&tag);
+    let riff_size = (wav.len() as u32 - 8).to_le_bytes();
+    wav[4..8].copy_from_slice(&riff_size);
+
+    let header = id3_header::read_from_bytes(&wav).unwrap().unwrap();
+    assert_eq!(header.version_name(), "2.3");
+    assert_eq!(header.padding_size, 4);
+    assert_eq!(id3_header::read_from_bytes(&common::mp3(1)).unwrap(), None);
+}
+
+#[test]
+fn dates_and_involved_people_are_translated_between_versions() {
+    // Sorted by ID like the frames read back below
+    let v24 = [
+        text("TDOR", "1999"),
+        text("TDRC", "2021-05-04T13:45"),
+        involved_people("TIPL", &[("producer", "Someone")]),
+    ];
+    let (bytes, _) = tags::update_bytes(
+        &common::mp3(2),
+        &v24,
+        WriteVersion::Version(Version::Id3v24),
+    )
+    .unwrap();
+
+    let (bytes, _) =
+        tags::update_bytes(&bytes, &[], WriteVersion::Version(Version::Id3v23)).unwrap();
+    let loaded = tags::load_from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.header.unwrap().version_name(), "2.3");
+    let mut carriers = loaded.carriers;
+    carriers.sort_by(|a, b| a.id.cmp(&b.id));
+    assert_eq!(
+        carriers,
+        [
+            involved_people("IPLS", &[("producer", "Someone")]),
+            text("TDAT", "0405"),
+            text("TIME", "1345"),
+            text("TORY", "1999"),
+            text("TYER", "2021"),
+        ]
+    );
+
+    // Preserving keeps ID3v2.3, upgrading restores the ID3v2.4 frames
+    let (bytes, _) = tags::update_bytes(&bytes, &[], WriteVersion::Preserve).unwrap();
+    let loaded = tags::load_from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.header.unwrap().version_name(), "2.3");
+    let (bytes, _) =
+        tags::update_bytes(&bytes, &[], WriteVersion::Version(Version::Id3v24)).unwrap();
+    let mut carriers = tags::load_from_bytes(&bytes).unwrap().carriers;
+    carriers.sort_by(|a, b| a.id.cmp(&b.id));
+    assert_eq!(carriers, v24);
+}
+
+#[test]
+fn frames_missing_from_id3v22_are_rejected() {
+    let error = tags::update_bytes(
+        &common::mp3(2),
+        &[text("TIT2", "Title"), text("TSST", "Disc")],
+        WriteVersion::Version(Version::Id3v22),
+    )
+    .unwrap_err();
+    assert_eq!(error.code, ErrorCode::UnsupportedFrame);
+    assert_eq!(error.frame_id.as_deref(), Some("TSST"));
+}
+
+#[test]
+fn malformed_frame_ids_are_rejected() {
+    let error = tags::update_bytes(
+        &common::mp3(2),
+        &[text("TIT2", "Title"), text("tit2", "Title")],
+        WriteVersion::Preserve,
+    )
+    .unwrap_err();
+
+    assert_eq!(error.code, ErrorCode::BadCarrier);
+    assert_eq!(error.frame_index, Some(1));
+    assert_eq!(error.frame_id.as_deref(), Some("tit2"));
+}
+
+#[test]
+fn vorbis_fields_take_several_values() {
+    let flac = common::flac();
+    let mods = [
+        text("TPE1", "One"),
+        text("TPE1", "Two"),
+        text("TIT2", "Title"),
+        Carrier::new("APIC", picture(&[4, 5, 6])),
+    ];
+
+    let (bytes, updated) = tags::update_bytes(&flac, &mods, WriteVersion::Preserve).unwrap();
+    assert_eq!(updated.format, TagFormat::Vorbis);
+    assert!(
+        bytes.ends_with(&flac[flac.len() - 8..]),
+        "the audio is kept"
+    );
+
+    let loaded = tags::load_from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.carriers, mods);
+
+    // A later update replaces every value of a field it sets, named either way
+    let (bytes, _) =
+        tags::update_bytes(&bytes, &[text("ARTIST", "Three")], WriteVersion::Preserve).unwrap();
+    assert_eq!(
+        tags::load_from_bytes(&bytes).unwrap().carriers[..2],
+        [text("TIT2", "Title"), text("TPE1", "Three")]
+    );
+}
+
+#[test]
+fn vorbis_fields_without_equals_are_kept() {
+    let comment = common::vorbis_comment("vendor", &["TITLE=Title", "no separator"]);
+    let flac = common::flac_with_blocks(&[(4, comment)]);
+
+    let loaded = tags::load_from_bytes(&flac).unwrap();
+    assert!(loaded.had_tag);
+    assert_eq!(loaded.carriers, [text("TIT2", "Title")]);
+
+    let (bytes, _) =
+        tags::update_bytes(&flac, &[text("ARTIST", "Artist")], WriteVersion::Preserve).unwrap();
+    let tag = flac::read_from_bytes(&bytes).unwrap();
+    assert_eq!(tag.comment.vendor, "vendor");
+    assert_eq!(tag.comment.unnamed, ["no separator"]);
+    assert_eq!(
+        tag.comment.fields,
+        [
+            (String::from("TITLE"), String::from("Title")),
+            (String::from("ARTIST"), String::from("Artist"))
+        ]
+    );
+}
+
+#[test]
+fn vorbis_pictures_match_on_their_identity() {
+    let cover = |description: &str, data: &[u8]| {
+        Carrier::new(
+            "APIC",
+            Content::Picture(Picture {
+                mime_type: String::from("image/png"),
+                picture_type: PictureType::CoverFront,
+                description: description.to_string(),
+                data: data.to_vec(),
+            }),
+        )
+    };
+    let (bytes, _) = tags::update_bytes(
+        &common::flac(),
+        &[cover("front", b"abc"), cover("alternate", b"def")],
+        WriteVersion::Preserve,
+    )
+    .unwrap();
+
+    // A cover of the same type with another description is another picture
+    let (_, updated) =
+        tags::update_bytes(&bytes, &[cover("front", b"ghi")], WriteVersion::Preserve).unwrap();
+    assert_eq!(
+        updated.carriers,
+        [cover("alternate", b"def"), cover("front", b"ghi")]
+    );
+    let (_, updated) = tags::update_bytes(
+        &bytes,
+        &[removal(cover("alternate", b""))],
+        WriteVersion::Preserve,
+    )
+    .unwrap();
+    assert_eq!(updated.carriers, [cover("front", b"abc")]);
+
+    // A handle whose data changed since loading removes nothing
+    let path = common::temp_file("song.flac", &bytes);
+    let handles = tags::load(&path).unwrap().strip_payloads().carriers;
+    let mut stale = removal(handles[0].clone());
+    stale.payload.as_mut().unwrap().hash = handles[1].payload.as_ref().unwrap().hash.clone();
+    let (_, updated) = tags::update_bytes(&bytes, &[stale], WriteVersion::Preserve).unwrap();
+    assert_eq!(updated.carriers.len(), 2);
+}
+
+#[test]
+fn flac_padding_absorbs_growth_until_it_runs_out() {
+    let flac =
+        common::flac_with_blocks(&[(4, common::vorbis_comment("vendor", &[])), (1, vec![0; 32])]);
+
+    // "TITLE=Hi" and its length take 12 of the 32 padding bytes
+    let (bytes, _) =
+        tags::update_bytes(&flac, &[text("TITLE", "Hi")], WriteVersion::Preserve).unwrap();
+    assert_eq!(bytes.len(), flac.len());
+    assert!(bytes.ends_with(&flac[flac.len() - 8..]));
+
+    // A longer title doesn't fit the 20 bytes left, so the padding is kept as it is and the
+    // audio moves by the growth of the title
+    let title = "x".repeat(100);
+    let (bytes, _) =
+        tags::update_bytes(&bytes, &[text("TITLE", &title)], WriteVersion::Preserve).unwrap();
+    assert_eq!(bytes.len(), flac.len() + title.len() - "Hi".len());
+    assert!(bytes.ends_with(&flac[flac.len() - 8..]));
+    assert_eq!(
+        tags::load_from_bytes(&bytes).unwrap().carriers,
+        [text("TIT2", &title)]
+    );
+}
+
+#[test]
+fn flac_audio_keeps_its_offset_while_padding_lasts() {
+    let seektable = [0xAB; 18].to_vec();
+    let flac = common::flac_with_blocks(&[
+        (3, seektable.clone()),
+        (4, common::vorbis_comment("vendor", &["TITLE=Title"])),
+        (1, vec![0; 64]),
+    ]);
+    let audio_start = flac.len() - (common::flac().len() - 42);
+
+    for title in ["A longer title", ""] {
+        let (bytes, _) =
+            tags::update_bytes(&flac, &[text("TITLE", title)], WriteVersion::Preserve).unwrap();
+
+        assert_eq!(bytes.len(), flac.len(), "{:?}", title);
+        assert_eq!(bytes[audio_start..], flac[audio_start..], "{:?}", title);
+        // STREAMINFO and SEEKTABLE come first and are copied as they are
+        assert_eq!(bytes[..42 + 4 + 18], flac[..42 + 4 + 18], "{:?}", title);
+        let tag = flac::read_from_bytes(&bytes).unwrap();
+        assert_eq!(
+            tag.comment.fields,
+            [(String::from("TITLE"), title.to_string())]
+        );
+    }
+}
+
+#[test]
+fn stripped_vorbis_comments_keep_only_the_vendor() {
+    let comment = common::vorbis_comment("vendor", &["TITLE=Title", "no separator"]);
+    let path = common::temp_file("song.flac", &common::flac_with_blocks(&[(4, comment)]));
+
+    assert!(tags::strip(&path, SaveOptions::default()).unwrap());
+    let loaded = tags::load(&path).unwrap();
+    assert!(!loaded.had_tag);
+    assert!(loaded.carriers.is_empty());
+    let tag = flac::read_from_path(&path).unwrap();
+    assert_eq!(tag.comment.vendor, "vendor");
+    assert!(tag.comment.unnamed.is_empty());
+
+    // Nothing is left to strip
+    assert!(!tags::strip(&path, SaveOptions::default()).unwrap());
+}
+
+#[test]
+fn ogg_comments_round_trip_with_valid_pages() {
+    let opus = common::opus(3);
+    let mods = [text("TIT2", "Title"), text("TPE1", "Artist")];
+
+    let (bytes, updated) = tags::update_bytes(&opus, &mods, WriteVersion::Preserve).unwrap();
+    assert_eq!(updated.format, TagFormat::Vorbis);
+    assert_eq!(tags::load_from_bytes(&bytes).unwrap().carriers, mods);
+
+    // The comment header still fits one page, so the audio pages are copied as they were
+    let pages = common::ogg_pages(&bytes);
+    assert_eq!(pages.len(), 5);
+    assert!(pages.iter().all(|page| page.crc_matches));
+    assert_eq!(pages[2..], common::ogg_pages(&opus)[2..]);
+}
+
+#[test]
+fn large_ogg_comments_are_paginated() {
+    let opus = common::opus(3);
+    let original = common::ogg_pages(&opus);
+    // Encoded in base64 the picture needs more than the 65025 bytes of one page
+    let cover = vec![0xAB; 60000];
+
+    let (bytes, _) = tags::update_bytes(
+        &opus,
+        &[Carrier::new("APIC", picture(&cover))],
+        WriteVersion::Preserve,
+    )
+    .unwrap();
+    let pages = common::ogg_pages(&bytes);
+    assert_eq!(pages.len(), original.len() + 1);
+    assert!(pages.iter().all(|page| page.crc_matches));
+    let sequences: Vec<u32> = pages.iter().map(|page| page.sequence).collect();
+    assert_eq!(sequences, [0, 1, 2, 3, 4, 5]);
+
+    // The comment packet spans two pages, the first of which ends no packet
+    assert_eq!(pages[1].lacing.len(), 255);
+    assert_eq!(pages[1].granule, u64::MAX);
+    assert_eq!(pages[2].header_type, 0x01);
+    assert_eq!(pages[2].granule, 0);
+    for (page, old) in pages[3..].iter().zip(&original[2..]) {
+        assert_eq!(
+            (page.header_type, page.granule, &page.body),
+            (old.header_type, old.granule, &old.body)
+        );
+    }
+    assert_eq!(
+        common::ogg_packets(&pages)[2..],
+        common::ogg_packets(&original)[2..]
+    );
+    assert_eq!(
+        tags::load_from_bytes(&bytes).unwrap().carriers,
+        [Carrier::new("APIC", picture(&cover))]
+    );
+
+    // Writing the empty comment back renumbers the audio pages to the original file
+    let empty = ogg::read_from_bytes(&opus).unwrap();
+    assert_eq!(ogg::write_to_bytes(&bytes, &empty).unwrap(), opus);
+}
+
+#[test]
+fn vorbis_setup_headers_are_kept() {
+    let vorbis = common::ogg_vorbis();
+    let original = common::ogg_packets(&common::ogg_pages(&vorbis));
+
+    let (bytes, _) =
+        tags::update_bytes(&vorbis, &[text("TITLE", "New")], WriteVersion::Preserve).unwrap();
+    let pages = common::ogg_pages(&bytes);
+    assert!(pages.iter().all(|page| page.crc_matches));
+    let packets = common::ogg_packets(&pages);
+    assert_eq!(packets.len(), original.len());
+    assert_eq!(packets[0], original[0]);
+    assert_eq!(packets[2..], original[2..]);
+    assert_eq!(packets[1].last(), Some(&1), "the framing bit is kept");
+    assert_eq!(
+        tags::load_from_bytes(&bytes).unwrap().carriers,
+        [text("TIT2", "New")]
+    );
+}
+
+/// Checks that every chunk offset of an MP4 file still points at its chunk
+fn assert_chunks_found(bytes: &[u8]) {
+    let offsets = common::mp4_chunk_offsets(bytes);
+    for (offset, chunk) in offsets.iter().zip(common::MP4_CHUNKS) {
+        let offset = *offset as usize;
+        assert_eq!(&bytes[offset..offset + 4], chunk);
+    }
+}
+
+#[test]
+fn mp4_items_round_trip_and_move_chunk_offsets() {
+    let mods = [
+        text("TIT2", "Title"),
+        text("TRCK", "3/12"),
+        text("TBPM", "120"),
+        Carrier::new(
+            "TXXX",
+            Content::ExtendedText(ExtendedText {
+                description: String::from("MOOD"),
+                value: String::from("calm"),
+            }),
+        ),
+        Carrier::new("APIC", picture(&[1, 2, 3])),
+    ];
+
+    for offsets in [b"stco", b"co64"] {
+        let mp4 = common::mp4(offsets, None);
+        let (bytes, updated) = tags::update_bytes(&mp4, &mods, WriteVersion::Preserve).unwrap();
+        assert_eq!(updated.format, TagFormat::Mp4);
+        assert!(bytes.len() > mp4.len());
+        assert_chunks_found(&bytes);
+
+        let loaded = tags::load_from_bytes(&bytes).unwrap();
+        assert!(loaded.had_tag);
+        assert_eq!(loaded.carriers.len(), mods.len());
+        assert_eq!(loaded.carriers[..4], mods[..4]);
+        match &loaded.carriers[4].content {
+            Content::Picture(cover) => assert_eq!(cover.data, [1, 2, 3]),
+            content => panic!("covr is read as {:?}", content),
+        }
+
+        // Shrinking the tag moves the chunks back
+        let (bytes, _) =
+            tags::update_bytes(&bytes, &[removal(text("TIT2", ""))], WriteVersion::Preserve)
+                .unwrap();
+        assert_chunks_found(&bytes);
+        assert_eq!(tags::load_from_bytes(&bytes).unwrap().carriers.len(), 4);
+    }
+}
+
+#[test]
+fn mp4_data_atom_types_are_kept() {
+    let mut ilst = common::mp4_item(b"akID", 21, &[2]);
+    ilst.extend_from_slice(&common::mp4_item(b"xyzw", 22, &[0, 7]));
+    let artist: Vec<u8> = "Artist".encode_utf16().flat_map(u16::to_be_bytes).collect();
+    ilst.extend_from_slice(&common::mp4_item(b"\xa9ART", 2, &artist));
+    let udta = common::mp4_udta(&ilst);
+    let mp4 = common::mp4(b"stco", Some(&udta));
+
+    let loaded = tags::load_from_bytes(&mp4).unwrap();
+    let data_types: Vec<Option<u32>> = loaded.carriers.iter().map(|c| c.data_type).collect();
+    assert_eq!(data_types, [Some(21), Some(22), Some(2)]);
+    assert_eq!(loaded.carriers[0].content, Content::Text(String::from("2")));
+
+    // Writing back what was read, as the app does, changes nothing
+    let (bytes, _) = tags::update_bytes(&mp4, &loaded.carriers, WriteVersion::Preserve).unwrap();
+    assert_eq!(bytes, mp4);
+
+    // Carriers without a type write new values with the type the item had
+    let mods = [text("akID", "5"), text("©ART", "Other")];
+    let (bytes, _) = tags::update_bytes(&mp4, &mods, WriteVersion::Preserve).unwrap();
+    let tag = mp4::read_from_bytes(&bytes).unwrap();
+    let atoms: Vec<(u32, &[u8])> = tag
+        .items
+        .iter()
+        .map(|item| (item.data[0].type_code, item.data[0].value.as_slice()))
+        .collect();
+    let other: Vec<u8> = "Other".encode_utf16().flat_map(u16::to_be_bytes).collect();
+    assert_eq!(atoms, [(21, &[5][..]), (22, &[0, 7][..]), (2, &other[..])]);
+}
+
+#[test]
+fn udta_terminators_are_kept() {
+    for trailer in [&[0, 0, 0, 0][..], &[1, 2, 3][..]] {
+        let mut udta = common::mp4_udta(&common::mp4_item(b"\xa9nam", 1, b"Title"));
+        udta.extend_from_slice(trailer);
+        let size = (udta.len() as u32).to_be_bytes();
+        udta[..4].copy_from_slice(&size);
+        let mp4 = common::mp4(b"stco", Some(&udta));
+
+        assert_eq!(
+            tags::load_from_bytes(&mp4).unwrap().carriers,
+            [text("TIT2", "Title")]
+        );
+        let (bytes, _) =
+            tags::update_bytes(&mp4, &[text("©nam", "New")], WriteVersion::Preserve).unwrap();
+        assert_chunks_found(&bytes);
+        assert_eq!(
+            tags::load_from_bytes(&bytes).unwrap().carriers,
+            [text("TIT2", "New")]
+        );
+
+        let mdat = bytes.windows(4).position(|kind| kind == b"mdat").unwrap() - 4;
+        assert!(bytes[..mdat].ends_with(trailer));
+    }
+}
+
+#[test]
+fn id3_frame_ids_round_trip_through_every_format() {
+    let mods = [
+        text("TIT2", "Title"),
+        text("TPE1", "Artist"),
+        Carrier::new("APIC", picture(&[1, 2, 3])),
+    ];
+    let vorbis_fields = [
+        (String::from("TITLE"), String::from("Title")),
+        (String::from("ARTIST"), String::from("Artist")),
+    ];
+
+    let (bytes, _) = tags::update_bytes(&common::flac(), &mods, WriteVersion::Preserve).unwrap();
+    assert_eq!(tags::load_from_bytes(&bytes).unwrap().carriers, mods);
+    let tag = flac::read_from_bytes(&bytes).unwrap();
+    assert_eq!(tag.comment.fields, vorbis_fields);
+    assert_eq!(tag.pictures.len(), 1);
+
+    let (bytes, _) = tags::update_bytes(&common::opus(2), &mods, WriteVersion::Preserve).unwrap();
+    assert_eq!(tags::load_from_bytes(&bytes).unwrap().carriers, mods);
+    let tag = ogg::read_from_bytes(&bytes).unwrap();
+    assert_eq!(tag.comment.fields, vorbis_fields);
+    assert_eq!(tag.pictures.len(), 1);
+
+    let mp4 = common::mp4(b"stco", None);
+    let (bytes, _) = tags::update_bytes(&mp4, &mods, WriteVersion::Preserve).unwrap();
+    let loaded = tags::load_from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.carriers[..2], mods[..2]);
+    assert_eq!(loaded.carriers[2].id, "APIC");
+    let tag = mp4::read_from_bytes(&bytes).unwrap();
+    let atoms: Vec<String> = tag
+        .items
+        .iter()
+        .map(|item| item.ident.to_string())
+        .collect();
+    assert_eq!(atoms, ["©nam", "©ART", "covr"]);
+}
+
+#[test]
+fn id3_frames_without_a_field_or_atom_are_rejected() {
+    for (name, bytes, id) in [
+        ("song.flac", common::flac(), "TLEN"),
+        ("song.opus", common::opus(2), "TOAL"),
+        ("song.m4a", common::mp4(b"stco", None), "TIT3"),
+    ] {
+        let mods = [text("TIT2", "Title"), text(id, "Value")];
+        let error = tags::update_bytes(&bytes, &mods, WriteVersion::Preserve).unwrap_err();
+        assert_eq!(error.code, ErrorCode::BadCarrier, "{}", name);
+        assert_eq!(error.frame_index, Some(1), "{}", name);
+        assert_eq!(error.frame_id.as_deref(), Some(id), "{}", name);
+    }
+}
+
+#[test]
+fn vorbis_comments_reject_id3_only_frames() {
+    let comment = Carrier::new(
+        "COMM",
+        Content::Comment(Comment {
+            lang: String::from("eng"),
+            description: String::new(),
+            text: String::from("A comment"),
+        }),
+    );
+
+    let error =
+        tags::update_bytes(&common::flac(), &[comment], WriteVersion::Preserve).unwrap_err();
+    assert_eq!(error.code, ErrorCode::UnsupportedFrame);
+    assert_eq!(error.frame_index, Some(0));
+}
+
+#[test]
+fn files_are_updated_in_place() {
+    let path = common::temp_file("song.mp3", &common::mp3(4));
+    let save = SaveOptions {
+        backup: true,
+        preserve_mtime: false,
+    };
+
+    let updated = tags::update(
+        &path,
+        &[text("TIT2", "Title")],
+        WriteVersion::Preserve,
+        save,
+    )
+    .unwrap();
+    assert_eq!(updated.carriers, [text("TIT2", "Title")]);
+    assert_eq!(tags::load(&path).unwrap().carriers, [text("TIT2", "Title")]);
+    assert_eq!(fs::read(format!("{}.bak", path)).unwrap(), common::mp3(4));
+}
+
+#[cfg(unix)]
+#[test]
+fn symbolic_links_are_kept_and_their_target_updated() {
+    let path = common::temp_file("song.mp3", &common::mp3(4));
+    let link = format!("{}.link.mp3", path);
+    std::os::unix::fs::symlink(&path, &link).unwrap();
+
+    tags::update(
+        &link,
+        &[text("TIT2", "Title")],
+        WriteVersion::Preserve,
+        SaveOptions::default(),
+    )
+    .unwrap();
+    assert!(fs::symlink_metadata(&link)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(tags::load(&path).unwrap().carriers, [text("TIT2", "Title")]);
+}
+
+#[test]
+fn read_only_files_are_refused() {
+    let path = common::temp_file("song.mp3", &common::mp3(4));
+    let mut permissions = fs::metadata(&path).unwrap().permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&path, permissions).unwrap();
+
+    let error = tags::update(
+        &path,
+        &[text("TIT2", "Title")],
+        WriteVersion::Preserve,
+        SaveOptions::default(),
+    )
+    .unwrap_err();
+    assert_eq!(error.code, ErrorCode::Io);
+    assert_eq!(error.message, "The file is read-only");
+    assert_eq!(error.path.as_deref(), Some(path.as_str()));
+    assert_eq!(fs::read(&path).unwrap(), common::mp3(4));
+}
+
+#[test]
+fn flac_updates_failing_verification_are_rolled_back() {
+    let flac = common::flac();
+    let path = common::temp_file("rollback.flac", &flac);
+    let save = SaveOptions {
+        backup: true,
+        preserve_mtime: false,
+    };
+
+    // A field name holding '=' reads back as another field
+    let error = tags::update(
+        &path,
+        &[text("TITLE", "Title"), text("A=B", "value")],
+        WriteVersion::Preserve,
+        save,
+    )
+    .unwrap_err();
+    assert_eq!(error.code, ErrorCode::Verification);
+    assert_eq!(error.frame_index, Some(1));
+    assert_eq!(error.frame_id.as_deref(), Some("A=B"));
+    assert_eq!(error.path.as_deref(), Some(path.as_str()));
+
+    // Neither the temporary copy nor a backup is left next to the untouched file
+    assert_eq!(fs::read(&path).unwrap(), flac);
+    let dir = std::path::Path::new(&path).parent().unwrap();
+    assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+}
+
+/// Checks that tagging left the audio digest of a file alone, and that it covers the last byte
+/// of the untagged file, which is audio in every file built here
+fn assert_audio_hash(untagged: &[u8], tagged: &[u8], audio_hash: impl Fn(&[u8]) -> [u8; 32]) {
+    assert_ne!(untagged, tagged);
+    assert_eq!(audio_hash(untagged), audio_hash(tagged));
+
+    let mut damaged = untagged.to_vec();
+    *damaged.last_mut().unwrap() ^= 1;
+    assert_ne!(audio_hash(&damaged), audio_hash(untagged));
+}
+
+#[test]
+fn audio_hashes_leave_out_the_tag() {
+    let tag = |bytes: &[u8]| {
+        let mods = [text("TITLE", &"long title ".repeat(10000))];
+        tags::update_bytes(bytes, &mods, WriteVersion::Preserve)
+            .unwrap()
+            .0
+    };
+    let mp3 = common::mp3(4);
+    let (tagged_mp3, _) =
+        tags::update_bytes(&mp3, &[text("TIT2", "Title")], WriteVersion::Preserve).unwrap();
+    assert_audio_hash(&mp3, &tagged_mp3, id3_header::audio_hash);
+
+    let wav = common::sine_wav(8000, 1, 0.01, 0.5);
+    let path = common::temp_file("song.wav", &wav);
+    tags::update(
+        &path,
+        &[text("TIT2", "Title")],
+        WriteVersion::Preserve,
+        SaveOptions::default(),
+    )
+    .unwrap();
+    assert_audio_hash(&wav, &fs::read(&path).unwrap(), id3_header::audio_hash);
+
+    let flac = common::flac();
+    assert_audio_hash(&flac, &tag(&flac), |bytes| flac::audio_hash(bytes).unwrap());
+    // The comment needs new pages, renumbering the audio pages
+    let opus = common::opus(3);
+    assert_audio_hash(&opus, &tag(&opus), |bytes| ogg::audio_hash(bytes).unwrap());
+    // moov grows, moving mdat and its chunk offsets
+    let mp4 = common::mp4(b"stco", None);
+    let (tagged_mp4, _) =
+        tags::update_bytes(&mp4, &[text("©nam", "Title")], WriteVersion::Preserve).unwrap();
+    assert_audio_hash(&mp4, &tagged_mp4, |bytes| mp4::audio_hash(bytes).unwrap());
+}
+
+#[test]
+fn previews_leave_files_untouched() {
+    let (bytes, _) = tags::update_bytes(
+        &common::mp3(2),
+        &[text("TIT2", "Old"), text("TPE1", "Artist")],
+        WriteVersion::Preserve,
+    )
+    .unwrap();
+    let path = common::temp_file("song.mp3", &bytes);
+
+    let preview = tags::preview(
+        &path,
+        &[
+            text("TIT2", "New"),
+            removal(text("TPE1", "")),
+            text("TALB", "Album"),
+        ],
+        WriteVersion::Preserve,
+    )
+    .unwrap();
+    assert_eq!(
+        preview.diff.changed,
+        [(text("TIT2", "Old"), text("TIT2", "New"))]
+    );
+    assert_eq!(preview.diff.removed, [text("TPE1", "Artist")]);
+    assert_eq!(preview.diff.added, [text("TALB", "Album")]);
+    assert_eq!(fs::read(&path).unwrap(), bytes);
+}
+
+/// Previews an update of a file holding bytes and checks the predicted audio shift against the
+/// update, the audio ending every file built here
+fn preview_layout(name: &str, bytes: &[u8], mods: &[Carrier]) -> Layout {
+    let path = common::temp_file(name, bytes);
+    let preview = tags::preview(&path, mods, WriteVersion::Preserve).unwrap();
+    tags::update(&path, mods, WriteVersion::Preserve, SaveOptions::default()).unwrap();
+
+    let shift = fs::read(&path).unwrap().len() as i64 - bytes.len() as i64;
+    assert_eq!(preview.layout.audio_shift, shift, "{}", name);
+    preview.layout
+}
+
+#[test]
+fn previews_predict_the_layout_of_the_update() {
+    let tag = id3v23_tag(0, &[], 100);
+    let mut padded = tag.clone();
+    padded.extend_from_slice(&common::mp3(2));
+
+    // The padding absorbs a small change but not a large one
+    assert_eq!(
+        preview_layout("song.mp3", &padded, &[text("TIT2", "New title")]),
+        Layout {
+            tag_size: tag.len(),
+            audio_shift: 0
+        }
+    );
+    let layout = preview_layout("song.mp3", &padded, &[text("TIT2", &"x".repeat(200))]);
+    assert!(layout.audio_shift > 0);
+    assert_eq!(
+        layout.tag_size as i64,
+        tag.len() as i64 + layout.audio_shift
+    );
+
+    let title = [text("TITLE", "Title")];
+    preview_layout("song.flac", &common::flac(), &title);
+    preview_layout("song.opus", &common::opus(2), &title);
+    let layout = preview_layout(
+        "song.m4a",
+        &common::mp4(b"stco", None),
+        &[text("©nam", "Title")],
+    );
+    assert!(layout.audio_shift > 0);
+}
+
+#[test]
+fn payloads_are_replaced_by_handles() {
+    let (bytes, _) = tags::update_bytes(
+        &common::mp3(2),
+        &[Carrier::new("APIC", picture(b"abc"))],
+        WriteVersion::Preserve,
+    )
+    .unwrap();
+    let path = common::temp_file("song.mp3", &bytes);
+
+    let loaded = tags::load(&path).unwrap().strip_payloads();
+    let handle = loaded.carriers[0].payload.as_ref().unwrap();
+    assert_eq!(handle.size, 3);
+    assert_eq!(
+        handle.hash,
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(tags::load_payload(&path, 0).unwrap(), b"abc");
+    assert_eq!(
+        tags::load_payload(&path, 1).unwrap_err().code,
+        ErrorCode::FrameNotFound
+    );
+}
+
+#[test]
+fn payload_handles_remove_the_frame_with_their_data() {
+    let object = |description: &str, data: &[u8]| {
+        Carrier::new(
+            "GEOB",
+            Content::EncapsulatedObject(EncapsulatedObject {
+                mime_type: String::from("application/octet-stream"),
+                filename: String::from("a.bin"),
+                description: description.to_string(),
+                data: data.to_vec(),
+            }),
+        )
+    };
+    let (bytes, _) = tags::update_bytes(
+        &common::mp3(2),
+        &[object("first", b"abc"), object("second", b"def")],
+        WriteVersion::Preserve,
+    )
+    .unwrap();
+    let path = common::temp_file("song.mp3", &bytes);
+    let handles = tags::load(&path).unwrap().strip_payloads().carriers;
+
+    // A handle whose data changed since loading removes nothing
+    let mut stale = removal(handles[0].clone());
+    stale.payload.as_mut().unwrap().hash = handles[1].payload.as_ref().unwrap().hash.clone();
+    let (_, updated) = tags::update_bytes(&bytes, &[stale], WriteVersion::Preserve).unwrap();
+    assert_eq!(updated.carriers.len(), 2);
+
+    let (_, updated) = tags::update_bytes(
+        &bytes,
+        &[removal(handles[0].clone())],
+        WriteVersion::Preserve,
+    )
+    .unwrap();
+    assert_eq!(updated.carriers, [object("second", b"def")]);
+
+    // Without a handle the data is left out of the match
+    let (_, updated) = tags::update_bytes(
+        &bytes,
+        &[removal(object("second", b""))],
+        WriteVersion::Preserve,
+    )
+    .unwrap();
+    assert_eq!(updated.carriers, [object("first", b"abc")]);
+}
+
+#[test]
+fn frame_kinds_round_trip_through_their_names() {
+    for kind in FrameKind::ALL.iter() {
+        assert_eq!(FrameKind::from_name(kind.as_str()), Some(*kind));
+    }
+    assert_eq!(FrameKind::from_name("texts"), None);
+
+    let counter = carrier::carrier_to_frame(&Carrier::new(
+        "PCNT",
+        Content::Unknown(metashine_core::id3::frame::Unknown {
+            data: vec![0, 0, 0, 7],
+            version: metashine_core::id3::Version::Id3v24,
+        }),
+    ))
+    .unwrap();
+    assert_eq!(counter.id(), "PCNT");
+}
+
+#[test]
+fn popularimeters_and_play_counters_round_trip() {
+    let mods = [
+        Carrier::new(
+            "POPM",
+            Content::Popularimeter(Popularimeter {
+                user: String::from("user@example.com"),
+                rating: 196,
+                counter: 12,
+            }),
+        ),
+        Carrier::new(
+            "PCNT",
+            Content::Unknown(Unknown {
+                data: popularimeter::encode_play_counter(300),
+                version: Version::Id3v24,
+            }),
+        ),
+    ];
+
+    let (bytes, _) = tags::update_bytes(&common::mp3(2), &mods, WriteVersion::Preserve).unwrap();
+    let loaded = tags::load_from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.carriers, mods);
+    assert_eq!(loaded.carriers[1].kind(), FrameKind::PlayCounter);
+    match &loaded.carriers[1].content {
+        Content::Unknown(counter) => {
+            assert_eq!(popularimeter::decode_play_counter(&counter.data), 300)
+        }
+        content => panic!("PCNT read as {:?}", content),
+    }
+}
+
+#[test]
+fn synchronised_lyrics_round_trip_from_lrc() {
+    let mods = [Carrier::new(
+        "SYLT",
+        Content::SynchronisedLyrics(SynchronisedLyrics {
+            lang: String::from("eng"),
+            timestamp_format: TimestampFormat::Ms,
+            content_type: SynchronisedLyricsType::Lyrics,
+            description: String::new(),
+            content: lrc::parse("[00:01.00]One\n[00:02.50]Two\n"),
+        }),
+    )];
+
+    let (bytes, _) = tags::update_bytes(&common::mp3(2), &mods, WriteVersion::Preserve).unwrap();
+    let loaded = tags::load_from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.carriers, mods);
+    match &loaded.carriers[0].content {
+        Content::SynchronisedLyrics(lyrics) => assert_eq!(
+            lrc::format(&lyrics.content, 0, false),
+            "[00:01.00]One\n[00:02.50]Two\n"
+        ),
+        content => panic!("SYLT read as {:?}", content),
+    }
+}
+
+fn chapter(element_id: &str, start_time: u32, end_time: u32, title: &str) -> Carrier {
+    Carrier::new(
+        "CHAP",
+        Content::Chapter(Chapter {
+            element_id: element_id.to_string(),
+            start_time,
+            end_time,
+            start_offset: u32::MAX,
+            end_offset: u32::MAX,
+            frames: vec![Frame::text("TIT2", title)],
+        }),
+    )
+}
+
+#[test]
+fn chapters_and_tables_of_contents_round_trip() {
+    let mods = [
+        Carrier::new(
+            "CTOC",
+            Content::TableOfContents(TableOfContents {
+                element_id: String::from("toc"),
+                top_level: true,
+                ordered: true,
+                elements: vec![String::from("ch0"), String::from("ch1")],
+                frames: Vec::new(),
+            }),
+        ),
+        chapter("ch0", 0, 1000, "Intro"),
+        chapter("ch1", 1000, 2500, "Outro"),
+    ];
+
+    let (bytes, _) = tags::update_bytes(&common::mp3(2), &mods, WriteVersion::Preserve).unwrap();
+    let loaded = tags::load_from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.carriers, mods);
+
+    let frames: Vec<Frame> = mods
+        .iter()
+        .map(|carrier| carrier::carrier_to_frame(carrier).unwrap())
+        .collect();
+    assert!(chapters::validate(&frames).is_empty());
+
+    // Overlapping chapters and missing children are reported by element ID
+    let mut frames = frames;
+    frames[2] = carrier::carrier_to_frame(&chapter("ch1", 500, 2500, "Outro")).unwrap();
+    frames.push(
+        carrier::carrier_to_frame(&Carrier::new(
+            "CTOC",
+            Content::TableOfContents(TableOfContents {
+                element_id: String::from("sub"),
+                top_level: false,
+                ordered: true,
+                elements: vec![String::from("ch9")],
+                frames: Vec::new(),
+            }),
+        ))
+        .unwrap(),
+    );
+    let issues: Vec<String> = chapters::validate(&frames)
+        .into_iter()
+        .map(|issue| issue.element_id)
+        .collect();
+    assert!(issues.contains(&String::from("ch1")));
+    assert!(issues.contains(&String::from("sub")));
+}
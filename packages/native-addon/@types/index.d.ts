@@ -1,3 +1,5 @@
+// Generated from src/typings.rs by build.rs, do not edit.
+// Regenerate with `cargo build --features write-types` after changing the declarations.
 declare module 'native-addon' {
   /**
    * Standard ID3 frame content types
@@ -17,18 +19,34 @@ declare module 'native-addon' {
     link: string;
   };
 
-  export type ID3Lyrics = {
+  export type ID3Comment = {
     lang: string;
     description: string;
-    text: ArrayBuffer;
+    text: string;
   };
 
-  export type ID3Comment = {
+  export type ID3Popularimeter = {
+    user: string;
+    rating: number;
+    counter: number;
+  };
+
+  export type ID3PlayCounter = number;
+
+  export type ID3Lyrics = {
     lang: string;
     description: string;
     text: string;
   };
 
+  export type ID3SynchronisedLyrics = {
+    lang: string;
+    timestampFormat: 'ms' | 'mpeg';
+    contentType: 'other' | 'lyrics' | 'transcription' | 'part name' | 'event' | 'chord' | 'trivia';
+    description: string;
+    content: [number, string][];
+  };
+
   export type ID3Picture = {
     MIMEType: string;
     pictureType: number;
@@ -43,18 +61,89 @@ declare module 'native-addon' {
     data: ArrayBuffer;
   };
 
+  /**
+   * Byte offsets are null when the chapter doesn't use them
+   */
+  export type ID3Chapter = {
+    elementId: string;
+    startTime: number;
+    endTime: number;
+    startOffset: number | null;
+    endOffset: number | null;
+    /** Sub-frames, in the frame format of the tag holding the chapter */
+    frames: TagCarrier | FrameObject[];
+  };
+
+  export type ID3TableOfContents = {
+    elementId: string;
+    topLevel: boolean;
+    ordered: boolean;
+    elements: string[];
+    frames: TagCarrier | FrameObject[];
+  };
+
+  /**
+   * IPLS in ID3v2.3, TIPL and TMCL in ID3v2.4
+   */
+  export type ID3InvolvedPeople = {
+    involvement: string;
+    involvee: string;
+  }[];
+
+  export type ID3Private = {
+    owner: string;
+    data: ArrayBuffer;
+  };
+
+  export type ID3UniqueFileIdentifier = {
+    owner: string;
+    identifier: ArrayBuffer;
+  };
+
+  /**
+   * Frames that can be read but not written yet
+   */
+  export type ID3Unknown = {
+    data: ArrayBuffer;
+    /** Type code of the MP4 data atom, when it isn't 0 (implicit) */
+    type?: number;
+  };
+
+  /**
+   * Stands for the data of a picture or encapsulated object loaded with lazyPayloads,
+   * hash is its SHA-256 digest in hexadecimal
+   */
+  export type ID3PayloadHandle = {
+    size: number;
+    hash: string;
+  };
+
+  export type ID3PictureHandle = Omit<ID3Picture, 'data'> & ID3PayloadHandle;
+
+  export type ID3EncapsulatedObjectHandle = Omit<ID3EncapsulatedObject, 'data'> & ID3PayloadHandle;
+
   export type ID3Content = ID3Text
   | ID3ExtendedText
   | ID3Link
   | ID3ExtendedLink
-  | ID3Lyrics
   | ID3Comment
+  | ID3Popularimeter
+  | ID3PlayCounter
+  | ID3Lyrics
+  | ID3SynchronisedLyrics
   | ID3Picture
-  | ID3EncapsulatedObject;
+  | ID3EncapsulatedObject
+  | ID3Chapter
+  | ID3TableOfContents
+  | ID3InvolvedPeople
+  | ID3Private
+  | ID3UniqueFileIdentifier
+  | ID3Unknown;
 
   /**
    * Frame carriers
    */
+
   export type TextCarrier = [
     'text',
     string,
@@ -83,6 +172,27 @@ declare module 'native-addon' {
     boolean,
   ];
 
+  export type CommentCarrier = [
+    'comment',
+    string,
+    ID3Comment,
+    boolean,
+  ];
+
+  export type PopularimeterCarrier = [
+    'popularimeter',
+    string,
+    ID3Popularimeter,
+    boolean,
+  ];
+
+  export type PlayCounterCarrier = [
+    'play counter',
+    string,
+    ID3PlayCounter,
+    boolean,
+  ];
+
   export type LyricsCarrier = [
     'lyrics',
     string,
@@ -90,31 +200,66 @@ declare module 'native-addon' {
     boolean,
   ];
 
-  export type CommentCarrier = [
-    'comment',
+  export type SynchronisedLyricsCarrier = [
+    'synchronised lyrics',
     string,
-    ID3Comment,
+    ID3SynchronisedLyrics,
     boolean,
   ];
 
   export type PictureCarrier = [
     'picture',
     string,
-    ID3Picture,
+    ID3Picture | ID3PictureHandle,
     boolean,
   ];
 
   export type EncapsulatedObjectCarrier = [
     'encapsulated object',
     string,
-    ID3EncapsulatedObject,
+    ID3EncapsulatedObject | ID3EncapsulatedObjectHandle,
+    boolean,
+  ];
+
+  export type ChapterCarrier = [
+    'chapter',
+    string,
+    ID3Chapter,
+    boolean,
+  ];
+
+  export type TableOfContentsCarrier = [
+    'table of contents',
+    string,
+    ID3TableOfContents,
+    boolean,
+  ];
+
+  export type InvolvedPeopleCarrier = [
+    'involved people',
+    string,
+    ID3InvolvedPeople,
+    boolean,
+  ];
+
+  export type PrivateCarrier = [
+    'private',
+    string,
+    ID3Private,
+    boolean,
+  ];
+
+  export type UniqueFileIdentifierCarrier = [
+    'unique file identifier',
+    string,
+    ID3UniqueFileIdentifier,
     boolean,
   ];
 
   export type UnknownCarrier = [
     'unknown',
     string,
-    ArrayBuffer,
+    ID3Unknown,
     boolean,
   ];
 
@@ -122,18 +267,585 @@ declare module 'native-addon' {
   | ExtendedTextCarrier
   | LinkCarrier
   | ExtendedLinkCarrier
-  | LyricsCarrier
   | CommentCarrier
+  | PopularimeterCarrier
+  | PlayCounterCarrier
+  | LyricsCarrier
+  | SynchronisedLyricsCarrier
   | PictureCarrier
   | EncapsulatedObjectCarrier
+  | ChapterCarrier
+  | TableOfContentsCarrier
+  | InvolvedPeopleCarrier
+  | PrivateCarrier
+  | UniqueFileIdentifierCarrier
   | UnknownCarrier;
 
+  /**
+   * Carriers with their last element set to true remove the ID3 frames they stand for:
+   * comments and lyrics with the same language and description, user-defined texts and links
+   * with the same description, pictures with the same type and description, private frames,
+   * unique file identifiers and popularimeters with the same owner or user, and chapters and
+   * tables of contents with the same element ID, and encapsulated objects with the same
+   * description, MIME type and filename. Pictures and encapsulated objects loaded with
+   * lazyPayloads only remove the frame whose data has the hash of their handle. Other frames
+   * are removed by ID
+   */
   export type TagCarrier = FrameCarrier[];
 
+  /**
+   * Frame objects
+   */
+
+  export type FrameKind = FrameCarrier[0];
+
+  /**
+   * The frame schema version of frame objects, checked when a frame object has a schema
+   */
+  export const FRAME_SCHEMA_VERSION: 1;
+
+  /**
+   * A frame as a `{kind, id, value, op}` object, returned instead of tuples with the
+   * frameFormat 'object' option and accepted by updates alongside tuples. value has the shape
+   * of the content of the tuple of the same kind. Frames are removed like with tuples and
+   * only need the fields telling them apart, so value can be left out when op is 'remove'
+   */
+  export type FrameObjectOf<K extends FrameKind, V> = {
+    schema?: typeof FRAME_SCHEMA_VERSION;
+    kind: K;
+    id: string;
+  } & ({ op: 'set'; value: V } | { op: 'remove'; value?: V extends object ? Partial<V> : V });
+
+  export type FrameObject = {
+    [C in FrameCarrier as C[0]]: FrameObjectOf<C[0], C[2]>;
+  }[FrameKind];
+
+  /**
+   * The frames of an update, as objects or legacy tuples
+   */
+  export type TagUpdate = (FrameCarrier | FrameObject)[];
+
+  /**
+   * How loaded and written frames are returned: 'tuple' for legacy `[type, id, content,
+   * remove]` carriers, the default for now, or 'object' for frame objects
+   */
+  export type FrameFormat = 'tuple' | 'object';
+
+  export type FrameOptions<F extends FrameFormat> = {
+    frameFormat?: F;
+  };
+
+  export type TagFrames<F extends FrameFormat = 'tuple'> = F extends 'object'
+    ? FrameObject[]
+    : TagCarrier;
+
+  /**
+   * Vorbis comments of FLAC, Ogg Vorbis and Opus files and MP4 atoms are returned as carriers
+   * keyed by the ID3 frame ID standing for them, like TIT2 for TITLE and ©nam, and are written
+   * from those IDs the same way. Fields and atoms without one keep their own name, and ID3 frame
+   * IDs without a field or atom are rejected with ERR_BAD_CARRIER.
+   * Pictures are APIC picture carriers, MP4 trkn and disk are TRCK and TPOS texts as "n/total"
+   * and iTunes freeform atoms are TXXX extended text carriers
+   */
+  export type TagFormat = 'id3' | 'vorbis' | 'mp4';
+
+  export type LoadedTag<F extends FrameFormat = 'tuple'> = TagFrames<F> & { format: TagFormat };
+
+  export type ID3HeaderFlags = {
+    unsynchronisation: boolean;
+    /** Marks a compressed tag in ID3v2.2 */
+    extendedHeader: boolean;
+    experimental: boolean;
+    footer: boolean;
+  };
+
+  /**
+   * Returned by loadTag. version, tagSize, paddingSize and flags describe the ID3 tag header
+   * and are null for other formats and for files without an ID3 tag
+   */
+  export type TagInfo<F extends FrameFormat = 'tuple'> = {
+    format: TagFormat;
+    hadTag: boolean;
+    version: '2.2' | '2.3' | '2.4' | null;
+    /** Bytes taken by the whole tag, including its header and footer */
+    tagSize: number | null;
+    paddingSize: number | null;
+    flags: ID3HeaderFlags | null;
+    frames: TagFrames<F>;
+  };
+
+  /**
+   * Errors
+   */
+
+  export type TagErrorCode = 'ERR_IO'
+  | 'ERR_PARSE'
+  | 'ERR_UNSUPPORTED_FRAME'
+  | 'ERR_BAD_CARRIER'
+  | 'ERR_CANCELLED'
+  | 'ERR_VERIFY'
+  | 'ERR_FRAME_NOT_FOUND'
+  | 'ERR_DECODE'
+  | 'ERR_UNSUPPORTED_CODEC'
+  | 'ERR_BAD_DOCUMENT'
+  | 'ERR_INTERNAL';
+
+  /**
+   * Thrown by loadTag and updateTag, and rejected by their asynchronous versions,
+   * so failures can be told apart with `instanceof TagError`
+   */
+  export class TagError extends Error {
+    name: 'TagError';
+    code: TagErrorCode;
+    path?: string;
+    frameIndex?: number;
+    frameId?: string;
+  }
+
   /**
    * Functions
    */
 
-  export function loadTag(path: string): TagCarrier;
-  export function updateTag(path: string, update: TagCarrier): TagCarrier;
+  export type LoadOptions<F extends FrameFormat = FrameFormat> = FrameOptions<F> & {
+    /**
+     * Return pictures and encapsulated objects with a handle instead of their data,
+     * which loadFramePayload reads when it is needed
+     */
+    lazyPayloads?: boolean;
+  };
+
+  export function loadTag<F extends FrameFormat = 'tuple'>(
+    path: string,
+    options?: LoadOptions<F>,
+  ): TagInfo<F>;
+
+  /**
+   * Reads the data of the picture or encapsulated object at frameIndex in the frames of
+   * loadTag, failing with ERR_FRAME_NOT_FOUND if the frame there has none
+   */
+  export function loadFramePayload(path: string, frameIndex: number): ArrayBuffer;
+
+  /**
+   * The ID3 version tags are written in. preserve keeps the version the file was read with
+   * and writes new tags as ID3v2.4. Frames are translated when changing versions:
+   * TDRC to TYER/TDAT/TIME, TDOR to TORY and TIPL/TMCL to IPLS, and back
+   */
+  export type ID3WriteVersion = '2.2' | '2.3' | '2.4' | 'preserve';
+
+  /**
+   * Tags are written to a temporary copy of the file, which replaces the original once it
+   * reads back with the written frames and the audio of the original. Otherwise the
+   * original is kept and the update fails with ERR_VERIFY. Symbolic links are followed, so
+   * the file they point to is replaced, and read-only files fail with ERR_IO
+   */
+  export type WriteOptions<F extends FrameFormat = FrameFormat> = FrameOptions<F> & {
+    version?: ID3WriteVersion;
+    /** Keep the original file next to the updated one, with .bak appended to its name */
+    backup?: boolean;
+    /** Give the updated file the modification time of the original */
+    preserveMtime?: boolean;
+  };
+
+  /**
+   * The padding of ID3 tags and the first PADDING block of FLAC files grow or shrink by the
+   * change in size of the tag when they can, so the audio keeps its offset. Every other FLAC
+   * block is copied untouched
+   */
+  export function updateTag<F extends FrameFormat = 'tuple'>(
+    path: string,
+    update: TagUpdate,
+    options?: WriteOptions<F>,
+  ): LoadedTag<F>;
+
+  /**
+   * The changes updateTag would make to a file, worked out without writing it.
+   * Frames are matched by ID and by what tells apart frames sharing one, like descriptions,
+   * languages and picture types. The audio moves when the new tag doesn't fit in the space
+   * of the old one, padding included
+   */
+  export type UpdatePreview<F extends FrameFormat = 'tuple'> = {
+    format: TagFormat;
+    added: TagFrames<F>;
+    changed: { old: TagFrames<F>[number]; new: TagFrames<F>[number] }[];
+    removed: TagFrames<F>;
+    /** Bytes the new tag takes in the file */
+    tagSize: number;
+    /** Bytes the audio moves by, negative when it moves towards the start of the file */
+    audioShift: number;
+    needsShift: boolean;
+  };
+
+  export function previewUpdate<F extends FrameFormat = 'tuple'>(
+    path: string,
+    update: TagUpdate,
+    options?: Pick<WriteOptions<F>, 'frameFormat' | 'version'>,
+  ): UpdatePreview<F>;
+
+  /**
+   * Versions of loadTag and updateTag working on a whole file held in memory, such as a
+   * Node.js Buffer. writeTagToBuffer returns a new buffer with the new tag and the same audio
+   */
+  export function loadTagFromBuffer<F extends FrameFormat = 'tuple'>(
+    buffer: ArrayBuffer | Uint8Array,
+    options?: FrameOptions<F>,
+  ): TagInfo<F>;
+
+  export function writeTagToBuffer(
+    buffer: ArrayBuffer | Uint8Array,
+    update: TagUpdate,
+    options?: Pick<WriteOptions, 'version'>,
+  ): ArrayBuffer;
+
+  export type LameReplayGain = {
+    /** In dB */
+    gain: number;
+    originator: 'unset' | 'artist' | 'user' | 'automatic' | 'rms' | 'other';
+  };
+
+  /**
+   * The extension LAME and encoders based on it add after the Xing or Info header
+   */
+  export type LameHeader = {
+    encoder: string;
+    revision: number;
+    /** 1 and 8 are CBR, 2 and 9 are ABR, 3 to 6 are VBR and 0 is unknown */
+    vbrMethod: number;
+    /** Lowpass filter frequency in Hz */
+    lowpass: number | null;
+    /** Target bitrate of ABR, minimum bitrate of VBR or bitrate of CBR in kbit/s, up to 255 */
+    bitrate: number;
+    /** Peak amplitude, 1 being full scale */
+    peak: number | null;
+    /** Track gain */
+    radioGain: LameReplayGain | null;
+    /** Album gain */
+    audiophileGain: LameReplayGain | null;
+    /** Samples added by the encoder before the audio */
+    encoderDelay: number;
+    /** Samples added by the encoder after the audio to fill the last frame */
+    encoderPadding: number;
+    /** Bytes from the start of the Info frame to the end of the audio */
+    musicLength: number;
+    /** CRC-16 of the audio frames after the Info frame */
+    musicCrc: number;
+    /** CRC-16 of the Info frame up to this field */
+    crc: number;
+    /** Whether crc matches the Info frame, a mismatch means it was damaged or edited */
+    crcValid: boolean;
+  };
+
+  /**
+   * Gapless playback information iTunes writes in an iTunSMPB comment
+   */
+  export type ITunesGapless = {
+    encoderDelay: number;
+    encoderPadding: number;
+    /** Samples of audio without the delay and padding */
+    samples: number;
+  };
+
+  /**
+   * Properties of the audio of an MPEG file, read from its frame headers and the Xing, Info
+   * or VBRI header of its first frame. Without such a header every frame is counted
+   */
+  export type AudioProperties = {
+    /** In seconds */
+    duration: number;
+    /** Average bitrate in kbit/s */
+    bitrate: number;
+    /** The constant bitrate or ABR target in kbit/s, null for VBR */
+    nominalBitrate: number | null;
+    bitrateMode: 'CBR' | 'VBR' | 'ABR';
+    sampleRate: number;
+    channels: 1 | 2;
+    channelMode: 'stereo' | 'joint stereo' | 'dual channel' | 'mono';
+    mpegVersion: '1' | '2' | '2.5';
+    layer: 1 | 2 | 3;
+    frames: number;
+    vbrHeader: 'Xing' | 'Info' | 'VBRI' | null;
+    /** Such as LAME3.100, from the LAME extension of the Xing or Info header */
+    encoder: string | null;
+    lame: LameHeader | null;
+    /** From an iTunSMPB COMM or TXXX frame of the ID3v2 tag */
+    iTunSMPB: ITunesGapless | null;
+  };
+
+  /**
+   * Fails with ERR_PARSE for files that aren't MPEG audio
+   */
+  export function probeAudio(path: string): AudioProperties;
+
+  /**
+   * Asynchronous versions of loadTag and updateTag, reading and writing files on worker
+   * threads. Malformed carriers and options still throw right away
+   */
+  export function loadTagAsync<F extends FrameFormat = 'tuple'>(
+    path: string,
+    options?: LoadOptions<F>,
+  ): Promise<TagInfo<F>>;
+
+  export function updateTagAsync<F extends FrameFormat = 'tuple'>(
+    path: string,
+    update: TagUpdate,
+    options?: WriteOptions<F>,
+  ): Promise<LoadedTag<F>>;
+
+  /**
+   * Tag documents
+   */
+
+  /**
+   * The schema version of tag documents, checked when one is imported
+   */
+  export const TAG_DOCUMENT_SCHEMA_VERSION: 1;
+
+  export type TagDocumentFormat = 'json' | 'yaml';
+
+  export type ID3TextEncoding = 'ISO-8859-1' | 'UTF-16' | 'UTF-16BE' | 'UTF-8';
+
+  /**
+   * A content type with its binary data as base64 strings
+   */
+  export type Base64<T> = { [K in keyof T]: T[K] extends ArrayBuffer ? string : T[K] };
+
+  export type DocumentFrameContent = { kind: 'text'; text: string }
+  | ({ kind: 'extended text' } & ID3ExtendedText)
+  | { kind: 'link'; link: string }
+  | ({ kind: 'extended link' } & ID3ExtendedLink)
+  | ({ kind: 'comment' } & ID3Comment)
+  | ({ kind: 'popularimeter' } & ID3Popularimeter)
+  | ({ kind: 'lyrics' } & ID3Lyrics)
+  | ({ kind: 'synchronised lyrics' } & ID3SynchronisedLyrics)
+  | ({ kind: 'picture' } & Base64<ID3Picture>)
+  | ({ kind: 'encapsulated object' } & Base64<ID3EncapsulatedObject>)
+  | ({ kind: 'chapter'; frames: DocumentFrame[] } & Omit<ID3Chapter, 'frames'>)
+  | ({ kind: 'table of contents'; frames: DocumentFrame[] } & Omit<ID3TableOfContents, 'frames'>)
+  | { kind: 'involved people'; items: ID3InvolvedPeople }
+  | ({ kind: 'private' } & Base64<ID3Private>)
+  | ({ kind: 'unique file identifier' } & Base64<ID3UniqueFileIdentifier>)
+  | ({ kind: 'unknown' } & Base64<ID3Unknown>);
+
+  /**
+   * An ID3 frame as it is stored, with its text encoding and status flags. Frames with
+   * tagAlterPreservation are dropped when the tag is written, as ID3 asks
+   */
+  export type DocumentFrame = DocumentFrameContent & {
+    id: string;
+    /** Left out for frames without text, new frames get the default of the tag version */
+    encoding?: ID3TextEncoding;
+    tagAlterPreservation?: boolean;
+    fileAlterPreservation?: boolean;
+  };
+
+  export type ID3TagDocument = {
+    format: 'id3';
+    version: '2.2' | '2.3' | '2.4';
+    /** In the order they are stored, repeated frames included */
+    frames: DocumentFrame[];
+  };
+
+  /**
+   * A picture in the FLAC PICTURE block layout
+   */
+  export type VorbisDocumentPicture = Base64<ID3Picture> & {
+    width: number;
+    height: number;
+    depth: number;
+    colors: number;
+  };
+
+  export type VorbisTagDocument = {
+    format: 'vorbis';
+    vendor: string;
+    fields: { name: string; value: string }[];
+    /** Fields without `=` written by other taggers */
+    unnamed?: string[];
+    pictures: VorbisDocumentPicture[];
+  };
+
+  export type MP4TagDocument = {
+    format: 'mp4';
+    /** ident is the atom type or `----:mean:name`, data atoms of type 1 hold UTF-8 text */
+    items: {
+      ident: string;
+      data: ({ type: number; locale: number } & ({ text: string } | { data: string }))[];
+    }[];
+  };
+
+  /**
+   * A whole tag as exported by exportTag, written as JSON or YAML. Binary data is base64
+   */
+  export type TagDocument = (ID3TagDocument | VorbisTagDocument | MP4TagDocument) & {
+    schema: typeof TAG_DOCUMENT_SCHEMA_VERSION;
+  };
+
+  /**
+   * Writes the whole tag of a file as a TagDocument, in JSON unless format is 'yaml'
+   */
+  export function exportTag(path: string, format?: TagDocumentFormat): string;
+
+  /**
+   * Replaces the whole tag of a file with the tag of a JSON or YAML TagDocument. The tag is
+   * written in the version of the document and must read back byte for byte, otherwise the
+   * file is left as it was and the import fails with ERR_VERIFY. Documents of another tag
+   * format or schema fail with ERR_BAD_DOCUMENT
+   */
+  export function importTag<F extends FrameFormat = 'tuple'>(
+    path: string,
+    document: string,
+    options?: Omit<WriteOptions<F>, 'version'>,
+  ): LoadedTag<F>;
+
+  /**
+   * Reported after each file of a batch operation is finished
+   */
+  export type JobProgress = {
+    filesDone: number;
+    filesTotal: number;
+    /** Total size of the finished files */
+    bytesProcessed: number;
+    /** The file just finished */
+    path: string;
+  };
+
+  export type JobOptions = {
+    onProgress?: (progress: JobProgress) => void;
+  };
+
+  /**
+   * The promise of a batch operation. cancel() skips the files not started yet, which then
+   * fail with ERR_CANCELLED, files being written are always finished
+   */
+  export type Job<T> = Promise<T> & { cancel(): void };
+
+  /**
+   * Loads the tags of many files in parallel. Files that can't be loaded get their TagError
+   * in place of a TagInfo
+   */
+  export function loadTags<F extends FrameFormat = 'tuple'>(
+    paths: string[],
+    options?: LoadOptions<F> & JobOptions,
+  ): Job<(TagInfo<F> | TagError)[]>;
+
+  export type BatchOptions<F extends FrameFormat = FrameFormat> = WriteOptions<F> & JobOptions & {
+    /** Skip the files not started yet once one fails instead of updating all of them */
+    stopOnError?: boolean;
+  };
+
+  /**
+   * The outcome of updating one file with updateTags.
+   * Files skipped after a failure with stopOnError have neither an error nor a tag
+   */
+  export type UpdateResult<F extends FrameFormat = 'tuple'> = {
+    path: string;
+    ok: boolean;
+    error: TagError | null;
+    tag: LoadedTag<F> | null;
+  };
+
+  /**
+   * Applies the same update to many files in parallel, resolving with a result per path
+   */
+  export function updateTags<F extends FrameFormat = 'tuple'>(
+    paths: string[],
+    update: TagUpdate,
+    options?: BatchOptions<F>,
+  ): Job<UpdateResult<F>[]>;
+
+  /**
+   * Options of analyzeLoudness. With write, the gains are written like with updateTag:
+   * REPLAYGAIN_* TXXX frames to ID3 tags, REPLAYGAIN_* freeform atoms to MP4 files and
+   * REPLAYGAIN_* fields to Vorbis comments. Peaks are written as true peaks. Neither RVA2
+   * frames nor the R128_* fields of Opus are written
+   */
+  export type LoudnessOptions = Omit<WriteOptions, 'frameFormat'> & JobOptions & {
+    /** Also measure the files together as one album and write album gains */
+    albumMode?: boolean;
+    write?: boolean;
+  };
+
+  /**
+   * Loudness is null for silence and for audio shorter than 400 ms, and so are the gains
+   */
+  export type LoudnessMeasurement = {
+    /** Integrated loudness in LUFS, gated per EBU R128 */
+    integratedLoudness: number | null;
+    /** Largest value between samples found by oversampling, 1 being full scale */
+    truePeak: number | null;
+    samplePeak: number | null;
+  };
+
+  export type TrackLoudness = LoudnessMeasurement & {
+    path: string;
+    ok: boolean;
+    /**
+     * ERR_DECODE for audio that can't be decoded, ERR_UNSUPPORTED_CODEC for Opus audio,
+     * or the error of writing the gains
+     */
+    error: TagError | null;
+    /** ReplayGain 2.0 gain in dB, bringing the track to -18 LUFS */
+    trackGain: number | null;
+    albumGain: number | null;
+    written: boolean;
+  };
+
+  export type LoudnessReport = {
+    tracks: TrackLoudness[];
+    /** The measurement of all tracks together with albumMode, null otherwise */
+    album: (LoudnessMeasurement & { gain: number | null }) | null;
+  };
+
+  /**
+   * Decodes MP3, FLAC, Ogg Vorbis and WAV files and measures their loudness per ITU-R BS.1770.
+   * Opus files can't be decoded and fail with ERR_UNSUPPORTED_CODEC, without stopping the others.
+   * Files are only written once all of them are measured, progress is reported as each one
+   * is measured
+   */
+  export function analyzeLoudness(paths: string[], options?: LoudnessOptions): Job<LoudnessReport>;
+
+  /**
+   * Sets how many files asynchronous functions work on at once,
+   * the number of CPU cores by default
+   */
+  export function setConcurrency(limit: number): void;
+
+  /**
+   * Star rating conventions of players writing POPM frames,
+   * Windows Media Player is used when none is given
+   */
+  export type RatingConvention = 'wmp' | 'foobar2000' | 'musicbee';
+
+  /**
+   * Converts a POPM rating to stars. Ratings other than integers from 0 to 255 throw a
+   * RangeError
+   */
+  export function ratingToStars(rating: number, convention?: RatingConvention): number;
+
+  export function starsToRating(stars: number, convention?: RatingConvention): number;
+
+  /**
+   * Converts between LRC text and synchronised lyrics with millisecond timestamps.
+   * A positive offset makes lyrics appear sooner, compact merges repeated lines. Offsets
+   * other than 32-bit integers of milliseconds throw a RangeError
+   */
+  export function lrcToSynchronisedLyrics(
+    lrc: string,
+    lang?: string,
+    description?: string,
+  ): ID3SynchronisedLyrics;
+
+  export function synchronisedLyricsToLrc(
+    lyrics: ID3SynchronisedLyrics,
+    options?: { offset?: number; compact?: boolean },
+  ): string;
+
+  export type ChapterIssue = {
+    elementId: string;
+    message: string;
+  };
+
+  /**
+   * Checks that chapters don't overlap and every table of contents child exists
+   */
+  export function validateChapters(tag: TagUpdate): ChapterIssue[];
 }
@@ -0,0 +1,18 @@
+//! Renders the TypeScript declarations of src/typings.rs to OUT_DIR, and over
+//! @types/index.d.ts with the write-types feature
+
+use std::{env, fs, path::Path};
+
+#[path = "src/typings.rs"]
+mod typings;
+
+fn main() {
+    println!("cargo:rerun-if-changed=src/typings.rs");
+    let declarations = typings::render(&typings::module());
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    fs::write(Path::new(&out_dir).join("index.d.ts"), &declarations)
+        .expect("failed to write index.d.ts to OUT_DIR");
+    if env::var_os("CARGO_FEATURE_WRITE_TYPES").is_some() {
+        fs::write("@types/index.d.ts", &declarations).expect("failed to write @types/index.d.ts");
+    }
+}
@@ -0,0 +1,79 @@
+use metashine_core::error::TagError;
+use neon::{prelude::*, reflect};
+
+/// Defines the `TagError` class once per JavaScript realm and evaluates to it. The class is
+/// kept on the global object so every instance of the addon, worker threads included, throws
+/// errors of the class it exports.
+const TAG_ERROR_CLASS: &str = "(() => {
+  const key = Symbol.for('metashine.TagError');
+  globalThis[key] = globalThis[key] || class TagError extends Error {
+    constructor(message, code) {
+      super(message);
+      this.name = 'TagError';
+      this.code = code;
+    }
+  };
+  return globalThis[key];
+})()";
+
+/// The `TagError` subclass of `Error` that tag errors are thrown as
+pub fn tag_error_class<'a, C: Context<'a>>(cx: &mut C) -> JsResult<'a, JsFunction> {
+    let script = cx.string(TAG_ERROR_CLASS);
+    reflect::eval(cx, script)?.downcast_or_throw(cx)
+}
+
+/// Conversion of tag errors to JavaScript exceptions.
+///
+/// Errors are thrown as instances of the exported `TagError` class, carrying `code`, `path`,
+/// `frameIndex` and `frameId` properties.
+pub trait ToJsError {
+    /// Converts the error to a JavaScript `TagError` object
+    fn to_js<'a, C: Context<'a>>(&self, cx: &mut C) -> JsResult<'a, JsObject>;
+
+    /// Throws the error as a JavaScript exception
+    fn throw<'a, C: Context<'a>, T>(self, cx: &mut C) -> NeonResult<T>;
+}
+
+impl ToJsError for TagError {
+    fn to_js<'a, C: Context<'a>>(&self, cx: &mut C) -> JsResult<'a, JsObject> {
+        let js_class = tag_error_class(cx)?;
+        let js_message = cx.string(self.to_string());
+        let js_code = cx.string(self.code.as_str());
+        let js_error =
+            js_class.construct(cx, vec![js_message.upcast::<JsValue>(), js_code.upcast()])?;
+
+        if let Some(path) = &self.path {
+            let js_path = cx.string(path);
+            js_error.set(cx, "path", js_path)?;
+        }
+        if let Some(index) = self.frame_index {
+            let js_index = cx.number(index);
+            js_error.set(cx, "frameIndex", js_index)?;
+        }
+        if let Some(id) = &self.frame_id {
+            let js_id = cx.string(id);
+            js_error.set(cx, "frameId", js_id)?;
+        }
+
+        Ok(js_error)
+    }
+
+    fn throw<'a, C: Context<'a>, T>(self, cx: &mut C) -> NeonResult<T> {
+        let js_error = self.to_js(cx)?;
+        cx.throw(js_error)
+    }
+}
+
+/// Converts a `Result` carrying a `TagError` into a thrown JavaScript exception
+pub trait OrThrow<T> {
+    fn or_throw<'a, C: Context<'a>>(self, cx: &mut C) -> NeonResult<T>;
+}
+
+impl<T> OrThrow<T> for Result<T, TagError> {
+    fn or_throw<'a, C: Context<'a>>(self, cx: &mut C) -> NeonResult<T> {
+        match self {
+            Ok(value) => Ok(value),
+            Err(error) => error.throw(cx),
+        }
+    }
+}
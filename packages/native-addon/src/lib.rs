@@ -1,12 +1,43 @@
+use std::sync::Arc;
+
 use id3::{
-    frame::{Comment, EncapsulatedObject, ExtendedLink, ExtendedText, Lyrics, PictureType},
-    Frame, Tag, TagLike,
+    frame::{
+        Chapter, Comment, EncapsulatedObject, ExtendedLink, ExtendedText, InvolvedPeopleList,
+        InvolvedPeopleListItem, Lyrics, Private, SynchronisedLyrics, SynchronisedLyricsType,
+        TableOfContents, TimestampFormat, UniqueFileIdentifier,
+    },
+    Frame,
+};
+use metashine_core::{
+    atomic::SaveOptions,
+    carrier::{self, Carrier, FrameKind, PayloadHandle, FRAME_SCHEMA_VERSION},
+    chapters,
+    document::{DocumentFormat, TagDocument, DOCUMENT_SCHEMA_VERSION},
+    error::{ErrorCode, TagError},
+    id3_header::Id3Header,
+    id3_version::WriteVersion,
+    job::{Job, Progress},
+    loudness::{self, Loudness},
+    lrc,
+    mpeg::{self, AudioProperties, ITunesGapless, LameHeader, ReplayGain},
+    pool,
+    popularimeter::{self, RatingConvention},
+    tags::{self, LoadedTag, Preview, UpdatedTag},
 };
-use neon::{prelude::*, types::buffer::TypedArray};
+use neon::{
+    event::Channel,
+    object::PropertyKey,
+    prelude::*,
+    types::{buffer::TypedArray, Deferred},
+};
+
+mod error;
+
+use error::{tag_error_class, OrThrow, ToJsError};
 
 fn u8_vec_to_arraybuffer<'a, C: Context<'a>>(
     cx: &mut C,
-    vec: &Vec<u8>,
+    vec: &[u8],
 ) -> JsResult<'a, JsArrayBuffer> {
     let mut buffer = cx.array_buffer(vec.len())?;
     buffer.as_mut_slice(cx).copy_from_slice(vec);
@@ -20,460 +51,2273 @@ fn arraybuffer_to_u8_vec<'a, C: Context<'a>>(
     buffer.as_slice(cx).to_vec()
 }
 
-fn tag_to_js_tag<'a, C: Context<'a>>(cx: &mut C, tag: &Tag) -> JsResult<'a, JsArray> {
+/// Offsets of 0xFFFFFFFF mark chapters that don't use byte offsets
+const CHAPTER_OFFSET_UNUSED: u32 = u32::MAX;
+
+fn chapter_offset_to_js<'a, C: Context<'a>>(cx: &mut C, offset: u32) -> Handle<'a, JsValue> {
+    match offset {
+        CHAPTER_OFFSET_UNUSED => cx.null().upcast(),
+        offset => cx.number(offset).upcast(),
+    }
+}
+
+/// How frame carriers are handed to JavaScript, chosen with the `frameFormat` option
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CarrierFormat {
+    /// Legacy `[type, id, content, remove]` tuples
+    Tuple,
+    /// `{kind, id, value, op}` objects of the current frame schema version
+    Object,
+}
+
+fn frames_to_js_tag<'a, 'f, C: Context<'a>>(
+    cx: &mut C,
+    frames: impl IntoIterator<Item = &'f Frame>,
+    format: CarrierFormat,
+) -> JsResult<'a, JsArray> {
+    let carriers = carrier::frames_to_carriers(frames).or_throw(cx)?;
+    carriers_to_js(cx, &carriers, format)
+}
+
+fn carriers_to_js<'a, C: Context<'a>>(
+    cx: &mut C,
+    carriers: &[Carrier],
+    format: CarrierFormat,
+) -> JsResult<'a, JsArray> {
     let js_tag: Handle<JsArray> = cx.empty_array();
 
-    macro_rules! transfer_frame_as_tuple {
-        ($i:expr, $tag_type:expr, $tag_id:expr, $tag_content:expr) => {
-            let js_type = cx.string($tag_type);
-            let js_key = cx.string($tag_id);
-            let js_remove = cx.boolean(false);
+    for (i, carrier) in (0u32..).zip(carriers) {
+        let js_carrier = carrier_to_js(cx, carrier, format)?;
+        js_tag.set(cx, i, js_carrier)?;
+    }
+
+    Ok(js_tag)
+}
 
-            let js_tuple = cx.empty_array();
-            js_tuple.set(cx, 0, js_type).unwrap();
-            js_tuple.set(cx, 1, js_key).unwrap();
-            js_tuple.set(cx, 2, $tag_content).unwrap();
-            js_tuple.set(cx, 3, js_remove).unwrap();
+fn carrier_to_js<'a, C: Context<'a>>(
+    cx: &mut C,
+    carrier: &Carrier,
+    format: CarrierFormat,
+) -> JsResult<'a, JsObject> {
+    let js_content = content_to_js(cx, carrier, format)?;
+    let kind = carrier.kind().as_str();
+    match format {
+        CarrierFormat::Tuple => Ok(js_carrier(cx, kind, &carrier.id, js_content)?.upcast()),
+        CarrierFormat::Object => js_carrier_object(cx, kind, &carrier.id, js_content),
+    }
+}
 
-            js_tag
-                .set(cx, $i, js_tuple)
-                .expect("Failed writing a tag to JavaScript object");
+fn js_carrier<'a, C: Context<'a>, V: Value>(
+    cx: &mut C,
+    frame_type: &str,
+    frame_id: &str,
+    js_content: Handle<'a, V>,
+) -> JsResult<'a, JsArray> {
+    let js_tuple = cx.empty_array();
+    let js_type = cx.string(frame_type);
+    let js_id = cx.string(frame_id);
+    let js_remove = cx.boolean(false);
 
-            $i += 1;
-        };
+    js_tuple.set(cx, 0, js_type)?;
+    js_tuple.set(cx, 1, js_id)?;
+    js_tuple.set(cx, 2, js_content)?;
+    js_tuple.set(cx, 3, js_remove)?;
+
+    Ok(js_tuple)
+}
+
+fn js_carrier_object<'a, C: Context<'a>, V: Value>(
+    cx: &mut C,
+    kind: &str,
+    frame_id: &str,
+    js_value: Handle<'a, V>,
+) -> JsResult<'a, JsObject> {
+    let js_carrier = cx.empty_object();
+    let js_kind = cx.string(kind);
+    let js_id = cx.string(frame_id);
+    let js_op = cx.string("set");
+
+    js_carrier.set(cx, "kind", js_kind)?;
+    js_carrier.set(cx, "id", js_id)?;
+    js_carrier.set(cx, "value", js_value)?;
+    js_carrier.set(cx, "op", js_op)?;
+
+    Ok(js_carrier)
+}
+
+/// Sets the `data` of a picture or encapsulated object, or the `size` and `hash` of its
+/// handle when the data was left out
+fn set_payload<'a, C: Context<'a>>(
+    cx: &mut C,
+    js_content: Handle<'a, JsObject>,
+    data: &[u8],
+    payload: Option<&PayloadHandle>,
+) -> NeonResult<()> {
+    match payload {
+        Some(payload) => {
+            let js_size = cx.number(payload.size as f64);
+            let js_hash = cx.string(&payload.hash);
+            js_content.set(cx, "size", js_size)?;
+            js_content.set(cx, "hash", js_hash)?;
+        }
+        None => {
+            let js_data = u8_vec_to_arraybuffer(cx, data)?;
+            js_content.set(cx, "data", js_data)?;
+        }
     }
+    Ok(())
+}
 
-    // Write frames to js_tag
-    let mut i: u32 = 0;
-    tag.frames().for_each(|frame| {
-        match frame.content() {
-            // Texts
-            id3::Content::Text(content) => {
-                let js_string = cx.string(content);
-                transfer_frame_as_tuple!(i, "text", frame.id(), js_string);
-                return;
-            }
+fn content_to_js<'a, C: Context<'a>>(
+    cx: &mut C,
+    carrier: &Carrier,
+    format: CarrierFormat,
+) -> JsResult<'a, JsValue> {
+    let js_content = match &carrier.content {
+        // Texts
+        id3::Content::Text(content) => cx.string(content).upcast(),
 
-            // Extended texts
-            id3::Content::ExtendedText(content) => {
-                let js_value = cx.string(&content.value);
-                let js_description = cx.string(&content.description);
-
-                let js_extended_text = cx.empty_object();
-                js_extended_text
-                    .set(cx, "value", js_value)
-                    .expect("Failed writing an extended text frame value to Javascript runtime");
-                js_extended_text
-                    .set(cx, "description", js_description)
-                    .expect(
-                        "Failed writing an extended text frame description to Javascript runtime",
-                    );
-
-                transfer_frame_as_tuple!(i, "extended text", frame.id(), js_extended_text);
-                return;
+        // Extended texts
+        id3::Content::ExtendedText(content) => {
+            let js_value = cx.string(&content.value);
+            let js_description = cx.string(&content.description);
+
+            let js_extended_text = cx.empty_object();
+            js_extended_text.set(cx, "value", js_value)?;
+            js_extended_text.set(cx, "description", js_description)?;
+            js_extended_text.upcast()
+        }
+
+        // Links
+        id3::Content::Link(content) => cx.string(content).upcast(),
+
+        // Extended links
+        id3::Content::ExtendedLink(content) => {
+            let js_extended_link = cx.empty_object();
+            let js_description = cx.string(&content.description);
+            let js_link = cx.string(&content.link);
+
+            js_extended_link.set(cx, "description", js_description)?;
+            js_extended_link.set(cx, "link", js_link)?;
+            js_extended_link.upcast()
+        }
+
+        // Comments
+        id3::Content::Comment(content) => {
+            let js_lang = cx.string(&content.lang);
+            let js_description = cx.string(&content.description);
+            let js_text = cx.string(&content.text);
+
+            let js_comment = cx.empty_object();
+            js_comment.set(cx, "lang", js_lang)?;
+            js_comment.set(cx, "description", js_description)?;
+            js_comment.set(cx, "text", js_text)?;
+            js_comment.upcast()
+        }
+
+        // Popularimeters
+        id3::Content::Popularimeter(content) => {
+            let js_popularimeter = cx.empty_object();
+            let js_user = cx.string(&content.user);
+            let js_rating = cx.number(content.rating);
+            let js_counter = cx.number(content.counter as f64);
+
+            js_popularimeter.set(cx, "user", js_user)?;
+            js_popularimeter.set(cx, "rating", js_rating)?;
+            js_popularimeter.set(cx, "counter", js_counter)?;
+            js_popularimeter.upcast()
+        }
+
+        // Lyrics
+        id3::Content::Lyrics(content) => {
+            let js_lyrics = cx.empty_object();
+            let js_lang = cx.string(&content.lang);
+            let js_description = cx.string(&content.description);
+            let js_text = cx.string(&content.text);
+
+            js_lyrics.set(cx, "lang", js_lang)?;
+            js_lyrics.set(cx, "description", js_description)?;
+            js_lyrics.set(cx, "text", js_text)?;
+            js_lyrics.upcast()
+        }
+
+        // SynchronisedLyrics
+        id3::Content::SynchronisedLyrics(content) => {
+            synchronised_lyrics_to_js(cx, content)?.upcast()
+        }
+
+        // Pictures
+        id3::Content::Picture(content) => {
+            let js_picture = cx.empty_object();
+            let js_mime_type = cx.string(&content.mime_type);
+            let js_picture_type = cx.number(u8::from(content.picture_type));
+            let js_description = cx.string(&content.description);
+
+            js_picture.set(cx, "MIMEType", js_mime_type)?;
+            js_picture.set(cx, "pictureType", js_picture_type)?;
+            js_picture.set(cx, "description", js_description)?;
+            set_payload(cx, js_picture, &content.data, carrier.payload.as_ref())?;
+            js_picture.upcast()
+        }
+
+        // Encapsulated objects
+        id3::Content::EncapsulatedObject(content) => {
+            let js_enc_object = cx.empty_object();
+            let js_mime_type = cx.string(&content.mime_type);
+            let js_filename = cx.string(&content.filename);
+            let js_description = cx.string(&content.description);
+
+            js_enc_object.set(cx, "MIMEType", js_mime_type)?;
+            js_enc_object.set(cx, "filename", js_filename)?;
+            js_enc_object.set(cx, "description", js_description)?;
+            set_payload(cx, js_enc_object, &content.data, carrier.payload.as_ref())?;
+            js_enc_object.upcast()
+        }
+
+        // Private frames
+        id3::Content::Private(content) => {
+            let js_private = cx.empty_object();
+            let js_owner = cx.string(&content.owner_identifier);
+            let js_data = u8_vec_to_arraybuffer(cx, &content.private_data)?;
+
+            js_private.set(cx, "owner", js_owner)?;
+            js_private.set(cx, "data", js_data)?;
+            js_private.upcast()
+        }
+
+        // Unique file identifiers
+        id3::Content::UniqueFileIdentifier(content) => {
+            let js_ufid = cx.empty_object();
+            let js_owner = cx.string(&content.owner_identifier);
+            let js_identifier = u8_vec_to_arraybuffer(cx, &content.identifier)?;
+
+            js_ufid.set(cx, "owner", js_owner)?;
+            js_ufid.set(cx, "identifier", js_identifier)?;
+            js_ufid.upcast()
+        }
+
+        // Chapters
+        id3::Content::Chapter(content) => {
+            let js_chapter = cx.empty_object();
+            let js_element_id = cx.string(&content.element_id);
+            let js_start_time = cx.number(content.start_time);
+            let js_end_time = cx.number(content.end_time);
+            let js_start_offset = chapter_offset_to_js(cx, content.start_offset);
+            let js_end_offset = chapter_offset_to_js(cx, content.end_offset);
+            let js_frames = frames_to_js_tag(cx, content.frames.iter(), format)?;
+
+            js_chapter.set(cx, "elementId", js_element_id)?;
+            js_chapter.set(cx, "startTime", js_start_time)?;
+            js_chapter.set(cx, "endTime", js_end_time)?;
+            js_chapter.set(cx, "startOffset", js_start_offset)?;
+            js_chapter.set(cx, "endOffset", js_end_offset)?;
+            js_chapter.set(cx, "frames", js_frames)?;
+            js_chapter.upcast()
+        }
+
+        // Tables of contents
+        id3::Content::TableOfContents(content) => {
+            let js_table = cx.empty_object();
+            let js_element_id = cx.string(&content.element_id);
+            let js_top_level = cx.boolean(content.top_level);
+            let js_ordered = cx.boolean(content.ordered);
+            let js_elements = cx.empty_array();
+            for (j, element) in (0u32..).zip(&content.elements) {
+                let js_element = cx.string(element);
+                js_elements.set(cx, j, js_element)?;
             }
+            let js_frames = frames_to_js_tag(cx, content.frames.iter(), format)?;
 
-            // Links
-            id3::Content::Link(content) => {
-                let js_text = cx.string(content);
-                transfer_frame_as_tuple!(i, "link", frame.id(), js_text);
-                return;
+            js_table.set(cx, "elementId", js_element_id)?;
+            js_table.set(cx, "topLevel", js_top_level)?;
+            js_table.set(cx, "ordered", js_ordered)?;
+            js_table.set(cx, "elements", js_elements)?;
+            js_table.set(cx, "frames", js_frames)?;
+            js_table.upcast()
+        }
+
+        // Involved people lists
+        id3::Content::InvolvedPeopleList(content) => {
+            let js_people = cx.empty_array();
+            for (j, item) in (0u32..).zip(&content.items) {
+                let js_item = cx.empty_object();
+                let js_involvement = cx.string(&item.involvement);
+                let js_involvee = cx.string(&item.involvee);
+                js_item.set(cx, "involvement", js_involvement)?;
+                js_item.set(cx, "involvee", js_involvee)?;
+                js_people.set(cx, j, js_item)?;
             }
+            js_people.upcast()
+        }
 
-            // Extended links
-            id3::Content::ExtendedLink(content) => {
-                let js_extended_link = cx.empty_object();
-                let js_description = cx.string(&content.description);
-                let js_link = cx.string(&content.link);
+        // Play counters
+        id3::Content::Unknown(content) if carrier.kind() == FrameKind::PlayCounter => {
+            let counter = popularimeter::decode_play_counter(&content.data);
+            cx.number(counter as f64).upcast()
+        }
 
-                js_extended_link
-                    .set(cx, "description", js_description)
-                    .unwrap();
-                js_extended_link.set(cx, "link", js_link).unwrap();
+        // Unknown frames
+        id3::Content::Unknown(content) => {
+            let js_unknown = cx.empty_object();
+            let js_data = u8_vec_to_arraybuffer(cx, &content.data)?;
 
-                transfer_frame_as_tuple!(i, "extended link", frame.id(), js_extended_link);
-                return;
+            js_unknown.set(cx, "data", js_data)?;
+            if let Some(data_type) = carrier.data_type {
+                let js_type = cx.number(data_type);
+                js_unknown.set(cx, "type", js_type)?;
             }
+            js_unknown.upcast()
+        }
 
-            // Comments
-            id3::Content::Comment(content) => {
-                let js_lang = cx.string(&content.lang);
-                let js_description = cx.string(&content.description);
-                let js_text = cx.string(&content.text);
-
-                let js_comment = cx.empty_object();
-                js_comment
-                    .set(cx, "lang", js_lang)
-                    .expect("Failed writing a comment frame lang to Javascript runtime");
-                js_comment
-                    .set(cx, "description", js_description)
-                    .expect("Failed writing a comment frame description to Javascript runtime");
-                js_comment
-                    .set(cx, "text", js_text)
-                    .expect("Failed writing a comment frame text to Javascript runtime");
-
-                transfer_frame_as_tuple!(i, "comment", frame.id(), js_comment);
-                return;
-            }
+        // Frames that are not implemented yet, carrier::frames_to_carriers rejects them
+        _ => {
+            return TagError::new(
+                ErrorCode::UnsupportedFrame,
+                format!("Reading frame {} is not implemented yet", carrier.id),
+            )
+            .throw(cx);
+        }
+    };
 
-            // Popularimeters
-            // id3::Content::Popularimeter(content) => todo!(),
+    Ok(js_content)
+}
 
-            // Lyrics
-            id3::Content::Lyrics(content) => {
-                let js_lyrics = cx.empty_object();
-                let js_lang = cx.string(&content.lang);
-                let js_description = cx.string(&content.description);
-                let js_text = cx.string(&content.text);
+fn timestamp_format_to_str(format: TimestampFormat) -> &'static str {
+    match format {
+        TimestampFormat::Ms => "ms",
+        TimestampFormat::Mpeg => "mpeg",
+    }
+}
 
-                js_lyrics.set(cx, "lang", js_lang).unwrap();
-                js_lyrics.set(cx, "description", js_description).unwrap();
-                js_lyrics.set(cx, "text", js_text).unwrap();
+fn str_to_timestamp_format(format: &str) -> Option<TimestampFormat> {
+    match format {
+        "ms" => Some(TimestampFormat::Ms),
+        "mpeg" => Some(TimestampFormat::Mpeg),
+        _ => None,
+    }
+}
 
-                transfer_frame_as_tuple!(i, "lyrics", frame.id(), js_lyrics);
-                return;
-            }
+fn synchronised_lyrics_type_to_str(content_type: SynchronisedLyricsType) -> &'static str {
+    match content_type {
+        SynchronisedLyricsType::Other => "other",
+        SynchronisedLyricsType::Lyrics => "lyrics",
+        SynchronisedLyricsType::Transcription => "transcription",
+        SynchronisedLyricsType::PartName => "part name",
+        SynchronisedLyricsType::Event => "event",
+        SynchronisedLyricsType::Chord => "chord",
+        SynchronisedLyricsType::Trivia => "trivia",
+    }
+}
 
-            // SynchronisedLyrics
-            // id3::Content::SynchronisedLyrics(content) => todo!(),
-
-            // Pictures
-            id3::Content::Picture(content) => {
-                let js_picture = cx.empty_object();
-                let js_mime_type = cx.string(&content.mime_type);
-                let js_picture_type = cx.number(u8::from(content.picture_type));
-                let js_description = cx.string(&content.description);
-                let js_data = u8_vec_to_arraybuffer(cx, &content.data)
-                    .expect("Failed loading image data into Javascript runtime");
-
-                js_picture
-                    .set(cx, "MIMEType", js_mime_type)
-                    .expect("Failed writing a picture frame MIME type to Javascript runtime");
-                js_picture
-                    .set(cx, "pictureType", js_picture_type)
-                    .expect("Failed writing picture frame picture type to Javascript runtime");
-                js_picture
-                    .set(cx, "description", js_description)
-                    .expect("Failed writing picture frame description to Javascript runtime");
-                js_picture
-                    .set(cx, "data", js_data)
-                    .expect("Failed writing picture frame picture data to Javascript runtime");
-
-                transfer_frame_as_tuple!(i, "picture", frame.id(), js_picture);
-                return;
+fn str_to_synchronised_lyrics_type(content_type: &str) -> Option<SynchronisedLyricsType> {
+    match content_type {
+        "other" => Some(SynchronisedLyricsType::Other),
+        "lyrics" => Some(SynchronisedLyricsType::Lyrics),
+        "transcription" => Some(SynchronisedLyricsType::Transcription),
+        "part name" => Some(SynchronisedLyricsType::PartName),
+        "event" => Some(SynchronisedLyricsType::Event),
+        "chord" => Some(SynchronisedLyricsType::Chord),
+        "trivia" => Some(SynchronisedLyricsType::Trivia),
+        _ => None,
+    }
+}
+
+fn synchronised_lyrics_to_js<'a, C: Context<'a>>(
+    cx: &mut C,
+    content: &SynchronisedLyrics,
+) -> JsResult<'a, JsObject> {
+    let js_synchronised_lyrics = cx.empty_object();
+    let js_lang = cx.string(&content.lang);
+    let js_timestamp_format = cx.string(timestamp_format_to_str(content.timestamp_format));
+    let js_content_type = cx.string(synchronised_lyrics_type_to_str(content.content_type));
+    let js_description = cx.string(&content.description);
+
+    let js_content = cx.empty_array();
+    for (i, (timestamp, text)) in (0u32..).zip(&content.content) {
+        let js_pair = cx.empty_array();
+        let js_timestamp = cx.number(*timestamp);
+        let js_text = cx.string(text);
+        js_pair.set(cx, 0, js_timestamp)?;
+        js_pair.set(cx, 1, js_text)?;
+        js_content.set(cx, i, js_pair)?;
+    }
+
+    js_synchronised_lyrics.set(cx, "lang", js_lang)?;
+    js_synchronised_lyrics.set(cx, "timestampFormat", js_timestamp_format)?;
+    js_synchronised_lyrics.set(cx, "contentType", js_content_type)?;
+    js_synchronised_lyrics.set(cx, "description", js_description)?;
+    js_synchronised_lyrics.set(cx, "content", js_content)?;
+
+    Ok(js_synchronised_lyrics)
+}
+
+/// Converts the ID3 header to the `version`, `tagSize`, `paddingSize` and `flags` properties of
+/// `loadTag`, which are null for other formats or files without a tag
+fn set_id3_header_properties<'a, C: Context<'a>>(
+    cx: &mut C,
+    js_loaded: Handle<'a, JsObject>,
+    header: Option<&Id3Header>,
+) -> NeonResult<()> {
+    let (js_version, js_tag_size, js_padding_size, js_flags): (
+        Handle<JsValue>,
+        Handle<JsValue>,
+        Handle<JsValue>,
+        Handle<JsValue>,
+    ) = match header {
+        Some(header) => {
+            let js_flags = cx.empty_object();
+            let js_unsynchronisation = cx.boolean(header.flags.unsynchronisation);
+            let js_extended_header = cx.boolean(header.flags.extended_header);
+            let js_experimental = cx.boolean(header.flags.experimental);
+            let js_footer = cx.boolean(header.flags.footer);
+            js_flags.set(cx, "unsynchronisation", js_unsynchronisation)?;
+            js_flags.set(cx, "extendedHeader", js_extended_header)?;
+            js_flags.set(cx, "experimental", js_experimental)?;
+            js_flags.set(cx, "footer", js_footer)?;
+
+            (
+                cx.string(header.version_name()).upcast(),
+                cx.number(header.tag_size as f64).upcast(),
+                cx.number(header.padding_size as f64).upcast(),
+                js_flags.upcast(),
+            )
+        }
+        None => (
+            cx.null().upcast(),
+            cx.null().upcast(),
+            cx.null().upcast(),
+            cx.null().upcast(),
+        ),
+    };
+
+    js_loaded.set(cx, "version", js_version)?;
+    js_loaded.set(cx, "tagSize", js_tag_size)?;
+    js_loaded.set(cx, "paddingSize", js_padding_size)?;
+    js_loaded.set(cx, "flags", js_flags)?;
+    Ok(())
+}
+
+/// Converts a loaded tag to the object returned by `loadTag`
+fn loaded_tag_to_js<'a, C: Context<'a>>(
+    cx: &mut C,
+    loaded: &LoadedTag,
+    format: CarrierFormat,
+) -> JsResult<'a, JsObject> {
+    let js_frames = carriers_to_js(cx, &loaded.carriers, format)?;
+
+    let js_loaded = cx.empty_object();
+    let js_format = cx.string(loaded.format.as_str());
+    let js_had_tag = cx.boolean(loaded.had_tag);
+    js_loaded.set(cx, "format", js_format)?;
+    js_loaded.set(cx, "hadTag", js_had_tag)?;
+    set_id3_header_properties(cx, js_loaded, loaded.header.as_ref())?;
+    js_loaded.set(cx, "frames", js_frames)?;
+
+    Ok(js_loaded)
+}
+
+/// Converts an updated tag to the carrier array with a `format` property returned by
+/// `updateTag`
+fn updated_tag_to_js<'a, C: Context<'a>>(
+    cx: &mut C,
+    updated: &UpdatedTag,
+    format: CarrierFormat,
+) -> JsResult<'a, JsArray> {
+    let js_tag = carriers_to_js(cx, &updated.carriers, format)?;
+    let js_format = cx.string(updated.format.as_str());
+    js_tag.set(cx, "format", js_format)?;
+
+    Ok(js_tag)
+}
+
+/// Converts the outcome of a dry run to the object returned by `previewUpdate`
+fn preview_to_js<'a, C: Context<'a>>(
+    cx: &mut C,
+    preview: &Preview,
+    format: CarrierFormat,
+) -> JsResult<'a, JsObject> {
+    let js_added = carriers_to_js(cx, &preview.diff.added, format)?;
+    let js_removed = carriers_to_js(cx, &preview.diff.removed, format)?;
+    let js_changed = cx.empty_array();
+    for (i, (old, new)) in (0u32..).zip(&preview.diff.changed) {
+        let js_change = cx.empty_object();
+        let js_old = carrier_to_js(cx, old, format)?;
+        let js_new = carrier_to_js(cx, new, format)?;
+        js_change.set(cx, "old", js_old)?;
+        js_change.set(cx, "new", js_new)?;
+        js_changed.set(cx, i, js_change)?;
+    }
+
+    let js_preview = cx.empty_object();
+    let js_format = cx.string(preview.format.as_str());
+    let js_tag_size = cx.number(preview.layout.tag_size as f64);
+    let js_audio_shift = cx.number(preview.layout.audio_shift as f64);
+    let js_needs_shift = cx.boolean(preview.layout.audio_shift != 0);
+    js_preview.set(cx, "format", js_format)?;
+    js_preview.set(cx, "added", js_added)?;
+    js_preview.set(cx, "changed", js_changed)?;
+    js_preview.set(cx, "removed", js_removed)?;
+    js_preview.set(cx, "tagSize", js_tag_size)?;
+    js_preview.set(cx, "audioShift", js_audio_shift)?;
+    js_preview.set(cx, "needsShift", js_needs_shift)?;
+
+    Ok(js_preview)
+}
+
+/// Loads the tag at path, leaving picture and encapsulated object data out with
+/// `lazyPayloads`
+fn load_with_options(path: &str, lazy_payloads: bool) -> Result<LoadedTag, TagError> {
+    let loaded = tags::load(path)?;
+    Ok(match lazy_payloads {
+        true => loaded.strip_payloads(),
+        false => loaded,
+    })
+}
+
+fn load_tag(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let js_path: Handle<JsString> = cx.argument(0)?;
+    let path = js_path.value(&mut cx);
+    let lazy_payloads = bool_option(&mut cx, 1, "lazyPayloads")?;
+    let format = carrier_format_option(&mut cx, 1)?;
+
+    let loaded = load_with_options(&path, lazy_payloads).or_throw(&mut cx)?;
+    loaded_tag_to_js(&mut cx, &loaded, format)
+}
+
+/// Reads the data of a picture or encapsulated object left out by `lazyPayloads`
+fn load_frame_payload(mut cx: FunctionContext) -> JsResult<JsArrayBuffer> {
+    let js_path: Handle<JsString> = cx.argument(0)?;
+    let path = js_path.value(&mut cx);
+    let js_index: Handle<JsNumber> = cx.argument(1)?;
+    let index = js_index.value(&mut cx);
+    if index.is_nan() || index < 0.0 || index.fract() != 0.0 {
+        return cx.throw_range_error(format!("{} is not a frame index", index));
+    }
+
+    let data = tags::load_payload(&path, index as usize).or_throw(&mut cx)?;
+    u8_vec_to_arraybuffer(&mut cx, &data)
+}
+
+fn replay_gain_to_js<'a, C: Context<'a>>(
+    cx: &mut C,
+    replay_gain: Option<&ReplayGain>,
+) -> JsResult<'a, JsValue> {
+    let replay_gain = match replay_gain {
+        Some(replay_gain) => replay_gain,
+        None => return Ok(cx.null().upcast()),
+    };
+
+    let js_replay_gain = cx.empty_object();
+    let js_gain = cx.number(replay_gain.gain);
+    let js_originator = cx.string(replay_gain.originator.as_str());
+    js_replay_gain.set(cx, "gain", js_gain)?;
+    js_replay_gain.set(cx, "originator", js_originator)?;
+
+    Ok(js_replay_gain.upcast())
+}
+
+fn lame_header_to_js<'a, C: Context<'a>>(cx: &mut C, lame: &LameHeader) -> JsResult<'a, JsObject> {
+    let js_lame = cx.empty_object();
+
+    let js_encoder = cx.string(&lame.encoder);
+    let js_revision = cx.number(lame.revision);
+    let js_vbr_method = cx.number(lame.vbr_method);
+    let js_lowpass: Handle<JsValue> = match lame.lowpass {
+        Some(lowpass) => cx.number(lowpass).upcast(),
+        None => cx.null().upcast(),
+    };
+    let js_bitrate = cx.number(lame.bitrate);
+    js_lame.set(cx, "encoder", js_encoder)?;
+    js_lame.set(cx, "revision", js_revision)?;
+    js_lame.set(cx, "vbrMethod", js_vbr_method)?;
+    js_lame.set(cx, "lowpass", js_lowpass)?;
+    js_lame.set(cx, "bitrate", js_bitrate)?;
+
+    let js_peak: Handle<JsValue> = match lame.peak {
+        Some(peak) => cx.number(peak).upcast(),
+        None => cx.null().upcast(),
+    };
+    let js_radio_gain = replay_gain_to_js(cx, lame.radio_gain.as_ref())?;
+    let js_audiophile_gain = replay_gain_to_js(cx, lame.audiophile_gain.as_ref())?;
+    js_lame.set(cx, "peak", js_peak)?;
+    js_lame.set(cx, "radioGain", js_radio_gain)?;
+    js_lame.set(cx, "audiophileGain", js_audiophile_gain)?;
+
+    let js_encoder_delay = cx.number(lame.encoder_delay);
+    let js_encoder_padding = cx.number(lame.encoder_padding);
+    let js_music_length = cx.number(lame.music_length);
+    let js_music_crc = cx.number(lame.music_crc);
+    let js_crc = cx.number(lame.crc);
+    let js_crc_valid = cx.boolean(lame.crc_valid);
+    js_lame.set(cx, "encoderDelay", js_encoder_delay)?;
+    js_lame.set(cx, "encoderPadding", js_encoder_padding)?;
+    js_lame.set(cx, "musicLength", js_music_length)?;
+    js_lame.set(cx, "musicCrc", js_music_crc)?;
+    js_lame.set(cx, "crc", js_crc)?;
+    js_lame.set(cx, "crcValid", js_crc_valid)?;
+
+    Ok(js_lame)
+}
+
+fn itunes_gapless_to_js<'a, C: Context<'a>>(
+    cx: &mut C,
+    gapless: &ITunesGapless,
+) -> JsResult<'a, JsObject> {
+    let js_gapless = cx.empty_object();
+    let js_encoder_delay = cx.number(gapless.encoder_delay);
+    let js_encoder_padding = cx.number(gapless.encoder_padding);
+    let js_samples = cx.number(gapless.samples as f64);
+    js_gapless.set(cx, "encoderDelay", js_encoder_delay)?;
+    js_gapless.set(cx, "encoderPadding", js_encoder_padding)?;
+    js_gapless.set(cx, "samples", js_samples)?;
+
+    Ok(js_gapless)
+}
+
+/// Converts MPEG audio properties to the object returned by `probeAudio`
+fn audio_properties_to_js<'a, C: Context<'a>>(
+    cx: &mut C,
+    properties: &AudioProperties,
+) -> JsResult<'a, JsObject> {
+    let js_properties = cx.empty_object();
+
+    let js_duration = cx.number(properties.duration);
+    let js_bitrate = cx.number(properties.bitrate);
+    let js_nominal_bitrate: Handle<JsValue> = match properties.nominal_bitrate {
+        Some(bitrate) => cx.number(bitrate).upcast(),
+        None => cx.null().upcast(),
+    };
+    let js_bitrate_mode = cx.string(properties.bitrate_mode.as_str());
+    js_properties.set(cx, "duration", js_duration)?;
+    js_properties.set(cx, "bitrate", js_bitrate)?;
+    js_properties.set(cx, "nominalBitrate", js_nominal_bitrate)?;
+    js_properties.set(cx, "bitrateMode", js_bitrate_mode)?;
+
+    let js_sample_rate = cx.number(properties.sample_rate);
+    let js_channels = cx.number(properties.channel_mode.channels());
+    let js_channel_mode = cx.string(properties.channel_mode.as_str());
+    let js_version = cx.string(properties.version.as_str());
+    let js_layer = cx.number(properties.layer);
+    let js_frames = cx.number(properties.frames as f64);
+    js_properties.set(cx, "sampleRate", js_sample_rate)?;
+    js_properties.set(cx, "channels", js_channels)?;
+    js_properties.set(cx, "channelMode", js_channel_mode)?;
+    js_properties.set(cx, "mpegVersion", js_version)?;
+    js_properties.set(cx, "layer", js_layer)?;
+    js_properties.set(cx, "frames", js_frames)?;
+
+    let js_vbr_header: Handle<JsValue> = match &properties.vbr_header {
+        Some(header) => cx.string(header.kind.as_str()).upcast(),
+        None => cx.null().upcast(),
+    };
+    let js_encoder: Handle<JsValue> = match properties.encoder() {
+        Some(encoder) => cx.string(encoder).upcast(),
+        None => cx.null().upcast(),
+    };
+    js_properties.set(cx, "vbrHeader", js_vbr_header)?;
+    js_properties.set(cx, "encoder", js_encoder)?;
+
+    let js_lame: Handle<JsValue> = match properties.lame() {
+        Some(lame) => lame_header_to_js(cx, lame)?.upcast(),
+        None => cx.null().upcast(),
+    };
+    let js_itunes_gapless: Handle<JsValue> = match &properties.itunes_gapless {
+        Some(gapless) => itunes_gapless_to_js(cx, gapless)?.upcast(),
+        None => cx.null().upcast(),
+    };
+    js_properties.set(cx, "lame", js_lame)?;
+    js_properties.set(cx, "iTunSMPB", js_itunes_gapless)?;
+
+    Ok(js_properties)
+}
+
+fn probe_audio(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let js_path: Handle<JsString> = cx.argument(0)?;
+    let path = js_path.value(&mut cx);
+
+    let properties = mpeg::probe_path(&path).or_throw(&mut cx)?;
+    audio_properties_to_js(&mut cx, &properties)
+}
+
+/// Position of a frame carrier in an update, used to annotate carrier errors
+struct CarrierRef<'p> {
+    path: Option<&'p str>,
+    index: u32,
+    id: String,
+    /// Whether the carrier removes a frame, so missing fields are left empty
+    removal: bool,
+    /// The form of the carrier, which names its fields in errors: the content of tuples is
+    /// field `2` and its fields are named alone, while in objects they are `value.field`
+    format: CarrierFormat,
+}
+
+impl CarrierRef<'_> {
+    fn annotate(&self, error: TagError) -> TagError {
+        let error = error.with_frame(self.index, &self.id);
+        match self.path {
+            Some(path) => error.with_path(path),
+            None => error,
+        }
+    }
+
+    fn error(&self, code: ErrorCode, message: impl Into<String>) -> TagError {
+        self.annotate(TagError::new(code, message))
+    }
+
+    /// Records the frame ID of the carrier, after the ID of the parent frame for sub-frames
+    fn push_id(&mut self, id: &str) {
+        self.id = match self.id.is_empty() {
+            true => id.to_string(),
+            false => format!("{}/{}", self.id, id),
+        };
+    }
+}
+
+/// Values frame carrier fields can have, with the empty value missing fields of removal
+/// carriers take
+trait CarrierField: Value {
+    /// What the field must be, for error messages
+    const NAME: &'static str;
+
+    fn empty<'a, C: Context<'a>>(cx: &mut C) -> JsResult<'a, Self>;
+}
+
+impl CarrierField for JsString {
+    const NAME: &'static str = "a string";
+
+    fn empty<'a, C: Context<'a>>(cx: &mut C) -> JsResult<'a, Self> {
+        Ok(cx.string(""))
+    }
+}
+
+impl CarrierField for JsNumber {
+    const NAME: &'static str = "a number";
+
+    fn empty<'a, C: Context<'a>>(cx: &mut C) -> JsResult<'a, Self> {
+        Ok(cx.number(0))
+    }
+}
+
+impl CarrierField for JsBoolean {
+    const NAME: &'static str = "a boolean";
+
+    fn empty<'a, C: Context<'a>>(cx: &mut C) -> JsResult<'a, Self> {
+        Ok(cx.boolean(false))
+    }
+}
+
+impl CarrierField for JsObject {
+    const NAME: &'static str = "an object";
+
+    fn empty<'a, C: Context<'a>>(cx: &mut C) -> JsResult<'a, Self> {
+        Ok(cx.empty_object())
+    }
+}
+
+impl CarrierField for JsArray {
+    const NAME: &'static str = "an array";
+
+    fn empty<'a, C: Context<'a>>(cx: &mut C) -> JsResult<'a, Self> {
+        Ok(cx.empty_array())
+    }
+}
+
+impl CarrierField for JsArrayBuffer {
+    const NAME: &'static str = "an ArrayBuffer";
+
+    fn empty<'a, C: Context<'a>>(cx: &mut C) -> JsResult<'a, Self> {
+        cx.array_buffer(0)
+    }
+}
+
+/// Describes a JavaScript value in carrier error messages
+fn js_type_name<'a, C: Context<'a>>(cx: &mut C, value: Handle<'a, JsValue>) -> &'static str {
+    if value.is_a::<JsUndefined, _>(cx) {
+        "undefined"
+    } else if value.is_a::<JsNull, _>(cx) {
+        "null"
+    } else if value.is_a::<JsBoolean, _>(cx) {
+        "a boolean"
+    } else if value.is_a::<JsNumber, _>(cx) {
+        "a number"
+    } else if value.is_a::<JsString, _>(cx) {
+        "a string"
+    } else if value.is_a::<JsArray, _>(cx) {
+        "an array"
+    } else if value.is_a::<JsArrayBuffer, _>(cx) {
+        "an ArrayBuffer"
+    } else if value.is_a::<JsFunction, _>(cx) {
+        "a function"
+    } else {
+        "an object"
+    }
+}
+
+/// Checks the type of a carrier field named `name` in errors, throwing `ERR_BAD_CARRIER` if
+/// it is missing or mistyped
+fn carrier_field<'a, V: CarrierField>(
+    cx: &mut FunctionContext<'a>,
+    value: Handle<'a, JsValue>,
+    name: &dyn std::fmt::Display,
+    at: &CarrierRef,
+) -> JsResult<'a, V> {
+    if at.removal && (value.is_a::<JsUndefined, _>(cx) || value.is_a::<JsNull, _>(cx)) {
+        return V::empty(cx);
+    }
+    match value.downcast::<V, _>(cx) {
+        Ok(value) => Ok(value),
+        Err(_) => {
+            let actual = js_type_name(cx, value);
+            at.error(
+                ErrorCode::BadCarrier,
+                format!("Field {} must be {}, got {}", name, V::NAME, actual),
+            )
+            .throw(cx)
+        }
+    }
+}
+
+/// Reads a field of the content of a frame carrier, throwing `ERR_BAD_CARRIER` if it is
+/// missing or mistyped
+fn carrier_get<'a, V, O, K>(
+    cx: &mut FunctionContext<'a>,
+    object: Handle<'a, O>,
+    key: K,
+    at: &CarrierRef,
+) -> JsResult<'a, V>
+where
+    V: CarrierField,
+    O: Object,
+    K: PropertyKey + std::fmt::Display + Copy,
+{
+    carrier_get_named(cx, object, key, &key.to_string(), at)
+}
+
+/// Reads a field of the content of a frame carrier like `carrier_get`, naming it `name` in
+/// errors, such as `content[2][0]` for items of arrays
+fn carrier_get_named<'a, V, O, K>(
+    cx: &mut FunctionContext<'a>,
+    object: Handle<'a, O>,
+    key: K,
+    name: &str,
+    at: &CarrierRef,
+) -> JsResult<'a, V>
+where
+    V: CarrierField,
+    O: Object,
+    K: PropertyKey,
+{
+    let value: Handle<JsValue> = object.get(cx, key)?;
+    carrier_field(cx, value, &content_field_name(name, at), at)
+}
+
+/// Names a field of the content of a frame carrier in errors, inside `value` for frame objects
+fn content_field_name(name: &str, at: &CarrierRef) -> String {
+    match at.format {
+        CarrierFormat::Tuple => name.to_string(),
+        CarrierFormat::Object if name.starts_with('[') => format!("value{}", name),
+        CarrierFormat::Object => format!("value.{}", name),
+    }
+}
+
+/// The largest integer JavaScript numbers hold exactly, `Number.MAX_SAFE_INTEGER`
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// Checks that a number field of a frame carrier named `name` is an integer from 0 to max,
+/// throwing `ERR_BAD_CARRIER` instead of saturating or truncating other numbers
+fn carrier_integer<'a>(
+    cx: &mut FunctionContext<'a>,
+    js_number: Handle<'a, JsNumber>,
+    name: &str,
+    max: u64,
+    at: &CarrierRef,
+) -> NeonResult<u64> {
+    let number = js_number.value(cx);
+    if number.fract() == 0.0 && (0.0..=max as f64).contains(&number) {
+        return Ok(number as u64);
+    }
+    at.error(
+        ErrorCode::BadCarrier,
+        format!(
+            "Field {} must be an integer from 0 to {}, got {}",
+            name, max, number
+        ),
+    )
+    .throw(cx)
+}
+
+/// Reads a number field of the content of a frame carrier that must be an integer fitting in
+/// 32 bits, throwing `ERR_BAD_CARRIER` instead of saturating or truncating other numbers
+fn carrier_get_u32<'a, O: Object>(
+    cx: &mut FunctionContext<'a>,
+    object: Handle<'a, O>,
+    key: &str,
+    at: &CarrierRef,
+) -> NeonResult<u32> {
+    carrier_get_integer(cx, object, key, u32::MAX as u64, at).map(|number| number as u32)
+}
+
+/// Reads a number field of the content of a frame carrier that must be an integer from 0 to max
+fn carrier_get_integer<'a, O: Object>(
+    cx: &mut FunctionContext<'a>,
+    object: Handle<'a, O>,
+    key: &str,
+    max: u64,
+    at: &CarrierRef,
+) -> NeonResult<u64> {
+    let js_number: Handle<JsNumber> = carrier_get(cx, object, key, at)?;
+    carrier_integer(cx, js_number, &content_field_name(key, at), max, at)
+}
+
+fn js_to_synchronised_lyrics<'a>(
+    cx: &mut FunctionContext<'a>,
+    js_content: Handle<'a, JsObject>,
+    at: &CarrierRef,
+) -> NeonResult<SynchronisedLyrics> {
+    let js_lang: Handle<JsString> = carrier_get(cx, js_content, "lang", at)?;
+    let js_timestamp_format: Handle<JsString> = carrier_get(cx, js_content, "timestampFormat", at)?;
+    let js_content_type: Handle<JsString> = carrier_get(cx, js_content, "contentType", at)?;
+    let js_description: Handle<JsString> = carrier_get(cx, js_content, "description", at)?;
+    let js_pairs: Handle<JsArray> = carrier_get(cx, js_content, "content", at)?;
+
+    let timestamp_format = js_timestamp_format.value(cx);
+    let timestamp_format = match str_to_timestamp_format(&timestamp_format) {
+        Some(timestamp_format) => timestamp_format,
+        None => {
+            return at
+                .error(
+                    ErrorCode::BadCarrier,
+                    format!("Unknown timestamp format {}", timestamp_format),
+                )
+                .throw(cx)
+        }
+    };
+    let content_type = js_content_type.value(cx);
+    let content_type = match str_to_synchronised_lyrics_type(&content_type) {
+        Some(content_type) => content_type,
+        None => {
+            return at
+                .error(
+                    ErrorCode::BadCarrier,
+                    format!("Unknown synchronised lyrics content type {}", content_type),
+                )
+                .throw(cx)
+        }
+    };
+
+    let mut content = Vec::new();
+    for (i, js_pair) in js_pairs.to_vec(cx)?.into_iter().enumerate() {
+        let js_pair = match js_pair.downcast::<JsArray, _>(cx) {
+            Ok(js_pair) => js_pair,
+            Err(_) => {
+                return at
+                    .error(
+                        ErrorCode::BadCarrier,
+                        "Synchronised lyrics content must be [timestamp, text] pairs",
+                    )
+                    .throw(cx)
             }
+        };
+        let timestamp_name = format!("content[{}][0]", i);
+        let js_timestamp: Handle<JsNumber> =
+            carrier_get_named(cx, js_pair, 0, &timestamp_name, at)?;
+        let timestamp = carrier_integer(
+            cx,
+            js_timestamp,
+            &content_field_name(&timestamp_name, at),
+            u32::MAX as u64,
+            at,
+        )?;
+        let js_text: Handle<JsString> =
+            carrier_get_named(cx, js_pair, 1, &format!("content[{}][1]", i), at)?;
+        content.push((timestamp as u32, js_text.value(cx)));
+    }
+
+    Ok(SynchronisedLyrics {
+        lang: js_lang.value(cx),
+        timestamp_format,
+        content_type,
+        description: js_description.value(cx),
+        content,
+    })
+}
 
-            // Encapsulated objects
-            id3::Content::EncapsulatedObject(content) => {
-                let js_enc_object = cx.empty_object();
-                let js_mime_type = cx.string(&content.mime_type);
-                let js_filename = cx.string(&content.filename);
-                let js_description = cx.string(&content.description);
-                let js_data = u8_vec_to_arraybuffer(cx, &content.data)
-                    .expect("Failed loading image data into Javascript runtime");
-
-                js_enc_object.set(cx, "MIMEType", js_mime_type).unwrap();
-                js_enc_object.set(cx, "filename", js_filename).unwrap();
-                js_enc_object
-                    .set(cx, "description", js_description)
-                    .unwrap();
-                js_enc_object.set(cx, "data", js_data).unwrap();
-
-                transfer_frame_as_tuple!(i, "encapsulated object", frame.id(), js_enc_object);
+/// Reads an optional chapter byte offset, where null marks an unused offset
+fn js_chapter_offset<'a>(
+    cx: &mut FunctionContext<'a>,
+    js_chapter: Handle<'a, JsObject>,
+    key: &str,
+    at: &CarrierRef,
+) -> NeonResult<u32> {
+    let js_offset: Handle<JsValue> = js_chapter.get(cx, key)?;
+    if js_offset.is_a::<JsNull, _>(cx) || js_offset.is_a::<JsUndefined, _>(cx) {
+        return Ok(CHAPTER_OFFSET_UNUSED);
+    }
+    carrier_get_u32(cx, js_chapter, key, at)
+}
+
+/// Reads the optional MP4 data atom `type` of an unknown content
+fn js_data_type<'a>(
+    cx: &mut FunctionContext<'a>,
+    js_value: Handle<'a, JsValue>,
+    at: &CarrierRef,
+) -> NeonResult<Option<u32>> {
+    let js_unknown = match js_value.downcast::<JsObject, _>(cx) {
+        Ok(js_unknown) => js_unknown,
+        Err(_) => return Ok(None),
+    };
+    let js_type: Handle<JsValue> = js_unknown.get(cx, "type")?;
+    if js_type.is_a::<JsNull, _>(cx) || js_type.is_a::<JsUndefined, _>(cx) {
+        return Ok(None);
+    }
+    carrier_get_u32(cx, js_unknown, "type", at).map(Some)
+}
+
+/// Reads the `size` and `hash` of the handle a picture or encapsulated object loaded with
+/// `lazyPayloads` has instead of its data
+fn js_payload_handle<'a>(
+    cx: &mut FunctionContext<'a>,
+    js_value: Handle<'a, JsValue>,
+    at: &CarrierRef,
+) -> NeonResult<Option<PayloadHandle>> {
+    let js_content = match js_value.downcast::<JsObject, _>(cx) {
+        Ok(js_content) => js_content,
+        Err(_) => return Ok(None),
+    };
+    let js_hash: Handle<JsValue> = js_content.get(cx, "hash")?;
+    if js_hash.is_a::<JsNull, _>(cx) || js_hash.is_a::<JsUndefined, _>(cx) {
+        return Ok(None);
+    }
+    let js_hash: Handle<JsString> = carrier_get(cx, js_content, "hash", at)?;
+    let size = carrier_get_u32(cx, js_content, "size", at)?;
+    Ok(Some(PayloadHandle {
+        size: size as usize,
+        hash: js_hash.value(cx),
+    }))
+}
+
+/// Reads a `{kind, id, value, op}` object or a legacy `[type, id, content, remove]` tuple
+/// frame carrier and records its frame ID in `at`, after the ID of the parent frame for
+/// sub-frames.
+///
+/// Carriers removing frames only need the fields identifying the frame, missing ones are left
+/// empty.
+fn js_to_carrier<'a>(
+    cx: &mut FunctionContext<'a>,
+    value: Handle<'a, JsValue>,
+    at: &mut CarrierRef,
+) -> NeonResult<Carrier> {
+    let (js_carrier, frame_name, frame_type, remove, value_key) =
+        if let Ok(tuple) = value.downcast::<JsArray, _>(cx) {
+            at.format = CarrierFormat::Tuple;
+            let js_frame_name: Handle<JsString> = carrier_get(cx, tuple, 1, at)?;
+            let frame_name = js_frame_name.value(cx);
+            at.push_id(&frame_name);
+            let js_frame_type: Handle<JsString> = carrier_get(cx, tuple, 0, at)?;
+            let js_remove: Handle<JsBoolean> = carrier_get(cx, tuple, 3, at)?;
+
+            (
+                tuple.upcast::<JsObject>(),
+                frame_name,
+                js_frame_type.value(cx),
+                js_remove.value(cx),
+                "2",
+            )
+        } else if let Ok(object) = value.downcast::<JsObject, _>(cx) {
+            at.format = CarrierFormat::Object;
+            let js_id = object.get_value(cx, "id")?;
+            let js_id: Handle<JsString> = carrier_field(cx, js_id, &"id", at)?;
+            let frame_name = js_id.value(cx);
+            at.push_id(&frame_name);
+
+            let js_schema = object.get_value(cx, "schema")?;
+            if !js_schema.is_a::<JsUndefined, _>(cx) {
+                let js_schema: Handle<JsNumber> = carrier_field(cx, js_schema, &"schema", at)?;
+                let schema = js_schema.value(cx);
+                if schema != FRAME_SCHEMA_VERSION as f64 {
+                    return at
+                        .error(
+                            ErrorCode::BadCarrier,
+                            format!(
+                                "Frame schema version {} is not supported, expected {}",
+                                schema, FRAME_SCHEMA_VERSION
+                            ),
+                        )
+                        .throw(cx);
+                }
             }
+            let js_kind = object.get_value(cx, "kind")?;
+            let js_kind: Handle<JsString> = carrier_field(cx, js_kind, &"kind", at)?;
+            let js_op = object.get_value(cx, "op")?;
+            let js_op: Handle<JsString> = carrier_field(cx, js_op, &"op", at)?;
+            let remove = match js_op.value(cx).as_str() {
+                "set" => false,
+                "remove" => true,
+                op => {
+                    return at
+                        .error(
+                            ErrorCode::BadCarrier,
+                            format!("Field op must be \"set\" or \"remove\", got {:?}", op),
+                        )
+                        .throw(cx)
+                }
+            };
+
+            (object, frame_name, js_kind.value(cx), remove, "value")
+        } else {
+            return at
+                .error(
+                    ErrorCode::BadCarrier,
+                    "Frame carrier is not an object or an array",
+                )
+                .throw(cx);
+        };
+
+    at.removal = remove;
+    let kind = match FrameKind::from_name(&frame_type) {
+        Some(kind) => kind,
+        None => {
+            return at
+                .error(
+                    ErrorCode::UnsupportedFrame,
+                    format!("Saving frame of type {} is not implemented yet", frame_type),
+                )
+                .throw(cx)
+        }
+    };
+    let js_value = js_carrier.get_value(cx, value_key)?;
+    let content = js_to_content(cx, kind, js_value, value_key, at)?;
+    let data_type = match kind {
+        FrameKind::Unknown => js_data_type(cx, js_value, at)?,
+        _ => None,
+    };
+    // Removals can pass a payload handle to only remove the frame holding its data
+    let payload = match kind {
+        FrameKind::Picture | FrameKind::EncapsulatedObject if remove => {
+            js_payload_handle(cx, js_value, at)?
+        }
+        _ => None,
+    };
+
+    Ok(Carrier {
+        // Play counters are stored in unknown contents recognized by their ID
+        id: match kind {
+            FrameKind::PlayCounter => String::from("PCNT"),
+            _ => frame_name,
+        },
+        content,
+        remove,
+        payload,
+        data_type,
+    })
+}
+
+/// Reads the frame carriers of an update
+fn js_to_carriers<'a>(
+    cx: &mut FunctionContext<'a>,
+    js_tag: Handle<'a, JsArray>,
+    path: Option<&str>,
+) -> NeonResult<Vec<Carrier>> {
+    let mut carriers = Vec::new();
+
+    for (i, js_frame) in (0u32..).zip(js_tag.to_vec(cx)?) {
+        let mut at = CarrierRef {
+            path,
+            index: i,
+            id: String::new(),
+            removal: false,
+            format: CarrierFormat::Tuple,
+        };
+        carriers.push(js_to_carrier(cx, js_frame, &mut at)?);
+    }
+
+    Ok(carriers)
+}
 
-            // Chapters
-            // id3::Content::Chapter(content) => todo!(),
+/// Builds the sub-frames of a chapter or table of contents, skipping carriers marked for removal
+fn js_frames_to_frames<'a>(
+    cx: &mut FunctionContext<'a>,
+    js_frames: Handle<'a, JsArray>,
+    at: &CarrierRef,
+) -> NeonResult<Vec<Frame>> {
+    let mut frames = Vec::new();
 
-            // MpegLocationLookupTables
-            // id3::Content::MpegLocationLookupTable(content) => todo!(),
+    for js_frame in js_frames.to_vec(cx)? {
+        let mut sub_at = CarrierRef {
+            path: at.path,
+            index: at.index,
+            id: at.id.clone(),
+            removal: false,
+            format: CarrierFormat::Tuple,
+        };
+        let carrier = js_to_carrier(cx, js_frame, &mut sub_at)?;
 
-            // Unknown frames
-            id3::Content::Unknown(content) => {
-                let js_unknown = cx.empty_object();
-                // let js_version = cx.string(&content.version);
-                let js_data = u8_vec_to_arraybuffer(cx, &content.data).unwrap();
+        if !carrier.remove {
+            let frame = carrier::carrier_to_frame(&carrier)
+                .map_err(|error| sub_at.annotate(error))
+                .or_throw(cx)?;
+            frames.push(frame);
+        }
+    }
 
-                // js_unknown.set(&mut cx, "version", js_version).unwrap();
-                js_unknown.set(cx, "data", js_data).unwrap();
+    Ok(frames)
+}
 
-                transfer_frame_as_tuple!(i, "unknown", frame.id(), js_unknown);
+/// Reads the content of a frame carrier
+fn js_to_content<'a>(
+    cx: &mut FunctionContext<'a>,
+    kind: FrameKind,
+    js_value: Handle<'a, JsValue>,
+    value_key: &str,
+    at: &CarrierRef,
+) -> NeonResult<id3::Content> {
+    match kind {
+        // Texts
+        FrameKind::Text => {
+            let js_frame_content: Handle<JsString> = carrier_field(cx, js_value, &value_key, at)?;
+            Ok(id3::Content::Text(js_frame_content.value(cx)))
+        }
+        // Extended texts
+        FrameKind::ExtendedText => {
+            let js_frame_content: Handle<JsObject> = carrier_field(cx, js_value, &value_key, at)?;
+            let js_description: Handle<JsString> =
+                carrier_get(cx, js_frame_content, "description", at)?;
+            let js_value: Handle<JsString> = carrier_get(cx, js_frame_content, "value", at)?;
+
+            Ok(id3::Content::ExtendedText(ExtendedText {
+                description: js_description.value(cx),
+                value: js_value.value(cx),
+            }))
+        }
+        // Links
+        FrameKind::Link => {
+            let js_frame_content: Handle<JsString> = carrier_field(cx, js_value, &value_key, at)?;
+            Ok(id3::Content::Link(js_frame_content.value(cx)))
+        }
+        // Extended links
+        FrameKind::ExtendedLink => {
+            let js_frame_content: Handle<JsObject> = carrier_field(cx, js_value, &value_key, at)?;
+            let js_description: Handle<JsString> =
+                carrier_get(cx, js_frame_content, "description", at)?;
+            let js_link: Handle<JsString> = carrier_get(cx, js_frame_content, "link", at)?;
+
+            Ok(id3::Content::ExtendedLink(ExtendedLink {
+                description: js_description.value(cx),
+                link: js_link.value(cx),
+            }))
+        }
+        // Lyrics
+        FrameKind::Lyrics => {
+            let js_frame_content: Handle<JsObject> = carrier_field(cx, js_value, &value_key, at)?;
+            let js_lang: Handle<JsString> = carrier_get(cx, js_frame_content, "lang", at)?;
+            let js_description: Handle<JsString> =
+                carrier_get(cx, js_frame_content, "description", at)?;
+            let js_text: Handle<JsString> = carrier_get(cx, js_frame_content, "text", at)?;
+
+            Ok(id3::Content::Lyrics(Lyrics {
+                lang: js_lang.value(cx),
+                description: js_description.value(cx),
+                text: js_text.value(cx),
+            }))
+        }
+        // Synchronised lyrics
+        FrameKind::SynchronisedLyrics => {
+            let js_frame_content: Handle<JsObject> = carrier_field(cx, js_value, &value_key, at)?;
+            let synchronised_lyrics = js_to_synchronised_lyrics(cx, js_frame_content, at)?;
+
+            Ok(id3::Content::SynchronisedLyrics(synchronised_lyrics))
+        }
+        // Comments
+        FrameKind::Comment => {
+            let js_frame_content: Handle<JsObject> = carrier_field(cx, js_value, &value_key, at)?;
+            let js_lang: Handle<JsString> = carrier_get(cx, js_frame_content, "lang", at)?;
+            let js_description: Handle<JsString> =
+                carrier_get(cx, js_frame_content, "description", at)?;
+            let js_text: Handle<JsString> = carrier_get(cx, js_frame_content, "text", at)?;
+
+            Ok(id3::Content::Comment(Comment {
+                lang: js_lang.value(cx),
+                description: js_description.value(cx),
+                text: js_text.value(cx),
+            }))
+        }
+        // Popularimeters
+        FrameKind::Popularimeter => {
+            let js_frame_content: Handle<JsObject> = carrier_field(cx, js_value, &value_key, at)?;
+            let js_user: Handle<JsString> = carrier_get(cx, js_frame_content, "user", at)?;
+            let rating = carrier_get_integer(cx, js_frame_content, "rating", 255, at)?;
+            let counter =
+                carrier_get_integer(cx, js_frame_content, "counter", MAX_SAFE_INTEGER, at)?;
+
+            Ok(id3::Content::Popularimeter(id3::frame::Popularimeter {
+                user: js_user.value(cx),
+                rating: rating as u8,
+                counter,
+            }))
+        }
+        // Play counters
+        FrameKind::PlayCounter => {
+            let js_frame_content: Handle<JsNumber> = carrier_field(cx, js_value, &value_key, at)?;
+            let counter = carrier_integer(cx, js_frame_content, value_key, MAX_SAFE_INTEGER, at)?;
+
+            Ok(id3::Content::Unknown(id3::frame::Unknown {
+                data: popularimeter::encode_play_counter(counter),
+                version: id3::Version::Id3v24,
+            }))
+        }
+        // Chapters
+        FrameKind::Chapter => {
+            let js_frame_content: Handle<JsObject> = carrier_field(cx, js_value, &value_key, at)?;
+            let js_element_id: Handle<JsString> =
+                carrier_get(cx, js_frame_content, "elementId", at)?;
+            let start_time = carrier_get_u32(cx, js_frame_content, "startTime", at)?;
+            let end_time = carrier_get_u32(cx, js_frame_content, "endTime", at)?;
+            let js_frames: Handle<JsArray> = carrier_get(cx, js_frame_content, "frames", at)?;
+            let start_offset = js_chapter_offset(cx, js_frame_content, "startOffset", at)?;
+            let end_offset = js_chapter_offset(cx, js_frame_content, "endOffset", at)?;
+
+            Ok(id3::Content::Chapter(Chapter {
+                element_id: js_element_id.value(cx),
+                start_time,
+                end_time,
+                start_offset,
+                end_offset,
+                frames: js_frames_to_frames(cx, js_frames, at)?,
+            }))
+        }
+        // Tables of contents
+        FrameKind::TableOfContents => {
+            let js_frame_content: Handle<JsObject> = carrier_field(cx, js_value, &value_key, at)?;
+            let js_element_id: Handle<JsString> =
+                carrier_get(cx, js_frame_content, "elementId", at)?;
+            let js_top_level: Handle<JsBoolean> =
+                carrier_get(cx, js_frame_content, "topLevel", at)?;
+            let js_ordered: Handle<JsBoolean> = carrier_get(cx, js_frame_content, "ordered", at)?;
+            let js_elements: Handle<JsArray> = carrier_get(cx, js_frame_content, "elements", at)?;
+            let js_frames: Handle<JsArray> = carrier_get(cx, js_frame_content, "frames", at)?;
+
+            let mut elements = Vec::new();
+            for j in 0..js_elements.len(cx) {
+                let js_element: Handle<JsString> =
+                    carrier_get_named(cx, js_elements, j, &format!("elements[{}]", j), at)?;
+                elements.push(js_element.value(cx));
             }
 
-            // Frames that are not implemented yet
-            _ => {
-                panic!("Unsupporeted frame type {}", frame.to_string());
+            Ok(id3::Content::TableOfContents(TableOfContents {
+                element_id: js_element_id.value(cx),
+                top_level: js_top_level.value(cx),
+                ordered: js_ordered.value(cx),
+                elements,
+                frames: js_frames_to_frames(cx, js_frames, at)?,
+            }))
+        }
+        // Involved people lists
+        FrameKind::InvolvedPeople => {
+            let js_frame_content: Handle<JsArray> = carrier_field(cx, js_value, &value_key, at)?;
+
+            let mut items = Vec::new();
+            for j in 0..js_frame_content.len(cx) {
+                let item = format!("[{}]", j);
+                let js_item: Handle<JsObject> =
+                    carrier_get_named(cx, js_frame_content, j, &item, at)?;
+                let js_involvement: Handle<JsString> = carrier_get_named(
+                    cx,
+                    js_item,
+                    "involvement",
+                    &format!("{}.involvement", item),
+                    at,
+                )?;
+                let js_involvee: Handle<JsString> =
+                    carrier_get_named(cx, js_item, "involvee", &format!("{}.involvee", item), at)?;
+                items.push(InvolvedPeopleListItem {
+                    involvement: js_involvement.value(cx),
+                    involvee: js_involvee.value(cx),
+                });
             }
+
+            Ok(id3::Content::InvolvedPeopleList(InvolvedPeopleList {
+                items,
+            }))
+        }
+        // Pictures
+        FrameKind::Picture => {
+            let js_frame_content: Handle<JsObject> = carrier_field(cx, js_value, &value_key, at)?;
+            let js_mime_type: Handle<JsString> = carrier_get(cx, js_frame_content, "MIMEType", at)?;
+            let picture_type = carrier_get_integer(cx, js_frame_content, "pictureType", 20, at)?;
+            let js_description: Handle<JsString> =
+                carrier_get(cx, js_frame_content, "description", at)?;
+            let js_data: Handle<JsArrayBuffer> = carrier_get(cx, js_frame_content, "data", at)?;
+
+            Ok(id3::Content::Picture(id3::frame::Picture {
+                mime_type: js_mime_type.value(cx),
+                picture_type: carrier::u8_to_picture_ype(picture_type as u8),
+                description: js_description.value(cx),
+                data: arraybuffer_to_u8_vec(cx, &js_data),
+            }))
         }
+        // Encapsulated object
+        FrameKind::EncapsulatedObject => {
+            let js_frame_content: Handle<JsObject> = carrier_field(cx, js_value, &value_key, at)?;
+            let js_mime_type: Handle<JsString> = carrier_get(cx, js_frame_content, "MIMEType", at)?;
+            let js_filename: Handle<JsString> = carrier_get(cx, js_frame_content, "filename", at)?;
+            let js_description: Handle<JsString> =
+                carrier_get(cx, js_frame_content, "description", at)?;
+            let js_data: Handle<JsArrayBuffer> = carrier_get(cx, js_frame_content, "data", at)?;
+
+            Ok(id3::Content::EncapsulatedObject(EncapsulatedObject {
+                mime_type: js_mime_type.value(cx),
+                filename: js_filename.value(cx),
+                description: js_description.value(cx),
+                data: arraybuffer_to_u8_vec(cx, &js_data),
+            }))
+        }
+        // Private frames
+        FrameKind::Private => {
+            let js_frame_content: Handle<JsObject> = carrier_field(cx, js_value, &value_key, at)?;
+            let js_owner: Handle<JsString> = carrier_get(cx, js_frame_content, "owner", at)?;
+            let js_data: Handle<JsArrayBuffer> = carrier_get(cx, js_frame_content, "data", at)?;
+
+            Ok(id3::Content::Private(Private {
+                owner_identifier: js_owner.value(cx),
+                private_data: arraybuffer_to_u8_vec(cx, &js_data),
+            }))
+        }
+        // Unique file identifiers
+        FrameKind::UniqueFileIdentifier => {
+            let js_frame_content: Handle<JsObject> = carrier_field(cx, js_value, &value_key, at)?;
+            let js_owner: Handle<JsString> = carrier_get(cx, js_frame_content, "owner", at)?;
+            let js_identifier: Handle<JsArrayBuffer> =
+                carrier_get(cx, js_frame_content, "identifier", at)?;
+
+            Ok(id3::Content::UniqueFileIdentifier(UniqueFileIdentifier {
+                owner_identifier: js_owner.value(cx),
+                identifier: arraybuffer_to_u8_vec(cx, &js_identifier),
+            }))
+        }
+        // Unknown frames
+        FrameKind::Unknown => {
+            let js_frame_content: Handle<JsObject> = carrier_field(cx, js_value, &value_key, at)?;
+            let js_data: Handle<JsArrayBuffer> = carrier_get(cx, js_frame_content, "data", at)?;
+
+            Ok(id3::Content::Unknown(id3::frame::Unknown {
+                data: arraybuffer_to_u8_vec(cx, &js_data),
+                version: id3::Version::Id3v24,
+            }))
+        }
+    }
+}
+
+/// Reads the ID3 version from the optional write options argument, defaulting to preserve
+fn write_version_option(cx: &mut FunctionContext, i: i32) -> NeonResult<WriteVersion> {
+    let js_options = match cx.argument_opt(i) {
+        Some(js_value) if !js_value.is_a::<JsUndefined, _>(cx) => {
+            js_value.downcast_or_throw::<JsObject, _>(cx)?
+        }
+        _ => return Ok(WriteVersion::Preserve),
+    };
+
+    let js_version = js_options.get_value(cx, "version")?;
+    if js_version.is_a::<JsUndefined, _>(cx) {
+        return Ok(WriteVersion::Preserve);
+    }
+    let name = js_version.downcast_or_throw::<JsString, _>(cx)?.value(cx);
+    match WriteVersion::from_name(&name) {
+        Some(version) => Ok(version),
+        None => cx.throw_range_error(format!("Unknown ID3 version {}", name)),
+    }
+}
+
+/// Reads the `frameFormat` of the optional options argument, legacy tuples by default
+fn carrier_format_option(cx: &mut FunctionContext, i: i32) -> NeonResult<CarrierFormat> {
+    let js_options = match cx.argument_opt(i) {
+        Some(js_value) if !js_value.is_a::<JsUndefined, _>(cx) => {
+            js_value.downcast_or_throw::<JsObject, _>(cx)?
+        }
+        _ => return Ok(CarrierFormat::Tuple),
+    };
+
+    let js_format = js_options.get_value(cx, "frameFormat")?;
+    if js_format.is_a::<JsUndefined, _>(cx) {
+        return Ok(CarrierFormat::Tuple);
+    }
+    let name = js_format.downcast_or_throw::<JsString, _>(cx)?.value(cx);
+    match name.as_str() {
+        "tuple" => Ok(CarrierFormat::Tuple),
+        "object" => Ok(CarrierFormat::Object),
+        _ => cx.throw_range_error(format!("Unknown frame format {}", name)),
+    }
+}
+
+/// Reads the `backup` and `preserveMtime` flags of the optional write options argument
+fn save_options(cx: &mut FunctionContext, i: i32) -> NeonResult<SaveOptions> {
+    Ok(SaveOptions {
+        backup: bool_option(cx, i, "backup")?,
+        preserve_mtime: bool_option(cx, i, "preserveMtime")?,
+    })
+}
+
+fn update_tag(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let js_path: Handle<JsString> = cx.argument(0)?;
+    let path = js_path.value(&mut cx);
+    let js_tag: Handle<JsArray> = cx.argument(1)?;
+    let write_version = write_version_option(&mut cx, 2)?;
+    let save = save_options(&mut cx, 2)?;
+    let format = carrier_format_option(&mut cx, 2)?;
+
+    let mods = js_to_carriers(&mut cx, js_tag, Some(&path))?;
+    let updated = tags::update(&path, &mods, write_version, save).or_throw(&mut cx)?;
+    updated_tag_to_js(&mut cx, &updated, format)
+}
+
+/// Reads the optional document format argument, JSON by default
+fn document_format_argument(cx: &mut FunctionContext, i: i32) -> NeonResult<DocumentFormat> {
+    let js_format = match cx.argument_opt(i) {
+        Some(js_value) if !js_value.is_a::<JsUndefined, _>(cx) => js_value,
+        _ => return Ok(DocumentFormat::Json),
+    };
+    let name = js_format.downcast_or_throw::<JsString, _>(cx)?.value(cx);
+    match DocumentFormat::from_name(&name) {
+        Some(format) => Ok(format),
+        None => cx.throw_range_error(format!("Unknown document format {}", name)),
+    }
+}
+
+fn export_tag(mut cx: FunctionContext) -> JsResult<JsString> {
+    let js_path: Handle<JsString> = cx.argument(0)?;
+    let path = js_path.value(&mut cx);
+    let format = document_format_argument(&mut cx, 1)?;
+
+    let document = tags::export(&path).or_throw(&mut cx)?;
+    Ok(cx.string(document.to_text(format)))
+}
+
+fn import_tag(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let js_path: Handle<JsString> = cx.argument(0)?;
+    let path = js_path.value(&mut cx);
+    let js_document: Handle<JsString> = cx.argument(1)?;
+    let save = save_options(&mut cx, 2)?;
+    let format = carrier_format_option(&mut cx, 2)?;
+
+    let document = TagDocument::parse(&js_document.value(&mut cx))
+        .map_err(|error| error.with_path(&path))
+        .or_throw(&mut cx)?;
+    let updated = tags::import(&path, &document, save).or_throw(&mut cx)?;
+    updated_tag_to_js(&mut cx, &updated, format)
+}
+
+/// Runs f on the contents of an ArrayBuffer or Uint8Array argument, like a Node.js Buffer
+fn with_bytes_argument<T>(
+    cx: &mut FunctionContext,
+    i: i32,
+    f: impl FnOnce(&[u8]) -> T,
+) -> NeonResult<T> {
+    let js_value: Handle<JsValue> = cx.argument(i)?;
+    if let Ok(js_buffer) = js_value.downcast::<JsArrayBuffer, _>(cx) {
+        return Ok(f(js_buffer.as_slice(cx)));
+    }
+    match js_value.downcast::<JsTypedArray<u8>, _>(cx) {
+        Ok(js_array) => Ok(f(js_array.as_slice(cx))),
+        Err(_) => cx.throw_type_error("Expected an ArrayBuffer or a Uint8Array"),
+    }
+}
+
+fn load_tag_from_buffer(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let format = carrier_format_option(&mut cx, 1)?;
+    let loaded = with_bytes_argument(&mut cx, 0, tags::load_from_bytes)?.or_throw(&mut cx)?;
+    loaded_tag_to_js(&mut cx, &loaded, format)
+}
+
+/// Applies an update to a file held in memory, returning a new ArrayBuffer with the new tag
+/// and the same audio
+fn write_tag_to_buffer(mut cx: FunctionContext) -> JsResult<JsArrayBuffer> {
+    let js_tag: Handle<JsArray> = cx.argument(1)?;
+    let write_version = write_version_option(&mut cx, 2)?;
+    let mods = js_to_carriers(&mut cx, js_tag, None)?;
+
+    let (output, _) = with_bytes_argument(&mut cx, 0, |bytes| {
+        tags::update_bytes(bytes, &mods, write_version)
+    })?
+    .or_throw(&mut cx)?;
+    u8_vec_to_arraybuffer(&mut cx, &output)
+}
+
+/// Runs `updateTag` without writing the file, returning what it would change
+fn preview_update(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let js_path: Handle<JsString> = cx.argument(0)?;
+    let path = js_path.value(&mut cx);
+    let js_tag: Handle<JsArray> = cx.argument(1)?;
+    let write_version = write_version_option(&mut cx, 2)?;
+    let format = carrier_format_option(&mut cx, 2)?;
+
+    let mods = js_to_carriers(&mut cx, js_tag, Some(&path))?;
+    let preview = tags::preview(&path, &mods, write_version).or_throw(&mut cx)?;
+    preview_to_js(&mut cx, &preview, format)
+}
+
+/// Resolves the promise of an asynchronous function with the result of a job, converted on
+/// the JavaScript thread
+fn settle<T, V, F>(channel: &Channel, deferred: Deferred, result: Result<T, TagError>, to_js: F)
+where
+    T: Send + 'static,
+    V: Value,
+    F: for<'a> FnOnce(&mut TaskContext<'a>, &T) -> JsResult<'a, V> + Send + 'static,
+{
+    deferred.settle_with(channel, move |mut cx| {
+        let value = result.or_throw(&mut cx)?;
+        to_js(&mut cx, &value)
     });
+}
 
-    Ok(js_tag)
+fn load_tag_async(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let js_path: Handle<JsString> = cx.argument(0)?;
+    let path = js_path.value(&mut cx);
+    let lazy_payloads = bool_option(&mut cx, 1, "lazyPayloads")?;
+    let format = carrier_format_option(&mut cx, 1)?;
+
+    let channel = cx.channel();
+    let (deferred, promise) = cx.promise();
+    pool::global().spawn(move || {
+        let loaded = pool::catch(|| load_with_options(&path, lazy_payloads));
+        settle(&channel, deferred, loaded, move |cx, loaded| {
+            loaded_tag_to_js(cx, loaded, format)
+        });
+    });
+
+    Ok(promise)
 }
 
-fn load_tag(mut cx: FunctionContext) -> JsResult<JsArray> {
-    let js_path: Handle<JsString> = cx.argument(0).expect("Incorrect argument 0 received");
+fn update_tag_async(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let js_path: Handle<JsString> = cx.argument(0)?;
     let path = js_path.value(&mut cx);
+    let js_tag: Handle<JsArray> = cx.argument(1)?;
+    let write_version = write_version_option(&mut cx, 2)?;
+    let save = save_options(&mut cx, 2)?;
+    let format = carrier_format_option(&mut cx, 2)?;
+    let mods = js_to_carriers(&mut cx, js_tag, Some(&path))?;
+
+    let channel = cx.channel();
+    let (deferred, promise) = cx.promise();
+    pool::global().spawn(move || {
+        let updated = pool::catch(|| tags::update(&path, &mods, write_version, save));
+        settle(&channel, deferred, updated, move |cx, updated| {
+            updated_tag_to_js(cx, updated, format)
+        });
+    });
 
-    // Read tag or create a new one
-    let tag;
-    match Tag::read_from_path(&path) {
-        Ok(t) => {
-            tag = t;
+    Ok(promise)
+}
+
+fn paths_argument(cx: &mut FunctionContext, i: i32) -> NeonResult<Vec<String>> {
+    let js_paths: Handle<JsArray> = cx.argument(i)?;
+    let mut paths = Vec::new();
+    for js_path in js_paths.to_vec(cx)? {
+        let js_path = js_path.downcast_or_throw::<JsString, _>(cx)?;
+        paths.push(js_path.value(cx));
+    }
+    Ok(paths)
+}
+
+/// Reads the `onProgress` callback of the optional batch options argument
+fn progress_option(cx: &mut FunctionContext, i: i32) -> NeonResult<Option<Root<JsFunction>>> {
+    let js_options = match cx.argument_opt(i) {
+        Some(js_value) if !js_value.is_a::<JsUndefined, _>(cx) => {
+            js_value.downcast_or_throw::<JsObject, _>(cx)?
         }
-        Err(error) => match error.kind {
-            id3::ErrorKind::NoTag => tag = Tag::new(),
-            _ => panic!("Error reading tag: {}", &error.description),
-        },
+        _ => return Ok(None),
     };
 
-    let js_tag =
-        tag_to_js_tag(&mut cx, &tag).expect("Failed converting tag to a javascript tuple array");
+    let js_on_progress = js_options.get_value(cx, "onProgress")?;
+    if js_on_progress.is_a::<JsUndefined, _>(cx) {
+        return Ok(None);
+    }
+    let js_on_progress = js_on_progress.downcast_or_throw::<JsFunction, _>(cx)?;
+    Ok(Some(js_on_progress.root(cx)))
+}
 
-    Ok(js_tag)
+/// The native side of a batch operation started from JavaScript
+struct JobHandle {
+    job: Arc<Job>,
+    channel: Channel,
 }
 
-fn u8_to_picture_ype(i: u8) -> PictureType {
-    match i {
-        1 => PictureType::Icon,
-        2 => PictureType::OtherIcon,
-        3 => PictureType::CoverFront,
-        4 => PictureType::CoverBack,
-        5 => PictureType::Leaflet,
-        6 => PictureType::Media,
-        7 => PictureType::LeadArtist,
-        8 => PictureType::Artist,
-        9 => PictureType::Conductor,
-        10 => PictureType::Band,
-        11 => PictureType::Composer,
-        12 => PictureType::Lyricist,
-        13 => PictureType::RecordingLocation,
-        14 => PictureType::DuringRecording,
-        15 => PictureType::DuringPerformance,
-        16 => PictureType::ScreenCapture,
-        17 => PictureType::BrightFish,
-        18 => PictureType::Illustration,
-        19 => PictureType::BandLogo,
-        20 => PictureType::PublisherLogo,
-        _ => PictureType::Other,
+impl JobHandle {
+    /// Starts a job over paths, returning its handle and a promise with a `cancel()` method
+    /// to be settled by the deferred
+    fn start<'a>(
+        cx: &mut FunctionContext<'a>,
+        paths: &[String],
+        options: i32,
+    ) -> NeonResult<(Arc<JobHandle>, Deferred, Handle<'a, JsPromise>)> {
+        let channel = cx.channel();
+        let mut job = Job::new(paths.len());
+        if let Some(on_progress) = progress_option(cx, options)? {
+            let on_progress = Arc::new(on_progress);
+            let channel = channel.clone();
+            job = job.with_progress(Box::new(move |path, progress| {
+                let on_progress = Arc::clone(&on_progress);
+                let path = path.to_string();
+                let progress = *progress;
+                channel.send(move |mut cx| {
+                    let js_progress = progress_to_js(&mut cx, &progress, &path)?;
+                    let js_on_progress = on_progress.to_inner(&mut cx);
+                    js_on_progress.call_with(&cx).arg(js_progress).exec(&mut cx)
+                });
+            }));
+        }
+        let handle = Arc::new(JobHandle {
+            job: Arc::new(job),
+            channel,
+        });
+        let (deferred, promise) = cx.promise();
+
+        // The job holds the channel, which must not keep the event loop alive once the job is
+        // done, so `cancel()` only holds it weakly
+        let job = Arc::downgrade(&handle.job);
+        let js_cancel = JsFunction::new(cx, move |mut cx| {
+            if let Some(job) = job.upgrade() {
+                job.cancel();
+            }
+            Ok(cx.undefined())
+        })?;
+        promise.set(cx, "cancel", js_cancel)?;
+
+        Ok((handle, deferred, promise))
     }
 }
 
-fn update_tag(mut cx: FunctionContext) -> JsResult<JsArray> {
-    let js_path: Handle<JsString> = cx.argument(0).expect("Incorrect argument 0 received");
-    let path = js_path.value(&mut cx);
-    let js_tag: Handle<JsArray> = cx.argument(1).expect("Incorrect argument 1 received");
-
-    // Create a tag instance and try to load tag from path
-    let mut tag;
-    match Tag::read_from_path(&path) {
-        Ok(t) => {
-            tag = t;
-        }
-        Err(error) => match error.kind {
-            // File at path has no tag, creat one
-            id3::ErrorKind::NoTag => tag = Tag::new(),
-            _ => panic!("Error reading tag: {}", &error.description),
+fn progress_to_js<'a, C: Context<'a>>(
+    cx: &mut C,
+    progress: &Progress,
+    path: &str,
+) -> JsResult<'a, JsObject> {
+    let js_progress = cx.empty_object();
+    let js_files_done = cx.number(progress.files_done as f64);
+    let js_files_total = cx.number(progress.files_total as f64);
+    let js_bytes_processed = cx.number(progress.bytes_processed as f64);
+    let js_path = cx.string(path);
+    js_progress.set(cx, "filesDone", js_files_done)?;
+    js_progress.set(cx, "filesTotal", js_files_total)?;
+    js_progress.set(cx, "bytesProcessed", js_bytes_processed)?;
+    js_progress.set(cx, "path", js_path)?;
+
+    Ok(js_progress)
+}
+
+/// Loads the tags of many files, resolving with a `TagInfo` or a `TagError` for each path
+fn load_tags(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let paths = paths_argument(&mut cx, 0)?;
+    let lazy_payloads = bool_option(&mut cx, 1, "lazyPayloads")?;
+    let format = carrier_format_option(&mut cx, 1)?;
+    let (handle, deferred, promise) = JobHandle::start(&mut cx, &paths, 1)?;
+
+    let job = Arc::clone(&handle.job);
+    pool::map(
+        paths,
+        move |path| job.run(&path, || load_with_options(&path, lazy_payloads)),
+        move |results| {
+            deferred.settle_with(&handle.channel, move |mut cx| {
+                let js_results = cx.empty_array();
+                for (i, result) in (0u32..).zip(results) {
+                    let js_result = match result.and_then(|loaded| loaded) {
+                        Ok(loaded) => {
+                            loaded_tag_to_js(&mut cx, &loaded, format)?.upcast::<JsValue>()
+                        }
+                        Err(error) => error.to_js(&mut cx)?.upcast(),
+                    };
+                    js_results.set(&mut cx, i, js_result)?;
+                }
+                Ok(js_results)
+            });
         },
+    );
+
+    Ok(promise)
+}
+
+/// Reads a flag of the optional options argument, false by default
+fn bool_option(cx: &mut FunctionContext, i: i32, name: &str) -> NeonResult<bool> {
+    let js_options = match cx.argument_opt(i) {
+        Some(js_value) if !js_value.is_a::<JsUndefined, _>(cx) => {
+            js_value.downcast_or_throw::<JsObject, _>(cx)?
+        }
+        _ => return Ok(false),
     };
 
-    let frame_tuples: Vec<Handle<JsValue>> = js_tag.to_vec(&mut cx).expect("");
-
-    frame_tuples.iter().for_each(|tuple| {
-        match tuple.downcast_or_throw::<JsArray, FunctionContext>(&mut cx) {
-            Ok(js_tuple) => {
-                let js_frame_type: Handle<JsString> = js_tuple.get(&mut cx, 0).unwrap();
-                let frame_type = js_frame_type.value(&mut cx);
-                let js_frame_name: Handle<JsString> = js_tuple.get(&mut cx, 1).unwrap();
-                let frame_name = js_frame_name.value(&mut cx);
-                let js_frame_remove: Handle<JsBoolean> = js_tuple.get(&mut cx, 3).unwrap();
-                let frame_remove = js_frame_remove.value(&mut cx);
-
-                // Remove or set this frame
-                if frame_remove {
-                    // Remove pictures
-                    if frame_type == "picture" {
-                        let js_frame_content: Handle<JsObject> = js_tuple.get(&mut cx, 2).unwrap();
-                        let js_picture_type: Handle<JsNumber> = js_frame_content
-                            .get(&mut cx, "pictureType")
-                            .expect("APIC.pictureType not provided");
-                        let picture_type = u8_to_picture_ype(js_picture_type.value(&mut cx) as u8);
-
-                        println!("{}", picture_type);
-
-                        tag.remove_picture_by_type(picture_type);
-                        // tag.remove_all_pictures();
-                    }
-                    // Remove encapsulate objects
-                    else if frame_type == "encapsulated object" {
-                        let js_frame_content: Handle<JsObject> = js_tuple.get(&mut cx, 2).unwrap();
-                        let js_mime_type: Handle<JsString> =
-                            js_frame_content.get(&mut cx, "MIMEType").unwrap();
-                        let js_filename: Handle<JsString> =
-                            js_frame_content.get(&mut cx, "filename").unwrap();
-                        let js_description: Handle<JsString> =
-                            js_frame_content.get(&mut cx, "description").unwrap();
-                        let js_data: Handle<JsArrayBuffer> =
-                            js_frame_content.get(&mut cx, "data").unwrap();
-
-                        let mime_type = js_mime_type.value(&mut cx);
-                        let filename = js_filename.value(&mut cx);
-                        let description = js_description.value(&mut cx);
-                        let data = arraybuffer_to_u8_vec(&mut cx, &js_data);
-
-                        tag.remove_encapsulated_object(
-                            Some(&description),
-                            Some(&mime_type),
-                            Some(&filename),
-                            Some(&data),
-                        );
-                    }
-                    // Remove other frame types
-                    else {
-                        tag.remove(frame_name);
-                    }
-                } else {
-                    // Texts
-                    if frame_type == "text" {
-                        let js_frame_content: Handle<JsString> = js_tuple.get(&mut cx, 2).unwrap();
-                        let frame_content = js_frame_content.value(&mut cx);
-                        tag.add_frame(Frame::text(frame_name, frame_content));
-                    }
-                    // Extended texts
-                    else if frame_type == "extended text" {
-                        let js_frame_content: Handle<JsObject> = js_tuple.get(&mut cx, 2).unwrap();
-                        let js_description: Handle<JsString> =
-                            js_frame_content.get(&mut cx, "description").unwrap();
-                        let js_value: Handle<JsString> =
-                            js_frame_content.get(&mut cx, "value").unwrap();
-
-                        tag.add_frame(ExtendedText {
-                            description: js_description.value(&mut cx),
-                            value: js_value.value(&mut cx),
-                        });
-                    }
-                    // Links
-                    else if frame_type == "link" {
-                        let js_frame_content: Handle<JsString> = js_tuple.get(&mut cx, 2).unwrap();
-                        let frame_content = js_frame_content.value(&mut cx);
-                        tag.add_frame(Frame::link(frame_name, frame_content));
-                    }
-                    // Extended links
-                    else if frame_type == "extended link" {
-                        let js_frame_content: Handle<JsObject> = js_tuple.get(&mut cx, 2).unwrap();
-                        let js_description: Handle<JsString> =
-                            js_frame_content.get(&mut cx, "description").unwrap();
-                        let js_link: Handle<JsString> =
-                            js_frame_content.get(&mut cx, "link").unwrap();
-
-                        tag.add_frame(ExtendedLink {
-                            description: js_description.value(&mut cx),
-                            link: js_link.value(&mut cx),
-                        });
-                    }
-                    // Lyrics
-                    else if frame_type == "lyrics" {
-                        let js_frame_content: Handle<JsObject> = js_tuple.get(&mut cx, 2).unwrap();
-                        let js_lang: Handle<JsString> =
-                            js_frame_content.get(&mut cx, "lang").unwrap();
-                        let js_description: Handle<JsString> =
-                            js_frame_content.get(&mut cx, "description").unwrap();
-                        let js_text: Handle<JsString> =
-                            js_frame_content.get(&mut cx, "text").unwrap();
-
-                        tag.add_frame(Lyrics {
-                            lang: js_lang.value(&mut cx),
-                            description: js_description.value(&mut cx),
-                            text: js_text.value(&mut cx),
-                        });
-                    }
-                    // Comments
-                    else if frame_type == "comment" {
-                        let js_frame_content: Handle<JsObject> = js_tuple.get(&mut cx, 2).unwrap();
-                        let js_lang: Handle<JsString> =
-                            js_frame_content.get(&mut cx, "lang").unwrap();
-                        let js_description: Handle<JsString> =
-                            js_frame_content.get(&mut cx, "description").unwrap();
-                        let js_text: Handle<JsString> =
-                            js_frame_content.get(&mut cx, "text").unwrap();
-
-                        tag.add_frame(Comment {
-                            lang: js_lang.value(&mut cx),
-                            description: js_description.value(&mut cx),
-                            text: js_text.value(&mut cx),
-                        });
+    let js_flag = js_options.get_value(cx, name)?;
+    if js_flag.is_a::<JsUndefined, _>(cx) {
+        return Ok(false);
+    }
+    Ok(js_flag.downcast_or_throw::<JsBoolean, _>(cx)?.value(cx))
+}
+
+/// Converts the outcome of updating one file of a batch to its `{path, ok, error, tag}`
+/// result, where files skipped after a failure have neither an error nor a tag
+fn update_result_to_js<'a, C: Context<'a>>(
+    cx: &mut C,
+    path: &str,
+    result: Option<Result<UpdatedTag, TagError>>,
+    format: CarrierFormat,
+) -> JsResult<'a, JsObject> {
+    let (ok, js_error, js_tag): (bool, Handle<JsValue>, Handle<JsValue>) = match result {
+        Some(Ok(updated)) => (
+            true,
+            cx.null().upcast(),
+            updated_tag_to_js(cx, &updated, format)?.upcast(),
+        ),
+        Some(Err(error)) => (false, error.to_js(cx)?.upcast(), cx.null().upcast()),
+        None => (false, cx.null().upcast(), cx.null().upcast()),
+    };
+
+    let js_result = cx.empty_object();
+    let js_path = cx.string(path);
+    let js_ok = cx.boolean(ok);
+    js_result.set(cx, "path", js_path)?;
+    js_result.set(cx, "ok", js_ok)?;
+    js_result.set(cx, "error", js_error)?;
+    js_result.set(cx, "tag", js_tag)?;
+
+    Ok(js_result)
+}
+
+/// Applies the same carriers to many files in parallel.
+///
+/// Every file gets a result. With `stopOnError`, files not started yet when one fails are
+/// skipped, otherwise all files are updated on a best-effort basis. Cancelled files fail with
+/// `ERR_CANCELLED`.
+fn update_tags(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let paths = paths_argument(&mut cx, 0)?;
+    let js_tag: Handle<JsArray> = cx.argument(1)?;
+    let write_version = write_version_option(&mut cx, 2)?;
+    let save = save_options(&mut cx, 2)?;
+    let stop_on_error = bool_option(&mut cx, 2, "stopOnError")?;
+    let format = carrier_format_option(&mut cx, 2)?;
+    let mods = js_to_carriers(&mut cx, js_tag, None)?;
+    let (handle, deferred, promise) = JobHandle::start(&mut cx, &paths, 2)?;
+
+    tags::update_many(
+        paths.clone(),
+        mods,
+        write_version,
+        save,
+        stop_on_error,
+        Arc::clone(&handle.job),
+        move |results| {
+            deferred.settle_with(&handle.channel, move |mut cx| {
+                let js_results = cx.empty_array();
+                for (i, (path, result)) in (0u32..).zip(paths.iter().zip(results)) {
+                    let js_result = update_result_to_js(&mut cx, path, result, format)?;
+                    js_results.set(&mut cx, i, js_result)?;
+                }
+                Ok(js_results)
+            });
+        },
+    );
+
+    Ok(promise)
+}
+
+/// The outcome of measuring, and with `write` tagging, one file of `analyzeLoudness`
+struct LoudnessResult {
+    path: String,
+    loudness: Result<Loudness, TagError>,
+    /// Whether gains were written, which they aren't for silent or unreadable files
+    written: Result<bool, TagError>,
+}
+
+fn nullable_number<'a, C: Context<'a>>(cx: &mut C, value: Option<f64>) -> Handle<'a, JsValue> {
+    match value {
+        Some(value) => cx.number(value).upcast(),
+        None => cx.null().upcast(),
+    }
+}
+
+/// Sets the `integratedLoudness`, `truePeak` and `samplePeak` properties and the gain property
+/// named gain_key, all null if the file couldn't be measured
+fn set_loudness_properties<'a, C: Context<'a>>(
+    cx: &mut C,
+    js_object: Handle<'a, JsObject>,
+    loudness: Option<&Loudness>,
+    gain_key: &str,
+) -> NeonResult<()> {
+    let js_integrated = nullable_number(cx, loudness.and_then(Loudness::integrated));
+    let js_true_peak = nullable_number(cx, loudness.map(|loudness| loudness.true_peak));
+    let js_sample_peak = nullable_number(cx, loudness.map(|loudness| loudness.sample_peak));
+    let js_gain = nullable_number(cx, loudness.and_then(Loudness::replaygain));
+    js_object.set(cx, "integratedLoudness", js_integrated)?;
+    js_object.set(cx, "truePeak", js_true_peak)?;
+    js_object.set(cx, "samplePeak", js_sample_peak)?;
+    js_object.set(cx, gain_key, js_gain)?;
+    Ok(())
+}
+
+fn loudness_result_to_js<'a, C: Context<'a>>(
+    cx: &mut C,
+    result: &LoudnessResult,
+    album: Option<&Loudness>,
+) -> JsResult<'a, JsObject> {
+    let error = result
+        .loudness
+        .as_ref()
+        .err()
+        .or(result.written.as_ref().err());
+    let js_error: Handle<JsValue> = match error {
+        Some(error) => error.to_js(cx)?.upcast(),
+        None => cx.null().upcast(),
+    };
+
+    let js_result = cx.empty_object();
+    let js_path = cx.string(&result.path);
+    let js_ok = cx.boolean(error.is_none());
+    js_result.set(cx, "path", js_path)?;
+    js_result.set(cx, "ok", js_ok)?;
+    js_result.set(cx, "error", js_error)?;
+
+    let loudness = result.loudness.as_ref().ok();
+    set_loudness_properties(cx, js_result, loudness, "trackGain")?;
+    let js_album_gain = nullable_number(
+        cx,
+        album
+            .filter(|_| loudness.is_some())
+            .and_then(Loudness::replaygain),
+    );
+    let js_written = cx.boolean(matches!(result.written, Ok(true)));
+    js_result.set(cx, "albumGain", js_album_gain)?;
+    js_result.set(cx, "written", js_written)?;
+
+    Ok(js_result)
+}
+
+/// Measures the loudness of many files in parallel, optionally writing their ReplayGain tags.
+///
+/// With `albumMode` the files are also measured together as one album. Files are only written
+/// once all of them are measured, so the album gain is known.
+fn analyze_loudness(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let paths = paths_argument(&mut cx, 0)?;
+    let album_mode = bool_option(&mut cx, 1, "albumMode")?;
+    let write = bool_option(&mut cx, 1, "write")?;
+    let write_version = write_version_option(&mut cx, 1)?;
+    let save = save_options(&mut cx, 1)?;
+    let (handle, deferred, promise) = JobHandle::start(&mut cx, &paths, 1)?;
+
+    let job = Arc::clone(&handle.job);
+    pool::map(
+        paths.clone(),
+        move |path| job.run(&path, || loudness::analyze(&path)),
+        move |results| {
+            let tracks: Vec<(String, Result<Loudness, TagError>)> = paths
+                .into_iter()
+                .zip(results)
+                .map(|(path, loudness)| (path, loudness.and_then(|loudness| loudness)))
+                .collect();
+            let album = match album_mode {
+                true => Some(Loudness::album(
+                    tracks
+                        .iter()
+                        .filter_map(|(_, loudness)| loudness.as_ref().ok()),
+                )),
+                false => None,
+            };
+
+            let channel = handle.channel.clone();
+            let reported_album = album.clone();
+            let settle = move |results: Vec<LoudnessResult>| {
+                deferred.settle_with(&channel, move |mut cx| {
+                    let js_tracks = cx.empty_array();
+                    for (i, result) in (0u32..).zip(&results) {
+                        let js_track =
+                            loudness_result_to_js(&mut cx, result, reported_album.as_ref())?;
+                        js_tracks.set(&mut cx, i, js_track)?;
                     }
-                    // Pictures
-                    else if frame_type == "picture" {
-                        let js_frame_content: Handle<JsObject> = js_tuple.get(&mut cx, 2).unwrap();
-                        let js_mime_type: Handle<JsString> = js_frame_content
-                            .get(&mut cx, "MIMEType")
-                            .expect("APIC.MIMEType not provided");
-                        let js_picture_type: Handle<JsNumber> = js_frame_content
-                            .get(&mut cx, "pictureType")
-                            .expect("APIC.pictureType not provided");
-                        let js_description: Handle<JsString> = js_frame_content
-                            .get(&mut cx, "description")
-                            .expect("APIC.description not provided");
-                        let js_data: Handle<JsArrayBuffer> = js_frame_content
-                            .get(&mut cx, "data")
-                            .expect("APIC.data not provided");
-
-                        let mime_type = js_mime_type.value(&mut cx);
-                        let picture_type = u8_to_picture_ype(js_picture_type.value(&mut cx) as u8);
-                        let description = js_description.value(&mut cx);
-                        let data: Vec<u8> = arraybuffer_to_u8_vec(&mut cx, &js_data);
-
-                        let picture = id3::frame::Picture {
-                            mime_type,
-                            picture_type,
-                            description,
-                            data,
-                        };
-                        tag.add_frame(Frame::with_content(
-                            "APIC",
-                            id3::Content::Picture(picture.clone()),
+                    let js_album: Handle<JsValue> = match &reported_album {
+                        Some(album) => {
+                            let js_album = cx.empty_object();
+                            set_loudness_properties(&mut cx, js_album, Some(album), "gain")?;
+                            js_album.upcast()
+                        }
+                        None => cx.null().upcast(),
+                    };
+
+                    let js_report = cx.empty_object();
+                    js_report.set(&mut cx, "tracks", js_tracks)?;
+                    js_report.set(&mut cx, "album", js_album)?;
+                    Ok(js_report)
+                });
+            };
+
+            if !write {
+                settle(
+                    tracks
+                        .into_iter()
+                        .map(|(path, loudness)| LoudnessResult {
+                            path,
+                            loudness,
+                            written: Ok(false),
+                        })
+                        .collect(),
+                );
+                return;
+            }
+
+            pool::map(
+                tracks.clone(),
+                move |(path, loudness)| match &loudness {
+                    Ok(track) => handle.job.check(&path).and_then(|_| {
+                        loudness::write_gains(&path, track, album.as_ref(), write_version, save)
+                            .map(|updated| updated.is_some())
+                    }),
+                    Err(_) => Ok(false),
+                },
+                move |written| {
+                    settle(
+                        tracks
+                            .into_iter()
+                            .zip(written)
+                            .map(|((path, loudness), written)| LoudnessResult {
+                                path,
+                                loudness,
+                                written: written.and_then(|written| written),
+                            })
+                            .collect(),
+                    )
+                },
+            );
+        },
+    );
+
+    Ok(promise)
+}
+
+/// Sets how many files asynchronous functions work on at once
+fn set_concurrency(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let limit = cx.argument::<JsNumber>(0)?.value(&mut cx);
+    if limit.is_nan() || limit < 1.0 {
+        return cx.throw_range_error("The concurrency limit must be at least 1");
+    }
+
+    pool::global().set_limit(limit as usize);
+    Ok(cx.undefined())
+}
+
+/// Reads the optional rating convention argument, defaulting to Windows Media Player
+fn rating_convention_argument(cx: &mut FunctionContext, i: i32) -> NeonResult<RatingConvention> {
+    match cx.argument_opt(i) {
+        Some(js_value) if !js_value.is_a::<JsUndefined, _>(cx) => {
+            let js_name = js_value.downcast_or_throw::<JsString, _>(cx)?;
+            let name = js_name.value(cx);
+            match RatingConvention::from_name(&name) {
+                Some(convention) => Ok(convention),
+                None => cx.throw_range_error(format!("Unknown rating convention {}", name)),
+            }
+        }
+        _ => Ok(RatingConvention::WindowsMediaPlayer),
+    }
+}
+
+fn rating_to_stars(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let rating = cx.argument::<JsNumber>(0)?.value(&mut cx);
+    if rating.fract() != 0.0 || !(0.0..=255.0).contains(&rating) {
+        return cx.throw_range_error(format!(
+            "The rating must be an integer from 0 to 255, got {}",
+            rating
+        ));
+    }
+    let convention = rating_convention_argument(&mut cx, 1)?;
+
+    Ok(cx.number(popularimeter::rating_to_stars(rating as u8, convention)))
+}
+
+fn stars_to_rating(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let stars = cx.argument::<JsNumber>(0)?.value(&mut cx);
+    let convention = rating_convention_argument(&mut cx, 1)?;
+
+    Ok(cx.number(popularimeter::stars_to_rating(stars, convention)))
+}
+
+fn lrc_to_synchronised_lyrics(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let lrc = cx.argument::<JsString>(0)?.value(&mut cx);
+    let lang = match cx.argument_opt(1) {
+        Some(js_lang) if js_lang.is_a::<JsString, _>(&mut cx) => js_lang
+            .downcast_or_throw::<JsString, _>(&mut cx)?
+            .value(&mut cx),
+        _ => String::from("eng"),
+    };
+    let description = match cx.argument_opt(2) {
+        Some(js_description) if js_description.is_a::<JsString, _>(&mut cx) => js_description
+            .downcast_or_throw::<JsString, _>(&mut cx)?
+            .value(&mut cx),
+        _ => String::new(),
+    };
+
+    let synchronised_lyrics = SynchronisedLyrics {
+        lang,
+        timestamp_format: TimestampFormat::Ms,
+        content_type: SynchronisedLyricsType::Lyrics,
+        description,
+        content: lrc::parse(&lrc),
+    };
+
+    synchronised_lyrics_to_js(&mut cx, &synchronised_lyrics)
+}
+
+fn synchronised_lyrics_to_lrc(mut cx: FunctionContext) -> JsResult<JsString> {
+    let js_synchronised_lyrics: Handle<JsObject> = cx.argument(0)?;
+    let at = CarrierRef {
+        path: None,
+        index: 0,
+        id: String::from("SYLT"),
+        removal: false,
+        format: CarrierFormat::Tuple,
+    };
+    let synchronised_lyrics = js_to_synchronised_lyrics(&mut cx, js_synchronised_lyrics, &at)?;
+
+    let (offset, compact) = match cx.argument_opt(1) {
+        Some(js_options) if js_options.is_a::<JsObject, _>(&mut cx) => {
+            let js_options = js_options.downcast_or_throw::<JsObject, _>(&mut cx)?;
+            let js_offset: Handle<JsValue> = js_options.get(&mut cx, "offset")?;
+            let js_compact: Handle<JsValue> = js_options.get(&mut cx, "compact")?;
+            let offset = match js_offset.downcast::<JsNumber, _>(&mut cx) {
+                Ok(js_offset) => {
+                    let offset = js_offset.value(&mut cx);
+                    if offset.fract() != 0.0
+                        || !(i32::MIN as f64..=i32::MAX as f64).contains(&offset)
+                    {
+                        return cx.throw_range_error(format!(
+                            "The offset must be an integer from {} to {}, got {}",
+                            i32::MIN,
+                            i32::MAX,
+                            offset
                         ));
                     }
-                    // Encapsulated object
-                    else if frame_type == "encapsulated object" {
-                        let js_frame_content: Handle<JsObject> = js_tuple.get(&mut cx, 2).unwrap();
-                        let js_mime_type: Handle<JsString> =
-                            js_frame_content.get(&mut cx, "MIMEType").unwrap();
-                        let js_filename: Handle<JsString> =
-                            js_frame_content.get(&mut cx, "filename").unwrap();
-                        let js_description: Handle<JsString> =
-                            js_frame_content.get(&mut cx, "description").unwrap();
-                        let js_data: Handle<JsArrayBuffer> =
-                            js_frame_content.get(&mut cx, "data").unwrap();
-
-                        tag.add_frame(EncapsulatedObject {
-                            mime_type: js_mime_type.value(&mut cx),
-                            filename: js_filename.value(&mut cx),
-                            description: js_description.value(&mut cx),
-                            data: arraybuffer_to_u8_vec(&mut cx, &js_data),
-                        });
-                    } else {
-                        panic!("Saving frame of type {} is not implemented yet", frame_type);
-                    }
+                    offset as i32
                 }
-            }
+                Err(_) => 0,
+            };
+            let compact = match js_compact.downcast::<JsBoolean, _>(&mut cx) {
+                Ok(js_compact) => js_compact.value(&mut cx),
+                Err(_) => false,
+            };
+            (offset, compact)
+        }
+        _ => (0, false),
+    };
+
+    if synchronised_lyrics.timestamp_format != TimestampFormat::Ms {
+        return at
+            .error(
+                ErrorCode::BadCarrier,
+                "Only synchronised lyrics with millisecond timestamps can be converted to LRC",
+            )
+            .throw(&mut cx);
+    }
+
+    Ok(cx.string(lrc::format(&synchronised_lyrics.content, offset, compact)))
+}
+
+fn validate_chapters(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let js_tag: Handle<JsArray> = cx.argument(0)?;
 
-            Err(_) => {}
+    let mut frames = Vec::new();
+    for (i, carrier) in (0u32..).zip(js_to_carriers(&mut cx, js_tag, None)?) {
+        if !carrier.remove
+            && matches!(
+                carrier.kind(),
+                FrameKind::Chapter | FrameKind::TableOfContents
+            )
+        {
+            let frame = carrier::carrier_to_frame(&carrier)
+                .map_err(|error| error.with_frame(i, &carrier.id))
+                .or_throw(&mut cx)?;
+            frames.push(frame);
         }
-    });
+    }
 
-    tag.write_to_path(&path, id3::Version::Id3v24)
-        .expect("Failed saving tag to file");
+    let js_issues = cx.empty_array();
+    for (i, issue) in (0u32..).zip(chapters::validate(&frames)) {
+        let js_issue = cx.empty_object();
+        let js_element_id = cx.string(&issue.element_id);
+        let js_message = cx.string(&issue.message);
+        js_issue.set(&mut cx, "elementId", js_element_id)?;
+        js_issue.set(&mut cx, "message", js_message)?;
+        js_issues.set(&mut cx, i, js_issue)?;
+    }
 
-    let js_tag =
-        tag_to_js_tag(&mut cx, &tag).expect("Failed converting tag to a javascript tuple array");
+    Ok(js_issues)
+}
 
-    Ok(js_tag)
+/// Declares the functions exported to JavaScript, each of which needs a declaration in
+/// typings.rs, as the test below checks
+macro_rules! exports {
+    ($($name:literal => $function:ident,)*) => {
+        #[cfg(test)]
+        const EXPORTED_FUNCTIONS: &[&str] = &[$($name),*];
+
+        fn export_functions(cx: &mut ModuleContext) -> NeonResult<()> {
+            $(cx.export_function($name, $function)?;)*
+            Ok(())
+        }
+    };
+}
+
+exports! {
+    "loadTag" => load_tag,
+    "updateTag" => update_tag,
+    "previewUpdate" => preview_update,
+    "exportTag" => export_tag,
+    "importTag" => import_tag,
+    "loadFramePayload" => load_frame_payload,
+    "probeAudio" => probe_audio,
+    "loadTagFromBuffer" => load_tag_from_buffer,
+    "writeTagToBuffer" => write_tag_to_buffer,
+    "loadTagAsync" => load_tag_async,
+    "updateTagAsync" => update_tag_async,
+    "loadTags" => load_tags,
+    "updateTags" => update_tags,
+    "analyzeLoudness" => analyze_loudness,
+    "setConcurrency" => set_concurrency,
+    "ratingToStars" => rating_to_stars,
+    "starsToRating" => stars_to_rating,
+    "lrcToSynchronisedLyrics" => lrc_to_synchronised_lyrics,
+    "synchronisedLyricsToLrc" => synchronised_lyrics_to_lrc,
+    "validateChapters" => validate_chapters,
 }
 
 #[neon::main]
 fn main(mut cx: ModuleContext) -> NeonResult<()> {
-    cx.export_function("loadTag", load_tag)?;
-    cx.export_function("updateTag", update_tag)?;
-    Ok(())
+    let js_schema_version = cx.number(FRAME_SCHEMA_VERSION);
+    cx.export_value("FRAME_SCHEMA_VERSION", js_schema_version)?;
+    let js_document_schema_version = cx.number(DOCUMENT_SCHEMA_VERSION);
+    cx.export_value("TAG_DOCUMENT_SCHEMA_VERSION", js_document_schema_version)?;
+    let js_tag_error = tag_error_class(&mut cx)?;
+    cx.export_value("TagError", js_tag_error)?;
+    export_functions(&mut cx)
+}
+
+#[cfg(test)]
+mod typings;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::{typings, EXPORTED_FUNCTIONS};
+
+    #[test]
+    fn declarations_match_checked_in_file() {
+        let checked_in = include_str!("../@types/index.d.ts").replace("\r\n", "\n");
+        assert!(
+            checked_in == typings::render(&typings::module()),
+            "@types/index.d.ts is out of date with src/typings.rs, \
+             regenerate it with `cargo build --features write-types`"
+        );
+    }
+
+    #[test]
+    fn every_export_is_declared() {
+        let declared: BTreeSet<_> = typings::module()
+            .iter()
+            .filter_map(typings::Decl::function_name)
+            .collect();
+        let exported: BTreeSet<_> = EXPORTED_FUNCTIONS.iter().copied().collect();
+        assert_eq!(declared, exported);
+    }
 }
@@ -0,0 +1,1245 @@
+//! The TypeScript declarations of the addon. build.rs renders them to `index.d.ts` in OUT_DIR,
+//! and over `@types/index.d.ts` with the write-types feature. The content, carrier and error
+//! code types are derived from `FrameKind` and `ErrorCode`, so they follow the Rust side. The
+//! fields of the content types and the options of the functions are written by hand,
+//! tests/contents.rs checks the field names and types against the contents the addon returns
+//! and the option names against the options each function reads
+
+use metashine_core::{
+    carrier::FRAME_SCHEMA_VERSION, document::DOCUMENT_SCHEMA_VERSION, ErrorCode, FrameKind,
+};
+
+/// Declarations are broken over lines past this width, like prettier does
+const MAX_WIDTH: usize = 100;
+
+/// The generic parameter of functions and types returning frames
+const FORMAT_PARAM: &str = "<F extends FrameFormat = 'tuple'>";
+/// The generic parameter of options accepting any frame format
+const OPTIONS_PARAM: &str = "<F extends FrameFormat = FrameFormat>";
+
+/// A field of an object type, optional when its name ends with `?`
+pub struct Field {
+    name: &'static str,
+    ty: String,
+    doc: Option<&'static str>,
+}
+
+fn field(name: &'static str, ty: impl Into<String>) -> Field {
+    Field {
+        name,
+        ty: ty.into(),
+        doc: None,
+    }
+}
+
+fn documented(doc: &'static str, name: &'static str, ty: impl Into<String>) -> Field {
+    Field {
+        doc: Some(doc),
+        ..field(name, ty)
+    }
+}
+
+pub enum Ty {
+    /// A type expression, written as is
+    Raw(String),
+    /// Types joined with `|`, one per line when they don't fit on one
+    Union(Vec<String>),
+    Tuple(Vec<String>),
+    Object(Vec<Field>),
+    /// An intersection of a type with an object type
+    Extends(String, Vec<Field>),
+    ObjectArray(Vec<Field>),
+}
+
+fn raw(ty: impl Into<String>) -> Ty {
+    Ty::Raw(ty.into())
+}
+
+fn union<S: Into<String>>(types: impl IntoIterator<Item = S>) -> Ty {
+    Ty::Union(types.into_iter().map(Into::into).collect())
+}
+
+pub enum Item {
+    /// A comment heading the declarations after it
+    Section,
+    Type {
+        name: String,
+        generics: &'static str,
+        ty: Ty,
+    },
+    Class {
+        name: &'static str,
+        extends: &'static str,
+        fields: Vec<Field>,
+    },
+    Const {
+        name: &'static str,
+        ty: String,
+    },
+    Function {
+        name: &'static str,
+        generics: &'static str,
+        params: Vec<(&'static str, &'static str)>,
+        returns: &'static str,
+    },
+}
+
+pub struct Decl {
+    doc: Option<&'static str>,
+    item: Item,
+}
+
+impl Decl {
+    fn documented(mut self, doc: &'static str) -> Self {
+        self.doc = Some(doc);
+        self
+    }
+
+    #[cfg(test)]
+    pub fn function_name(&self) -> Option<&'static str> {
+        match self.item {
+            Item::Function { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+}
+
+fn section(doc: &'static str) -> Decl {
+    Decl {
+        doc: Some(doc),
+        item: Item::Section,
+    }
+}
+
+fn ty(name: impl Into<String>, ty: Ty) -> Decl {
+    generic_ty(name, "", ty)
+}
+
+fn generic_ty(name: impl Into<String>, generics: &'static str, ty: Ty) -> Decl {
+    Decl {
+        doc: None,
+        item: Item::Type {
+            name: name.into(),
+            generics,
+            ty,
+        },
+    }
+}
+
+fn function(
+    name: &'static str,
+    generics: &'static str,
+    params: &[(&'static str, &'static str)],
+    returns: &'static str,
+) -> Decl {
+    Decl {
+        doc: None,
+        item: Item::Function {
+            name,
+            generics,
+            params: params.to_vec(),
+            returns,
+        },
+    }
+}
+
+/// `extended text` becomes `ExtendedText`
+fn pascal_case(kind: FrameKind) -> String {
+    kind.as_str()
+        .split(' ')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// The type of the content of carriers of the kind
+fn content_name(kind: FrameKind) -> String {
+    format!("ID3{}", pascal_case(kind))
+}
+
+fn content(kind: FrameKind) -> Decl {
+    let lang = || field("lang", "string");
+    let description = || field("description", "string");
+    let mime_type = || field("MIMEType", "string");
+    let decl = match kind {
+        FrameKind::Text | FrameKind::Link => raw("string"),
+        FrameKind::ExtendedText => Ty::Object(vec![description(), field("value", "string")]),
+        FrameKind::ExtendedLink => Ty::Object(vec![description(), field("link", "string")]),
+        FrameKind::Comment | FrameKind::Lyrics => {
+            Ty::Object(vec![lang(), description(), field("text", "string")])
+        }
+        FrameKind::Popularimeter => Ty::Object(vec![
+            field("user", "string"),
+            field("rating", "number"),
+            field("counter", "number"),
+        ]),
+        FrameKind::PlayCounter => raw("number"),
+        FrameKind::SynchronisedLyrics => Ty::Object(vec![
+            lang(),
+            field("timestampFormat", "'ms' | 'mpeg'"),
+            field(
+                "contentType",
+                "'other' | 'lyrics' | 'transcription' | 'part name' | 'event' | 'chord' | 'trivia'",
+            ),
+            description(),
+            field("content", "[number, string][]"),
+        ]),
+        FrameKind::Picture => Ty::Object(vec![
+            mime_type(),
+            field("pictureType", "number"),
+            description(),
+            field("data", "ArrayBuffer"),
+        ]),
+        FrameKind::EncapsulatedObject => Ty::Object(vec![
+            mime_type(),
+            field("filename", "string"),
+            description(),
+            field("data", "ArrayBuffer"),
+        ]),
+        FrameKind::Chapter => Ty::Object(vec![
+            field("elementId", "string"),
+            field("startTime", "number"),
+            field("endTime", "number"),
+            field("startOffset", "number | null"),
+            field("endOffset", "number | null"),
+            documented(
+                "Sub-frames, in the frame format of the tag holding the chapter",
+                "frames",
+                "TagCarrier | FrameObject[]",
+            ),
+        ]),
+        FrameKind::TableOfContents => Ty::Object(vec![
+            field("elementId", "string"),
+            field("topLevel", "boolean"),
+            field("ordered", "boolean"),
+            field("elements", "string[]"),
+            field("frames", "TagCarrier | FrameObject[]"),
+        ]),
+        FrameKind::InvolvedPeople => Ty::ObjectArray(vec![
+            field("involvement", "string"),
+            field("involvee", "string"),
+        ]),
+        FrameKind::Private => {
+            Ty::Object(vec![field("owner", "string"), field("data", "ArrayBuffer")])
+        }
+        FrameKind::UniqueFileIdentifier => Ty::Object(vec![
+            field("owner", "string"),
+            field("identifier", "ArrayBuffer"),
+        ]),
+        FrameKind::Unknown => Ty::Object(vec![
+            field("data", "ArrayBuffer"),
+            documented(
+                "Type code of the MP4 data atom, when it isn't 0 (implicit)",
+                "type?",
+                "number",
+            ),
+        ]),
+    };
+    let decl = ty(content_name(kind), decl);
+    match kind {
+        FrameKind::Chapter => {
+            decl.documented("Byte offsets are null when the chapter doesn't use them")
+        }
+        FrameKind::InvolvedPeople => decl.documented("IPLS in ID3v2.3, TIPL and TMCL in ID3v2.4"),
+        FrameKind::Unknown => decl.documented("Frames that can be read but not written yet"),
+        _ => decl,
+    }
+}
+
+fn carrier(kind: FrameKind) -> Decl {
+    let content = match kind {
+        FrameKind::Picture | FrameKind::EncapsulatedObject => {
+            let name = content_name(kind);
+            format!("{} | {}Handle", name, name)
+        }
+        kind => content_name(kind),
+    };
+    ty(
+        format!("{}Carrier", pascal_case(kind)),
+        Ty::Tuple(vec![
+            format!("'{}'", kind.as_str()),
+            "string".to_owned(),
+            content,
+            "boolean".to_owned(),
+        ]),
+    )
+}
+
+fn frames() -> Vec<Decl> {
+    let mut decls = vec![section("Standard ID3 frame content types")];
+    decls.extend(FrameKind::ALL.iter().copied().map(content));
+    decls.extend(vec![
+        ty(
+            "ID3PayloadHandle",
+            Ty::Object(vec![field("size", "number"), field("hash", "string")]),
+        )
+        .documented(
+            "Stands for the data of a picture or encapsulated object loaded with lazyPayloads,\n\
+             hash is its SHA-256 digest in hexadecimal",
+        ),
+        ty(
+            "ID3PictureHandle",
+            raw("Omit<ID3Picture, 'data'> & ID3PayloadHandle"),
+        ),
+        ty(
+            "ID3EncapsulatedObjectHandle",
+            raw("Omit<ID3EncapsulatedObject, 'data'> & ID3PayloadHandle"),
+        ),
+        ty(
+            "ID3Content",
+            union(FrameKind::ALL.iter().copied().map(content_name)),
+        ),
+        section("Frame carriers"),
+    ]);
+    decls.extend(FrameKind::ALL.iter().copied().map(carrier));
+    decls.extend(vec![
+        ty(
+            "FrameCarrier",
+            union(
+                FrameKind::ALL
+                    .iter()
+                    .map(|&kind| format!("{}Carrier", pascal_case(kind))),
+            ),
+        ),
+        ty("TagCarrier", raw("FrameCarrier[]")).documented(
+            "Carriers with their last element set to true remove the ID3 frames they stand for:\n\
+             comments and lyrics with the same language and description, user-defined texts and links\n\
+             with the same description, pictures with the same type and description, private frames,\n\
+             unique file identifiers and popularimeters with the same owner or user, and chapters and\n\
+             tables of contents with the same element ID, and encapsulated objects with the same\n\
+             description, MIME type and filename. Pictures and encapsulated objects loaded with\n\
+             lazyPayloads only remove the frame whose data has the hash of their handle. Other frames\n\
+             are removed by ID",
+        ),
+        section("Frame objects"),
+        ty("FrameKind", raw("FrameCarrier[0]")),
+        Decl {
+            doc: None,
+            item: Item::Const {
+                name: "FRAME_SCHEMA_VERSION",
+                ty: FRAME_SCHEMA_VERSION.to_string(),
+            },
+        }
+        .documented(
+            "The frame schema version of frame objects, checked when a frame object has a schema",
+        ),
+        generic_ty(
+            "FrameObjectOf",
+            "<K extends FrameKind, V>",
+            raw(
+                "{\n  schema?: typeof FRAME_SCHEMA_VERSION;\n  kind: K;\n  id: string;\n} \
+                 & ({ op: 'set'; value: V } | { op: 'remove'; value?: V extends object ? Partial<V> : V })",
+            ),
+        )
+        .documented(
+            "A frame as a `{kind, id, value, op}` object, returned instead of tuples with the\n\
+             frameFormat 'object' option and accepted by updates alongside tuples. value has the shape\n\
+             of the content of the tuple of the same kind. Frames are removed like with tuples and\n\
+             only need the fields telling them apart, so value can be left out when op is 'remove'",
+        ),
+        ty(
+            "FrameObject",
+            raw("{\n  [C in FrameCarrier as C[0]]: FrameObjectOf<C[0], C[2]>;\n}[FrameKind]"),
+        ),
+        ty("TagUpdate", raw("(FrameCarrier | FrameObject)[]"))
+            .documented("The frames of an update, as objects or legacy tuples"),
+        ty("FrameFormat", union(vec!["'tuple'", "'object'"])).documented(
+            "How loaded and written frames are returned: 'tuple' for legacy `[type, id, content,\n\
+             remove]` carriers, the default for now, or 'object' for frame objects",
+        ),
+        generic_ty(
+            "FrameOptions",
+            "<F extends FrameFormat>",
+            Ty::Object(vec![field("frameFormat?", "F")]),
+        ),
+        generic_ty(
+            "TagFrames",
+            FORMAT_PARAM,
+            raw("F extends 'object'\n  ? FrameObject[]\n  : TagCarrier"),
+        ),
+    ]);
+    decls
+}
+
+fn tags() -> Vec<Decl> {
+    vec![
+        ty("TagFormat", union(vec!["'id3'", "'vorbis'", "'mp4'"])).documented(
+            "Vorbis comments of FLAC, Ogg Vorbis and Opus files and MP4 atoms are returned as carriers\n\
+             keyed by the ID3 frame ID standing for them, like TIT2 for TITLE and ©nam, and are written\n\
+             from those IDs the same way. Fields and atoms without one keep their own name, and ID3 frame\n\
+             IDs without a field or atom are rejected with ERR_BAD_CARRIER.\n\
+             Pictures are APIC picture carriers, MP4 trkn and disk are TRCK and TPOS texts as \"n/total\"\n\
+             and iTunes freeform atoms are TXXX extended text carriers",
+        ),
+        generic_ty(
+            "LoadedTag",
+            FORMAT_PARAM,
+            raw("TagFrames<F> & { format: TagFormat }"),
+        ),
+        ty(
+            "ID3HeaderFlags",
+            Ty::Object(vec![
+                field("unsynchronisation", "boolean"),
+                documented("Marks a compressed tag in ID3v2.2", "extendedHeader", "boolean"),
+                field("experimental", "boolean"),
+                field("footer", "boolean"),
+            ]),
+        ),
+        generic_ty(
+            "TagInfo",
+            FORMAT_PARAM,
+            Ty::Object(vec![
+                field("format", "TagFormat"),
+                field("hadTag", "boolean"),
+                field("version", "'2.2' | '2.3' | '2.4' | null"),
+                documented(
+                    "Bytes taken by the whole tag, including its header and footer",
+                    "tagSize",
+                    "number | null",
+                ),
+                field("paddingSize", "number | null"),
+                field("flags", "ID3HeaderFlags | null"),
+                field("frames", "TagFrames<F>"),
+            ]),
+        )
+        .documented(
+            "Returned by loadTag. version, tagSize, paddingSize and flags describe the ID3 tag header\n\
+             and are null for other formats and for files without an ID3 tag",
+        ),
+        section("Errors"),
+        ty(
+            "TagErrorCode",
+            union(
+                ErrorCode::ALL
+                    .iter()
+                    .map(|code| format!("'{}'", code.as_str())),
+            ),
+        ),
+        Decl {
+            doc: None,
+            item: Item::Class {
+                name: "TagError",
+                extends: "Error",
+                fields: vec![
+                    field("name", "'TagError'"),
+                    field("code", "TagErrorCode"),
+                    field("path?", "string"),
+                    field("frameIndex?", "number"),
+                    field("frameId?", "string"),
+                ],
+            },
+        }
+        .documented(
+            "Thrown by loadTag and updateTag, and rejected by their asynchronous versions,\n\
+             so failures can be told apart with `instanceof TagError`",
+        ),
+    ]
+}
+
+fn functions() -> Vec<Decl> {
+    vec![
+        section("Functions"),
+        generic_ty(
+            "LoadOptions",
+            OPTIONS_PARAM,
+            Ty::Extends(
+                "FrameOptions<F>".to_owned(),
+                vec![documented(
+                    "Return pictures and encapsulated objects with a handle instead of their data,\n\
+                     which loadFramePayload reads when it is needed",
+                    "lazyPayloads?",
+                    "boolean",
+                )],
+            ),
+        ),
+        function(
+            "loadTag",
+            FORMAT_PARAM,
+            &[("path", "string"), ("options?", "LoadOptions<F>")],
+            "TagInfo<F>",
+        ),
+        function(
+            "loadFramePayload",
+            "",
+            &[("path", "string"), ("frameIndex", "number")],
+            "ArrayBuffer",
+        )
+        .documented(
+            "Reads the data of the picture or encapsulated object at frameIndex in the frames of\n\
+             loadTag, failing with ERR_FRAME_NOT_FOUND if the frame there has none",
+        ),
+        ty(
+            "ID3WriteVersion",
+            union(vec!["'2.2'", "'2.3'", "'2.4'", "'preserve'"]),
+        )
+        .documented(
+            "The ID3 version tags are written in. preserve keeps the version the file was read with\n\
+             and writes new tags as ID3v2.4. Frames are translated when changing versions:\n\
+             TDRC to TYER/TDAT/TIME, TDOR to TORY and TIPL/TMCL to IPLS, and back",
+        ),
+        generic_ty(
+            "WriteOptions",
+            OPTIONS_PARAM,
+            Ty::Extends(
+                "FrameOptions<F>".to_owned(),
+                vec![
+                    field("version?", "ID3WriteVersion"),
+                    documented(
+                        "Keep the original file next to the updated one, with .bak appended to its name",
+                        "backup?",
+                        "boolean",
+                    ),
+                    documented(
+                        "Give the updated file the modification time of the original",
+                        "preserveMtime?",
+                        "boolean",
+                    ),
+                ],
+            ),
+        )
+        .documented(
+            "Tags are written to a temporary copy of the file, which replaces the original once it\n\
+             reads back with the written frames and the audio of the original. Otherwise the\n\
+             original is kept and the update fails with ERR_VERIFY. Symbolic links are followed, so\n\
+             the file they point to is replaced, and read-only files fail with ERR_IO",
+        ),
+        function(
+            "updateTag",
+            FORMAT_PARAM,
+            &[
+                ("path", "string"),
+                ("update", "TagUpdate"),
+                ("options?", "WriteOptions<F>"),
+            ],
+            "LoadedTag<F>",
+        )
+        .documented(
+            "The padding of ID3 tags and the first PADDING block of FLAC files grow or shrink by the\n\
+             change in size of the tag when they can, so the audio keeps its offset. Every other FLAC\n\
+             block is copied untouched",
+        ),
+        generic_ty(
+            "UpdatePreview",
+            FORMAT_PARAM,
+            Ty::Object(vec![
+                field("format", "TagFormat"),
+                field("added", "TagFrames<F>"),
+                field(
+                    "changed",
+                    "{ old: TagFrames<F>[number]; new: TagFrames<F>[number] }[]",
+                ),
+                field("removed", "TagFrames<F>"),
+                documented("Bytes the new tag takes in the file", "tagSize", "number"),
+                documented(
+                    "Bytes the audio moves by, negative when it moves towards the start of the file",
+                    "audioShift",
+                    "number",
+                ),
+                field("needsShift", "boolean"),
+            ]),
+        )
+        .documented(
+            "The changes updateTag would make to a file, worked out without writing it.\n\
+             Frames are matched by ID and by what tells apart frames sharing one, like descriptions,\n\
+             languages and picture types. The audio moves when the new tag doesn't fit in the space\n\
+             of the old one, padding included",
+        ),
+        function(
+            "previewUpdate",
+            FORMAT_PARAM,
+            &[
+                ("path", "string"),
+                ("update", "TagUpdate"),
+                ("options?", "Pick<WriteOptions<F>, 'frameFormat' | 'version'>"),
+            ],
+            "UpdatePreview<F>",
+        ),
+        function(
+            "loadTagFromBuffer",
+            FORMAT_PARAM,
+            &[
+                ("buffer", "ArrayBuffer | Uint8Array"),
+                ("options?", "FrameOptions<F>"),
+            ],
+            "TagInfo<F>",
+        )
+        .documented(
+            "Versions of loadTag and updateTag working on a whole file held in memory, such as a\n\
+             Node.js Buffer. writeTagToBuffer returns a new buffer with the new tag and the same audio",
+        ),
+        function(
+            "writeTagToBuffer",
+            "",
+            &[
+                ("buffer", "ArrayBuffer | Uint8Array"),
+                ("update", "TagUpdate"),
+                ("options?", "Pick<WriteOptions, 'version'>"),
+            ],
+            "ArrayBuffer",
+        ),
+        ty(
+            "LameReplayGain",
+            Ty::Object(vec![
+                documented("In dB", "gain", "number"),
+                field(
+                    "originator",
+                    "'unset' | 'artist' | 'user' | 'automatic' | 'rms' | 'other'",
+                ),
+            ]),
+        ),
+        ty(
+            "LameHeader",
+            Ty::Object(vec![
+                field("encoder", "string"),
+                field("revision", "number"),
+                documented(
+                    "1 and 8 are CBR, 2 and 9 are ABR, 3 to 6 are VBR and 0 is unknown",
+                    "vbrMethod",
+                    "number",
+                ),
+                documented("Lowpass filter frequency in Hz", "lowpass", "number | null"),
+                documented(
+                    "Target bitrate of ABR, minimum bitrate of VBR or bitrate of CBR in kbit/s, up to 255",
+                    "bitrate",
+                    "number",
+                ),
+                documented("Peak amplitude, 1 being full scale", "peak", "number | null"),
+                documented("Track gain", "radioGain", "LameReplayGain | null"),
+                documented("Album gain", "audiophileGain", "LameReplayGain | null"),
+                documented(
+                    "Samples added by the encoder before the audio",
+                    "encoderDelay",
+                    "number",
+                ),
+                documented(
+                    "Samples added by the encoder after the audio to fill the last frame",
+                    "encoderPadding",
+                    "number",
+                ),
+                documented(
+                    "Bytes from the start of the Info frame to the end of the audio",
+                    "musicLength",
+                    "number",
+                ),
+                documented(
+                    "CRC-16 of the audio frames after the Info frame",
+                    "musicCrc",
+                    "number",
+                ),
+                documented("CRC-16 of the Info frame up to this field", "crc", "number"),
+                documented(
+                    "Whether crc matches the Info frame, a mismatch means it was damaged or edited",
+                    "crcValid",
+                    "boolean",
+                ),
+            ]),
+        )
+        .documented(
+            "The extension LAME and encoders based on it add after the Xing or Info header",
+        ),
+        ty(
+            "ITunesGapless",
+            Ty::Object(vec![
+                field("encoderDelay", "number"),
+                field("encoderPadding", "number"),
+                documented(
+                    "Samples of audio without the delay and padding",
+                    "samples",
+                    "number",
+                ),
+            ]),
+        )
+        .documented("Gapless playback information iTunes writes in an iTunSMPB comment"),
+        ty(
+            "AudioProperties",
+            Ty::Object(vec![
+                documented("In seconds", "duration", "number"),
+                documented("Average bitrate in kbit/s", "bitrate", "number"),
+                documented(
+                    "The constant bitrate or ABR target in kbit/s, null for VBR",
+                    "nominalBitrate",
+                    "number | null",
+                ),
+                field("bitrateMode", "'CBR' | 'VBR' | 'ABR'"),
+                field("sampleRate", "number"),
+                field("channels", "1 | 2"),
+                field(
+                    "channelMode",
+                    "'stereo' | 'joint stereo' | 'dual channel' | 'mono'",
+                ),
+                field("mpegVersion", "'1' | '2' | '2.5'"),
+                field("layer", "1 | 2 | 3"),
+                field("frames", "number"),
+                field("vbrHeader", "'Xing' | 'Info' | 'VBRI' | null"),
+                documented(
+                    "Such as LAME3.100, from the LAME extension of the Xing or Info header",
+                    "encoder",
+                    "string | null",
+                ),
+                field("lame", "LameHeader | null"),
+                documented(
+                    "From an iTunSMPB COMM or TXXX frame of the ID3v2 tag",
+                    "iTunSMPB",
+                    "ITunesGapless | null",
+                ),
+            ]),
+        )
+        .documented(
+            "Properties of the audio of an MPEG file, read from its frame headers and the Xing, Info\n\
+             or VBRI header of its first frame. Without such a header every frame is counted",
+        ),
+        function("probeAudio", "", &[("path", "string")], "AudioProperties")
+            .documented("Fails with ERR_PARSE for files that aren't MPEG audio"),
+        function(
+            "loadTagAsync",
+            FORMAT_PARAM,
+            &[("path", "string"), ("options?", "LoadOptions<F>")],
+            "Promise<TagInfo<F>>",
+        )
+        .documented(
+            "Asynchronous versions of loadTag and updateTag, reading and writing files on worker\n\
+             threads. Malformed carriers and options still throw right away",
+        ),
+        function(
+            "updateTagAsync",
+            FORMAT_PARAM,
+            &[
+                ("path", "string"),
+                ("update", "TagUpdate"),
+                ("options?", "WriteOptions<F>"),
+            ],
+            "Promise<LoadedTag<F>>",
+        ),
+    ]
+}
+
+/// The content of document frames of the kind, with binary data as base64 strings
+fn document_content(kind: FrameKind) -> String {
+    let tagged = format!("{{ kind: '{}' }}", kind.as_str());
+    let name = content_name(kind);
+    match kind {
+        FrameKind::Text => "{ kind: 'text'; text: string }".to_owned(),
+        FrameKind::Link => "{ kind: 'link'; link: string }".to_owned(),
+        FrameKind::Picture
+        | FrameKind::EncapsulatedObject
+        | FrameKind::Private
+        | FrameKind::UniqueFileIdentifier
+        | FrameKind::Unknown => format!("({} & Base64<{}>)", tagged, name),
+        FrameKind::Chapter | FrameKind::TableOfContents => format!(
+            "({{ kind: '{}'; frames: DocumentFrame[] }} & Omit<{}, 'frames'>)",
+            kind.as_str(),
+            name
+        ),
+        FrameKind::InvolvedPeople => format!("{{ kind: 'involved people'; items: {} }}", name),
+        _ => format!("({} & {})", tagged, name),
+    }
+}
+
+fn documents() -> Vec<Decl> {
+    vec![
+        section("Tag documents"),
+        Decl {
+            doc: None,
+            item: Item::Const {
+                name: "TAG_DOCUMENT_SCHEMA_VERSION",
+                ty: DOCUMENT_SCHEMA_VERSION.to_string(),
+            },
+        }
+        .documented("The schema version of tag documents, checked when one is imported"),
+        ty("TagDocumentFormat", union(vec!["'json'", "'yaml'"])),
+        ty(
+            "ID3TextEncoding",
+            union(vec!["'ISO-8859-1'", "'UTF-16'", "'UTF-16BE'", "'UTF-8'"]),
+        ),
+        generic_ty(
+            "Base64",
+            "<T>",
+            raw("{ [K in keyof T]: T[K] extends ArrayBuffer ? string : T[K] }"),
+        )
+        .documented("A content type with its binary data as base64 strings"),
+        ty(
+            "DocumentFrameContent",
+            union(
+                FrameKind::ALL
+                    .iter()
+                    .copied()
+                    // Play counters are stored as unknown frames
+                    .filter(|kind| *kind != FrameKind::PlayCounter)
+                    .map(document_content),
+            ),
+        ),
+        ty(
+            "DocumentFrame",
+            Ty::Extends(
+                "DocumentFrameContent".to_owned(),
+                vec![
+                    field("id", "string"),
+                    documented(
+                        "Left out for frames without text, new frames get the default of the tag version",
+                        "encoding?",
+                        "ID3TextEncoding",
+                    ),
+                    field("tagAlterPreservation?", "boolean"),
+                    field("fileAlterPreservation?", "boolean"),
+                ],
+            ),
+        )
+        .documented(
+            "An ID3 frame as it is stored, with its text encoding and status flags. Frames with\n\
+             tagAlterPreservation are dropped when the tag is written, as ID3 asks",
+        ),
+        ty(
+            "ID3TagDocument",
+            Ty::Object(vec![
+                field("format", "'id3'"),
+                field("version", "'2.2' | '2.3' | '2.4'"),
+                documented("In the order they are stored, repeated frames included", "frames", "DocumentFrame[]"),
+            ]),
+        ),
+        ty(
+            "VorbisDocumentPicture",
+            Ty::Extends(
+                "Base64<ID3Picture>".to_owned(),
+                vec![
+                    field("width", "number"),
+                    field("height", "number"),
+                    field("depth", "number"),
+                    field("colors", "number"),
+                ],
+            ),
+        )
+        .documented("A picture in the FLAC PICTURE block layout"),
+        ty(
+            "VorbisTagDocument",
+            Ty::Object(vec![
+                field("format", "'vorbis'"),
+                field("vendor", "string"),
+                field("fields", "{ name: string; value: string }[]"),
+                documented("Fields without `=` written by other taggers", "unnamed?", "string[]"),
+                field("pictures", "VorbisDocumentPicture[]"),
+            ]),
+        ),
+        ty(
+            "MP4TagDocument",
+            Ty::Object(vec![
+                field("format", "'mp4'"),
+                documented(
+                    "ident is the atom type or `----:mean:name`, data atoms of type 1 hold UTF-8 text",
+                    "items",
+                    "{\n    ident: string;\n    data: ({ type: number; locale: number } & \
+                     ({ text: string } | { data: string }))[];\n  }[]",
+                ),
+            ]),
+        ),
+        ty(
+            "TagDocument",
+            Ty::Extends(
+                "(ID3TagDocument | VorbisTagDocument | MP4TagDocument)".to_owned(),
+                vec![field("schema", "typeof TAG_DOCUMENT_SCHEMA_VERSION")],
+            ),
+        )
+        .documented(
+            "A whole tag as exported by exportTag, written as JSON or YAML. Binary data is base64",
+        ),
+        function(
+            "exportTag",
+            "",
+            &[("path", "string"), ("format?", "TagDocumentFormat")],
+            "string",
+        )
+        .documented(
+            "Writes the whole tag of a file as a TagDocument, in JSON unless format is 'yaml'",
+        ),
+        function(
+            "importTag",
+            FORMAT_PARAM,
+            &[
+                ("path", "string"),
+                ("document", "string"),
+                ("options?", "Omit<WriteOptions<F>, 'version'>"),
+            ],
+            "LoadedTag<F>",
+        )
+        .documented(
+            "Replaces the whole tag of a file with the tag of a JSON or YAML TagDocument. The tag is\n\
+             written in the version of the document and must read back byte for byte, otherwise the\n\
+             file is left as it was and the import fails with ERR_VERIFY. Documents of another tag\n\
+             format or schema fail with ERR_BAD_DOCUMENT",
+        ),
+    ]
+}
+
+fn batches() -> Vec<Decl> {
+    vec![
+        ty(
+            "JobProgress",
+            Ty::Object(vec![
+                field("filesDone", "number"),
+                field("filesTotal", "number"),
+                documented("Total size of the finished files", "bytesProcessed", "number"),
+                documented("The file just finished", "path", "string"),
+            ]),
+        )
+        .documented("Reported after each file of a batch operation is finished"),
+        ty(
+            "JobOptions",
+            Ty::Object(vec![field(
+                "onProgress?",
+                "(progress: JobProgress) => void",
+            )]),
+        ),
+        generic_ty("Job", "<T>", raw("Promise<T> & { cancel(): void }")).documented(
+            "The promise of a batch operation. cancel() skips the files not started yet, which then\n\
+             fail with ERR_CANCELLED, files being written are always finished",
+        ),
+        function(
+            "loadTags",
+            FORMAT_PARAM,
+            &[
+                ("paths", "string[]"),
+                ("options?", "LoadOptions<F> & JobOptions"),
+            ],
+            "Job<(TagInfo<F> | TagError)[]>",
+        )
+        .documented(
+            "Loads the tags of many files in parallel. Files that can't be loaded get their TagError\n\
+             in place of a TagInfo",
+        ),
+        generic_ty(
+            "BatchOptions",
+            OPTIONS_PARAM,
+            Ty::Extends(
+                "WriteOptions<F> & JobOptions".to_owned(),
+                vec![documented(
+                    "Skip the files not started yet once one fails instead of updating all of them",
+                    "stopOnError?",
+                    "boolean",
+                )],
+            ),
+        ),
+        generic_ty(
+            "UpdateResult",
+            FORMAT_PARAM,
+            Ty::Object(vec![
+                field("path", "string"),
+                field("ok", "boolean"),
+                field("error", "TagError | null"),
+                field("tag", "LoadedTag<F> | null"),
+            ]),
+        )
+        .documented(
+            "The outcome of updating one file with updateTags.\n\
+             Files skipped after a failure with stopOnError have neither an error nor a tag",
+        ),
+        function(
+            "updateTags",
+            FORMAT_PARAM,
+            &[
+                ("paths", "string[]"),
+                ("update", "TagUpdate"),
+                ("options?", "BatchOptions<F>"),
+            ],
+            "Job<UpdateResult<F>[]>",
+        )
+        .documented(
+            "Applies the same update to many files in parallel, resolving with a result per path",
+        ),
+        ty(
+            "LoudnessOptions",
+            Ty::Extends(
+                "Omit<WriteOptions, 'frameFormat'> & JobOptions".to_owned(),
+                vec![
+                    documented(
+                        "Also measure the files together as one album and write album gains",
+                        "albumMode?",
+                        "boolean",
+                    ),
+                    field("write?", "boolean"),
+                ],
+            ),
+        )
+        .documented(
+            "Options of analyzeLoudness. With write, the gains are written like with updateTag:\n\
+             REPLAYGAIN_* TXXX frames to ID3 tags, REPLAYGAIN_* freeform atoms to MP4 files and\n\
+             REPLAYGAIN_* fields to Vorbis comments. Peaks are written as true peaks. Neither RVA2\n\
+             frames nor the R128_* fields of Opus are written",
+        ),
+        ty(
+            "LoudnessMeasurement",
+            Ty::Object(vec![
+                documented(
+                    "Integrated loudness in LUFS, gated per EBU R128",
+                    "integratedLoudness",
+                    "number | null",
+                ),
+                documented(
+                    "Largest value between samples found by oversampling, 1 being full scale",
+                    "truePeak",
+                    "number | null",
+                ),
+                field("samplePeak", "number | null"),
+            ]),
+        )
+        .documented("Loudness is null for silence and for audio shorter than 400 ms, and so are the gains"),
+        ty(
+            "TrackLoudness",
+            Ty::Extends(
+                "LoudnessMeasurement".to_owned(),
+                vec![
+                    field("path", "string"),
+                    field("ok", "boolean"),
+                    documented(
+                        "ERR_DECODE for audio that can't be decoded, ERR_UNSUPPORTED_CODEC for Opus audio,\n\
+                         or the error of writing the gains",
+                        "error",
+                        "TagError | null",
+                    ),
+                    documented(
+                        "ReplayGain 2.0 gain in dB, bringing the track to -18 LUFS",
+                        "trackGain",
+                        "number | null",
+                    ),
+                    field("albumGain", "number | null"),
+                    field("written", "boolean"),
+                ],
+            ),
+        ),
+        ty(
+            "LoudnessReport",
+            Ty::Object(vec![
+                field("tracks", "TrackLoudness[]"),
+                documented(
+                    "The measurement of all tracks together with albumMode, null otherwise",
+                    "album",
+                    "(LoudnessMeasurement & { gain: number | null }) | null",
+                ),
+            ]),
+        ),
+        function(
+            "analyzeLoudness",
+            "",
+            &[("paths", "string[]"), ("options?", "LoudnessOptions")],
+            "Job<LoudnessReport>",
+        )
+        .documented(
+            "Decodes MP3, FLAC, Ogg Vorbis and WAV files and measures their loudness per ITU-R BS.1770.\n\
+             Opus files can't be decoded and fail with ERR_UNSUPPORTED_CODEC, without stopping the others.\n\
+             Files are only written once all of them are measured, progress is reported as each one\n\
+             is measured",
+        ),
+        function("setConcurrency", "", &[("limit", "number")], "void").documented(
+            "Sets how many files asynchronous functions work on at once,\n\
+             the number of CPU cores by default",
+        ),
+    ]
+}
+
+fn helpers() -> Vec<Decl> {
+    vec![
+        ty(
+            "RatingConvention",
+            union(vec!["'wmp'", "'foobar2000'", "'musicbee'"]),
+        )
+        .documented(
+            "Star rating conventions of players writing POPM frames,\n\
+             Windows Media Player is used when none is given",
+        ),
+        function(
+            "ratingToStars",
+            "",
+            &[("rating", "number"), ("convention?", "RatingConvention")],
+            "number",
+        )
+        .documented(
+            "Converts a POPM rating to stars. Ratings other than integers from 0 to 255 throw a\n\
+             RangeError",
+        ),
+        function(
+            "starsToRating",
+            "",
+            &[("stars", "number"), ("convention?", "RatingConvention")],
+            "number",
+        ),
+        function(
+            "lrcToSynchronisedLyrics",
+            "",
+            &[
+                ("lrc", "string"),
+                ("lang?", "string"),
+                ("description?", "string"),
+            ],
+            "ID3SynchronisedLyrics",
+        )
+        .documented(
+            "Converts between LRC text and synchronised lyrics with millisecond timestamps.\n\
+             A positive offset makes lyrics appear sooner, compact merges repeated lines. Offsets\n\
+             other than 32-bit integers of milliseconds throw a RangeError",
+        ),
+        function(
+            "synchronisedLyricsToLrc",
+            "",
+            &[
+                ("lyrics", "ID3SynchronisedLyrics"),
+                ("options?", "{ offset?: number; compact?: boolean }"),
+            ],
+            "string",
+        ),
+        ty(
+            "ChapterIssue",
+            Ty::Object(vec![
+                field("elementId", "string"),
+                field("message", "string"),
+            ]),
+        ),
+        function(
+            "validateChapters",
+            "",
+            &[("tag", "TagUpdate")],
+            "ChapterIssue[]",
+        )
+        .documented("Checks that chapters don't overlap and every table of contents child exists"),
+    ]
+}
+
+/// Every declaration of the `native-addon` module, in the order they are written
+pub fn module() -> Vec<Decl> {
+    let mut decls = frames();
+    decls.extend(tags());
+    decls.extend(functions());
+    decls.extend(documents());
+    decls.extend(batches());
+    decls.extend(helpers());
+    decls
+}
+
+fn push_doc(out: &mut Vec<String>, doc: &str, indent: &str) {
+    if !doc.contains('\n') && !indent.is_empty() {
+        out.push(format!("{}/** {} */", indent, doc));
+        return;
+    }
+    out.push(format!("{}/**", indent));
+    out.extend(doc.lines().map(|line| format!("{} * {}", indent, line)));
+    out.push(format!("{} */", indent));
+}
+
+fn push_fields(out: &mut Vec<String>, fields: &[Field]) {
+    for field in fields {
+        if let Some(doc) = field.doc {
+            push_doc(out, doc, "  ");
+        }
+        out.push(format!("  {}: {};", field.name, field.ty));
+    }
+}
+
+/// Appends the lines of the declaration, unindented
+fn push_decl(out: &mut Vec<String>, decl: &Decl) {
+    if let Some(doc) = decl.doc {
+        push_doc(out, doc, "");
+    }
+    match &decl.item {
+        Item::Section => {}
+        Item::Type { name, generics, ty } => {
+            let head = format!("export type {}{} =", name, generics);
+            match ty {
+                Ty::Raw(ty) => out.push(format!("{} {};", head, ty)),
+                Ty::Union(types) => {
+                    let line = format!("{} {};", head, types.join(" | "));
+                    if line.len() + 2 <= MAX_WIDTH {
+                        out.push(line);
+                    } else {
+                        out.push(format!("{} {}", head, types[0]));
+                        out.extend(types[1..].iter().map(|ty| format!("| {}", ty)));
+                        if let Some(last) = out.last_mut() {
+                            last.push(';');
+                        }
+                    }
+                }
+                Ty::Tuple(types) => {
+                    out.push(format!("{} [", head));
+                    out.extend(types.iter().map(|ty| format!("  {},", ty)));
+                    out.push("];".to_owned());
+                }
+                Ty::Object(fields) | Ty::ObjectArray(fields) => {
+                    out.push(format!("{} {{", head));
+                    push_fields(out, fields);
+                    out.push(match ty {
+                        Ty::ObjectArray(_) => "}[];".to_owned(),
+                        _ => "};".to_owned(),
+                    });
+                }
+                Ty::Extends(base, fields) => {
+                    out.push(format!("{} {} & {{", head, base));
+                    push_fields(out, fields);
+                    out.push("};".to_owned());
+                }
+            }
+        }
+        Item::Class {
+            name,
+            extends,
+            fields,
+        } => {
+            out.push(format!("export class {} extends {} {{", name, extends));
+            push_fields(out, fields);
+            out.push("}".to_owned());
+        }
+        Item::Const { name, ty } => out.push(format!("export const {}: {};", name, ty)),
+        Item::Function {
+            name,
+            generics,
+            params,
+            returns,
+        } => {
+            let params: Vec<_> = params
+                .iter()
+                .map(|(name, ty)| format!("{}: {}", name, ty))
+                .collect();
+            let line = format!(
+                "export function {}{}({}): {};",
+                name,
+                generics,
+                params.join(", "),
+                returns
+            );
+            if line.len() + 2 <= MAX_WIDTH {
+                out.push(line);
+            } else {
+                out.push(format!("export function {}{}(", name, generics));
+                out.extend(params.iter().map(|param| format!("  {},", param)));
+                out.push(format!("): {};", returns));
+            }
+        }
+    }
+}
+
+/// Renders the declarations as the contents of a `.d.ts` file
+pub fn render(decls: &[Decl]) -> String {
+    let mut out = vec![
+        "// Generated from src/typings.rs by build.rs, do not edit.".to_owned(),
+        "// Regenerate with `cargo build --features write-types` after changing the declarations."
+            .to_owned(),
+        "declare module 'native-addon' {".to_owned(),
+    ];
+    for (i, decl) in decls.iter().enumerate() {
+        if i > 0 {
+            out.push(String::new());
+        }
+        let mut lines = Vec::new();
+        push_decl(&mut lines, decl);
+        // Raw types may span lines, which are indented like the others
+        out.extend(lines.iter().flat_map(|line| line.lines()).map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("  {}", line)
+            }
+        }));
+    }
+    out.push("}".to_owned());
+    out.join("\n") + "\n"
+}
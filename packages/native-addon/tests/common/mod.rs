@@ -0,0 +1,54 @@
+//! Helpers shared by the tests running the built addon in Node
+
+use std::{
+    env,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+/// Header of a 417 byte MPEG-1 layer III frame at 128 kbit/s and 44.1 kHz, joint stereo
+pub const MP3_FRAME_HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x44];
+
+/// Four silent MPEG frames without a tag
+pub fn mp3() -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for _ in 0..4 {
+        bytes.extend_from_slice(&MP3_FRAME_HEADER);
+        bytes.resize(bytes.len() + 413, 0);
+    }
+    bytes
+}
+
+/// The addon cargo built for the tests, next to the tests themselves
+fn addon() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    exe.parent().unwrap().join(format!(
+        "{}native_addon{}",
+        env::consts::DLL_PREFIX,
+        env::consts::DLL_SUFFIX
+    ))
+}
+
+/// Runs script in Node with the path of the addon as `process.argv[1]` followed by args, and
+/// returns what it printed. None when Node is not installed, so the test can be skipped
+pub fn run_node(script: &str, args: &[&str]) -> Option<String> {
+    if Command::new("node").arg("--version").output().is_err() {
+        eprintln!("Node is not installed, skipping the addon test");
+        return None;
+    }
+
+    let output = Command::new("node")
+        .arg("-e")
+        .arg(script)
+        .arg(addon())
+        .args(args)
+        .stderr(Stdio::piped())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Some(String::from_utf8(output.stdout).unwrap())
+}
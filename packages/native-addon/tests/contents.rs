@@ -0,0 +1,595 @@
+//! Runs the built addon in Node and checks it against @types/index.d.ts: the contents of a tag
+//! holding a frame of every kind must have the declared fields with values of the declared
+//! types, and every export taking options must read the option names its declaration lists
+
+mod common;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    io::Cursor,
+};
+
+use id3::{
+    frame::{
+        Chapter, Comment, EncapsulatedObject, ExtendedLink, ExtendedText, InvolvedPeopleList,
+        InvolvedPeopleListItem, Lyrics, Picture, PictureType, Popularimeter, Private,
+        SynchronisedLyrics, SynchronisedLyricsType, TableOfContents, TimestampFormat,
+        UniqueFileIdentifier, Unknown,
+    },
+    Content, Encoder, Frame, Tag, Version,
+};
+use metashine_core::FrameKind;
+
+/// Prints `kind|form|fields` for every loaded content, where form is `object`, `array` for
+/// arrays of objects, or the type of other values, and fields lists `name:type` pairs. Types
+/// are those of typeof, with `null`, `array` and `ArrayBuffer` told apart. Pictures and
+/// encapsulated objects loaded with lazyPayloads are printed with a `lazy ` kind. The frames of
+/// writable kinds are then written to the untagged copy and must read back the same, so updates
+/// accept the fields too
+const CONTENTS_SCRIPT: &str = r#"
+const addon = { exports: {} };
+process.dlopen(addon, process.argv[1]);
+const { loadTag, updateTag } = addon.exports;
+const [, , path, copy] = process.argv;
+
+const typeName = (value) => {
+  if (value === null) return 'null';
+  if (value instanceof ArrayBuffer) return 'ArrayBuffer';
+  if (Array.isArray(value)) return 'array';
+  return typeof value;
+};
+const fieldTypes = (items) => {
+  const fields = new Map();
+  for (const item of items) {
+    for (const [name, value] of Object.entries(item)) fields.set(name, typeName(value));
+  }
+  return [...fields].map(([name, type]) => `${name}:${type}`).join(',');
+};
+const describe = (kind, content) => {
+  if (Array.isArray(content)) return `${kind}|array|${fieldTypes(content)}`;
+  if (typeName(content) === 'object') return `${kind}|object|${fieldTypes([content])}`;
+  return `${kind}|${typeName(content)}|`;
+};
+const plain = (value) =>
+  JSON.stringify(value, (_, item) =>
+    item instanceof ArrayBuffer ? Array.from(new Uint8Array(item)) : item,
+  );
+
+const loaded = loadTag(path).frames;
+for (const [kind, , content] of loaded) console.log(describe(kind, content));
+for (const [kind, , content] of loadTag(path, { lazyPayloads: true }).frames) {
+  if (kind === 'picture' || kind === 'encapsulated object') {
+    console.log(describe(`lazy ${kind}`, content));
+  }
+}
+
+const writable = loaded.filter(([kind]) => kind !== 'unknown');
+updateTag(copy, writable);
+if (plain(loadTag(copy).frames) !== plain(writable)) {
+  throw new Error(`The frames read back as ${plain(loadTag(copy).frames)}`);
+}
+"#;
+
+/// Calls every export taking options with a proxy recording the options read, and prints
+/// `name|options` for each. Batch operations read their options before returning their job
+const OPTIONS_SCRIPT: &str = r#"
+const addon = { exports: {} };
+process.dlopen(addon, process.argv[1]);
+const exports = addon.exports;
+const [, , path] = process.argv;
+
+const buffer = new Uint8Array(require('fs').readFileSync(path)).buffer;
+const lyrics = exports.lrcToSynchronisedLyrics('[00:01.00]One');
+const document = exports.exportTag(path);
+const calls = {
+  loadTag: (options) => exports.loadTag(path, options),
+  updateTag: (options) => exports.updateTag(path, [], options),
+  previewUpdate: (options) => exports.previewUpdate(path, [], options),
+  loadTagFromBuffer: (options) => exports.loadTagFromBuffer(buffer, options),
+  writeTagToBuffer: (options) => exports.writeTagToBuffer(buffer, [], options),
+  loadTagAsync: (options) => exports.loadTagAsync(path, options),
+  updateTagAsync: (options) => exports.updateTagAsync(path, [], options),
+  importTag: (options) => exports.importTag(path, document, options),
+  loadTags: (options) => exports.loadTags([path], options),
+  updateTags: (options) => exports.updateTags([path], [], options),
+  analyzeLoudness: (options) => exports.analyzeLoudness([path], options),
+  synchronisedLyricsToLrc: (options) => exports.synchronisedLyricsToLrc(lyrics, options),
+};
+
+const pending = [];
+for (const [name, call] of Object.entries(calls)) {
+  const read = new Set();
+  const options = new Proxy(
+    {},
+    {
+      get: (_, key) => {
+        if (typeof key === 'string') read.add(key);
+        return undefined;
+      },
+    },
+  );
+  const result = call(options);
+  if (result instanceof Promise) pending.push(result);
+  console.log(`${name}|${[...read].join(',')}`);
+}
+Promise.all(pending).catch((error) => {
+  console.error(error);
+  process.exitCode = 1;
+});
+"#;
+
+/// The shape @types/index.d.ts declares for a type
+#[derive(Debug, PartialEq)]
+enum Declared {
+    /// A type that isn't an object, such as `string`
+    Value(String),
+    /// The types of the required and optional fields of an object, or of the objects of an
+    /// array
+    Object {
+        array: bool,
+        required: BTreeMap<String, String>,
+        optional: BTreeMap<String, String>,
+    },
+}
+
+/// Finds `export type {name} = ...;` in the declarations
+fn declared_type(declarations: &str, name: &str) -> Declared {
+    let head = format!("  export type {} = ", name);
+    let mut lines = declarations
+        .lines()
+        .skip_while(|line| !line.starts_with(&head));
+    let first = lines
+        .next()
+        .unwrap_or_else(|| panic!("{} is not declared", name));
+    let value = &first[head.len()..];
+    if value != "{" {
+        return Declared::Value(value.trim_end_matches(';').to_owned());
+    }
+
+    let mut required = BTreeMap::new();
+    let mut optional = BTreeMap::new();
+    for line in lines.by_ref() {
+        let line = line.trim();
+        if line.starts_with('}') {
+            return Declared::Object {
+                array: line == "}[];",
+                required,
+                optional,
+            };
+        }
+        if line.starts_with("/**") || line.starts_with('*') {
+            continue;
+        }
+        let (field, ty) = line.trim_end_matches(';').split_once(": ").unwrap();
+        match field.strip_suffix('?') {
+            Some(field) => optional.insert(field.to_owned(), ty.to_owned()),
+            None => required.insert(field.to_owned(), ty.to_owned()),
+        };
+    }
+    panic!("{} is not closed", name)
+}
+
+/// Splits a type at the separators outside of brackets, like the members of a union
+fn split_top_level<'t>(ty: &'t str, separator: &str) -> Vec<&'t str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in ty.char_indices() {
+        match c {
+            '<' | '{' | '[' | '(' => depth += 1,
+            // The arrow of function types closes nothing
+            '>' if ty[..i].ends_with('=') => {}
+            '>' | '}' | ']' | ')' => depth -= 1,
+            _ if depth == 0 && ty[i..].starts_with(separator) => {
+                parts.push(ty[start..i].trim());
+                start = i + separator.len();
+            }
+            _ => {}
+        }
+    }
+    parts.push(ty[start..].trim());
+    parts
+}
+
+/// Whether a value of the JavaScript type printed by CONTENTS_SCRIPT is of the declared type
+fn accepts(declarations: &str, ty: &str, actual: &str) -> bool {
+    split_top_level(ty, " | ")
+        .into_iter()
+        .any(|member| match member {
+            "string" | "number" | "boolean" | "null" | "ArrayBuffer" => member == actual,
+            _ if member.starts_with('\'') => actual == "string",
+            _ if member.starts_with('[') || member.ends_with("[]") => actual == "array",
+            _ => match declared_type(declarations, member) {
+                Declared::Value(ty) => accepts(declarations, &ty, actual),
+                Declared::Object { array: true, .. } => actual == "array",
+                Declared::Object { array: false, .. } => actual == "object",
+            },
+        })
+}
+
+/// The text of the declaration of a named type, generic parameters and comments left out, such
+/// as `FrameOptions<F> & { lazyPayloads?: boolean; }`
+fn declared_text(declarations: &str, name: &str) -> String {
+    let head = format!("  export type {}", name);
+    let mut lines = declarations.lines().skip_while(|line| {
+        !(line.starts_with(&format!("{} = ", head)) || line.starts_with(&format!("{}<", head)))
+    });
+    let first = lines
+        .next()
+        .unwrap_or_else(|| panic!("{} is not declared", name));
+    let mut text = first[first.rfind(" = ").unwrap() + 3..].to_owned();
+    if text.ends_with('{') {
+        for line in lines {
+            let line = line.trim();
+            if !line.starts_with("/**") && !line.starts_with('*') {
+                text.push(' ');
+                text.push_str(line);
+            }
+            if line == "};" {
+                break;
+            }
+        }
+    }
+    text.trim_end_matches(';').to_owned()
+}
+
+/// The field names of an object type built from named types, intersections, inline objects,
+/// `Pick` and `Omit`
+fn declared_fields(declarations: &str, ty: &str) -> BTreeSet<String> {
+    let keys = |keys: &str| -> BTreeSet<String> {
+        split_top_level(keys, " | ")
+            .into_iter()
+            .map(|key| key.trim_matches('\'').to_owned())
+            .collect()
+    };
+
+    let mut fields = BTreeSet::new();
+    for part in split_top_level(ty, " & ") {
+        if let Some(object) = part.strip_prefix('{') {
+            let object = object.trim_end_matches('}');
+            for field in split_top_level(object, ";") {
+                if let Some((name, _)) = field.split_once(':') {
+                    fields.insert(name.trim().trim_end_matches('?').to_owned());
+                }
+            }
+        } else if let Some(args) = part.strip_prefix("Pick<") {
+            let args = split_top_level(args.trim_end_matches('>'), ",");
+            let picked = keys(args[1]);
+            fields.extend(
+                declared_fields(declarations, args[0])
+                    .into_iter()
+                    .filter(|field| picked.contains(field)),
+            );
+        } else if let Some(args) = part.strip_prefix("Omit<") {
+            let args = split_top_level(args.trim_end_matches('>'), ",");
+            let omitted = keys(args[1]);
+            fields.extend(
+                declared_fields(declarations, args[0])
+                    .into_iter()
+                    .filter(|field| !omitted.contains(field)),
+            );
+        } else {
+            let name = part.split('<').next().unwrap();
+            fields.extend(declared_fields(
+                declarations,
+                &declared_text(declarations, name),
+            ));
+        }
+    }
+    fields
+}
+
+/// The type at the start of a parameter list, up to the comma or parenthesis ending it
+fn parameter_type(params: &str) -> &str {
+    let mut depth = 0;
+    for (i, c) in params.char_indices() {
+        match c {
+            '<' | '{' | '[' | '(' => depth += 1,
+            '>' if params[..i].ends_with('=') => {}
+            ',' | ')' if depth == 0 => return &params[..i],
+            '>' | '}' | ']' | ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    params
+}
+
+/// The type of the options parameter of every exported function having one
+fn declared_options(declarations: &str) -> BTreeMap<String, String> {
+    let mut options = BTreeMap::new();
+    let mut function = None;
+    for line in declarations.lines() {
+        if let Some(head) = line.strip_prefix("  export function ") {
+            let name = head.split(['<', '(']).next().unwrap();
+            function = Some(name.to_owned());
+        }
+        if let (Some(name), Some((_, ty))) = (&function, line.split_once("options?: ")) {
+            options.insert(name.clone(), parameter_type(ty).to_owned());
+        }
+        if line.starts_with("  ):")
+            || (line.starts_with("  export function ") && line.ends_with(';'))
+        {
+            function = None;
+        }
+    }
+    options
+}
+
+/// `extended text` becomes `ExtendedText`
+fn pascal_case(kind: &str) -> String {
+    kind.split(' ')
+        .map(|word| word[..1].to_ascii_uppercase() + &word[1..])
+        .collect()
+}
+
+/// An MP3 file whose ID3v2.4 tag holds a frame of every kind
+fn mp3_with_every_kind() -> Vec<u8> {
+    let text = |value: &str| Frame::text("TIT2", value);
+    let tag: Tag = vec![
+        text("Title"),
+        Frame::with_content(
+            "TXXX",
+            Content::ExtendedText(ExtendedText {
+                description: String::from("MOOD"),
+                value: String::from("calm"),
+            }),
+        ),
+        Frame::link("WOAR", "https://example.com"),
+        Frame::with_content(
+            "WXXX",
+            Content::ExtendedLink(ExtendedLink {
+                description: String::from("shop"),
+                link: String::from("https://example.com/shop"),
+            }),
+        ),
+        Frame::with_content(
+            "COMM",
+            Content::Comment(Comment {
+                lang: String::from("eng"),
+                description: String::new(),
+                text: String::from("A comment"),
+            }),
+        ),
+        Frame::with_content(
+            "POPM",
+            Content::Popularimeter(Popularimeter {
+                user: String::from("user@example.com"),
+                rating: 196,
+                counter: 12,
+            }),
+        ),
+        Frame::with_content(
+            "PCNT",
+            Content::Unknown(Unknown {
+                data: vec![0, 0, 1, 44],
+                version: Version::Id3v24,
+            }),
+        ),
+        Frame::with_content(
+            "USLT",
+            Content::Lyrics(Lyrics {
+                lang: String::from("eng"),
+                description: String::new(),
+                text: String::from("One\nTwo"),
+            }),
+        ),
+        Frame::with_content(
+            "SYLT",
+            Content::SynchronisedLyrics(SynchronisedLyrics {
+                lang: String::from("eng"),
+                timestamp_format: TimestampFormat::Ms,
+                content_type: SynchronisedLyricsType::Lyrics,
+                description: String::new(),
+                content: vec![(1000, String::from("One")), (2500, String::from("Two"))],
+            }),
+        ),
+        Frame::with_content(
+            "APIC",
+            Content::Picture(Picture {
+                mime_type: String::from("image/png"),
+                picture_type: PictureType::CoverFront,
+                description: String::from("front"),
+                data: vec![1, 2, 3],
+            }),
+        ),
+        Frame::with_content(
+            "GEOB",
+            Content::EncapsulatedObject(EncapsulatedObject {
+                mime_type: String::from("application/octet-stream"),
+                filename: String::from("a.bin"),
+                description: String::from("object"),
+                data: vec![9, 8, 7],
+            }),
+        ),
+        Frame::with_content(
+            "CTOC",
+            Content::TableOfContents(TableOfContents {
+                element_id: String::from("toc"),
+                top_level: true,
+                ordered: true,
+                elements: vec![String::from("ch0")],
+                frames: Vec::new(),
+            }),
+        ),
+        Frame::with_content(
+            "CHAP",
+            Content::Chapter(Chapter {
+                element_id: String::from("ch0"),
+                start_time: 0,
+                end_time: 1000,
+                start_offset: u32::MAX,
+                end_offset: u32::MAX,
+                frames: vec![text("Intro")],
+            }),
+        ),
+        Frame::with_content(
+            "TIPL",
+            Content::InvolvedPeopleList(InvolvedPeopleList {
+                items: vec![InvolvedPeopleListItem {
+                    involvement: String::from("producer"),
+                    involvee: String::from("Someone"),
+                }],
+            }),
+        ),
+        Frame::with_content(
+            "PRIV",
+            Content::Private(Private {
+                owner_identifier: String::from("metashine"),
+                private_data: vec![1, 2, 3],
+            }),
+        ),
+        Frame::with_content(
+            "UFID",
+            Content::UniqueFileIdentifier(UniqueFileIdentifier {
+                owner_identifier: String::from("http://musicbrainz.org"),
+                identifier: b"id".to_vec(),
+            }),
+        ),
+        Frame::with_content(
+            "XABC",
+            Content::Unknown(Unknown {
+                data: vec![0xde, 0xad],
+                version: Version::Id3v24,
+            }),
+        ),
+    ]
+    .into_iter()
+    .collect();
+
+    let mut file = Cursor::new(common::mp3());
+    Encoder::new()
+        .version(Version::Id3v24)
+        .padding(0)
+        .write_to_file(&tag, &mut file)
+        .unwrap();
+    file.into_inner()
+}
+
+#[test]
+fn loaded_contents_have_the_declared_fields() {
+    let dir = env::temp_dir().join(format!("native-addon-contents-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("tagged.mp3");
+    let copy = dir.join("copy.mp3");
+    fs::write(&path, mp3_with_every_kind()).unwrap();
+    fs::write(&copy, common::mp3()).unwrap();
+
+    let args = [path.to_str().unwrap(), copy.to_str().unwrap()];
+    let output = match common::run_node(CONTENTS_SCRIPT, &args) {
+        Some(output) => output,
+        None => return,
+    };
+
+    let declarations = include_str!("../@types/index.d.ts");
+    let mut kinds = BTreeSet::new();
+    for line in output.lines() {
+        let mut parts = line.split('|');
+        let (kind, form, fields) = (
+            parts.next().unwrap(),
+            parts.next().unwrap(),
+            parts.next().unwrap(),
+        );
+        let (kind, lazy) = match kind.strip_prefix("lazy ") {
+            Some(kind) => (kind, true),
+            None => (kind, false),
+        };
+        kinds.insert(kind.to_owned());
+
+        let mut declared = declared_type(declarations, &format!("ID3{}", pascal_case(kind)));
+        // Handles replace the data with the fields of ID3PayloadHandle
+        if let (true, Declared::Object { required, .. }) = (lazy, &mut declared) {
+            if let Declared::Object {
+                required: handle, ..
+            } = declared_type(declarations, "ID3PayloadHandle")
+            {
+                required.remove("data");
+                required.extend(handle);
+            }
+        }
+
+        match declared {
+            Declared::Value(ty) => {
+                assert!(accepts(declarations, &ty, form), "{}: {}", kind, form);
+                assert_eq!(fields, "", "{}", kind);
+            }
+            Declared::Object {
+                array,
+                required,
+                optional,
+            } => {
+                let expected_form = if array { "array" } else { "object" };
+                assert_eq!(form, expected_form, "{}", kind);
+                let fields: BTreeMap<&str, &str> = fields
+                    .split(',')
+                    .map(|field| field.split_once(':').unwrap())
+                    .collect();
+                for name in required.keys() {
+                    assert!(fields.contains_key(name.as_str()), "{}: {}", kind, name);
+                }
+                for (name, actual) in fields {
+                    let ty = required
+                        .get(name)
+                        .or_else(|| optional.get(name))
+                        .unwrap_or_else(|| panic!("{}: {} is not declared", kind, name));
+                    assert!(
+                        accepts(declarations, ty, actual),
+                        "{}: {} is {}, declared {}",
+                        kind,
+                        name,
+                        actual,
+                        ty
+                    );
+                }
+            }
+        }
+    }
+
+    let every_kind: BTreeSet<String> = FrameKind::ALL
+        .iter()
+        .map(|kind| kind.as_str().to_owned())
+        .collect();
+    assert_eq!(kinds, every_kind);
+}
+
+#[test]
+fn exports_read_the_declared_options() {
+    let path = env::temp_dir().join(format!("native-addon-options-{}.mp3", std::process::id()));
+    fs::write(&path, common::mp3()).unwrap();
+
+    let output = match common::run_node(OPTIONS_SCRIPT, &[path.to_str().unwrap()]) {
+        Some(output) => output,
+        None => return,
+    };
+
+    let declarations = include_str!("../@types/index.d.ts");
+    let read: BTreeMap<&str, BTreeSet<String>> = output
+        .lines()
+        .map(|line| {
+            let (name, options) = line.split_once('|').unwrap();
+            let options = options
+                .split(',')
+                .filter(|option| !option.is_empty())
+                .map(str::to_owned)
+                .collect();
+            (name, options)
+        })
+        .collect();
+    let declared: BTreeMap<String, BTreeSet<String>> = declared_options(declarations)
+        .into_iter()
+        .map(|(name, ty)| {
+            let fields = declared_fields(declarations, &ty);
+            (name, fields)
+        })
+        .collect();
+
+    assert_eq!(
+        read.keys().collect::<Vec<_>>(),
+        declared.keys().collect::<Vec<_>>(),
+        "every export taking options is called"
+    );
+    for (name, options) in read {
+        assert_eq!(options, declared[name], "{}", name);
+    }
+}
@@ -0,0 +1,114 @@
+//! Checks through the built addon in Node that integer fields and arguments out of range or
+//! with a fraction are refused instead of being clamped or truncated
+
+mod common;
+
+use std::{env, fs};
+
+/// Writes every case to the file and prints `code|message` for the error each throws, and
+/// `name|message` for the helpers throwing a RangeError
+const SCRIPT: &str = r#"
+const addon = { exports: {} };
+process.dlopen(addon, process.argv[1]);
+const { updateTag, ratingToStars, synchronisedLyricsToLrc } = addon.exports;
+const [, , path] = process.argv;
+
+const picture = (pictureType) => ({
+  MIMEType: 'image/png',
+  pictureType,
+  description: '',
+  data: new ArrayBuffer(1),
+});
+const lyrics = (timestamp) => ({
+  lang: 'eng',
+  timestampFormat: 'ms',
+  contentType: 'lyrics',
+  description: '',
+  content: [[timestamp, 'One']],
+});
+const print = (error) => console.log(`${error.code ?? error.name}|${error.message}`);
+
+const frames = [
+  ['popularimeter', 'POPM', { user: 'a', rating: 256, counter: 0 }],
+  ['popularimeter', 'POPM', { user: 'a', rating: 1.5, counter: 0 }],
+  ['popularimeter', 'POPM', { user: 'a', rating: 1, counter: -1 }],
+  ['play counter', 'PCNT', 2 ** 53],
+  ['picture', 'APIC', picture(21)],
+  ['synchronised lyrics', 'SYLT', lyrics(-1)],
+];
+for (const frame of frames) {
+  try {
+    updateTag(path, [[...frame, false]]);
+    console.log('written');
+  } catch (error) {
+    print(error);
+  }
+}
+
+const calls = [
+  () => ratingToStars(256),
+  () => ratingToStars(0.5),
+  () => synchronisedLyricsToLrc(lyrics(0), { offset: 0.5 }),
+  () => synchronisedLyricsToLrc(lyrics(0), { offset: 2 ** 31 }),
+];
+for (const call of calls) {
+  try {
+    call();
+    console.log('returned');
+  } catch (error) {
+    print(error);
+  }
+}
+"#;
+
+#[test]
+fn integers_out_of_range_are_refused() {
+    let path = env::temp_dir().join(format!("native-addon-integers-{}.mp3", std::process::id()));
+    fs::write(&path, common::mp3()).unwrap();
+    let path = path.to_str().unwrap();
+
+    let output = match common::run_node(SCRIPT, &[path]) {
+        Some(output) => output,
+        None => return,
+    };
+    let frame_error = |id: &str, message: &str| {
+        format!("ERR_BAD_CARRIER|{}: frame 0 ({}): {}", path, id, message)
+    };
+    let range_error = |message: &str| format!("RangeError|{}", message);
+    assert_eq!(
+        output.lines().collect::<Vec<_>>(),
+        [
+            frame_error(
+                "POPM",
+                "Field rating must be an integer from 0 to 255, got 256"
+            ),
+            frame_error(
+                "POPM",
+                "Field rating must be an integer from 0 to 255, got 1.5"
+            ),
+            frame_error(
+                "POPM",
+                "Field counter must be an integer from 0 to 9007199254740991, got -1"
+            ),
+            frame_error(
+                "PCNT",
+                "Field 2 must be an integer from 0 to 9007199254740991, got 9007199254740992"
+            ),
+            frame_error(
+                "APIC",
+                "Field pictureType must be an integer from 0 to 20, got 21"
+            ),
+            frame_error(
+                "SYLT",
+                "Field content[0][0] must be an integer from 0 to 4294967295, got -1"
+            ),
+            range_error("The rating must be an integer from 0 to 255, got 256"),
+            range_error("The rating must be an integer from 0 to 255, got 0.5"),
+            range_error("The offset must be an integer from -2147483648 to 2147483647, got 0.5"),
+            range_error(
+                "The offset must be an integer from -2147483648 to 2147483647, got 2147483648"
+            ),
+        ]
+    );
+    assert_eq!(fs::read(path).unwrap(), common::mp3());
+}